@@ -0,0 +1,31 @@
+//! Feed arbitrary bytes to the frame reader (with and without checksum checks) and to the
+//! message parser. Any panic - a bad slice index, an oversized allocation - is a bug.
+#![no_main]
+
+use bitch::{parse_mavlink_frame, parse_mavlink_message, CrcCheck, FrameFlags, FrameReader};
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use tokio::runtime::{Builder, Runtime};
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| Builder::new_current_thread().build().unwrap())
+}
+
+fuzz_target!(|data: &[u8]| {
+    // The whole input as one packet, as the proxy parses frames from the reader
+    let _ = parse_mavlink_message(data);
+
+    for validate_crc in [false, true] {
+        let mut reader = FrameReader::new(data, CrcCheck::new(validate_crc, HashMap::new()));
+        runtime().block_on(async {
+            // The reader errors once the input runs out
+            while let Ok(frame) = reader.read_frame().await {
+                assert_eq!(frame[0], 0xFD, "frames start at a magic byte");
+                let _ = FrameFlags::of(&frame);
+                let _ = parse_mavlink_frame(&frame);
+            }
+        });
+    }
+});
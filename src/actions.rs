@@ -0,0 +1,493 @@
+use crate::batch::{BatchResult, Destination};
+use crate::config::ModifyErrorPolicy;
+use crate::framing::{encode_v2, RawHeader};
+use crate::proxy::{send_to_routers, ProxyState, RouterLinks};
+use crate::rules::{parse_mavlink_message, Action};
+use mavlink::ardupilotmega::MavMessage;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// Future returned by action handlers and chain execution
+pub type ChainFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// The rest of an action chain, handed to each handler
+/// A handler continues the chain by calling `run`, or stops it by dropping `Next`.
+#[derive(Clone)]
+pub struct Next {
+    /// Actions still to run after the current one
+    pub remaining_actions: Vec<Action>,
+    /// Where packets go once the chain is exhausted
+    pub destination: Destination,
+    pub state: Arc<ProxyState>,
+}
+
+impl Next {
+    /// Continue the chain with `packets`
+    pub fn run(self, packets: Vec<Vec<u8>>) -> ChainFuture {
+        run_chain(self.remaining_actions, packets, self.destination, self.state)
+    }
+}
+
+/// Behavior behind one action type
+pub trait ActionHandler: Send + Sync {
+    /// Apply `action` to `packets`, then continue (or end) the chain through `next`
+    fn handle(&self, action: Action, packets: Vec<Vec<u8>>, next: Next) -> ChainFuture;
+}
+
+/// Action handlers keyed by action name (as written in rule configs)
+pub struct ActionRegistry {
+    handlers: HashMap<&'static str, Arc<dyn ActionHandler>>,
+}
+
+impl ActionRegistry {
+    /// Registry with a handler for every built-in action
+    pub fn new() -> Self {
+        let mut registry = Self {
+            handlers: HashMap::new(),
+        };
+        registry.register("forward", ForwardHandler);
+        registry.register("block", BlockHandler);
+        registry.register("modify", ModifyHandler);
+        registry.register("delay", DelayHandler);
+        registry.register("batch", BatchHandler);
+        registry.register("burst", BurstHandler);
+        registry.register("coalesce", CoalesceHandler);
+        registry.register("retry_until_ack", RetryUntilAckHandler);
+        registry
+    }
+
+    /// Add or replace the handler for `name`
+    pub fn register(&mut self, name: &'static str, handler: impl ActionHandler + 'static) {
+        self.handlers.insert(name, Arc::new(handler));
+    }
+
+    fn get(&self, name: &str) -> Option<Arc<dyn ActionHandler>> {
+        self.handlers.get(name).cloned()
+    }
+}
+
+/// Run an action chain over `packets`, delivering whatever reaches its end to `destination`
+pub fn run_chain(
+    mut actions: Vec<Action>,
+    packets: Vec<Vec<u8>>,
+    destination: Destination,
+    state: Arc<ProxyState>,
+) -> ChainFuture {
+    Box::pin(async move {
+        if actions.is_empty() {
+            deliver(packets, &destination, &state).await;
+            return;
+        }
+
+        let action = actions.remove(0);
+        let handler = state.actions().get(action.name());
+        let next = Next {
+            remaining_actions: actions,
+            destination,
+            state,
+        };
+
+        match handler {
+            Some(handler) => handler.handle(action, packets, next).await,
+            None => {
+                warn!("No handler for '{}' action, skipping it", action.name());
+                next.run(packets).await;
+            }
+        }
+    })
+}
+
+/// Send packets that made it through the whole chain
+async fn deliver(packets: Vec<Vec<u8>>, destination: &Destination, state: &ProxyState) {
+    match destination {
+        Destination::Router(routers) => {
+            for packet in packets {
+                state.pace().await;
+                send_to_routers(routers, &packet).await;
+            }
+        }
+        Destination::Gcs => {
+            for packet in packets {
+                state.broadcast_to_all_gcs(&packet).await;
+            }
+        }
+        Destination::GcsGroup(group) => {
+            for packet in packets {
+                state.broadcast_to_group(group, &packet).await;
+            }
+        }
+        Destination::GcsClient(client_id) => {
+            for packet in packets {
+                state.send_to_gcs_client(*client_id, &packet).await;
+            }
+        }
+    }
+}
+
+/// `forward`: continue with the remaining actions
+struct ForwardHandler;
+
+impl ActionHandler for ForwardHandler {
+    fn handle(&self, _action: Action, packets: Vec<Vec<u8>>, next: Next) -> ChainFuture {
+        next.run(packets)
+    }
+}
+
+/// `block`: drop the packets and end the chain
+struct BlockHandler;
+
+impl ActionHandler for BlockHandler {
+    fn handle(&self, action: Action, packets: Vec<Vec<u8>>, next: Next) -> ChainFuture {
+        let Action::Block { rule, reason } = action else {
+            return next.run(packets);
+        };
+
+        warn!(
+            "{} message(s) blocked by rule '{}' ({}){}",
+            packets.len(),
+            rule,
+            next.destination.label(),
+            format_block_reason(&reason)
+        );
+        next.state.stats().record_block(&rule, packets.len() as u64);
+        Box::pin(async {})
+    }
+}
+
+/// `modify`: re-encode each packet with the modifier's message, keeping its header
+struct ModifyHandler;
+
+impl ActionHandler for ModifyHandler {
+    fn handle(&self, action: Action, packets: Vec<Vec<u8>>, next: Next) -> ChainFuture {
+        let Action::Modify {
+            modifier,
+            modified_message: Some(modified_msg),
+        } = action
+        else {
+            warn!("Modify action has no modified message, forwarding original");
+            return next.run(packets);
+        };
+
+        info!("Applying modification from '{}' ({})", modifier, next.destination.label());
+        let modified_packets: Vec<Vec<u8>> = packets
+            .into_iter()
+            .filter_map(|packet| reencode(packet, &modifier, &modified_msg, &next.state))
+            .collect();
+        if modified_packets.is_empty() {
+            return Box::pin(async {});
+        }
+        next.run(modified_packets)
+    }
+}
+
+/// Serialize `modified_msg` under `packet`'s header
+/// On failure `on_modify_error` decides what goes out instead; None drops the packet.
+fn reencode(packet: Vec<u8>, modifier: &str, modified_msg: &MavMessage, state: &ProxyState) -> Option<Vec<u8>> {
+    let policy = state.on_modify_error();
+    let header = match parse_mavlink_message(&packet) {
+        Ok((header, _original_msg)) => header,
+        Err(e) => {
+            error!("Modifier '{}': failed to parse packet for modification: {:#}", modifier, e);
+            return fallback(packet, modifier, policy);
+        }
+    };
+
+    match encode_v2(header, modified_msg, false) {
+        Ok(buf) => Some(buf),
+        Err(e) => {
+            error!("Modifier '{}': failed to serialize modified message: {:#}", modifier, e);
+            if policy == ModifyErrorPolicy::ForwardModifiedBestEffort {
+                // The untruncated encoder writes each field directly instead of going through the writer
+                match encode_v2(header, modified_msg, true) {
+                    Ok(buf) => return Some(buf),
+                    Err(e) => error!("Modifier '{}': best-effort encoding failed too: {:#}", modifier, e),
+                }
+            }
+            fallback(packet, modifier, policy)
+        }
+    }
+}
+
+/// What replaces a packet whose modification couldn't be applied
+fn fallback(packet: Vec<u8>, modifier: &str, policy: ModifyErrorPolicy) -> Option<Vec<u8>> {
+    if policy == ModifyErrorPolicy::Block {
+        warn!("Modifier '{}' failed, dropping packet (on_modify_error = block)", modifier);
+        None
+    } else {
+        warn!("Modifier '{}' failed, forwarding original packet", modifier);
+        Some(packet)
+    }
+}
+
+/// `delay`: continue the chain later without holding up other traffic
+struct DelayHandler;
+
+impl ActionHandler for DelayHandler {
+    fn handle(&self, action: Action, packets: Vec<Vec<u8>>, next: Next) -> ChainFuture {
+        let Action::Delay(duration) = action else {
+            return next.run(packets);
+        };
+
+        let delay_secs = duration.as_secs();
+        info!(
+            "Message(s) queued for {}s delay ({}, other traffic continues)",
+            delay_secs,
+            next.destination.label()
+        );
+        tokio::spawn(async move {
+            sleep(duration).await;
+            next.run(packets).await;
+            info!("Delayed message(s) forwarded after {}s", delay_secs);
+        });
+        Box::pin(async {})
+    }
+}
+
+/// `batch`: hold packets until enough systems sent one, then release them together
+struct BatchHandler;
+
+impl ActionHandler for BatchHandler {
+    fn handle(&self, action: Action, packets: Vec<Vec<u8>>, next: Next) -> ChainFuture {
+        let Action::Batch {
+            count,
+            timeout,
+            key,
+            forward_on_timeout,
+            system_id_field,
+            timeout_actions,
+        } = action
+        else {
+            return next.run(packets);
+        };
+
+        if matches!(next.destination, Destination::Gcs | Destination::GcsGroup(_) | Destination::GcsClient(_)) {
+            warn!("Batch action not supported in router->GCS direction, forwarding");
+            return next.run(packets);
+        }
+
+        Box::pin(async move {
+            // Batch action only makes sense for single packets
+            if packets.len() != 1 {
+                warn!("Batch action applied to {} packets, only batching first", packets.len());
+            }
+            let Some(packet) = packets.into_iter().next() else {
+                return;
+            };
+
+            let system_id = match parse_mavlink_message(&packet) {
+                Ok((header, msg)) => system_id_field
+                    .as_deref()
+                    .and_then(|field_name| extract_system_id_from_message(&msg, field_name))
+                    .unwrap_or(header.system_id),
+                Err(_) => 0,
+            };
+
+            let Next {
+                remaining_actions,
+                destination,
+                state,
+            } = next;
+            let batch_result = state
+                .batch_manager()
+                .queue_or_release(
+                    key.clone(),
+                    system_id,
+                    packet,
+                    count,
+                    timeout,
+                    forward_on_timeout,
+                    remaining_actions,
+                    timeout_actions,
+                    destination,
+                    state.clone(),
+                )
+                .await;
+
+            if let BatchResult::Release {
+                packets,
+                remaining_actions,
+                destination,
+            } = batch_result
+            {
+                info!(
+                    "Batch '{}' threshold met, applying {} remaining action(s) to {} packets",
+                    key,
+                    remaining_actions.len(),
+                    packets.len()
+                );
+                run_chain(remaining_actions, packets, destination, state).await;
+            }
+        })
+    }
+}
+
+/// `burst`: hold packets until `count` of them arrived, from any system, then release them together
+struct BurstHandler;
+
+impl ActionHandler for BurstHandler {
+    fn handle(&self, action: Action, packets: Vec<Vec<u8>>, next: Next) -> ChainFuture {
+        let Action::Burst {
+            count,
+            timeout,
+            key,
+            forward_on_timeout,
+        } = action
+        else {
+            return next.run(packets);
+        };
+
+        Box::pin(async move {
+            let Next {
+                remaining_actions,
+                destination,
+                state,
+            } = next;
+
+            // Each packet counts towards the burst; the one that completes it releases the group
+            for packet in packets {
+                let system_id = RawHeader::of(&packet).map_or(0, |header| header.system_id);
+                let burst_result = state
+                    .burst_manager()
+                    .queue_or_release(
+                        key.clone(),
+                        system_id,
+                        packet,
+                        count,
+                        timeout,
+                        forward_on_timeout,
+                        remaining_actions.clone(),
+                        None,
+                        destination.clone(),
+                        state.clone(),
+                    )
+                    .await;
+
+                if let BatchResult::Release {
+                    packets,
+                    remaining_actions: released_actions,
+                    destination,
+                } = burst_result
+                {
+                    info!(
+                        "Burst '{}' complete, applying {} remaining action(s) to {} packets",
+                        key,
+                        released_actions.len(),
+                        packets.len()
+                    );
+                    run_chain(released_actions, packets, destination, state.clone()).await;
+                }
+            }
+        })
+    }
+}
+
+/// Extract system_id from a message field generically
+fn extract_system_id_from_message(msg: &MavMessage, field_name: &str) -> Option<u8> {
+    // Serialize message to JSON (mavlink internally-tagged format)
+    let message_json = serde_json::to_value(msg).ok()?;
+    message_json.get(field_name)?.as_u64().map(|v| v as u8)
+}
+
+/// `coalesce`: hold only the freshest packet per key and forward it once per interval
+struct CoalesceHandler;
+
+impl ActionHandler for CoalesceHandler {
+    fn handle(&self, action: Action, packets: Vec<Vec<u8>>, next: Next) -> ChainFuture {
+        let Action::Coalesce { key, interval } = action else {
+            return next.run(packets);
+        };
+
+        // Later packets replace earlier ones, so only the freshest is held
+        for packet in packets {
+            next.state
+                .coalescer()
+                .submit(key.clone(), interval, packet, next.clone());
+        }
+        Box::pin(async {})
+    }
+}
+
+/// `retry_until_ack`: send now and re-send until the matching ACK is seen
+struct RetryUntilAckHandler;
+
+impl ActionHandler for RetryUntilAckHandler {
+    fn handle(&self, action: Action, packets: Vec<Vec<u8>>, next: Next) -> ChainFuture {
+        let Action::RetryUntilAck {
+            message_type,
+            timeout,
+            attempts,
+        } = action
+        else {
+            return next.run(packets);
+        };
+
+        let Destination::Router(routers) = &next.destination else {
+            warn!("Retry-until-ACK action not supported in router->GCS direction, forwarding");
+            return next.run(packets);
+        };
+
+        // Register before the original goes out so a fast ACK can't be missed
+        for packet in &packets {
+            let (id, acked) = next.state.retries().register(&message_type, packet);
+            tokio::spawn(retry_until_ack(
+                packet.clone(),
+                routers.clone(),
+                next.state.clone(),
+                id,
+                acked,
+                message_type.clone(),
+                timeout,
+                attempts,
+            ));
+        }
+        next.run(packets)
+    }
+}
+
+/// Re-send a packet to the routers every `timeout` until its ACK is observed or `attempts` run out
+#[allow(clippy::too_many_arguments)]
+async fn retry_until_ack(
+    packet: Vec<u8>,
+    routers: RouterLinks,
+    state: Arc<ProxyState>,
+    id: u64,
+    acked: CancellationToken,
+    message_type: String,
+    timeout: Duration,
+    attempts: u32,
+) {
+    for attempt in 1..=attempts {
+        tokio::select! {
+            _ = acked.cancelled() => return,
+            _ = sleep(timeout) => {}
+        }
+        warn!(
+            "No {} within {}s, re-sending (attempt {}/{})",
+            message_type,
+            timeout.as_secs(),
+            attempt,
+            attempts
+        );
+        send_to_routers(&routers, &packet).await;
+    }
+
+    tokio::select! {
+        _ = acked.cancelled() => {}
+        _ = sleep(timeout) => warn!("No {} after {} re-send(s), giving up", message_type, attempts),
+    }
+    state.retries().finish(id);
+}
+
+/// Format an optional block reason for log output
+fn format_block_reason(reason: &Option<String>) -> String {
+    reason
+        .as_ref()
+        .map(|r| format!(": {}", r))
+        .unwrap_or_default()
+}
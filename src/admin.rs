@@ -0,0 +1,340 @@
+use crate::config::{CommandRule, Config};
+use crate::proxy::ProxyState;
+use crate::rules::RuleEngine;
+use anyhow::{Context, Result};
+use serde_json::{json, Value as JsonValue};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+/// Largest accepted request head (request line + headers)
+const MAX_HEAD_LEN: usize = 8 * 1024;
+
+/// Largest accepted request body
+const MAX_BODY_LEN: usize = 64 * 1024;
+
+/// Time allowed for a client to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A parsed admin API request
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+/// JSON response: status code and body
+pub struct Response {
+    pub status: u16,
+    pub body: JsonValue,
+}
+
+impl Response {
+    fn ok(body: JsonValue) -> Self {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message }),
+        }
+    }
+}
+
+/// What the endpoints read and change
+struct AdminContext {
+    state: Arc<ProxyState>,
+    rule_engine: Arc<RuleEngine>,
+    /// Startup config, for `[defaults]` and condition templates of replaced rules
+    config: Arc<Config>,
+}
+
+/// Serve the admin API (one request per connection, JSON responses)
+pub async fn serve(listener: TcpListener, state: Arc<ProxyState>, rule_engine: Arc<RuleEngine>, config: Arc<Config>) {
+    let context = Arc::new(AdminContext {
+        state,
+        rule_engine,
+        config,
+    });
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                let context = context.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, &context).await {
+                        debug!("Admin API connection from {} failed: {:#}", addr, e);
+                    }
+                });
+            }
+            Err(e) => warn!("Failed to accept admin API connection: {}", e),
+        }
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, context: &AdminContext) -> Result<()> {
+    let response = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await {
+        Ok(Ok(request)) => route(&request, context).await,
+        Ok(Err(e)) => Response::error(400, &format!("{:#}", e)),
+        Err(_) => Response::error(408, "request timed out"),
+    };
+
+    let body = serde_json::to_vec_pretty(&response.body)?;
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason_phrase(response.status),
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Read the request line, headers and (Content-Length delimited) body
+async fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut buf = Vec::new();
+    let head_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        if buf.len() > MAX_HEAD_LEN {
+            anyhow::bail!("request head too large");
+        }
+        let mut chunk = [0u8; 1024];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            anyhow::bail!("connection closed before end of request head");
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = std::str::from_utf8(&buf[..head_end]).context("request head is not UTF-8")?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().context("missing method")?.to_string();
+    let target = request_line.next().context("missing path")?;
+    // Query strings are not used by any endpoint
+    let path = target.split('?').next().unwrap_or(target).to_string();
+
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.trim().parse::<usize>())
+        .transpose()
+        .context("invalid Content-Length")?
+        .unwrap_or(0);
+    if content_length > MAX_BODY_LEN {
+        anyhow::bail!("request body too large");
+    }
+
+    let mut body = buf[head_end + 4..].to_vec();
+    while body.len() < content_length {
+        let mut chunk = [0u8; 1024];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            anyhow::bail!("connection closed before end of request body");
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(content_length);
+
+    Ok(Request { method, path, body })
+}
+
+/// Dispatch a request to its endpoint
+async fn route(request: &Request, context: &AdminContext) -> Response {
+    let state = &context.state;
+    if let Some(system_id) = request.path.strip_prefix("/quarantine/") {
+        return match request.method.as_str() {
+            "PUT" => quarantine_system(context, system_id, &request.body),
+            "DELETE" => release_system(context, system_id),
+            _ => Response::error(405, "method not allowed"),
+        };
+    }
+    if let Some(client_id) = request.path.strip_prefix("/clients/").and_then(|rest| rest.strip_suffix("/disconnect")) {
+        return match request.method.as_str() {
+            "POST" => disconnect_client(state, client_id).await,
+            _ => Response::error(405, "method not allowed"),
+        };
+    }
+    let rule_name = request.path.strip_prefix("/rules/").filter(|name| !name.is_empty());
+    match (request.method.as_str(), request.path.as_str(), rule_name) {
+        ("GET", "/health", _) => health(state),
+        ("GET", "/batches", _) => batches(state).await,
+        ("GET", "/stats", _) => stats(state, &context.rule_engine),
+        ("GET", "/connections", _) => connections(state),
+        ("GET", "/clients", _) => clients(state).await,
+        ("GET", "/quarantine", _) => quarantine(&context.rule_engine),
+        ("PUT", _, Some(name)) => replace_rule(context, name, &request.body),
+        (_, "/health" | "/batches" | "/stats" | "/connections" | "/clients" | "/quarantine", _) | (_, _, Some(_)) => {
+            Response::error(405, "method not allowed")
+        }
+        _ => Response::error(404, "not found"),
+    }
+}
+
+/// GET /health - router link, clients, uptime and config files changed since they were loaded
+fn health(state: &ProxyState) -> Response {
+    let status = state.status();
+    let drift = state.config_drift();
+    let drifted = drift.drifted();
+    Response::ok(json!({
+        "router_connected": status.router_connected(),
+        "clients": status.client_count(),
+        "uptime_seconds": status.uptime_seconds(),
+        "config": {
+            "files": drift.files(),
+            "drifted": !drifted.is_empty(),
+            "changed_files": drifted,
+        },
+    }))
+}
+
+/// GET /batches - pending batch and burst groups
+async fn batches(state: &ProxyState) -> Response {
+    let batches = state.batch_manager().snapshot().await;
+    let bursts = state.burst_manager().snapshot().await;
+    Response::ok(json!({ "batches": batches, "bursts": bursts }))
+}
+
+/// GET /stats - runtime counters and Lua execution times
+fn stats(state: &ProxyState, rule_engine: &RuleEngine) -> Response {
+    let stats = state.stats();
+    let timings = rule_engine.script_timings();
+    Response::ok(json!({
+        "blocked_total": stats.blocked_total(),
+        "blocked_by_rule": stats.blocked_by_rule(),
+        "shadow": {
+            "evaluated": stats.shadow_evaluated(),
+            "mismatches": stats.shadow_mismatches(),
+        },
+        "signatures": {
+            "accepted": stats.signatures_accepted(),
+            "rejected": stats.signatures_rejected(),
+        },
+        "unparsed_by_message_id": stats.unparsed_by_message_id(),
+        "lua": {
+            "bucket_bounds_us": crate::stats::SCRIPT_TIME_BUCKETS_US,
+            "modifiers": timings.modifiers(),
+            "plugins": timings.plugins(),
+        },
+    }))
+}
+
+/// GET /connections - recent GCS connects/disconnects, oldest first
+fn connections(state: &ProxyState) -> Response {
+    Response::ok(json!({ "connections": state.connections().snapshot() }))
+}
+
+/// GET /clients - connected GCS clients with per-message-type frame counts
+async fn clients(state: &ProxyState) -> Response {
+    Response::ok(json!({ "clients": state.clients().await }))
+}
+
+/// POST /clients/{id}/disconnect - close one GCS client's connection
+async fn disconnect_client(state: &ProxyState, client_id: &str) -> Response {
+    let Ok(client_id) = client_id.parse::<u64>() else {
+        return Response::error(400, &format!("invalid client ID '{}'", client_id));
+    };
+    if state.disconnect_gcs_client(client_id).await {
+        info!("GCS client {} disconnected via admin API", client_id);
+        Response::ok(json!({ "disconnected": client_id }))
+    } else {
+        Response::error(404, &format!("client {} is not connected", client_id))
+    }
+}
+
+/// GET /quarantine - quarantined systems and how long each has left
+fn quarantine(rule_engine: &RuleEngine) -> Response {
+    Response::ok(json!({ "quarantine": rule_engine.quarantine().snapshot() }))
+}
+
+/// PUT /quarantine/{sysid} - quarantine a system; optional body `{"seconds": N}` (absent or 0 = until released)
+fn quarantine_system(context: &AdminContext, system_id: &str, body: &[u8]) -> Response {
+    let Ok(system_id) = system_id.parse::<u8>() else {
+        return Response::error(400, &format!("invalid system ID '{}'", system_id));
+    };
+    let seconds = if body.iter().all(u8::is_ascii_whitespace) {
+        None
+    } else {
+        match serde_json::from_slice::<JsonValue>(body) {
+            Ok(JsonValue::Object(fields)) => match fields.get("seconds") {
+                None | Some(JsonValue::Null) => None,
+                Some(value) => match value.as_u64() {
+                    Some(seconds) => Some(seconds),
+                    None => return Response::error(400, "seconds must be a non-negative integer"),
+                },
+            },
+            Ok(_) => return Response::error(400, "body must be a JSON object"),
+            Err(e) => return Response::error(400, &format!("invalid JSON body: {}", e)),
+        }
+    };
+
+    let duration = seconds.filter(|seconds| *seconds > 0).map(Duration::from_secs);
+    context.rule_engine.quarantine().add(system_id, duration);
+    info!("System {} quarantined via admin API", system_id);
+    Response::ok(json!({ "quarantined": system_id, "seconds": seconds.filter(|seconds| *seconds > 0) }))
+}
+
+/// DELETE /quarantine/{sysid} - lift a system's quarantine
+fn release_system(context: &AdminContext, system_id: &str) -> Response {
+    let Ok(system_id) = system_id.parse::<u8>() else {
+        return Response::error(400, &format!("invalid system ID '{}'", system_id));
+    };
+    if context.rule_engine.quarantine().release(system_id) {
+        Response::ok(json!({ "released": system_id }))
+    } else {
+        Response::error(404, &format!("system {} is not quarantined", system_id))
+    }
+}
+
+/// PUT /rules/{name} - swap one live rule for the rule in the body (TOML or JSON)
+fn replace_rule(context: &AdminContext, name: &str, body: &[u8]) -> Response {
+    let rule = match parse_rule(body) {
+        Ok(rule) => rule,
+        Err(e) => return Response::error(400, &format!("{:#}", e)),
+    };
+    if rule.name != name {
+        return Response::error(400, &format!("rule name '{}' doesn't match the path", rule.name));
+    }
+    if !context.rule_engine.has_rule(name) {
+        return Response::error(404, &format!("rule '{}' not found", name));
+    }
+
+    let rule = match context.config.prepare_rule(rule) {
+        Ok(rule) => rule,
+        Err(e) => return Response::error(400, &format!("{:#}", e)),
+    };
+    match context.rule_engine.replace_rule(rule) {
+        Ok(()) => {
+            info!("Rule '{}' replaced via admin API", name);
+            Response::ok(json!({ "replaced": name }))
+        }
+        Err(e) => Response::error(400, &e.to_string()),
+    }
+}
+
+/// A rule as a JSON object or a TOML table (the body of one `[[rules]]` entry)
+fn parse_rule(body: &[u8]) -> Result<CommandRule> {
+    let body = std::str::from_utf8(body).context("body is not UTF-8")?;
+    if body.trim_start().starts_with('{') {
+        serde_json::from_str(body).context("invalid JSON rule")
+    } else {
+        toml::from_str(body).context("invalid TOML rule")
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        _ => "Internal Server Error",
+    }
+}
@@ -1,4 +1,5 @@
 use crate::rules::Action;
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Duration;
@@ -9,27 +10,55 @@ use tracing::{debug, info, warn};
 /// Destination for forwarding packets
 #[derive(Clone)]
 pub enum Destination {
-    /// Send to Router (TCP stream write half)
-    Router(Arc<RwLock<tokio::net::tcp::OwnedWriteHalf>>),
+    /// Send to every router connection
+    Router(crate::proxy::RouterLinks),
+    /// Broadcast to every GCS client
+    Gcs,
+    /// Send to the GCS clients of one client group (picked by the route hook)
+    GcsGroup(String),
+    /// Send to a single GCS client (auto-ACKs for the client that sent the message)
+    GcsClient(u64),
+}
+
+impl Destination {
+    /// Traffic direction, for log output
+    pub fn label(&self) -> &'static str {
+        match self {
+            Destination::Router(_) => "GCS->Router",
+            Destination::Gcs => "Router->GCS broadcast",
+            Destination::GcsGroup(_) => "Router->GCS group",
+            Destination::GcsClient(_) => "GCS client",
+        }
+    }
 }
 
 /// Result of queuing a message to a batch
-#[derive(Debug)]
 pub enum BatchResult {
     /// Message queued, still waiting for more
     Queued,
-    /// Threshold met or timeout occurred, release all packets with remaining actions
+    /// Threshold met, release all packets with the remaining actions to the batch's destination
     Release {
         packets: Vec<Vec<u8>>,
         remaining_actions: Vec<Action>,
+        destination: Destination,
     },
 }
 
 /// A single queued packet
 type QueuedPacket = Vec<u8>;
 
+/// What a group counts towards its threshold
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Counting {
+    /// Unique system IDs (`batch`)
+    UniqueSystems,
+    /// Packets from any system (`burst`)
+    Packets,
+}
+
 /// State for a single batch group
-#[derive(Debug)]
+/// The chain and destination of the packet that opened the batch apply to every packet in it,
+/// whether it's released at the threshold or on timeout.
 struct BatchState {
     /// All queued packets
     packets: Vec<QueuedPacket>,
@@ -37,23 +66,43 @@ struct BatchState {
     systems: HashSet<u8>,
     /// Target threshold
     threshold: usize,
+    /// Whether `threshold` counts unique systems or packets
+    counting: Counting,
     /// When this batch was created
     created_at: Instant,
+    /// How long the batch waits before timing out
+    timeout: Duration,
     /// Whether to forward on timeout
     forward_on_timeout: bool,
     /// Remaining actions to apply after batch releases
     remaining_actions: Vec<Action>,
+    /// Actions to apply instead if the batch times out (overrides `forward_on_timeout`)
+    timeout_actions: Option<Vec<Action>>,
+    /// Where released packets go
+    destination: Destination,
 }
 
 impl BatchState {
-    fn new(threshold: usize, forward_on_timeout: bool, remaining_actions: Vec<Action>) -> Self {
+    fn new(
+        threshold: usize,
+        counting: Counting,
+        timeout: Duration,
+        forward_on_timeout: bool,
+        remaining_actions: Vec<Action>,
+        timeout_actions: Option<Vec<Action>>,
+        destination: Destination,
+    ) -> Self {
         Self {
             packets: Vec::new(),
             systems: HashSet::new(),
             threshold,
+            counting,
             created_at: Instant::now(),
+            timeout,
             forward_on_timeout,
             remaining_actions,
+            timeout_actions,
+            destination,
         }
     }
 
@@ -63,26 +112,118 @@ impl BatchState {
     }
 
     fn is_ready(&self) -> bool {
-        self.systems.len() >= self.threshold
+        match self.counting {
+            Counting::UniqueSystems => self.systems.len() >= self.threshold,
+            Counting::Packets => self.packets.len() >= self.threshold,
+        }
+    }
+
+    /// Progress towards the threshold, for log output
+    fn progress(&self) -> String {
+        match self.counting {
+            Counting::UniqueSystems => format!(
+                "{}/{} systems ({} packets)",
+                self.systems.len(),
+                self.threshold,
+                self.packets.len()
+            ),
+            Counting::Packets => format!(
+                "{}/{} packets from {} systems",
+                self.packets.len(),
+                self.threshold,
+                self.systems.len()
+            ),
+        }
     }
 
-    fn release(self) -> (Vec<Vec<u8>>, Vec<Action>) {
-        (self.packets, self.remaining_actions)
+    fn snapshot(&self, key: &str) -> BatchSnapshot {
+        let mut systems: Vec<u8> = self.systems.iter().copied().collect();
+        systems.sort_unstable();
+        BatchSnapshot {
+            key: key.to_string(),
+            unique_systems: systems.len(),
+            systems,
+            packets: self.packets.len(),
+            threshold: self.threshold,
+            age_seconds: self.created_at.elapsed().as_secs_f64(),
+            timeout_seconds: self.timeout.as_secs_f64(),
+            forward_on_timeout: self.forward_on_timeout,
+            timeout_actions: self
+                .timeout_actions
+                .as_ref()
+                .map(|actions| actions.iter().map(|a| a.name().to_string()).collect()),
+        }
     }
+
+    fn release(self) -> (Vec<Vec<u8>>, Vec<Action>, Destination) {
+        (self.packets, self.remaining_actions, self.destination)
+    }
+}
+
+/// Point-in-time view of a pending batch group
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchSnapshot {
+    /// Batch key (rule name or rendered `batch_key`)
+    pub key: String,
+    /// System IDs that have contributed a packet
+    pub systems: Vec<u8>,
+    /// Number of unique systems seen
+    pub unique_systems: usize,
+    /// Number of queued packets
+    pub packets: usize,
+    /// Unique systems (packets, for bursts) required to release
+    pub threshold: usize,
+    /// Time since the batch was created
+    pub age_seconds: f64,
+    /// Time after creation at which the batch times out
+    pub timeout_seconds: f64,
+    /// Whether packets are forwarded (rather than dropped) on timeout
+    pub forward_on_timeout: bool,
+    /// Actions run on timeout instead, if configured (`batch_timeout_actions`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_actions: Option<Vec<String>>,
 }
 
 /// Manager for batch operations
+/// Also holds `burst` groups, which release on a packet count instead of unique systems.
 pub struct BatchManager {
     batches: Arc<RwLock<HashMap<String, BatchState>>>,
+    counting: Counting,
 }
 
 impl BatchManager {
     pub fn new() -> Self {
         Self {
             batches: Arc::new(RwLock::new(HashMap::new())),
+            counting: Counting::UniqueSystems,
+        }
+    }
+
+    /// Manager for `burst` groups: `threshold` counts packets from any system
+    pub fn bursts() -> Self {
+        Self {
+            batches: Arc::new(RwLock::new(HashMap::new())),
+            counting: Counting::Packets,
+        }
+    }
+
+    /// Group kind, for log output
+    fn kind(&self) -> &'static str {
+        match self.counting {
+            Counting::UniqueSystems => "Batch",
+            Counting::Packets => "Burst",
         }
     }
 
+    /// Snapshot all pending batches, sorted by key
+    /// Only takes the read lock; timeout tasks are unaffected.
+    pub async fn snapshot(&self) -> Vec<BatchSnapshot> {
+        let batches = self.batches.read().await;
+        let mut snapshots: Vec<BatchSnapshot> = batches.iter().map(|(key, batch)| batch.snapshot(key)).collect();
+        snapshots.sort_by(|a, b| a.key.cmp(&b.key));
+        snapshots
+    }
+
     /// Queue a packet or release the batch if threshold is met
     #[allow(clippy::too_many_arguments)]
     pub async fn queue_or_release(
@@ -94,52 +235,60 @@ impl BatchManager {
         timeout: Duration,
         forward_on_timeout: bool,
         remaining_actions: Vec<Action>,
+        timeout_actions: Option<Vec<Action>>,
         destination: Destination,
         state: Arc<crate::proxy::ProxyState>,
     ) -> BatchResult {
         let mut batches = self.batches.write().await;
+        let kind = self.kind();
 
         // Get or create batch state
         let batch = batches
             .entry(key.clone())
             .or_insert_with(|| {
                 info!(
-                    "Created new batch group '{}' (threshold={}, timeout={}s)",
+                    "{} group '{}' created (threshold={}, timeout={}s)",
+                    kind,
                     key,
                     threshold,
                     timeout.as_secs()
                 );
 
-                // Spawn timeout handler
+                let batch = BatchState::new(
+                    threshold,
+                    self.counting,
+                    timeout,
+                    forward_on_timeout,
+                    remaining_actions,
+                    timeout_actions,
+                    destination,
+                );
+
+                // Spawn timeout handler for this batch (a later batch under the same key gets its own)
                 let batches_clone = self.batches.clone();
                 let key_clone = key.clone();
-                let destination_clone = destination.clone();
-                let state_clone = state.clone();
+                let created_at = batch.created_at;
                 tokio::spawn(async move {
                     sleep(timeout).await;
-                    Self::handle_timeout(batches_clone, key_clone, destination_clone, state_clone).await;
+                    Self::handle_timeout(batches_clone, kind, key_clone, created_at, state).await;
                 });
 
-                BatchState::new(threshold, forward_on_timeout, remaining_actions.clone())
+                batch
             });
 
         // Add packet to batch
         batch.add_packet(system_id, packet);
 
-        let unique_count = batch.systems.len();
-        let packet_count = batch.packets.len();
-
-        debug!(
-            "Batch '{}': added sysid={}, now {}/{} unique systems ({} total packets)",
-            key, system_id, unique_count, threshold, packet_count
-        );
+        debug!("{} '{}': added sysid={}, now {}", kind, key, system_id, batch.progress());
 
         // Check if threshold is met
         if batch.is_ready() {
             let batch_state = batches.remove(&key).unwrap();
-            let (packets, remaining_actions) = batch_state.release();
+            let unique_count = batch_state.systems.len();
+            let (packets, remaining_actions, destination) = batch_state.release();
             info!(
-                "Batch '{}' threshold met! Releasing {} packets from {} systems",
+                "{} '{}' threshold met! Releasing {} packets from {} systems",
+                kind,
                 key,
                 packets.len(),
                 unique_count
@@ -147,6 +296,7 @@ impl BatchManager {
             BatchResult::Release {
                 packets,
                 remaining_actions,
+                destination,
             }
         } else {
             BatchResult::Queued
@@ -154,47 +304,70 @@ impl BatchManager {
     }
 
     /// Handle batch timeout
+    /// `created_at` identifies the batch the timer was started for: if that batch was released
+    /// at its threshold, a newer batch under the same key keeps its own timer. The batch lock
+    /// is released before the packets continue down their chain, as on a threshold release.
     async fn handle_timeout(
         batches: Arc<RwLock<HashMap<String, BatchState>>>,
+        kind: &'static str,
         key: String,
-        destination: Destination,
+        created_at: Instant,
         state: Arc<crate::proxy::ProxyState>,
     ) {
-        let mut batches = batches.write().await;
+        let batch = {
+            let mut batches = batches.write().await;
+            match batches.get(&key) {
+                Some(batch) if batch.created_at == created_at => batches.remove(&key),
+                _ => None,
+            }
+        };
+        let Some(mut batch) = batch else {
+            return;
+        };
 
-        if let Some(batch) = batches.remove(&key) {
-            let elapsed = batch.created_at.elapsed();
-            let unique_count = batch.systems.len();
-            let packet_count = batch.packets.len();
+        let elapsed = batch.created_at.elapsed();
+        let progress = batch.progress();
+        let packet_count = batch.packets.len();
 
-            if batch.forward_on_timeout {
-                warn!(
-                    "Batch '{}' timed out after {:?} with {}/{} systems ({} packets) - FORWARDING",
-                    key, elapsed, unique_count, batch.threshold, packet_count
-                );
+        if let Some(timeout_actions) = batch.timeout_actions.take() {
+            let names: Vec<&str> = timeout_actions.iter().map(Action::name).collect();
+            warn!(
+                "{} '{}' timed out after {:?} with {} - applying timeout actions [{}]",
+                kind,
+                key,
+                elapsed,
+                progress,
+                names.join(", ")
+            );
 
-                // Execute remaining actions on timed-out packets (including delay, etc.)
-                let (packets, remaining_actions) = batch.release();
-                info!(
-                    "Forwarded {} timed-out packets, applying {} remaining action(s)",
-                    packet_count,
-                    remaining_actions.len()
-                );
+            crate::proxy::execute_actions_impl(
+                timeout_actions,
+                batch.packets,
+                batch.destination,
+                state,
+            )
+            .await;
+        } else if batch.forward_on_timeout {
+            warn!("{} '{}' timed out after {:?} with {} - FORWARDING", kind, key, elapsed, progress);
 
-                // Continue the action chain for timed-out packets
-                crate::proxy::execute_actions_impl(
-                    remaining_actions,
-                    packets,
-                    destination,
-                    state,
-                )
-                .await;
-            } else {
-                warn!(
-                    "Batch '{}' timed out after {:?} with {}/{} systems ({} packets) - DROPPING",
-                    key, elapsed, unique_count, batch.threshold, packet_count
-                );
-            }
+            // Execute remaining actions on timed-out packets (including delay, etc.)
+            let (packets, remaining_actions, destination) = batch.release();
+            info!(
+                "Forwarded {} timed-out packets, applying {} remaining action(s)",
+                packet_count,
+                remaining_actions.len()
+            );
+
+            // Continue the action chain for timed-out packets
+            crate::proxy::execute_actions_impl(
+                remaining_actions,
+                packets,
+                destination,
+                state,
+            )
+            .await;
+        } else {
+            warn!("{} '{}' timed out after {:?} with {} - DROPPING", kind, key, elapsed, progress);
         }
     }
 }
@@ -0,0 +1,67 @@
+use crate::config::Direction;
+use anyhow::{Context, Result};
+use std::fs::OpenOptions;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// Frames buffered for the capture writer before new ones are dropped
+const CAPTURE_QUEUE_LEN: usize = 4096;
+
+/// Raw frame capture: every frame read from a GCS client or router is appended,
+/// byte for byte, to `gcs_to_router.bin` or `router_to_gcs.bin` in the capture directory
+pub struct Capture {
+    frames: mpsc::Sender<(Direction, Vec<u8>)>,
+}
+
+impl Capture {
+    /// Open (appending) both capture files in `dir` and start the writer task
+    pub fn start(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create capture directory {:?}", dir))?;
+        let open = |name: &str| -> Result<File> {
+            let path = dir.join(name);
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("Failed to open capture file {:?}", path))?;
+            Ok(File::from_std(file))
+        };
+        let gcs_to_router = open("gcs_to_router.bin")?;
+        let router_to_gcs = open("router_to_gcs.bin")?;
+
+        let (frames, queue) = mpsc::channel(CAPTURE_QUEUE_LEN);
+        tokio::spawn(write_loop(gcs_to_router, router_to_gcs, queue));
+
+        info!("Capturing raw frames to {:?}", dir);
+        Ok(Self { frames })
+    }
+
+    /// Queue a frame for its direction's file without waiting
+    /// Frames are dropped (not delayed) if the writer falls behind
+    pub fn record(&self, direction: Direction, frame: &[u8]) {
+        if self.frames.try_send((direction, frame.to_vec())).is_err() {
+            debug!("Capture queue full, dropping {} frame", direction);
+        }
+    }
+}
+
+/// Append queued frames to the file for their direction
+async fn write_loop(mut gcs_to_router: File, mut router_to_gcs: File, mut queue: mpsc::Receiver<(Direction, Vec<u8>)>) {
+    while let Some((direction, frame)) = queue.recv().await {
+        let file = match direction {
+            Direction::RouterToGcs => &mut router_to_gcs,
+            _ => &mut gcs_to_router,
+        };
+        // Flushed per frame so the files can be followed while the proxy runs
+        let written = match file.write_all(&frame).await {
+            Ok(()) => file.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            warn!("Failed to write {} capture: {}", direction, e);
+        }
+    }
+}
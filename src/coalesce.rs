@@ -0,0 +1,60 @@
+use crate::actions::Next;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::debug;
+
+/// The freshest packet held for one coalesce key
+struct Held {
+    packet: Vec<u8>,
+    /// Rest of the chain the packet continues down once flushed
+    next: Next,
+    /// Older packets replaced since the window opened
+    replaced: u64,
+}
+
+/// Latest-value holders for `coalesce` rules
+/// The first packet for an idle key opens a window of `interval`; newer packets replace
+/// the held one, and when the window closes only the freshest continues down its chain.
+pub struct Coalescer {
+    held: Arc<Mutex<HashMap<String, Held>>>,
+}
+
+impl Coalescer {
+    pub fn new() -> Self {
+        Self {
+            held: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Hold `packet` as the latest for `key`, replacing any packet already waiting
+    pub fn submit(&self, key: String, interval: Duration, packet: Vec<u8>, next: Next) {
+        let mut held = self.held.lock().unwrap();
+        if let Some(existing) = held.get_mut(&key) {
+            existing.packet = packet;
+            existing.next = next;
+            existing.replaced += 1;
+            return;
+        }
+
+        held.insert(
+            key.clone(),
+            Held {
+                packet,
+                next,
+                replaced: 0,
+            },
+        );
+
+        let holders = self.held.clone();
+        tokio::spawn(async move {
+            sleep(interval).await;
+            let Some(latest) = holders.lock().unwrap().remove(&key) else {
+                return;
+            };
+            debug!("Coalesce '{}': flushing latest, {} older message(s) dropped", key, latest.replaced);
+            latest.next.run(vec![latest.packet]).await;
+        });
+    }
+}
@@ -0,0 +1,93 @@
+use mavlink::ardupilotmega::{MavMessage, MavResult, COMMAND_LONG_DATA};
+use mavlink::MavHeader;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// GCS client that sent a COMMAND_LONG we forwarded as COMMAND_INT
+struct Requester {
+    /// Connection ID of the client
+    client: u64,
+    /// Dropped once this passes without an answer
+    expires: Instant,
+}
+
+/// Upgraded commands waiting for their COMMAND_ACK (`upgrade_compat`), so each ACK can be
+/// sent only to the GCS client that sent the original COMMAND_LONG
+pub struct CommandCompat {
+    /// (target system, target component, command id) -> requesters, oldest first
+    pending: Mutex<HashMap<(u8, u8, u32), VecDeque<Requester>>>,
+}
+
+impl CommandCompat {
+    pub fn new() -> Self {
+        Self {
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Remember that GCS client `client` asked for `data`, which went out as COMMAND_INT
+    /// A re-send from the same client (e.g. with a higher `confirmation`) refreshes its entry
+    /// instead of queueing a second one.
+    pub fn record(&self, client: u64, data: &COMMAND_LONG_DATA, timeout: Duration) {
+        let now = Instant::now();
+        let key = (data.target_system, data.target_component, data.command as u32);
+        let mut pending = self.pending.lock().unwrap();
+        expire(&mut pending, now);
+
+        let queue = pending.entry(key).or_default();
+        let expires = now + timeout;
+        match queue.iter_mut().find(|r| r.client == client) {
+            Some(requester) => requester.expires = expires,
+            None => queue.push_back(Requester { client, expires }),
+        }
+    }
+
+    /// The GCS client a COMMAND_ACK for an upgraded command goes to: the one that asked first
+    /// None if `msg` isn't an ACK for a tracked command.
+    /// MAV_RESULT_IN_PROGRESS keeps the request pending; any other result completes it.
+    pub fn requester(&self, header: &MavHeader, msg: &MavMessage) -> Option<u64> {
+        let MavMessage::COMMAND_ACK(ack) = msg else {
+            return None;
+        };
+
+        let now = Instant::now();
+        let mut pending = self.pending.lock().unwrap();
+        if pending.is_empty() {
+            return None;
+        }
+        expire(&mut pending, now);
+
+        // A broadcast target (0) is answered by whichever component handles the command
+        let key = [header.component_id, 0]
+            .into_iter()
+            .map(|component| (header.system_id, component, ack.command as u32))
+            .find(|key| pending.contains_key(key))?;
+        let queue = pending.get_mut(&key)?;
+
+        let client = if ack.result == MavResult::MAV_RESULT_IN_PROGRESS {
+            queue.front()?.client
+        } else {
+            let requester = queue.pop_front()?;
+            if queue.is_empty() {
+                pending.remove(&key);
+            }
+            requester.client
+        };
+
+        debug!(
+            "COMMAND_ACK for upgraded {:?} from sysid={} goes to GCS client {}",
+            ack.command, header.system_id, client
+        );
+        Some(client)
+    }
+}
+
+/// Drop requests that were never answered in time
+fn expire(pending: &mut HashMap<(u8, u8, u32), VecDeque<Requester>>, now: Instant) {
+    pending.retain(|_, queue| {
+        queue.retain(|r| r.expires > now);
+        !queue.is_empty()
+    });
+}
@@ -1,9 +1,27 @@
 use anyhow::{Context, Result};
-use serde::Deserialize;
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::fmt;
 use std::fs;
-
-#[derive(Debug, Deserialize, Clone)]
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Config used when there is no config file: one GCS listener, one router, no rules
+const DEFAULT_CONFIG: &str = r#"
+[network]
+gcs_listen_address = "0.0.0.0"
+gcs_listen_port = 5760
+router_address = "127.0.0.1"
+router_port = 5761
+
+[logging]
+level = "info"
+"#;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub network: NetworkConfig,
     pub logging: LoggingConfig,
@@ -13,9 +31,89 @@ pub struct Config {
     pub modifiers: ModifiersConfig,
     #[serde(default)]
     pub rules: Vec<CommandRule>,
+    /// Candidate ruleset evaluated alongside `rules`; its decisions are only logged
+    #[serde(default)]
+    pub shadow_rules: Vec<CommandRule>,
+    /// Additional rule files or directories (*.toml) merged into `rules` at load
+    /// Relative paths are resolved against the main config file's directory
+    /// Not dumped: their rules are already in `rules` once loaded
+    #[serde(default, skip_serializing)]
+    pub include: Vec<String>,
+    /// Maximum nesting of `reprocess` re-evaluations per message (loop guard)
+    #[serde(default = "default_max_reprocess_depth")]
+    pub max_reprocess_depth: u32,
+    /// Drop redundant actions from rule chains before running them (see `CommandRule::simplified_actions`)
+    #[serde(default)]
+    pub simplify_actions: bool,
+    /// Condition defaults inherited by rules that don't set their own
+    #[serde(default)]
+    pub defaults: RuleDefaults,
+    /// Named condition blocks rules can reference (`conditions = "name"`)
+    #[serde(default)]
+    pub condition_templates: HashMap<String, RuleConditions>,
+    /// Optional: Local HTTP admin API (disabled when absent)
+    pub admin: Option<AdminConfig>,
+    /// Seconds between checks of the loaded config files for changes on disk
+    /// (reported, never applied; 0 = disabled, default: 60)
+    #[serde(default = "default_config_check_seconds")]
+    pub config_check_seconds: u64,
+    /// Deprecated settings found while loading (logged at startup, errors with `--strict`)
+    #[serde(skip)]
+    pub deprecations: Vec<String>,
+    /// Files the config was read from: the main file, an overlay and included rule files
+    #[serde(skip)]
+    pub files: Vec<ConfigFile>,
+}
+
+/// A file the config was read from, with a hash of the contents that were loaded
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    /// SHA-256 of the contents, hex-encoded
+    pub sha256: String,
+}
+
+impl ConfigFile {
+    fn new(path: impl Into<PathBuf>, contents: &str) -> Self {
+        Self {
+            path: path.into(),
+            sha256: content_hash(contents.as_bytes()),
+        }
+    }
+}
+
+/// Hex-encoded SHA-256 of a config file's contents
+pub fn content_hash(contents: &[u8]) -> String {
+    Sha256::digest(contents).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// `[admin]` section: JSON admin API for inspecting the running proxy
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Bind address (loopback by default; the API has no authentication)
+    #[serde(default = "default_admin_address")]
+    pub listen_address: String,
+    pub listen_port: u16,
+}
+
+/// `[defaults]` section: header conditions applied to every rule lacking its own
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RuleDefaults {
+    pub system_id: Option<u8>,
+    pub component_id: Option<u8>,
+}
+
+/// Contents of an included rules file (only `[[rules]]` tables are read)
+#[derive(Debug, Deserialize)]
+struct RulesFile {
+    #[serde(default)]
+    rules: Vec<CommandRule>,
 }
 
-#[derive(Debug, Deserialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
 pub struct PluginsConfig {
     /// Directory containing plugin files
     #[serde(default = "default_plugins_dir")]
@@ -23,9 +121,33 @@ pub struct PluginsConfig {
     /// List of plugins to load (name -> filename)
     #[serde(default)]
     pub load: HashMap<String, String>,
+    /// Plugins run as external processes (name -> command), talking JSON lines over stdio
+    #[serde(default)]
+    pub process: HashMap<String, ProcessPluginConfig>,
+    /// Optional per-plugin settings passed to on_load (name -> table)
+    #[serde(default)]
+    pub config: HashMap<String, toml::Value>,
+    /// Log on_load() errors and keep the plugin instead of failing the load
+    #[serde(default)]
+    pub warn_on_load_error: bool,
+    /// Maximum run time of a bounded plugin hook (e.g. on_shutdown) in milliseconds
+    #[serde(default = "default_plugin_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Optional: Directory for queued http.post_reliable requests (disabled when absent)
+    pub outbox_dir: Option<String>,
+}
+
+/// `[plugins.process.<name>]`: command started once in `[plugins] directory`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessPluginConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
 }
 
-#[derive(Debug, Deserialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
 pub struct ModifiersConfig {
     /// Directory containing modifier files
     #[serde(default = "default_modifiers_dir")]
@@ -33,12 +155,21 @@ pub struct ModifiersConfig {
     /// List of modifiers to load (name -> filename)
     #[serde(default)]
     pub load: HashMap<String, String>,
+    /// Modifiers (by name) whose script runs once and keeps its state between messages
+    #[serde(default)]
+    pub stateful: Vec<String>,
+    /// Optional: Seed for weighted random modifier selection (random each run when absent)
+    pub random_seed: Option<u64>,
 }
 
 fn default_plugins_dir() -> String {
     "plugins".to_string()
 }
 
+fn default_plugin_timeout_ms() -> u64 {
+    2000
+}
+
 fn default_modifiers_dir() -> String {
     "modifiers".to_string()
 }
@@ -47,12 +178,12 @@ fn default_batch_timeout_forward() -> bool {
     true
 }
 
-fn default_batch_key() -> String {
-    "default".to_string()
+fn default_burst_timeout_forward() -> bool {
+    true
 }
 
-fn default_direction() -> String {
-    "gcs_to_router".to_string()
+fn default_batch_key() -> String {
+    "default".to_string()
 }
 
 fn default_enabled() -> bool {
@@ -63,20 +194,425 @@ fn default_true() -> bool {
     true
 }
 
-#[derive(Debug, Deserialize, Clone)]
+fn default_congested_txbuf() -> u8 {
+    50
+}
+
+fn default_max_pacing_delay_ms() -> u64 {
+    200
+}
+
+fn default_radio_status_stale_seconds() -> u64 {
+    5
+}
+
+fn default_announce_message_type() -> String {
+    "HEARTBEAT".to_string()
+}
+
+fn default_announce_interval_seconds() -> u64 {
+    1
+}
+
+fn default_announce_system_id() -> u8 {
+    255
+}
+
+fn default_announce_component_id() -> u8 {
+    190 // MAV_COMP_ID_MISSIONPLANNER
+}
+
+fn default_admin_address() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_gcs_transport() -> String {
+    "tcp".to_string()
+}
+
+fn default_connection_log_size() -> usize {
+    100
+}
+
+fn default_router_transport() -> String {
+    "tcp".to_string()
+}
+
+fn default_router_baud() -> u32 {
+    57600
+}
+
+fn default_router_queue_size() -> usize {
+    256
+}
+
+fn default_router_max_fatal_retries() -> u32 {
+    3
+}
+
+fn default_router_write_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_config_check_seconds() -> u64 {
+    60
+}
+
+/// "host:port", with an IPv6 address in brackets ("[::1]:5761")
+fn endpoint(host: &str, port: u16) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Stands in for secrets in `--dump-config`
+const REDACTED: &str = "<redacted>";
+
+/// Keep only the scheme and host of a URL: "https://hooks.example.com/<redacted>"
+fn redact_url(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    if scheme.is_empty() {
+        format!("{}/{}", host, REDACTED)
+    } else {
+        format!("{}://{}/{}", scheme, host, REDACTED)
+    }
+}
+
+fn default_max_reprocess_depth() -> u32 {
+    4
+}
+
+fn default_keepalive_system_id() -> u8 {
+    1
+}
+
+fn default_keepalive_component_id() -> u8 {
+    191
+}
+
+fn default_timezone() -> String {
+    "utc".to_string()
+}
+
+fn default_notify_template() -> String {
+    r#"{"rule":"{rule}","message_type":"{message_type}","system_id":{system_id},"component_id":{component_id}}"#.to_string()
+}
+
+fn default_content_type() -> String {
+    "application/json".to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct NetworkConfig {
     pub gcs_listen_port: u16,
     pub gcs_listen_address: String,
+    /// Single router endpoint (omit when using `routers`)
+    #[serde(default)]
     pub router_address: String,
+    #[serde(default)]
     pub router_port: u16,
+    /// Optional: Several router endpoints; GCS traffic is mirrored to all of them
+    /// and traffic from any of them is broadcast to GCS clients
+    #[serde(default)]
+    pub routers: Vec<RouterEndpoint>,
+    /// Router link transport: "tcp" (default) or "serial" (telemetry radio on `router_serial_port`)
+    #[serde(default = "default_router_transport")]
+    pub router_transport: String,
+    /// Serial device of the router link, e.g. "/dev/ttyUSB0" (router_transport = "serial")
+    pub router_serial_port: Option<String>,
+    /// Baud rate of the router serial link (default: 57600)
+    #[serde(default = "default_router_baud")]
+    pub router_baud: u32,
+    /// Frames queued per router link before senders have to wait (default: 256)
+    #[serde(default = "default_router_queue_size")]
+    pub router_queue_size: usize,
+    /// How long a sender waits on a full router queue before the link is closed as stalled (default: 5000)
+    #[serde(default = "default_router_write_timeout_ms")]
+    pub router_write_timeout_ms: u64,
+    /// Retries after a router connect error that isn't transient (e.g. an unresolvable host)
+    /// before startup fails; refused/reset/timed-out connects are retried indefinitely (default: 3)
+    #[serde(default = "default_router_max_fatal_retries")]
+    pub router_max_fatal_retries: u32,
+    /// GCS listener transport: "tcp" (default) or "ws" (WebSocket, one binary message per frame)
+    #[serde(default = "default_gcs_transport")]
+    pub gcs_transport: String,
+    /// Optional: Wrap accepted GCS connections in TLS (plaintext when absent)
+    pub gcs_tls: Option<GcsTlsConfig>,
+    /// Optional: Client certificate allowlist (requires gcs_tls.client_ca_path)
+    /// "cn:<name>" matches the Common Name, anything else is a SHA-256 fingerprint
+    #[serde(default)]
+    pub gcs_allowed_clients: Vec<String>,
+    /// Optional: Only accept GCS connections from these CIDRs (empty = allow all)
+    #[serde(default)]
+    pub gcs_allow_cidrs: Vec<String>,
+    /// Optional: Reject GCS connections from these CIDRs (checked before the allowlist)
+    #[serde(default)]
+    pub gcs_deny_cidrs: Vec<String>,
+    /// Optional: Named groups GCS connections are tagged with (first matching group wins)
+    /// Rules with `client_group` only apply to messages from that group's clients
+    #[serde(default)]
+    pub client_groups: Vec<ClientGroupConfig>,
+    /// Verify frame checksums while reading; a bad checksum is treated as a false
+    /// magic byte and scanning resumes right after it (default: true)
+    #[serde(default = "default_true")]
+    pub validate_crc: bool,
+    /// Optional: CRC_EXTRA per message ID for messages missing from (or differing
+    /// in) the bundled dialect, e.g. `{ 60000 = 12 }`
+    #[serde(default)]
+    pub crc_extra_overrides: HashMap<String, u8>,
+    /// What to send when a modifier's message can't be re-encoded (default: forward_original)
+    #[serde(default)]
+    pub on_modify_error: ModifyErrorPolicy,
+    /// Set TCP_NODELAY on the router connection and accepted GCS sockets (default: true)
+    #[serde(default = "default_true")]
+    pub tcp_nodelay: bool,
+    /// Optional: Socket send buffer size in bytes for TCP router/GCS sockets (OS default when absent)
+    pub tcp_send_buffer: Option<usize>,
+    /// Optional: Socket receive buffer size in bytes for TCP router/GCS sockets (OS default when absent)
+    pub tcp_recv_buffer: Option<usize>,
+    /// Optional: Close a GCS client (or the router link) after this many seconds
+    /// without a complete frame. 0 or absent = wait forever
+    pub client_idle_timeout_seconds: Option<u64>,
+    /// Optional: Inject a HEARTBEAT to all GCS clients when no router -> GCS traffic
+    /// has flowed for this many seconds. 0 or absent = disabled
+    pub keepalive_seconds: Option<u64>,
+    /// Source system ID of injected keepalive HEARTBEATs (default: 1)
+    #[serde(default = "default_keepalive_system_id")]
+    pub keepalive_system_id: u8,
+    /// Source component ID of injected keepalive HEARTBEATs (default: 191, onboard computer)
+    #[serde(default = "default_keepalive_component_id")]
+    pub keepalive_component_id: u8,
+    /// Optional: Slow GCS -> router forwarding while RADIO_STATUS reports a filling radio buffer
+    pub radio_pacing: Option<RadioPacingConfig>,
+    /// Optional: Message sent to the router at a fixed interval, announcing BITCH as a component
+    pub announce: Option<AnnounceConfig>,
+    /// Sign every frame sent to the router with `signing_key` (MAVLink 2 signing, default: false)
+    #[serde(default)]
+    pub sign_outbound: bool,
+    /// Optional: MAVLink 2 signing secret key, 64 hex digits (32 bytes)
+    pub signing_key: Option<String>,
+    /// Link ID written into outbound signatures (default: 0)
+    #[serde(default)]
+    pub link_id: u8,
+    /// Drop GCS frames without a signature (requires signing_key, default: false)
+    #[serde(default)]
+    pub reject_unsigned: bool,
+    /// Drop GCS frames whose signature doesn't verify or is replayed (requires signing_key, default: false)
+    #[serde(default)]
+    pub reject_bad_signature: bool,
+    /// Optional: Plugin function ("plugin.function") that picks the destination of every
+    /// parsed frame before it is forwarded, bounded by `[plugins] timeout_ms`
+    pub route_hook: Option<String>,
+    /// Run messages BITCH builds itself (auto-ACKs, keepalives, announcements, status)
+    /// through the rules before sending them (default: false)
+    #[serde(default)]
+    pub process_injected: bool,
+    /// Only broadcast data stream messages to GCS clients that asked for them (REQUEST_DATA_STREAM,
+    /// MAV_CMD_SET_MESSAGE_INTERVAL), at most at the rate they asked for (default: false)
+    #[serde(default)]
+    pub stream_subscriptions: bool,
+}
+
+/// Fallback when a modified message fails to serialize (`network.on_modify_error`)
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ModifyErrorPolicy {
+    /// Send the unmodified packet
+    #[default]
+    ForwardOriginal,
+    /// Drop the packet (fail closed)
+    Block,
+    /// Re-encode the modified message field by field, without trailing-zero truncation
+    ForwardModifiedBestEffort,
+}
+
+/// `[network.announce]`: a message BITCH sends to the router on its own behalf
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AnnounceConfig {
+    /// Message type to send (default: HEARTBEAT)
+    #[serde(default = "default_announce_message_type")]
+    pub message_type: String,
+    /// Field values; fields not given keep the dialect defaults (a HEARTBEAT's describe a GCS)
+    #[serde(default)]
+    pub fields: toml::Table,
+    /// Seconds between announcements (default: 1)
+    #[serde(default = "default_announce_interval_seconds")]
+    pub interval_seconds: u64,
+    /// Source system ID (default: 255, the usual GCS system ID)
+    #[serde(default = "default_announce_system_id")]
+    pub system_id: u8,
+    /// Source component ID (default: 190, MAV_COMP_ID_MISSIONPLANNER)
+    #[serde(default = "default_announce_component_id")]
+    pub component_id: u8,
+}
+
+impl AnnounceConfig {
+    /// Build the announced message (called by `validate` too, so bad fields fail at load)
+    pub fn message(&self) -> Result<mavlink::ardupilotmega::MavMessage> {
+        let mut fields = serde_json::Map::new();
+        if self.message_type == "HEARTBEAT" {
+            fields.insert("mavtype".to_string(), "MAV_TYPE_GCS".into());
+            fields.insert("autopilot".to_string(), "MAV_AUTOPILOT_INVALID".into());
+            fields.insert("system_status".to_string(), "MAV_STATE_ACTIVE".into());
+            fields.insert("mavlink_version".to_string(), 3.into());
+        }
+        if let serde_json::Value::Object(configured) = serde_json::to_value(&self.fields)? {
+            fields.extend(configured);
+        }
+        crate::messages::build_message(&self.message_type, &fields)
+            .with_context(|| format!("Invalid [network.announce] {}", self.message_type))
+    }
+
+    /// Time between announcements
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_seconds)
+    }
+}
+
+/// `[network.radio_pacing]`: flow control driven by RADIO_STATUS.txbuf
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RadioPacingConfig {
+    /// Free transmit buffer (percent) below which the link counts as congested (default: 50)
+    #[serde(default = "default_congested_txbuf")]
+    pub congested_txbuf: u8,
+    /// Delay per forwarded packet when the buffer is full (default: 200ms); scales
+    /// linearly down to zero at `congested_txbuf`
+    #[serde(default = "default_max_pacing_delay_ms")]
+    pub max_delay_ms: u64,
+    /// Stop pacing when no RADIO_STATUS has arrived for this long (default: 5s)
+    #[serde(default = "default_radio_status_stale_seconds")]
+    pub stale_after_seconds: u64,
+}
+
+impl NetworkConfig {
+    /// Keepalive HEARTBEAT interval, if enabled
+    pub fn keepalive_interval(&self) -> Option<Duration> {
+        self.keepalive_seconds
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// Idle timeout for connection reads, if enabled
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.client_idle_timeout_seconds
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// Time a sender may wait on a full router write queue
+    pub fn router_write_timeout(&self) -> Duration {
+        Duration::from_millis(self.router_write_timeout_ms)
+    }
+
+    /// CRC_EXTRA overrides keyed by numeric message ID (keys are checked in `validate`)
+    pub fn crc_extra_overrides(&self) -> HashMap<u32, u8> {
+        self.crc_extra_overrides
+            .iter()
+            .filter_map(|(id, extra)| Some((id.parse().ok()?, *extra)))
+            .collect()
+    }
+
+    /// Signing secret key as bytes (the hex format is checked in `validate`)
+    pub fn signing_key(&self) -> Option<[u8; 32]> {
+        let hex = self.signing_key.as_deref()?.trim();
+        if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+        }
+        Some(key)
+    }
+
+    /// Plugin and function named by `route_hook` (the format is checked in `validate`)
+    pub fn route_hook(&self) -> Option<(&str, &str)> {
+        let (plugin, function) = self.route_hook.as_deref()?.rsplit_once('.')?;
+        (!plugin.is_empty() && !function.is_empty()).then_some((plugin, function))
+    }
+
+    /// Router addresses ("host:port") to connect to, in config order
+    pub fn router_endpoints(&self) -> Vec<String> {
+        if self.routers.is_empty() {
+            vec![endpoint(&self.router_address, self.router_port)]
+        } else {
+            self.routers
+                .iter()
+                .map(|router| endpoint(&router.address, router.port))
+                .collect()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RouterEndpoint {
+    pub address: String,
+    pub port: u16,
+}
+
+/// One `[[network.client_groups]]` entry: GCS connections tagged by address or certificate
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ClientGroupConfig {
+    /// Group name, as referenced by a rule's `client_group`
+    pub name: String,
+    /// Source CIDRs whose connections join the group
+    #[serde(default)]
+    pub cidrs: Vec<String>,
+    /// Client certificates that join the group ("cn:<name>" or a SHA-256 fingerprint,
+    /// requires gcs_tls.client_ca_path)
+    #[serde(default)]
+    pub clients: Vec<String>,
+    /// Block this group's messages unless a rule matches them (default: false)
+    #[serde(default)]
+    pub default_deny: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GcsTlsConfig {
+    /// PEM certificate chain presented to GCS clients
+    pub cert_path: String,
+    /// PEM private key for the certificate
+    pub key_path: String,
+    /// Optional: PEM CA bundle; when set, clients must present a certificate signed by it
+    pub client_ca_path: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct LoggingConfig {
     pub level: String,
+    /// Optional: Broadcast a proxy status STATUSTEXT to GCS clients every N seconds.
+    /// 0 or absent = disabled
+    pub status_broadcast_seconds: Option<u64>,
+    /// GCS connect/disconnect events kept for the admin API (default: 100, 0 = disabled)
+    #[serde(default = "default_connection_log_size")]
+    pub connection_log_size: usize,
+    /// Optional: Append every raw frame to gcs_to_router.bin / router_to_gcs.bin in this directory
+    pub capture_dir: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl LoggingConfig {
+    /// Status STATUSTEXT interval, if enabled
+    pub fn status_broadcast_interval(&self) -> Option<Duration> {
+        self.status_broadcast_seconds
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct AutoAckConfig {
     /// Message type to send as ACK (e.g., "COMMAND_ACK", "MISSION_ACK")
     pub message_type: String,
@@ -96,9 +632,86 @@ pub struct AutoAckConfig {
     /// Use "header.X" to copy from original message header
     #[serde(default)]
     pub copy_fields: HashMap<String, String>,
+
+    /// Where the ACK is sent (default: back to the sender of the matched message)
+    #[serde(default)]
+    pub direction: AckDirection,
+}
+
+/// Where a synthesized ACK is written (`ack.direction`)
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AckDirection {
+    /// Back to the connection the matched message came from
+    #[default]
+    ToSource,
+    /// To the routers, so everything downstream sees it too
+    ToRouter,
+    /// To the source and the routers
+    Both,
+}
+
+impl AckDirection {
+    /// Whether the ACK goes back to the sender
+    pub fn reaches_source(self) -> bool {
+        self != AckDirection::ToRouter
+    }
+
+    /// Whether the ACK goes to the routers
+    pub fn reaches_router(self) -> bool {
+        self != AckDirection::ToSource
+    }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// Message flow direction
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Messages from GCS clients to the router
+    #[default]
+    GcsToRouter,
+    /// Messages from the router to GCS clients
+    RouterToGcs,
+    /// Both directions (rule filter only)
+    Both,
+}
+
+impl Direction {
+    /// Check whether a rule with this direction applies to a message flowing in `flow`
+    pub fn matches(self, flow: Direction) -> bool {
+        self == Direction::Both || self == flow
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Direction::GcsToRouter => "gcs_to_router",
+            Direction::RouterToGcs => "router_to_gcs",
+            Direction::Both => "both",
+        };
+        f.write_str(name)
+    }
+}
+
+impl std::str::FromStr for Direction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "gcs_to_router" => Ok(Direction::GcsToRouter),
+            "router_to_gcs" => Ok(Direction::RouterToGcs),
+            "both" => Ok(Direction::Both),
+            _ => anyhow::bail!(
+                "Invalid direction '{}'. Must be: gcs_to_router, router_to_gcs, or both",
+                s
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct CommandRule {
     /// Unique name for this rule (required for trigger system)
     pub name: String,
@@ -111,11 +724,13 @@ pub struct CommandRule {
     pub enabled_by_default: bool,
 
     /// Optional: Conditions that must match for this rule to apply
-    #[serde(default)]
+    /// Either a table or the name of a `[condition_templates.<name>]` entry
+    #[serde(default, deserialize_with = "deserialize_conditions")]
     pub conditions: RuleConditions,
 
-    /// The action to take: "delay", "block", "forward", "modify", "batch"
-    /// DEPRECATED: Use `actions` array instead for sequential actions
+    /// The action to take: "delay", "block", "forward", "modify", "batch", "burst", "coalesce", "dedup_identical", "upgrade_command", "transform", "redact", "readdress"
+    /// DEPRECATED: Use `actions` array instead for sequential actions. Moved into `actions`
+    /// when the config is loaded
     #[serde(skip_serializing_if = "Option::is_none")]
     pub action: Option<String>,
 
@@ -124,22 +739,31 @@ pub struct CommandRule {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub actions: Option<Vec<String>>,
 
-    /// Optional: Delay duration in seconds (for action = "delay")
-    pub delay_seconds: Option<u64>,
+    /// Optional: Delay duration in seconds (for action = "delay"), or a `"{ctx.path}"` template
+    pub delay_seconds: Option<ActionValue>,
+
+    /// Optional: Forward at most one message (the latest) per system and message type
+    /// every N milliseconds (for action = "coalesce"), or a `"{ctx.path}"` template
+    pub coalesce_interval_ms: Option<ActionValue>,
 
     /// Optional: Number of unique system IDs to wait for (for action = "batch")
     pub batch_count: Option<usize>,
 
-    /// Optional: Timeout in seconds for batch completion (for action = "batch")
-    pub batch_timeout_seconds: Option<u64>,
+    /// Optional: Timeout in seconds for batch completion (for action = "batch"), or a `"{ctx.path}"` template
+    pub batch_timeout_seconds: Option<ActionValue>,
 
     /// Optional: Whether to forward messages on timeout (for action = "batch")
     /// If false, messages are dropped on timeout. Default: true
     #[serde(default = "default_batch_timeout_forward")]
     pub batch_timeout_forward: bool,
 
+    /// Optional: Actions run on a timed-out batch instead of forwarding/dropping it
+    /// (for action = "batch"; "delay", "block" and "forward" only). Overrides batch_timeout_forward
+    pub batch_timeout_actions: Option<Vec<String>>,
+
     /// Optional: Batch group key (for action = "batch")
     /// Allows multiple independent batch groups. Default: "default"
+    /// May contain `{field}` placeholders resolved per message (e.g. "cmd_{command}_sys_{target_system}")
     #[serde(default = "default_batch_key")]
     pub batch_key: String,
 
@@ -147,10 +771,29 @@ pub struct CommandRule {
     /// If not specified, uses header.system_id. Works for ANY message type.
     pub batch_system_id_field: Option<String>,
 
+    /// Optional: Number of matching packets, from any system, to hold before releasing them
+    /// together (for action = "burst")
+    pub burst_count: Option<usize>,
+
+    /// Optional: Timeout in seconds for a burst to fill (for action = "burst"), or a `"{ctx.path}"` template
+    pub burst_timeout_seconds: Option<ActionValue>,
+
+    /// Optional: Whether to release a burst that timed out (for action = "burst")
+    /// If false, its packets are dropped. Default: true
+    #[serde(default = "default_burst_timeout_forward")]
+    pub burst_timeout_forward: bool,
+
     /// Optional: List of plugins to execute when this rule matches
     #[serde(default)]
     pub plugins: Vec<String>,
 
+    /// Optional: Settings handed to this rule's plugins as `ctx.plugin_config`
+    #[serde(default)]
+    pub plugin_config: toml::Table,
+
+    /// Optional: Inline Lua plugin source, run on match like a listed plugin
+    pub plugin_script: Option<String>,
+
     /// Optional: Automatically send ACK response to GCS (works for ANY message type)
     #[serde(default)]
     pub auto_ack: bool,
@@ -158,9 +801,57 @@ pub struct CommandRule {
     /// Optional: ACK configuration (all ACK settings in one place)
     pub ack: Option<AutoAckConfig>,
 
+    /// Optional: Reason logged and counted when this rule blocks a message (for action = "block")
+    pub block_reason: Option<String>,
+
     /// Optional: Lua modifier script name (for action = "modify")
     pub modifier: Option<String>,
 
+    /// Optional: Inline Lua modifier source, used instead of `modifier` (for action = "modify")
+    pub modifier_script: Option<String>,
+
+    /// Optional: Modifiers to choose from at random per message, by weight (instead of `modifier`)
+    #[serde(default)]
+    pub modifiers: Vec<WeightedModifier>,
+
+    /// Optional: Re-run rule matching on the modified message (for action = "modify")
+    /// The first rule matching the new content supplies the remaining actions
+    #[serde(default)]
+    pub reprocess: bool,
+
+    /// Optional: COMMAND_INT frame used by action = "upgrade_command" (default: "MAV_FRAME_GLOBAL")
+    pub upgrade_frame: Option<String>,
+
+    /// Optional: Send COMMAND_ACKs for upgraded commands only to the GCS client that sent the
+    /// COMMAND_LONG (for action = "upgrade_command")
+    #[serde(default)]
+    pub upgrade_compat: bool,
+
+    /// Optional: Seconds an upgraded command waits for its ACK before it's forgotten
+    /// (for upgrade_compat, default: 5)
+    pub upgrade_compat_timeout_seconds: Option<u64>,
+
+    /// Optional: Message type that confirms delivery (for action = "retry_until_ack", default: "COMMAND_ACK")
+    pub retry_ack_type: Option<String>,
+
+    /// Optional: Seconds to wait for the ACK before each re-send (for action = "retry_until_ack", default: 3),
+    /// or a `"{ctx.path}"` template
+    pub retry_timeout_seconds: Option<ActionValue>,
+
+    /// Optional: Maximum number of re-sends (for action = "retry_until_ack", default: 3)
+    pub retry_attempts: Option<u32>,
+
+    /// Optional: Numeric field transforms for action = "transform" (field -> op)
+    #[serde(default)]
+    pub transform_fields: HashMap<String, FieldTransform>,
+
+    /// Optional: Fields reset to zero/empty for action = "redact"
+    #[serde(default)]
+    pub redact_fields: Vec<String>,
+
+    /// Optional: New target for action = "readdress"
+    pub readdress: Option<ReaddressConfig>,
+
     /// Optional: Human-readable description
     pub description: Option<String>,
 
@@ -170,14 +861,123 @@ pub struct CommandRule {
 
     /// Optional: Message flow direction this rule applies to
     /// "gcs_to_router" (default), "router_to_gcs", or "both"
-    #[serde(default = "default_direction")]
-    pub direction: String,
+    #[serde(default)]
+    pub direction: Direction,
+
+    /// Optional: Only apply to messages from GCS clients in this `[[network.client_groups]]` group
+    pub client_group: Option<String>,
 
     /// Optional: Trigger configuration for activating other rules
     pub triggers: Option<TriggerConfig>,
+
+    /// Optional: Time window during which this rule is active
+    /// Rules without a schedule are always eligible (subject to enabled state)
+    pub schedule: Option<ScheduleConfig>,
+
+    /// Optional: Webhook fired (non-blocking) when this rule matches
+    pub notify: Option<NotifyConfig>,
+
+    /// Optional: Fraction of matches (e.g. 0.01 = 1%) that run the rule's plugins and notify
+    /// webhook; actions run on every match. Default: 1.0
+    pub sample_rate: Option<f64>,
+
+    /// Optional: Record a named, timestamped event when this rule matches
+    /// (checked by other rules with the `since_event` condition)
+    pub record_event: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// Webhook URL to POST to
+    pub url: String,
+
+    /// Request body template
+    /// `{field}` is replaced with the matched message's field value; `{rule}`, `{message_type}`,
+    /// `{system_id}`, `{component_id}` and `{sequence}` are also available
+    #[serde(default = "default_notify_template")]
+    pub template: String,
+
+    /// Content-Type header for the request. Default: "application/json"
+    #[serde(default = "default_content_type")]
+    pub content_type: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    /// Window start time "HH:MM" (inclusive)
+    pub start: String,
+
+    /// Window end time "HH:MM" (exclusive)
+    /// May be earlier than `start` for windows spanning midnight (e.g. 22:00-06:00)
+    pub end: String,
+
+    /// Optional: Days on which the window starts ("mon".."sun"). Default: every day
+    /// For windows spanning midnight, the early-morning part belongs to the previous day
+    #[serde(default)]
+    pub days: Vec<String>,
+
+    /// Timezone used to evaluate the window: "utc" (default) or "local"
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+}
+
+impl ScheduleConfig {
+    /// Check whether the schedule window is active at the current wall-clock time
+    pub fn is_active_now(&self) -> bool {
+        let now = if self.timezone == "local" {
+            chrono::Local::now().naive_local()
+        } else {
+            chrono::Utc::now().naive_utc()
+        };
+        self.is_active_at(now)
+    }
+
+    /// Check whether the schedule window is active at the given time
+    pub fn is_active_at(&self, now: NaiveDateTime) -> bool {
+        let (Ok(start), Ok(end)) = (parse_schedule_time(&self.start), parse_schedule_time(&self.end)) else {
+            return false;
+        };
+
+        let time = now.time();
+        let today = now.weekday();
+
+        // Determine whether we're inside the window and which day the window started on
+        let window_day = if start == end {
+            // Identical start/end covers the whole day
+            Some(today)
+        } else if start < end {
+            (time >= start && time < end).then_some(today)
+        } else if time >= start {
+            Some(today)
+        } else if time < end {
+            Some(today.pred())
+        } else {
+            None
+        };
+
+        match window_day {
+            Some(day) => {
+                self.days.is_empty()
+                    || self
+                        .days
+                        .iter()
+                        .any(|d| d.parse::<Weekday>().map(|w| w == day).unwrap_or(false))
+            }
+            None => false,
+        }
+    }
+}
+
+/// Parse a schedule time in "HH:MM" format
+fn parse_schedule_time(value: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .context(format!("Invalid schedule time '{}', expected HH:MM", value))
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct TriggerConfig {
     /// List of rule names to activate when this rule matches
     #[serde(default)]
@@ -203,10 +1003,19 @@ pub struct TriggerConfig {
     /// Accessible in Lua as ctx.trigger_context
     #[serde(default)]
     pub context: bool,
+
+    /// Quarantine the system that sent the matching message (all its traffic is blocked)
+    #[serde(default)]
+    pub quarantine_source: bool,
+
+    /// How long a `quarantine_source` quarantine lasts; absent = until released
+    pub quarantine_seconds: Option<u64>,
 }
 
 impl CommandRule {
-    /// Get the normalized actions array (handles backward compatibility)
+    /// Get the normalized actions array
+    /// Rules loaded through `Config` only have `actions`; the `action` fallback covers
+    /// rules deserialized directly (e.g. by embedders)
     pub fn get_actions(&self) -> Vec<String> {
         if let Some(ref actions) = self.actions {
             actions.clone()
@@ -216,170 +1025,1589 @@ impl CommandRule {
             vec![]
         }
     }
-}
 
-#[derive(Debug, Deserialize, Clone, Default)]
-pub struct RuleConditions {
-    /// Match specific system IDs
-    pub system_id: Option<u8>,
+    /// Actions with the redundant ones removed: everything after `block`, repeats of
+    /// `forward`, `block` and `redact`, and `forward` next to other actions
+    pub fn simplified_actions(&self) -> Vec<String> {
+        simplify_actions(self.get_actions()).0
+    }
 
-    /// Match specific component IDs
-    pub component_id: Option<u8>,
+    /// One note per action `simplified_actions` removes, and one per modification setting
+    /// whose action isn't in the chain (logged at startup)
+    pub fn action_warnings(&self) -> Vec<String> {
+        let actions = self.get_actions();
+        let unused_settings: Vec<String> = modification_settings(self)
+            .into_iter()
+            .filter(|(action, _, configured)| *configured && !actions.iter().any(|a| a == action))
+            .map(|(action, setting, _)| format!("sets {} but has no '{}' action, so it is ignored", setting, action))
+            .collect();
+        simplify_actions(actions)
+            .1
+            .into_iter()
+            .chain(unused_settings)
+            .map(|note| format!("Rule '{}': {}", self.name, note))
+            .collect()
+    }
 
-    /// Generic field conditions - works for ALL message types
-    /// Example: param1 = 1.0, altitude = 100, fix_type = 3, etc.
-    #[serde(flatten)]
-    pub custom: HashMap<String, toml::Value>,
+    /// Name the rule's inline `modifier_script`/`plugin_script` is loaded under
+    pub fn inline_script_name(&self) -> String {
+        format!("inline:{}", self.name)
+    }
 }
 
-impl Config {
-    pub fn load(path: &str) -> Result<Self> {
-        let contents = fs::read_to_string(path)
-            .context(format!("Failed to read config file: {}", path))?;
-
-        let mut config: Config = toml::from_str(&contents)
-            .context("Failed to parse config file")?;
-
-        // Sort rules by priority (highest first)
-        config.rules.sort_by(|a, b| b.priority.cmp(&a.priority));
-
-        Ok(config)
-    }
+/// Commented example config written by `bitch init`
+pub const STARTER_CONFIG: &str = include_str!("starter_config.toml");
+
+/// Operator keys recognised in condition tables (e.g. `{ field_eq = "param2" }`)
+/// A table whose keys are all operators is evaluated as an operator condition;
+/// any other table (e.g. `{ type = "MAV_CMD_..." }`) is compared literally.
+pub const CONDITION_OPERATORS: &[&str] = &[
+    "field_eq",
+    "field_ne",
+    "header_eq",
+    "header_ne",
+    "bit_set",
+    "bits_any",
+    "bits_all",
+    "exists",
+    "starts_with",
+    "ends_with",
+    "contains",
+    "ignore_case",
+];
+
+/// Largest MAVLink 2 message ID (24-bit field)
+pub(crate) const MAX_MESSAGE_ID: u32 = 0xFF_FFFF;
+
+/// Header fields usable with `header_eq`/`header_ne`
+const HEADER_FIELDS: &[&str] = &["system_id", "component_id", "sequence"];
+
+/// Check whether a condition table is an operator condition
+pub fn is_operator_table(table: &toml::map::Map<String, toml::Value>) -> bool {
+    !table.is_empty() && table.keys().all(|k| CONDITION_OPERATORS.contains(&k.as_str()))
+}
 
-    pub fn validate(&self) -> Result<()> {
-        // Validate network config
-        if self.network.gcs_listen_port == 0 {
-            anyhow::bail!("gcs_listen_port must be greater than 0");
-        }
+/// Validate the operands of an operator condition
+fn validate_operator_table(
+    rule_name: &str,
+    field_name: &str,
+    table: &toml::map::Map<String, toml::Value>,
+) -> Result<()> {
+    for (operator, operand) in table {
+        match operator.as_str() {
+            "field_eq" | "field_ne" if !operand.is_str() => {
+                anyhow::bail!(
+                    "Rule '{}' condition '{}': {} must be a field name string",
+                    rule_name,
+                    field_name,
+                    operator
+                );
+            }
+            "header_eq" | "header_ne" if !operand.as_str().is_some_and(|h| HEADER_FIELDS.contains(&h)) => {
+                anyhow::bail!(
+                    "Rule '{}' condition '{}': {} must be one of: system_id, component_id, sequence",
+                    rule_name,
+                    field_name,
+                    operator
+                );
+            }
+            "bit_set" if operand.as_integer().is_none_or(|mask| mask <= 0) => {
+                anyhow::bail!(
+                    "Rule '{}' condition '{}': bit_set must be a positive integer mask",
+                    rule_name,
+                    field_name
+                );
+            }
+            "exists" if !operand.is_bool() => {
+                anyhow::bail!(
+                    "Rule '{}' condition '{}': exists must be true or false",
+                    rule_name,
+                    field_name
+                );
+            }
+            "bits_any" | "bits_all" => {
+                let valid = operand.as_array().is_some_and(|masks| {
+                    !masks.is_empty() && masks.iter().all(|m| m.as_integer().is_some_and(|mask| mask > 0))
+                });
+                if !valid {
+                    anyhow::bail!(
+                        "Rule '{}' condition '{}': {} must be a non-empty list of positive integer masks",
+                        rule_name,
+                        field_name,
+                        operator
+                    );
+                }
+            }
+            "starts_with" | "ends_with" | "contains" if !operand.is_str() => {
+                anyhow::bail!(
+                    "Rule '{}' condition '{}': {} must be a string",
+                    rule_name,
+                    field_name,
+                    operator
+                );
+            }
+            "ignore_case" => {
+                if !operand.is_bool() {
+                    anyhow::bail!(
+                        "Rule '{}' condition '{}': ignore_case must be true or false",
+                        rule_name,
+                        field_name
+                    );
+                }
+                if !["starts_with", "ends_with", "contains"].iter().any(|op| table.contains_key(*op)) {
+                    anyhow::bail!(
+                        "Rule '{}' condition '{}': ignore_case needs starts_with, ends_with or contains",
+                        rule_name,
+                        field_name
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
 
-        // Validate rules
-        for (idx, rule) in self.rules.iter().enumerate() {
-            let actions = rule.get_actions();
+/// Actions that do the same thing when run twice in a row
+const IDEMPOTENT_ACTIONS: &[&str] = &["forward", "block", "redact"];
+
+/// Remove redundant actions from a chain, returning the chain and a note per removal
+fn simplify_actions(actions: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let mut notes = Vec::new();
+    let mut chain: Vec<String> = Vec::with_capacity(actions.len());
+
+    for (idx, action) in actions.iter().enumerate() {
+        if action == "block" {
+            chain.push(action.clone());
+            if idx + 1 < actions.len() {
+                notes.push(format!(
+                    "actions after 'block' never run: {}",
+                    actions[idx + 1..].join(", ")
+                ));
+            }
+            break;
+        }
+        if chain.last() == Some(action) && IDEMPOTENT_ACTIONS.contains(&action.as_str()) {
+            notes.push(format!("'{}' is repeated; the repeat has no effect", action));
+            continue;
+        }
+        chain.push(action.clone());
+    }
+
+    // Forwarding is what happens at the end of every chain anyway
+    if chain.iter().any(|a| a != "forward") {
+        let before = chain.len();
+        chain.retain(|a| a != "forward");
+        if chain.len() < before {
+            notes.push("'forward' alongside other actions has no effect".to_string());
+        }
+    }
+
+    (chain, notes)
+}
+
+/// `readdress = { target_system = 2, target_component = 1 }`; unset fields are kept
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ReaddressConfig {
+    pub target_system: Option<u8>,
+    pub target_component: Option<u8>,
+}
+
+impl ReaddressConfig {
+    /// The message fields to set, with their new values
+    pub fn fields(&self) -> Vec<(&'static str, u8)> {
+        [("target_system", self.target_system), ("target_component", self.target_component)]
+            .into_iter()
+            .filter_map(|(field, value)| Some((field, value?)))
+            .collect()
+    }
+}
+
+/// One `transform_fields` entry: `{ op = "mul", value = 0.1 }` or `{ op = "clamp", min = 0, max = 100 }`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FieldTransform {
+    /// "add", "sub", "mul", "div" or "clamp"
+    pub op: String,
+    /// Operand for add/sub/mul/div
+    pub value: Option<f64>,
+    /// Lower bound for clamp
+    pub min: Option<f64>,
+    /// Upper bound for clamp
+    pub max: Option<f64>,
+}
 
-            // Ensure at least one action is specified
-            if actions.is_empty() {
-                anyhow::bail!("Rule {} has no action or actions specified", idx);
+impl FieldTransform {
+    fn validate(&self) -> Result<()> {
+        match self.op.as_str() {
+            "add" | "sub" | "mul" | "div" => {
+                let Some(value) = self.value else {
+                    anyhow::bail!("op '{}' requires a value", self.op);
+                };
+                if self.op == "div" && value == 0.0 {
+                    anyhow::bail!("op 'div' can't divide by zero");
+                }
             }
+            "clamp" => match (self.min, self.max) {
+                (None, None) => anyhow::bail!("op 'clamp' requires min and/or max"),
+                (Some(min), Some(max)) if min > max => anyhow::bail!("clamp min {} is greater than max {}", min, max),
+                _ => {}
+            },
+            op => anyhow::bail!("invalid op '{}'. Must be: add, sub, mul, div, or clamp", op),
+        }
+        Ok(())
+    }
 
-            // Validate each action
-            for action in &actions {
-                if !["delay", "block", "forward", "modify", "batch"].contains(&action.as_str()) {
-                    anyhow::bail!(
-                        "Rule {} has invalid action '{}'. Must be: delay, block, forward, modify, or batch",
-                        idx,
-                        action
-                    );
+    /// Apply the transform to a number
+    pub fn apply(&self, input: f64) -> f64 {
+        match self.op.as_str() {
+            "add" => input + self.value.unwrap_or(0.0),
+            "sub" => input - self.value.unwrap_or(0.0),
+            "mul" => input * self.value.unwrap_or(1.0),
+            "div" => input / self.value.unwrap_or(1.0),
+            "clamp" => {
+                let lower = self.min.map_or(input, |min| input.max(min));
+                self.max.map_or(lower, |max| lower.min(max))
+            }
+            _ => input,
+        }
+    }
+}
+
+/// A numeric action setting: a number, or `"{ctx.path}"` / `"{ctx.path|default}"` read from
+/// the trigger context the rule was activated with
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ActionValue {
+    Fixed(u64),
+    Template(String),
+}
+
+impl ActionValue {
+    /// Dot path and default of a template, `None` for a fixed value
+    fn template(&self) -> Result<Option<(Vec<&str>, Option<u64>)>> {
+        let ActionValue::Template(template) = self else {
+            return Ok(None);
+        };
+        let inner = template
+            .strip_prefix("{ctx.")
+            .and_then(|rest| rest.strip_suffix('}'))
+            .with_context(|| format!("'{}' is not a number or a \"{{ctx.path}}\" template", template))?;
+        let (path, default) = match inner.split_once('|') {
+            Some((path, default)) => {
+                let default = default
+                    .trim()
+                    .parse::<u64>()
+                    .with_context(|| format!("'{}' has a non-numeric default", template))?;
+                (path, Some(default))
+            }
+            None => (inner, None),
+        };
+        let path: Vec<&str> = path.trim().split('.').collect();
+        if path.iter().any(|segment| segment.is_empty()) {
+            anyhow::bail!("'{}' has an empty path segment", template);
+        }
+        Ok(Some((path, default)))
+    }
+
+    /// Check a template's syntax
+    pub fn validate(&self) -> Result<()> {
+        self.template().map(|_| ())
+    }
+
+    /// Resolve against a trigger context. Numbers are used as is (non-negative floats are rounded),
+    /// numeric strings are parsed; a missing or non-numeric value falls back to the template
+    /// default, and `None` means there is no default either
+    pub fn resolve(&self, context: &HashMap<String, serde_json::Value>) -> Option<u64> {
+        let (path, default) = match self {
+            ActionValue::Fixed(value) => return Some(*value),
+            ActionValue::Template(_) => self.template().ok()??,
+        };
+        let mut value = context.get(path[0]);
+        for segment in &path[1..] {
+            value = value.and_then(|v| match v {
+                serde_json::Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+                other => other.get(*segment),
+            });
+        }
+        let number = match value {
+            Some(serde_json::Value::Number(n)) => n
+                .as_u64()
+                .or_else(|| n.as_f64().filter(|f| f.is_finite() && *f >= 0.0).map(|f| f.round() as u64)),
+            Some(serde_json::Value::String(s)) => s.trim().parse::<u64>().ok(),
+            _ => None,
+        };
+        number.or(default)
+    }
+}
+
+impl fmt::Display for ActionValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionValue::Fixed(value) => write!(f, "{}", value),
+            ActionValue::Template(template) => write!(f, "{}", template),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct RuleConditions {
+    /// Optional: Name of a `[condition_templates]` entry merged in at load
+    /// (conditions set here take precedence over the template's)
+    pub template: Option<String>,
+
+    /// Match specific system IDs
+    pub system_id: Option<u8>,
+
+    /// Match specific component IDs
+    pub component_id: Option<u8>,
+
+    /// Match only when this system hasn't sent the rule's message type for N seconds
+    /// (the first message ever seen from a system always matches)
+    pub first_seen: Option<u64>,
+
+    /// Match only within N seconds of a named event (`record_event` on another rule)
+    pub since_event: Option<SinceEventCondition>,
+
+    /// Match when a numeric field changed by more than a threshold since the previous
+    /// message of the rule's type from the same system
+    pub delta: Option<DeltaCondition>,
+
+    /// Match when the header sequence advanced by more than N since the previous frame
+    /// from the same (system_id, component_id), i.e. at least N frames were lost
+    pub sequence_gap_above: Option<u8>,
+
+    /// Match on the frame's MAVLink 2 incompatibility flags byte
+    pub incompat_flags: Option<FlagsCondition>,
+
+    /// Match on the frame's MAVLink 2 compatibility flags byte
+    pub compat_flags: Option<FlagsCondition>,
+
+    /// Match frames that came in on this transport or link: "tcp:14550" (GCS listener),
+    /// "tcp:host:port" (router), "serial:/dev/ttyUSB0", or a prefix like "serial"
+    pub source: Option<String>,
+
+    /// Generic field conditions - works for ALL message types
+    /// Example: param1 = 1.0, altitude = 100, fix_type = 3, etc.
+    #[serde(flatten)]
+    pub custom: HashMap<String, toml::Value>,
+}
+
+/// `since_event = { name = "disarm", within_seconds = 5 }`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SinceEventCondition {
+    /// Event name, as set by `record_event`
+    pub name: String,
+    /// How long after the event the condition keeps matching
+    pub within_seconds: u64,
+    /// Only count events recorded from the same source system (default: false = any system)
+    #[serde(default)]
+    pub per_system: bool,
+}
+
+/// One `modifiers` entry: `{ name = "drop_gps", weight = 3 }`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WeightedModifier {
+    /// Modifier name, as in `[modifiers.load]`
+    pub name: String,
+    /// Relative chance of being picked (default: 1)
+    #[serde(default = "default_modifier_weight")]
+    pub weight: u32,
+}
+
+fn default_modifier_weight() -> u32 {
+    1
+}
+
+/// `incompat_flags = { unknown = true }`: tests on a header flags byte (all given tests must pass)
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FlagsCondition {
+    /// The byte equals this value
+    pub equals: Option<u8>,
+    /// At least one of these bits is set
+    pub bits_any: Option<u8>,
+    /// All of these bits are set
+    pub bits_all: Option<u8>,
+    /// None of these bits are set
+    pub bits_none: Option<u8>,
+    /// true: a bit this proxy doesn't know is set; false: only known bits are set
+    pub unknown: Option<bool>,
+}
+
+impl FlagsCondition {
+    /// Check a flags byte; `known` are the bits defined by the protocol
+    pub fn matches(&self, flags: u8, known: u8) -> bool {
+        self.equals.is_none_or(|value| flags == value)
+            && self.bits_any.is_none_or(|bits| flags & bits != 0)
+            && self.bits_all.is_none_or(|bits| flags & bits == bits)
+            && self.bits_none.is_none_or(|bits| flags & bits == 0)
+            && self.unknown.is_none_or(|unknown| (flags & !known != 0) == unknown)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.equals.is_none()
+            && self.bits_any.is_none()
+            && self.bits_all.is_none()
+            && self.bits_none.is_none()
+            && self.unknown.is_none()
+    }
+}
+
+/// `delta = { field = "alt", threshold = 100000 }`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DeltaCondition {
+    /// Numeric message field to compare between consecutive messages
+    pub field: String,
+    /// Minimum absolute change (exclusive), in the field's own units
+    pub threshold: f64,
+}
+
+/// Accept `conditions` as a table or as a bare template name (`conditions = "vehicle1"`)
+fn deserialize_conditions<'de, D>(deserializer: D) -> std::result::Result<RuleConditions, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct ConditionsVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for ConditionsVisitor {
+        type Value = RuleConditions;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a conditions table or a condition template name")
+        }
+
+        fn visit_str<E: serde::de::Error>(self, name: &str) -> std::result::Result<RuleConditions, E> {
+            Ok(RuleConditions {
+                template: Some(name.to_string()),
+                ..Default::default()
+            })
+        }
+
+        fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> std::result::Result<RuleConditions, A::Error> {
+            RuleConditions::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+        }
+    }
+
+    deserializer.deserialize_any(ConditionsVisitor)
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .context(format!("Failed to read config file: {}", path))?;
+
+        let base_dir = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+        let mut config = Self::from_toml(&contents, path, base_dir)?;
+        config.files.insert(0, ConfigFile::new(path, &contents));
+        Ok(config)
+    }
+
+    /// Load a base config file with an overlay file deep-merged onto it (`--overlay`)
+    /// Includes are resolved relative to the base file's directory
+    pub fn load_with_overlay(path: &str, overlay_path: &str) -> Result<Self> {
+        let base = fs::read_to_string(path)
+            .context(format!("Failed to read config file: {}", path))?;
+        let overlay_contents = fs::read_to_string(overlay_path)
+            .context(format!("Failed to read overlay file: {}", overlay_path))?;
+
+        let mut table: toml::Table = toml::from_str(&base)
+            .context(format!("Failed to parse config from {}", path))?;
+        let overlay: toml::Table = toml::from_str(&overlay_contents)
+            .context(format!("Failed to parse overlay from {}", overlay_path))?;
+        merge_overlay(&mut table, overlay)
+            .context(format!("Failed to apply overlay {}", overlay_path))?;
+
+        let source = format!("{} + {}", path, overlay_path);
+        let config: Config = toml::Value::Table(table.clone())
+            .try_into()
+            .map_err(|e| with_rule_context(e.into(), Some(&table)))
+            .context(format!("Failed to parse config from {}", source))?;
+
+        let base_dir = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+        let mut config = config.finish_loading(&source, base_dir)?;
+        config.files.insert(0, ConfigFile::new(overlay_path, &overlay_contents));
+        config.files.insert(0, ConfigFile::new(path, &base));
+        Ok(config)
+    }
+
+    /// Built-in defaults (a pass-through proxy on 0.0.0.0:5760 -> 127.0.0.1:5761, no rules)
+    /// with `overlay` deep-merged onto them, e.g. settings from the environment
+    pub fn load_defaults(overlay: toml::Table) -> Result<Self> {
+        let mut table: toml::Table = toml::from_str(DEFAULT_CONFIG).context("Failed to parse built-in defaults")?;
+        merge_overlay(&mut table, overlay).context("Failed to apply settings to built-in defaults")?;
+
+        let config: Config = toml::Value::Table(table)
+            .try_into()
+            .context("Failed to parse config from built-in defaults")?;
+        config.finish_loading("<defaults>", Path::new("."))
+    }
+
+    /// Overlay for `load_defaults` from `BITCH_*` environment variables, read through `lookup`
+    /// `BITCH_GCS_ADDRESS`, `BITCH_GCS_PORT`, `BITCH_GCS_TRANSPORT`, `BITCH_ROUTER_ADDR` ("host:port",
+    /// "[v6]:port"), `BITCH_LOG_LEVEL` and `BITCH_ADMIN_PORT`; unset or empty variables keep the default
+    pub fn env_overlay(lookup: impl Fn(&str) -> Option<String>) -> Result<toml::Table> {
+        let var = |name: &str| lookup(name).filter(|value| !value.trim().is_empty());
+        let port = |name: &str, value: &str| -> Result<toml::Value> {
+            let port: u16 = value
+                .trim()
+                .parse()
+                .with_context(|| format!("{} must be a port number, got '{}'", name, value))?;
+            Ok(toml::Value::Integer(i64::from(port)))
+        };
+
+        let mut network = toml::Table::new();
+        if let Some(address) = var("BITCH_GCS_ADDRESS") {
+            network.insert("gcs_listen_address".to_string(), address.into());
+        }
+        if let Some(value) = var("BITCH_GCS_PORT") {
+            network.insert("gcs_listen_port".to_string(), port("BITCH_GCS_PORT", &value)?);
+        }
+        if let Some(transport) = var("BITCH_GCS_TRANSPORT") {
+            network.insert("gcs_transport".to_string(), transport.into());
+        }
+        if let Some(addr) = var("BITCH_ROUTER_ADDR") {
+            let (host, value) = addr
+                .trim()
+                .rsplit_once(':')
+                .filter(|(host, _)| !host.is_empty())
+                .ok_or_else(|| anyhow::anyhow!("BITCH_ROUTER_ADDR must be host:port, got '{}'", addr))?;
+            // An IPv6 address needs brackets to be told apart from the port
+            let host = match host.strip_prefix('[').and_then(|host| host.strip_suffix(']')) {
+                Some(ipv6) => ipv6,
+                None if host.contains(':') => anyhow::bail!(
+                    "BITCH_ROUTER_ADDR must put an IPv6 address in brackets ([::1]:5761), got '{}'",
+                    addr
+                ),
+                None => host,
+            };
+            network.insert("router_address".to_string(), host.into());
+            network.insert("router_port".to_string(), port("BITCH_ROUTER_ADDR", value)?);
+        }
+
+        let mut overlay = toml::Table::new();
+        overlay.insert("network".to_string(), network.into());
+        if let Some(level) = var("BITCH_LOG_LEVEL") {
+            let mut logging = toml::Table::new();
+            logging.insert("level".to_string(), level.into());
+            overlay.insert("logging".to_string(), logging.into());
+        }
+        if let Some(value) = var("BITCH_ADMIN_PORT") {
+            let mut admin = toml::Table::new();
+            admin.insert("listen_port".to_string(), port("BITCH_ADMIN_PORT", &value)?);
+            overlay.insert("admin".to_string(), admin.into());
+        }
+        Ok(overlay)
+    }
+
+    /// Load the config TOML from stdin (`--config -`)
+    /// Includes are resolved relative to the working directory
+    pub fn load_from_stdin() -> Result<Self> {
+        let contents = std::io::read_to_string(std::io::stdin())
+            .context("Failed to read config from stdin")?;
+
+        Self::from_toml(&contents, "<stdin>", Path::new("."))
+    }
+
+    /// Load the config TOML from the body of an environment variable
+    /// Includes are resolved relative to the working directory
+    pub fn load_from_env(var: &str) -> Result<Self> {
+        let contents = std::env::var(var)
+            .context(format!("Failed to read config from ${}", var))?;
+
+        Self::from_toml(&contents, &format!("${}", var), Path::new("."))
+    }
+
+    /// Parse config TOML from any source (`source` names it in errors)
+    fn from_toml(contents: &str, source: &str, base_dir: &Path) -> Result<Self> {
+        let config: Config = toml::from_str(contents)
+            .map_err(|e| with_rule_context(e.into(), toml::from_str(contents).ok().as_ref()))
+            .context(format!("Failed to parse config from {}", source))?;
+
+        config.finish_loading(source, base_dir)
+    }
+
+    /// The effective config as TOML (`--dump-config`): defaults, templates and includes
+    /// applied, rules in evaluation order, secrets redacted
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(&self.redacted()).context("Failed to serialize the config as TOML")
+    }
+
+    /// The effective config as JSON (`--dump-config=json`)
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(&self.redacted()).context("Failed to serialize the config as JSON")
+    }
+
+    /// A copy without secrets: the signing key and the path, query and credentials of
+    /// notify webhook URLs (where services put their tokens)
+    fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        if config.network.signing_key.is_some() {
+            config.network.signing_key = Some(REDACTED.to_string());
+        }
+        for rule in config.rules.iter_mut().chain(config.shadow_rules.iter_mut()) {
+            if let Some(notify) = &mut rule.notify {
+                notify.url = redact_url(&notify.url);
+            }
+        }
+        config
+    }
+
+    /// Resolve includes, templates, defaults and inline scripts, then sort rules by priority
+    fn finish_loading(mut self, source: &str, base_dir: &Path) -> Result<Self> {
+        // Merge rules from included files before sorting
+        self.load_includes(source, base_dir)?;
+
+        // Merge referenced condition templates (included rules too)
+        self.apply_condition_templates()?;
+
+        // Fill in [defaults] for rules that don't set their own (included rules too)
+        self.apply_defaults();
+
+        // Point rules with inline scripts at the names they're loaded under
+        self.apply_inline_scripts()?;
+
+        // Fold the deprecated `action` into `actions` (included rules too)
+        self.normalize_actions();
+
+        // Sort rules by priority (highest first)
+        self.rules.sort_by_key(|rule| std::cmp::Reverse(rule.priority));
+        self.shadow_rules.sort_by_key(|rule| std::cmp::Reverse(rule.priority));
+
+        Ok(self)
+    }
+
+    /// Merge each rule's condition template into its conditions (rule values win)
+    fn apply_condition_templates(&mut self) -> Result<()> {
+        for (name, template) in &self.condition_templates {
+            if template.template.is_some() {
+                anyhow::bail!("Condition template '{}' can't reference another template", name);
+            }
+        }
+
+        for rule in self.rules.iter_mut().chain(&mut self.shadow_rules) {
+            merge_condition_template(rule, &self.condition_templates)?;
+        }
+
+        Ok(())
+    }
+
+    /// Prepare a single rule received at runtime (admin API) the way loading does:
+    /// merge its condition template and fill in `[defaults]`
+    /// Inline scripts are rejected, since scripts are only compiled at startup.
+    pub(crate) fn prepare_rule(&self, mut rule: CommandRule) -> Result<CommandRule> {
+        if rule.modifier_script.is_some() || rule.plugin_script.is_some() {
+            anyhow::bail!("Rule '{}': inline scripts can't be changed at runtime", rule.name);
+        }
+        merge_condition_template(&mut rule, &self.condition_templates)?;
+        apply_rule_defaults(&mut rule, &self.defaults);
+        normalize_rule_actions(&mut rule);
+        self.check_client_group(&rule)?;
+        Ok(rule)
+    }
+
+    /// A rule's `client_group` must name a `[[network.client_groups]]` entry
+    fn check_client_group(&self, rule: &CommandRule) -> Result<()> {
+        if let Some(group) = &rule.client_group {
+            if !self.network.client_groups.iter().any(|g| &g.name == group) {
+                anyhow::bail!("Rule '{}' references unknown client group '{}'", rule.name, group);
+            }
+        }
+        Ok(())
+    }
+
+    /// Move every rule's deprecated `action` into `actions`, noting each use in `deprecations`
+    fn normalize_actions(&mut self) {
+        for rule in self.rules.iter_mut().chain(&mut self.shadow_rules) {
+            if let Some(deprecation) = normalize_rule_actions(rule) {
+                self.deprecations.push(deprecation);
+            }
+        }
+    }
+
+    /// Reference each rule's inline modifier/plugin script by its inline script name
+    fn apply_inline_scripts(&mut self) -> Result<()> {
+        for rule in self.rules.iter_mut().chain(&mut self.shadow_rules) {
+            let name = rule.inline_script_name();
+            if rule.modifier_script.is_some() {
+                if rule.modifier.is_some() || !rule.modifiers.is_empty() {
+                    anyhow::bail!("Rule '{}' sets modifier_script together with modifier or modifiers", rule.name);
+                }
+                rule.modifier = Some(name.clone());
+            }
+            if rule.plugin_script.is_some() {
+                rule.plugins.push(name);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copy `[defaults]` conditions into rules that don't specify them
+    fn apply_defaults(&mut self) {
+        for rule in self.rules.iter_mut().chain(&mut self.shadow_rules) {
+            apply_rule_defaults(rule, &self.defaults);
+        }
+    }
+
+    /// Append rules from every `include` entry, rejecting duplicate rule names
+    fn load_includes(&mut self, path: &str, base_dir: &Path) -> Result<()> {
+        // Track where each rule name was defined for clear collision errors
+        let mut origins: HashMap<String, String> = self
+            .rules
+            .iter()
+            .map(|rule| (rule.name.clone(), path.to_string()))
+            .collect();
+
+        for entry in self.include.clone() {
+            for file in resolve_include(&base_dir.join(&entry))? {
+                let contents = fs::read_to_string(&file)
+                    .context(format!("Failed to read rules file: {}", file.display()))?;
+
+                let rules_file: RulesFile = toml::from_str(&contents)
+                    .map_err(|e| with_rule_context(e.into(), toml::from_str(&contents).ok().as_ref()))
+                    .context(format!("Failed to parse rules file: {}", file.display()))?;
+
+                self.files.push(ConfigFile::new(&file, &contents));
+                let origin = file.display().to_string();
+                for rule in rules_file.rules {
+                    if let Some(existing) = origins.get(&rule.name) {
+                        anyhow::bail!(
+                            "Duplicate rule name '{}' in {} (already defined in {})",
+                            rule.name,
+                            origin,
+                            existing
+                        );
+                    }
+                    origins.insert(rule.name.clone(), origin.clone());
+                    self.rules.push(rule);
                 }
             }
+        }
+
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        // Validate network config
+        if self.network.gcs_listen_port == 0 {
+            anyhow::bail!("gcs_listen_port must be greater than 0");
+        }
+
+        let single_router = !self.network.router_address.is_empty() || self.network.router_port != 0;
+        if self.network.router_transport == "serial" {
+            if self.network.router_serial_port.as_deref().unwrap_or_default().is_empty() {
+                anyhow::bail!("router_transport = \"serial\" requires router_serial_port");
+            }
+            if single_router || !self.network.routers.is_empty() {
+                anyhow::bail!("router_transport = \"serial\" replaces router_address/router_port and [[network.routers]]");
+            }
+            if self.network.router_baud == 0 {
+                anyhow::bail!("router_baud must be greater than 0");
+            }
+        } else if self.network.router_transport != "tcp" {
+            anyhow::bail!(
+                "Invalid router_transport '{}'. Must be: tcp or serial",
+                self.network.router_transport
+            );
+        } else if self.network.routers.is_empty() {
+            if self.network.router_address.is_empty() || self.network.router_port == 0 {
+                anyhow::bail!("router_address and router_port are required (or list endpoints in [[network.routers]])");
+            }
+        } else if single_router {
+            anyhow::bail!("Use either router_address/router_port or [[network.routers]], not both");
+        }
+
+        if self.network.tcp_send_buffer == Some(0) || self.network.tcp_recv_buffer == Some(0) {
+            anyhow::bail!("tcp_send_buffer and tcp_recv_buffer must be greater than 0");
+        }
+
+        if self.network.router_queue_size == 0 {
+            anyhow::bail!("router_queue_size must be greater than 0");
+        }
+        if self.network.router_write_timeout_ms == 0 {
+            anyhow::bail!("router_write_timeout_ms must be greater than 0");
+        }
+
+        for router in &self.network.routers {
+            if router.address.is_empty() || router.port == 0 {
+                anyhow::bail!("Each [[network.routers]] entry needs an address and a port greater than 0");
+            }
+        }
+
+        if !self.network.gcs_allowed_clients.is_empty()
+            && self
+                .network
+                .gcs_tls
+                .as_ref()
+                .and_then(|tls| tls.client_ca_path.as_ref())
+                .is_none()
+        {
+            anyhow::bail!("gcs_allowed_clients requires [network.gcs_tls] with client_ca_path");
+        }
+
+        for cidr in self.network.gcs_allow_cidrs.iter().chain(&self.network.gcs_deny_cidrs) {
+            if cidr.parse::<ipnet::IpNet>().is_err() {
+                anyhow::bail!("Invalid CIDR '{}' in gcs_allow_cidrs/gcs_deny_cidrs", cidr);
+            }
+        }
 
-            // Validate direction field
-            if !["gcs_to_router", "router_to_gcs", "both"].contains(&rule.direction.as_str()) {
+        let mut group_names = std::collections::HashSet::new();
+        for group in &self.network.client_groups {
+            if group.name.is_empty() || !group_names.insert(group.name.as_str()) {
+                anyhow::bail!("Each [[network.client_groups]] entry needs a unique, non-empty name");
+            }
+            if group.cidrs.is_empty() && group.clients.is_empty() {
+                anyhow::bail!("Client group '{}' has neither cidrs nor clients", group.name);
+            }
+            for cidr in &group.cidrs {
+                if cidr.parse::<ipnet::IpNet>().is_err() {
+                    anyhow::bail!("Invalid CIDR '{}' in client group '{}'", cidr, group.name);
+                }
+            }
+            let verifies_clients = self
+                .network
+                .gcs_tls
+                .as_ref()
+                .is_some_and(|tls| tls.client_ca_path.is_some());
+            if !group.clients.is_empty() && !verifies_clients {
+                anyhow::bail!(
+                    "Client group '{}' lists clients, which requires [network.gcs_tls] with client_ca_path",
+                    group.name
+                );
+            }
+        }
+
+        if let Some(pacing) = &self.network.radio_pacing {
+            if pacing.congested_txbuf == 0 || pacing.congested_txbuf > 100 {
+                anyhow::bail!("radio_pacing.congested_txbuf must be between 1 and 100");
+            }
+            if pacing.max_delay_ms == 0 || pacing.stale_after_seconds == 0 {
+                anyhow::bail!("radio_pacing.max_delay_ms and stale_after_seconds must be greater than 0");
+            }
+        }
+
+        if let Some(announce) = &self.network.announce {
+            if announce.interval_seconds == 0 {
+                anyhow::bail!("announce.interval_seconds must be greater than 0");
+            }
+            announce.message()?;
+        }
+
+        if self.network.signing_key.is_some() && self.network.signing_key().is_none() {
+            anyhow::bail!("signing_key must be 64 hex digits (32 bytes)");
+        }
+        if self.network.sign_outbound && self.network.signing_key.is_none() {
+            anyhow::bail!("sign_outbound requires signing_key");
+        }
+        if (self.network.reject_unsigned || self.network.reject_bad_signature) && self.network.signing_key.is_none() {
+            anyhow::bail!("reject_unsigned and reject_bad_signature require signing_key");
+        }
+
+        for id in self.network.crc_extra_overrides.keys() {
+            if !id.parse::<u32>().is_ok_and(|id| id <= MAX_MESSAGE_ID) {
+                anyhow::bail!(
+                    "Invalid crc_extra_overrides key '{}'. Must be a numeric message ID (0-{})",
+                    id,
+                    MAX_MESSAGE_ID
+                );
+            }
+        }
+
+        if !["tcp", "ws"].contains(&self.network.gcs_transport.as_str()) {
+            anyhow::bail!(
+                "Invalid gcs_transport '{}'. Must be: tcp or ws",
+                self.network.gcs_transport
+            );
+        }
+
+        for (name, process) in &self.plugins.process {
+            if process.command.trim().is_empty() {
+                anyhow::bail!("[plugins.process.{}] command must not be empty", name);
+            }
+            if self.plugins.load.contains_key(name) {
+                anyhow::bail!("Plugin '{}' is in both [plugins.load] and [plugins.process]", name);
+            }
+        }
+
+        for name in &self.modifiers.stateful {
+            if !self.modifiers.load.contains_key(name) {
+                anyhow::bail!("[modifiers] stateful lists '{}', which is not in [modifiers.load]", name);
+            }
+        }
+
+        if let Some(hook) = &self.network.route_hook {
+            let Some((plugin, _)) = self.network.route_hook() else {
+                anyhow::bail!("route_hook '{}' must name a plugin function as \"plugin.function\"", hook);
+            };
+            if !self.plugins.load.contains_key(plugin) && !self.plugins.process.contains_key(plugin) {
+                anyhow::bail!(
+                    "route_hook names plugin '{}', which is not in [plugins.load] or [plugins.process]",
+                    plugin
+                );
+            }
+        }
+
+        if let Some(admin) = &self.admin {
+            if admin.listen_port == 0 {
+                anyhow::bail!("[admin] listen_port must be greater than 0");
+            }
+        }
+
+        // Validate rules
+        validate_rules(&self.rules)?;
+        validate_rules(&self.shadow_rules).context("Invalid [[shadow_rules]]")?;
+        for rule in self.rules.iter().chain(&self.shadow_rules) {
+            self.check_client_group(rule)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Merge a rule's condition template (if any) into its conditions (rule values win)
+fn merge_condition_template(rule: &mut CommandRule, templates: &HashMap<String, RuleConditions>) -> Result<()> {
+    let Some(name) = &rule.conditions.template else {
+        return Ok(());
+    };
+    let Some(template) = templates.get(name) else {
+        anyhow::bail!("Rule '{}' references unknown condition template '{}'", rule.name, name);
+    };
+
+    let conditions = &mut rule.conditions;
+    conditions.system_id = conditions.system_id.or(template.system_id);
+    conditions.component_id = conditions.component_id.or(template.component_id);
+    conditions.first_seen = conditions.first_seen.or(template.first_seen);
+    conditions.since_event = conditions.since_event.take().or_else(|| template.since_event.clone());
+    conditions.delta = conditions.delta.take().or_else(|| template.delta.clone());
+    conditions.sequence_gap_above = conditions.sequence_gap_above.or(template.sequence_gap_above);
+    conditions.incompat_flags = conditions.incompat_flags.take().or_else(|| template.incompat_flags.clone());
+    conditions.compat_flags = conditions.compat_flags.take().or_else(|| template.compat_flags.clone());
+    conditions.source = conditions.source.take().or_else(|| template.source.clone());
+    for (field, value) in &template.custom {
+        conditions.custom.entry(field.clone()).or_insert_with(|| value.clone());
+    }
+
+    Ok(())
+}
+
+/// Move a rule's deprecated `action` into `actions`
+/// Returns a deprecation message if the rule used `action`
+fn normalize_rule_actions(rule: &mut CommandRule) -> Option<String> {
+    let action = rule.action.take()?;
+    if rule.actions.is_some() {
+        return Some(format!(
+            "Rule '{}' sets both `action` and `actions`; the deprecated `action` is ignored",
+            rule.name
+        ));
+    }
+
+    let deprecation = format!(
+        "Rule '{}' uses the deprecated `action` field; write `actions = [\"{}\"]` instead",
+        rule.name, action
+    );
+    rule.actions = Some(vec![action]);
+    Some(deprecation)
+}
+
+/// Fill in `[defaults]` header conditions a rule doesn't set itself
+fn apply_rule_defaults(rule: &mut CommandRule, defaults: &RuleDefaults) {
+    let conditions = &mut rule.conditions;
+    conditions.system_id = conditions.system_id.or(defaults.system_id);
+    conditions.component_id = conditions.component_id.or(defaults.component_id);
+}
+
+/// Actions that change the message, with the setting each one needs and whether `rule` has it
+fn modification_settings(rule: &CommandRule) -> [(&'static str, &'static str, bool); 4] {
+    let modifier = rule.modifier.is_some() || rule.modifier_script.is_some() || !rule.modifiers.is_empty();
+    let readdress = rule.readdress.as_ref().is_some_and(|readdress| !readdress.fields().is_empty());
+    [
+        ("modify", "Lua modifier (modifier, modifier_script or modifiers)", modifier),
+        ("transform", "transform_fields", !rule.transform_fields.is_empty()),
+        ("redact", "redact_fields", !rule.redact_fields.is_empty()),
+        ("readdress", "readdress.target_system or readdress.target_component", readdress),
+    ]
+}
+
+/// Check each modification action has its own setting (a setting without its action is
+/// only a warning, see `CommandRule::action_warnings`)
+/// Another action's setting never satisfies `modify`, so a rule that only sets e.g.
+/// `transform_fields` gets pointed at the action that uses them.
+fn validate_modification_actions(rule: &CommandRule, actions: &[String]) -> Result<()> {
+    let settings = modification_settings(rule);
+    for (action, setting, configured) in settings {
+        let used = actions.iter().any(|a| a == action);
+        if used && !configured {
+            let hint = settings
+                .iter()
+                .find(|(other, _, other_configured)| *other_configured && !actions.iter().any(|a| a == *other))
+                .map(|(other, other_setting, _)| format!("; {} is for the '{}' action", other_setting, other))
+                .unwrap_or_default();
+            anyhow::bail!("Rule '{}' has '{}' action but no {} specified{}", rule.name, action, setting, hint);
+        }
+    }
+    Ok(())
+}
+
+/// Validate a rule set (`[[rules]]` or `[[shadow_rules]]`): actions, their settings,
+/// unique names and trigger targets within the set
+pub(crate) fn validate_rules(rules: &[CommandRule]) -> Result<()> {
+    for (idx, rule) in rules.iter().enumerate() {
+        let actions = rule.get_actions();
+
+        // Ensure at least one action is specified
+        if actions.is_empty() {
+            anyhow::bail!("Rule {} has no action or actions specified", idx);
+        }
+
+        // Validate each action
+        for action in &actions {
+            if ![
+                "delay",
+                "block",
+                "forward",
+                "modify",
+                "batch",
+                "burst",
+                "coalesce",
+                "dedup_identical",
+                "upgrade_command",
+                "retry_until_ack",
+                "transform",
+                "redact",
+                "readdress",
+            ]
+            .contains(&action.as_str())
+            {
                 anyhow::bail!(
-                    "Rule {} has invalid direction '{}'. Must be: gcs_to_router, router_to_gcs, or both",
+                    "Rule {} has invalid action '{}'. Must be: delay, block, forward, modify, batch, burst, coalesce, dedup_identical, upgrade_command, retry_until_ack, transform, redact, or readdress",
                     idx,
-                    rule.direction
+                    action
                 );
             }
+        }
+
+        // Validate action-specific requirements
+        if actions.contains(&"delay".to_string()) && rule.delay_seconds.is_none() {
+            anyhow::bail!(
+                "Rule {} has 'delay' action but no delay_seconds specified",
+                idx
+            );
+        }
 
-            // Validate action-specific requirements
-            if actions.contains(&"delay".to_string()) && rule.delay_seconds.is_none() {
+        if rule.message_type == crate::rules::UNKNOWN_MESSAGE_TYPE {
+            if let Some(action) = actions.iter().find(|a| !["forward", "block", "delay"].contains(&a.as_str())) {
                 anyhow::bail!(
-                    "Rule {} has 'delay' action but no delay_seconds specified",
-                    idx
+                    "Rule {} matches {} frames but has action '{}' (only forward, block and delay apply to frames that can't be decoded)",
+                    idx,
+                    rule.message_type,
+                    action
                 );
             }
+            if rule.auto_ack {
+                anyhow::bail!("Rule {} matches {} frames, which are never auto-ACKed", idx, rule.message_type);
+            }
+        }
 
-            if actions.contains(&"batch".to_string()) {
-                if rule.batch_count.is_none() {
+        if actions.contains(&"batch".to_string()) {
+            if rule.batch_count.is_none() {
+                anyhow::bail!(
+                    "Rule {} has 'batch' action but no batch_count specified",
+                    idx
+                );
+            }
+            if rule.batch_timeout_seconds.is_none() {
+                anyhow::bail!(
+                    "Rule {} has 'batch' action but no batch_timeout_seconds specified",
+                    idx
+                );
+            }
+            if let Some(timeout_actions) = &rule.batch_timeout_actions {
+                for action in timeout_actions {
+                    if !["delay", "block", "forward"].contains(&action.as_str()) {
+                        anyhow::bail!(
+                            "Rule {} has invalid batch_timeout_actions entry '{}'. Must be: delay, block, or forward",
+                            idx,
+                            action
+                        );
+                    }
+                }
+                if timeout_actions.iter().any(|a| a == "delay") && rule.delay_seconds.is_none() {
                     anyhow::bail!(
-                        "Rule {} has 'batch' action but no batch_count specified",
+                        "Rule {} has 'delay' in batch_timeout_actions but no delay_seconds specified",
                         idx
                     );
                 }
-                if rule.batch_timeout_seconds.is_none() {
+            }
+        } else if rule.batch_timeout_actions.is_some() {
+            anyhow::bail!("Rule {} has batch_timeout_actions but no 'batch' action", idx);
+        }
+
+        if actions.contains(&"burst".to_string()) {
+            match rule.burst_count {
+                None => anyhow::bail!("Rule {} has 'burst' action but no burst_count specified", idx),
+                Some(0) => anyhow::bail!("Rule {} has burst_count = 0 (must be at least 1)", idx),
+                Some(_) => {}
+            }
+            if rule.burst_timeout_seconds.is_none() {
+                anyhow::bail!("Rule {} has 'burst' action but no burst_timeout_seconds specified", idx);
+            }
+        }
+
+        if actions.contains(&"coalesce".to_string()) {
+            match rule.coalesce_interval_ms {
+                None => anyhow::bail!(
+                    "Rule {} has 'coalesce' action but no coalesce_interval_ms specified",
+                    idx
+                ),
+                Some(ActionValue::Fixed(0)) => anyhow::bail!("Rule {} has coalesce_interval_ms = 0 (must be > 0)", idx),
+                Some(_) => {}
+            }
+        }
+
+        validate_modification_actions(rule, &actions)?;
+
+        if !rule.modifiers.is_empty() {
+            if rule.modifier.is_some() {
+                anyhow::bail!("Rule '{}' sets both modifier and modifiers", rule.name);
+            }
+            if let Some(entry) = rule.modifiers.iter().find(|m| m.name.is_empty() || m.weight == 0) {
+                anyhow::bail!(
+                    "Rule '{}' has modifiers entry '{}' with weight {} (needs a name and a weight of at least 1)",
+                    rule.name,
+                    entry.name,
+                    entry.weight
+                );
+            }
+        }
+
+        if rule.reprocess && !actions.contains(&"modify".to_string()) {
+            anyhow::bail!(
+                "Rule {} has reprocess = true but no 'modify' action (only Lua modifier results are matched again)",
+                idx
+            );
+        }
+
+        if actions.contains(&"upgrade_command".to_string()) {
+            if rule.message_type != "COMMAND_LONG" {
+                anyhow::bail!(
+                    "Rule {} has 'upgrade_command' action but message_type is '{}' (must be COMMAND_LONG)",
+                    idx,
+                    rule.message_type
+                );
+            }
+            if let Some(ref frame) = rule.upgrade_frame {
+                if crate::messages::parse_frame(frame).is_err() {
                     anyhow::bail!(
-                        "Rule {} has 'batch' action but no batch_timeout_seconds specified",
-                        idx
+                        "Rule {} has invalid upgrade_frame '{}' (expected a MAV_FRAME_* name)",
+                        idx,
+                        frame
                     );
                 }
             }
+            if rule.upgrade_compat_timeout_seconds == Some(0) {
+                anyhow::bail!("Rule {} has upgrade_compat_timeout_seconds = 0 (must be > 0)", idx);
+            }
+        } else if rule.upgrade_compat {
+            anyhow::bail!("Rule {} has upgrade_compat = true but no 'upgrade_command' action", idx);
+        }
+
+        if rule.client_group.is_some() && rule.direction != Direction::GcsToRouter {
+            anyhow::bail!(
+                "Rule {} has client_group but direction is {} (client groups only tag GCS -> router traffic)",
+                idx,
+                rule.direction
+            );
+        }
 
-            if actions.contains(&"modify".to_string()) && rule.modifier.is_none() {
+        if actions.contains(&"retry_until_ack".to_string()) {
+            if rule.direction == Direction::RouterToGcs {
                 anyhow::bail!(
-                    "Rule {} has 'modify' action but no modifier specified",
+                    "Rule {} has 'retry_until_ack' action but direction is router_to_gcs (retries are sent to the router)",
                     idx
                 );
             }
-
-            // Validate auto_ack requirements
-            if rule.auto_ack && rule.ack.is_none() {
+            if rule.retry_timeout_seconds == Some(ActionValue::Fixed(0)) || rule.retry_attempts == Some(0) {
                 anyhow::bail!(
-                    "Rule {} has auto_ack enabled but no [rules.ack] section specified",
+                    "Rule {} has retry_timeout_seconds or retry_attempts = 0 (must be at least 1)",
                     idx
                 );
             }
+        }
 
-            // Validate trigger requirements
-            if let Some(triggers) = &rule.triggers {
-                // Must have at least one trigger action
-                if triggers.activate_rules.is_empty() && triggers.deactivate_rules.is_empty() {
+        for (field, value) in [
+            ("delay_seconds", &rule.delay_seconds),
+            ("batch_timeout_seconds", &rule.batch_timeout_seconds),
+            ("burst_timeout_seconds", &rule.burst_timeout_seconds),
+            ("coalesce_interval_ms", &rule.coalesce_interval_ms),
+            ("retry_timeout_seconds", &rule.retry_timeout_seconds),
+        ] {
+            if let Some(value) = value {
+                value.validate().with_context(|| format!("Rule {} has invalid {}", idx, field))?;
+            }
+        }
+
+        for (field_name, transform) in &rule.transform_fields {
+            transform
+                .validate()
+                .context(format!("Rule '{}' transform_fields.{}", rule.name, field_name))?;
+        }
+
+        if actions.contains(&"redact".to_string()) {
+            let fields = crate::messages::default_fields(&rule.message_type)
+                .context(format!("Rule '{}' has 'redact' action", rule.name))?;
+            for field_name in &rule.redact_fields {
+                if !fields.contains_key(field_name) {
                     anyhow::bail!(
-                        "Rule '{}' has triggers section but no activate_rules or deactivate_rules",
-                        rule.name
+                        "Rule '{}' redact_fields: {} has no field '{}'",
+                        rule.name,
+                        rule.message_type,
+                        field_name
                     );
                 }
+            }
+        }
+
+        if actions.contains(&"readdress".to_string()) {
+            let fields = rule.readdress.as_ref().map(ReaddressConfig::fields).unwrap_or_default();
+            // Types the dialect doesn't know are checked when a message arrives
+            if let Ok(defaults) = crate::messages::default_fields(&rule.message_type) {
+                for (field_name, _) in fields {
+                    if !defaults.contains_key(field_name) {
+                        anyhow::bail!(
+                            "Rule '{}' has 'readdress' action but {} has no field '{}'",
+                            rule.name,
+                            rule.message_type,
+                            field_name
+                        );
+                    }
+                }
+            }
+        }
+
+        // Validate auto_ack requirements
+        if rule.auto_ack && rule.ack.is_none() {
+            anyhow::bail!(
+                "Rule {} has auto_ack enabled but no [rules.ack] section specified",
+                idx
+            );
+        }
+
+        if rule.conditions.first_seen == Some(0) {
+            anyhow::bail!("Rule '{}' has first_seen = 0 (must be at least 1 second)", rule.name);
+        }
+
+        if rule.record_event.as_deref() == Some("") {
+            anyhow::bail!("Rule '{}' has an empty record_event name", rule.name);
+        }
+        if let Some(since_event) = &rule.conditions.since_event {
+            if since_event.within_seconds == 0 {
+                anyhow::bail!("Rule '{}' has since_event.within_seconds = 0 (must be at least 1 second)", rule.name);
+            }
+            if !rules.iter().any(|r| r.record_event.as_deref() == Some(since_event.name.as_str())) {
+                anyhow::bail!(
+                    "Rule '{}' checks since_event '{}', but no rule records it (record_event)",
+                    rule.name,
+                    since_event.name
+                );
+            }
+        }
+
+        if let Some(gap) = rule.conditions.sequence_gap_above {
+            if !(1..=254).contains(&gap) {
+                anyhow::bail!("Rule '{}' has sequence_gap_above = {} (must be 1-254)", rule.name, gap);
+            }
+        }
+
+        for (name, flags) in [
+            ("incompat_flags", &rule.conditions.incompat_flags),
+            ("compat_flags", &rule.conditions.compat_flags),
+        ] {
+            if flags.as_ref().is_some_and(FlagsCondition::is_empty) {
+                anyhow::bail!(
+                    "Rule '{}' has an empty {} condition (set equals, bits_any, bits_all, bits_none or unknown)",
+                    rule.name,
+                    name
+                );
+            }
+        }
+
+        if let Some(source) = &rule.conditions.source {
+            if source.is_empty() || source.ends_with(':') {
+                anyhow::bail!(
+                    "Rule '{}' has an invalid source condition '{}' (e.g. \"serial\", \"tcp\" or \"tcp:14550\")",
+                    rule.name,
+                    source
+                );
+            }
+        }
+
+        if let Some(delta) = &rule.conditions.delta {
+            if delta.field.is_empty() {
+                anyhow::bail!("Rule '{}' has a delta condition with an empty field", rule.name);
+            }
+            if !delta.threshold.is_finite() || delta.threshold < 0.0 {
+                anyhow::bail!(
+                    "Rule '{}' has delta.threshold = {} (must be a non-negative number)",
+                    rule.name,
+                    delta.threshold
+                );
+            }
+        }
+
+        // Validate operator conditions
+        for (field_name, value) in &rule.conditions.custom {
+            if let toml::Value::Table(table) = value {
+                if is_operator_table(table) {
+                    validate_operator_table(&rule.name, field_name, table)?;
+                }
+            }
+        }
 
-                // Must have at least one trigger timing
-                if !triggers.on_match && !triggers.on_complete {
+        // Validate schedule format
+        if let Some(schedule) = &rule.schedule {
+            parse_schedule_time(&schedule.start)
+                .context(format!("Rule '{}' has invalid schedule start", rule.name))?;
+            parse_schedule_time(&schedule.end)
+                .context(format!("Rule '{}' has invalid schedule end", rule.name))?;
+            for day in &schedule.days {
+                if day.parse::<Weekday>().is_err() {
                     anyhow::bail!(
-                        "Rule '{}' has triggers but neither on_match nor on_complete is true",
-                        rule.name
+                        "Rule '{}' has invalid schedule day '{}'. Must be: mon, tue, wed, thu, fri, sat, or sun",
+                        rule.name,
+                        day
                     );
                 }
+            }
+            if !["utc", "local"].contains(&schedule.timezone.as_str()) {
+                anyhow::bail!(
+                    "Rule '{}' has invalid schedule timezone '{}'. Must be: utc or local",
+                    rule.name,
+                    schedule.timezone
+                );
+            }
+        }
 
-                // If activating rules, must specify duration
-                if !triggers.activate_rules.is_empty() && triggers.duration_seconds.is_none() {
+        // Validate notify webhook
+        if let Some(notify) = &rule.notify {
+            if !notify.url.starts_with("http://") && !notify.url.starts_with("https://") {
+                anyhow::bail!(
+                    "Rule '{}' has invalid notify url '{}'. Must start with http:// or https://",
+                    rule.name,
+                    notify.url
+                );
+            }
+        }
+
+        if let Some(rate) = rule.sample_rate {
+            if !(rate > 0.0 && rate <= 1.0) {
+                anyhow::bail!("Rule '{}' has sample_rate {} (must be > 0 and <= 1)", rule.name, rate);
+            }
+        }
+
+        // Validate trigger requirements
+        if let Some(triggers) = &rule.triggers {
+            // Must have at least one trigger action
+            if triggers.activate_rules.is_empty() && triggers.deactivate_rules.is_empty() && !triggers.quarantine_source {
+                anyhow::bail!(
+                    "Rule '{}' has triggers section but no activate_rules, deactivate_rules or quarantine_source",
+                    rule.name
+                );
+            }
+
+            // Must have at least one trigger timing
+            if !triggers.on_match && !triggers.on_complete {
+                anyhow::bail!(
+                    "Rule '{}' has triggers but neither on_match nor on_complete is true",
+                    rule.name
+                );
+            }
+
+            // If activating rules, must specify duration
+            if !triggers.activate_rules.is_empty() && triggers.duration_seconds.is_none() {
+                anyhow::bail!(
+                    "Rule '{}' activates rules but has no duration_seconds specified",
+                    rule.name
+                );
+            }
+
+            if triggers.quarantine_seconds.is_some() && !triggers.quarantine_source {
+                anyhow::bail!(
+                    "Rule '{}' sets quarantine_seconds without quarantine_source = true",
+                    rule.name
+                );
+            }
+        }
+    }
+
+    // Validate rule names are unique
+    let mut names = std::collections::HashSet::new();
+    for rule in rules {
+        if !names.insert(&rule.name) {
+            anyhow::bail!("Duplicate rule name: '{}'", rule.name);
+        }
+    }
+
+    // Validate triggered rule names exist
+    let rule_names: std::collections::HashSet<_> = rules.iter().map(|r| &r.name).collect();
+    for rule in rules {
+        if let Some(triggers) = &rule.triggers {
+            for target_name in &triggers.activate_rules {
+                if !rule_names.contains(target_name) {
                     anyhow::bail!(
-                        "Rule '{}' activates rules but has no duration_seconds specified",
-                        rule.name
+                        "Rule '{}' tries to activate non-existent rule '{}'",
+                        rule.name,
+                        target_name
+                    );
+                }
+            }
+            for target_name in &triggers.deactivate_rules {
+                if !rule_names.contains(target_name) {
+                    anyhow::bail!(
+                        "Rule '{}' tries to deactivate non-existent rule '{}'",
+                        rule.name,
+                        target_name
                     );
                 }
             }
         }
+    }
 
-        // Validate rule names are unique
-        let mut names = std::collections::HashSet::new();
-        for rule in &self.rules {
-            if !names.insert(&rule.name) {
-                anyhow::bail!("Duplicate rule name: '{}'", rule.name);
+    Ok(())
+}
+
+/// Name the rule behind a config parse error: `table` (the same TOML, read without a schema)
+/// has its `rules`/`shadow_rules` entries parsed one by one, and the first failing entry's
+/// list, index and name are added to `error`
+fn with_rule_context(error: anyhow::Error, table: Option<&toml::Table>) -> anyhow::Error {
+    let Some(table) = table else {
+        return error;
+    };
+    for list in ["rules", "shadow_rules"] {
+        let Some(toml::Value::Array(rules)) = table.get(list) else {
+            continue;
+        };
+        for (index, rule) in rules.iter().enumerate() {
+            if let Err(e) = rule.clone().try_into::<CommandRule>() {
+                let name = rule.get("name").and_then(toml::Value::as_str).unwrap_or("<unnamed>");
+                return error.context(format!("{}[{}] ('{}'): {}", list, index, name, e));
             }
         }
+    }
+    error
+}
 
-        // Validate triggered rule names exist
-        let rule_names: std::collections::HashSet<_> = self.rules.iter().map(|r| &r.name).collect();
-        for rule in &self.rules {
-            if let Some(triggers) = &rule.triggers {
-                for target_name in &triggers.activate_rules {
-                    if !rule_names.contains(target_name) {
-                        anyhow::bail!(
-                            "Rule '{}' tries to activate non-existent rule '{}'",
-                            rule.name,
-                            target_name
-                        );
+/// How an overlay's rule list combines with the base list (`[overlay]` in the overlay file)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RulesMerge {
+    /// Deep-merge rules with the same name, append the rest
+    #[default]
+    Merge,
+    /// Append the overlay's rules after the base rules
+    Append,
+    /// Use only the overlay's rules
+    Replace,
+}
+
+/// The `[overlay]` table of an overlay file: merge directives for the rule lists
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct OverlayDirectives {
+    rules: RulesMerge,
+    shadow_rules: RulesMerge,
+}
+
+/// Deep-merge an overlay onto a base config: tables merge key by key, everything else
+/// (scalars and arrays) is replaced, and `rules`/`shadow_rules` follow the `[overlay]` directives
+fn merge_overlay(base: &mut toml::Table, mut overlay: toml::Table) -> Result<()> {
+    let directives: OverlayDirectives = match overlay.remove("overlay") {
+        Some(value) => value.try_into().context("Invalid [overlay] table")?,
+        None => OverlayDirectives::default(),
+    };
+
+    for (key, value) in overlay {
+        let mode = match key.as_str() {
+            "rules" => Some(directives.rules),
+            "shadow_rules" => Some(directives.shadow_rules),
+            _ => None,
+        };
+        match (mode, base.get_mut(&key)) {
+            (Some(mode), Some(existing)) => merge_rule_list(&key, existing, value, mode)?,
+            (None, Some(existing)) => merge_value(existing, value),
+            (_, None) => {
+                base.insert(key, value);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Merge tables recursively; any other overlay value replaces the base value
+fn merge_value(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_value(existing, value),
+                    None => {
+                        base.insert(key, value);
                     }
                 }
-                for target_name in &triggers.deactivate_rules {
-                    if !rule_names.contains(target_name) {
-                        anyhow::bail!(
-                            "Rule '{}' tries to deactivate non-existent rule '{}'",
-                            rule.name,
-                            target_name
-                        );
-                    }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Combine a base rule list with an overlay rule list according to `mode`
+fn merge_rule_list(key: &str, base: &mut toml::Value, overlay: toml::Value, mode: RulesMerge) -> Result<()> {
+    let (toml::Value::Array(base_rules), toml::Value::Array(overlay_rules)) = (&mut *base, overlay) else {
+        anyhow::bail!("'{}' must be an array of rules", key);
+    };
+
+    match mode {
+        RulesMerge::Replace => *base_rules = overlay_rules,
+        RulesMerge::Append => base_rules.extend(overlay_rules),
+        RulesMerge::Merge => {
+            for rule in overlay_rules {
+                let name = rule
+                    .get("name")
+                    .and_then(toml::Value::as_str)
+                    .ok_or_else(|| anyhow::anyhow!("Overlay {} entry has no name", key))?
+                    .to_string();
+                let existing = base_rules
+                    .iter_mut()
+                    .find(|r| r.get("name").and_then(toml::Value::as_str) == Some(name.as_str()));
+                match existing {
+                    Some(existing) => merge_value(existing, rule),
+                    None => base_rules.push(rule),
                 }
             }
         }
+    }
 
-        Ok(())
+    Ok(())
+}
+
+/// Expand an include entry into the list of rule files it refers to
+/// Directories contribute every `*.toml` file inside them, in filename order
+fn resolve_include(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
     }
+
+    let mut files: Vec<PathBuf> = fs::read_dir(path)
+        .context(format!("Failed to read rules directory: {}", path.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    files.sort();
+
+    Ok(files)
 }
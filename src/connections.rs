@@ -0,0 +1,100 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// What happened to a GCS connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionEventKind {
+    /// Client registered and started forwarding
+    Connect,
+    /// Registered client went away
+    Disconnect,
+    /// Connection refused before registration (address filter, TLS, handshake)
+    Rejected,
+}
+
+/// One entry of the connection event log
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionEvent {
+    /// Event time (RFC 3339, UTC)
+    pub time: String,
+    pub event: ConnectionEventKind,
+    /// Remote address of the client
+    pub address: String,
+    /// Client ID (rejected connections never get one)
+    pub client_id: Option<u64>,
+    /// How long the client was connected (disconnects only)
+    pub duration_seconds: Option<f64>,
+    /// Why the connection ended or was refused
+    pub cause: Option<String>,
+}
+
+/// Bounded in-memory log of GCS connects/disconnects (oldest entries are dropped first)
+pub struct ConnectionLog {
+    capacity: usize,
+    events: Mutex<VecDeque<ConnectionEvent>>,
+}
+
+impl ConnectionLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Record a client that registered
+    pub fn connected(&self, address: SocketAddr, client_id: u64) {
+        self.push(ConnectionEventKind::Connect, address, Some(client_id), None, None);
+    }
+
+    /// Record a registered client that went away
+    pub fn disconnected(&self, address: SocketAddr, client_id: u64, duration: Duration, cause: &str) {
+        self.push(
+            ConnectionEventKind::Disconnect,
+            address,
+            Some(client_id),
+            Some(duration.as_secs_f64()),
+            Some(cause),
+        );
+    }
+
+    /// Record a connection refused before it was registered
+    pub fn rejected(&self, address: SocketAddr, cause: &str) {
+        self.push(ConnectionEventKind::Rejected, address, None, None, Some(cause));
+    }
+
+    /// Copy of the log, oldest first
+    pub fn snapshot(&self) -> Vec<ConnectionEvent> {
+        self.events.lock().unwrap().iter().cloned().collect()
+    }
+
+    fn push(
+        &self,
+        event: ConnectionEventKind,
+        address: SocketAddr,
+        client_id: Option<u64>,
+        duration_seconds: Option<f64>,
+        cause: Option<&str>,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut events = self.events.lock().unwrap();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(ConnectionEvent {
+            time: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            event,
+            address: address.to_string(),
+            client_id,
+            duration_seconds,
+            cause: cause.map(str::to_string),
+        });
+    }
+}
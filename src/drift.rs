@@ -0,0 +1,95 @@
+use crate::config::{content_hash, ConfigFile};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// A config file that no longer matches what was loaded
+#[derive(Debug, Clone, Serialize)]
+pub struct DriftedFile {
+    pub path: String,
+    /// "changed", "removed" or the read error
+    pub reason: String,
+}
+
+/// Compares the files the config was loaded from with what's on disk (`config_check_seconds`)
+/// Drift is only reported (logged and shown by the admin API); the running config stays
+/// as loaded until a restart.
+pub struct ConfigDrift {
+    files: Vec<ConfigFile>,
+    /// Drifted files by path, with the hash seen on disk (None if unreadable)
+    drifted: Mutex<BTreeMap<String, (Option<String>, DriftedFile)>>,
+}
+
+impl ConfigDrift {
+    pub fn new(files: Vec<ConfigFile>) -> Self {
+        Self {
+            files,
+            drifted: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Paths of the watched files
+    pub fn files(&self) -> Vec<String> {
+        self.files.iter().map(|file| file.path.display().to_string()).collect()
+    }
+
+    /// Files that currently differ from the loaded config, by path
+    pub fn drifted(&self) -> Vec<DriftedFile> {
+        self.drifted.lock().unwrap().values().map(|(_, file)| file.clone()).collect()
+    }
+
+    /// Hash every watched file; warns when a file starts to differ (or differs again after
+    /// another edit) and logs when it matches the loaded contents again
+    pub async fn check(&self) {
+        for file in &self.files {
+            let path = file.path.display().to_string();
+            let (seen, reason) = match tokio::fs::read(&file.path).await {
+                Ok(contents) => {
+                    let hash = content_hash(&contents);
+                    let reason = (hash != file.sha256).then(|| "changed".to_string());
+                    (Some(hash), reason)
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (None, Some("removed".to_string())),
+                Err(e) => (None, Some(e.to_string())),
+            };
+
+            let mut drifted = self.drifted.lock().unwrap();
+            match reason {
+                Some(reason) => {
+                    if drifted.get(&path).is_some_and(|(previous, _)| *previous == seen) {
+                        continue;
+                    }
+                    warn!(
+                        "Config file {} no longer matches the loaded config ({}); restart to apply it",
+                        path, reason
+                    );
+                    let entry = DriftedFile {
+                        path: path.clone(),
+                        reason,
+                    };
+                    drifted.insert(path, (seen, entry));
+                }
+                None => {
+                    if drifted.remove(&path).is_some() {
+                        info!("Config file {} matches the loaded config again", path);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Check the files every `interval` (never returns)
+    pub async fn watch(&self, interval: Duration) {
+        info!("Checking {} config file(s) for changes every {}s", self.files.len(), interval.as_secs());
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately; the files were just loaded
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            self.check().await;
+        }
+    }
+}
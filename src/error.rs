@@ -0,0 +1,55 @@
+use thiserror::Error;
+
+/// Errors returned by the core modules (rule engine, modifiers, plugins, proxy)
+/// The binary wraps these in `anyhow`; library users can match on the variant.
+#[derive(Debug, Error)]
+pub enum BitchError {
+    /// Invalid configuration, or a script/certificate file that can't be used
+    #[error("{0}")]
+    Config(String),
+
+    /// Socket-level failure (connect, bind, read, write)
+    #[error("{context}: {source}")]
+    Network {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// Lua script failed to compile, run, or exchange values
+    #[error("{context}: {source}")]
+    Lua {
+        context: String,
+        #[source]
+        source: mlua::Error,
+    },
+
+    /// MAVLink frame could not be parsed, built, or serialized
+    #[error("{0}")]
+    Mavlink(String),
+
+    /// External plugin process failed to start, stopped answering, or replied with an error
+    #[error("{0}")]
+    Process(String),
+}
+
+/// Result type for the core modules
+pub type Result<T> = std::result::Result<T, BitchError>;
+
+impl BitchError {
+    /// Network error with context
+    pub fn network(context: impl Into<String>, source: std::io::Error) -> Self {
+        BitchError::Network {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Lua error with context
+    pub fn lua(context: impl Into<String>, source: mlua::Error) -> Self {
+        BitchError::Lua {
+            context: context.into(),
+            source,
+        }
+    }
+}
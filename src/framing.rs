@@ -0,0 +1,275 @@
+use anyhow::{Context, Result};
+use mavlink::ardupilotmega::MavMessage;
+use mavlink::{MavHeader, MavlinkVersion, Message};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tracing::debug;
+
+/// MAVLink v2 magic byte
+pub(crate) const MAVLINK_V2_MAGIC: u8 = 0xFD;
+/// MAVLink 1 magic byte
+pub(crate) const MAVLINK_V1_MAGIC: u8 = 0xFE;
+/// Header length including the magic byte
+pub(crate) const HEADER_LEN: usize = 10;
+/// Checksum length
+pub(crate) const CHECKSUM_LEN: usize = 2;
+/// Signature length (present when incompat flag 0x01 is set)
+pub(crate) const SIGNATURE_LEN: usize = 13;
+/// Incompatibility flag: packet is signed
+pub(crate) const MAVLINK_IFLAG_SIGNED: u8 = 0x01;
+/// Incompatibility flags this proxy understands (any other bit makes receivers reject the frame)
+pub(crate) const KNOWN_INCOMPAT_FLAGS: u8 = MAVLINK_IFLAG_SIGNED;
+/// Compatibility flags this proxy understands (none are defined yet)
+pub(crate) const KNOWN_COMPAT_FLAGS: u8 = 0;
+
+/// The incompatibility/compatibility flag bytes of a v2 frame header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameFlags {
+    pub incompat: u8,
+    pub compat: u8,
+}
+
+impl FrameFlags {
+    /// Flags of a raw frame (both zero for MAVLink 1 or truncated frames)
+    pub fn of(frame: &[u8]) -> Self {
+        match frame {
+            [MAVLINK_V2_MAGIC, _, incompat, compat, ..] => Self {
+                incompat: *incompat,
+                compat: *compat,
+            },
+            _ => Self::default(),
+        }
+    }
+}
+
+/// Message ID of a raw v2 frame (None for MAVLink 1 or truncated frames)
+pub(crate) fn message_id(frame: &[u8]) -> Option<u32> {
+    match frame {
+        [MAVLINK_V2_MAGIC, _, _, _, _, _, _, low, mid, high, ..] => Some(u32::from_le_bytes([*low, *mid, *high, 0])),
+        _ => None,
+    }
+}
+
+/// Header fields read straight from a frame's bytes, for frames the dialect can't decode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawHeader {
+    pub system_id: u8,
+    pub component_id: u8,
+    pub sequence: u8,
+    pub message_id: u32,
+}
+
+impl RawHeader {
+    /// Header of a raw v2 or v1 frame (None for frames too short to hold one)
+    pub fn of(frame: &[u8]) -> Option<Self> {
+        match frame {
+            [MAVLINK_V2_MAGIC, _, _, _, sequence, system_id, component_id, low, mid, high, ..] => Some(Self {
+                system_id: *system_id,
+                component_id: *component_id,
+                sequence: *sequence,
+                message_id: u32::from_le_bytes([*low, *mid, *high, 0]),
+            }),
+            [MAVLINK_V1_MAGIC, _, sequence, system_id, component_id, message_id, ..] => Some(Self {
+                system_id: *system_id,
+                component_id: *component_id,
+                sequence: *sequence,
+                message_id: u32::from(*message_id),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The fields as a parsed-message header
+    pub fn mav_header(&self) -> MavHeader {
+        MavHeader {
+            system_id: self.system_id,
+            component_id: self.component_id,
+            sequence: self.sequence,
+        }
+    }
+}
+
+/// Checksum settings shared by every frame reader
+#[derive(Debug, Clone, Default)]
+pub struct CrcCheck {
+    /// Verify frame checksums (frames failing the check are treated as false magic)
+    pub enabled: bool,
+    /// CRC_EXTRA per message ID, taking precedence over the bundled dialect
+    pub extra_overrides: Arc<HashMap<u32, u8>>,
+}
+
+impl CrcCheck {
+    pub fn new(enabled: bool, extra_overrides: HashMap<u32, u8>) -> Self {
+        Self {
+            enabled,
+            extra_overrides: Arc::new(extra_overrides),
+        }
+    }
+
+    /// CRC_EXTRA for a message ID: override first, then the bundled dialect
+    pub(crate) fn crc_extra(&self, msg_id: u32) -> Option<u8> {
+        self.extra_overrides.get(&msg_id).copied().or_else(|| {
+            MavMessage::default_message_from_id(msg_id)
+                .ok()
+                .map(|_| MavMessage::extra_crc(msg_id))
+        })
+    }
+}
+
+/// Buffered MAVLink v2 frame reader
+/// Bytes are kept in an internal buffer until a full frame is validated, so a
+/// false magic byte (noise, or 0xFD inside a payload we joined mid-stream) can
+/// be rewound: scanning resumes at the byte after it instead of discarding the
+/// whole bogus frame length.
+pub struct FrameReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    crc: CrcCheck,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    pub fn new(reader: R, crc: CrcCheck) -> Self {
+        Self {
+            reader,
+            buf: Vec::with_capacity(512),
+            crc,
+        }
+    }
+
+    /// Read the next complete MAVLink frame
+    pub async fn read_frame(&mut self) -> Result<Vec<u8>> {
+        loop {
+            // Discard everything before the next magic byte
+            match self.buf.iter().position(|b| *b == MAVLINK_V2_MAGIC) {
+                Some(start) => {
+                    self.buf.drain(..start);
+                }
+                None => {
+                    self.buf.clear();
+                    self.fill().await.context("Failed to read magic byte")?;
+                    continue;
+                }
+            }
+
+            // Header gives us the payload length and whether a signature follows
+            self.fill_to(HEADER_LEN).await.context("Failed to read header")?;
+            let frame_len = frame_len(&self.buf[..HEADER_LEN]);
+
+            self.fill_to(frame_len).await.context("Failed to read packet")?;
+
+            if self.crc.enabled && !checksum_ok(&self.buf[..frame_len], &self.crc) {
+                // False magic: rewind to just after it and keep scanning
+                debug!("Dropping 0xFD with bad checksum, resyncing");
+                self.buf.drain(..1);
+                continue;
+            }
+
+            return Ok(self.buf.drain(..frame_len).collect());
+        }
+    }
+
+    /// Read more bytes from the underlying reader into the buffer
+    async fn fill(&mut self) -> Result<()> {
+        let mut chunk = [0u8; 512];
+        let n = self.reader.read(&mut chunk).await?;
+        if n == 0 {
+            anyhow::bail!("Connection closed");
+        }
+        self.buf.extend_from_slice(&chunk[..n]);
+        Ok(())
+    }
+
+    /// Ensure at least `len` bytes are buffered
+    async fn fill_to(&mut self, len: usize) -> Result<()> {
+        while self.buf.len() < len {
+            self.fill().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Total frame length (header + payload + checksum + optional signature) from a v2 header
+fn frame_len(header: &[u8]) -> usize {
+    let payload_len = header[1] as usize;
+    let signature_len = if header[2] & MAVLINK_IFLAG_SIGNED != 0 {
+        SIGNATURE_LEN
+    } else {
+        0
+    };
+    HEADER_LEN + payload_len + CHECKSUM_LEN + signature_len
+}
+
+/// Validate the X.25 checksum of a complete v2 frame
+/// Frames with message IDs unknown to both the dialect and `crc.extra_overrides`
+/// can't be checked and are accepted.
+pub fn checksum_ok(frame: &[u8], crc: &CrcCheck) -> bool {
+    if frame.len() < HEADER_LEN + CHECKSUM_LEN {
+        return false;
+    }
+
+    let payload_len = frame[1] as usize;
+    let crc_end = HEADER_LEN + payload_len;
+    if frame.len() < crc_end + CHECKSUM_LEN {
+        return false;
+    }
+
+    let msg_id = u32::from_le_bytes([frame[7], frame[8], frame[9], 0]);
+    let Some(crc_extra) = crc.crc_extra(msg_id) else {
+        return true;
+    };
+
+    // Checksum covers everything after the magic byte, then the message's CRC_EXTRA
+    let mut computed = crc16_x25(&frame[1..crc_end], 0xFFFF);
+    computed = crc16_x25(&[crc_extra], computed);
+
+    let received = u16::from_le_bytes([frame[crc_end], frame[crc_end + 1]]);
+    computed == received
+}
+
+/// Serialize a message as an unsigned v2 frame
+/// MAVLink 2 drops trailing zero bytes from the payload (typically unset extension fields)
+/// and receivers zero-fill them, so nothing is lost. With `full_extensions` the untruncated
+/// payload is sent instead, for peers that mishandle short payloads.
+pub fn encode_v2(header: MavHeader, msg: &MavMessage, full_extensions: bool) -> Result<Vec<u8>> {
+    if !full_extensions {
+        let mut buf = Vec::new();
+        mavlink::write_versioned_msg(&mut buf, MavlinkVersion::V2, header, msg)
+            .context("Failed to serialize message")?;
+        return Ok(buf);
+    }
+
+    // Every field is written to the buffer; only the returned v2 length is truncated.
+    // The v1 length is the full encoded length including extension fields.
+    let mut payload = [0u8; 255];
+    let truncated_len = msg.ser(MavlinkVersion::V2, &mut payload);
+    let payload_len = msg.ser(MavlinkVersion::V1, &mut [0u8; 255]).max(truncated_len);
+    let msg_id = msg.message_id();
+
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload_len + CHECKSUM_LEN);
+    frame.push(MAVLINK_V2_MAGIC);
+    frame.push(payload_len as u8);
+    frame.push(0); // incompat flags
+    frame.push(0); // compat flags
+    frame.push(header.sequence);
+    frame.push(header.system_id);
+    frame.push(header.component_id);
+    frame.extend_from_slice(&msg_id.to_le_bytes()[..3]);
+    frame.extend_from_slice(&payload[..payload_len]);
+
+    let mut crc = crc16_x25(&frame[1..], 0xFFFF);
+    crc = crc16_x25(&[MavMessage::extra_crc(msg_id)], crc);
+    frame.extend_from_slice(&crc.to_le_bytes());
+
+    Ok(frame)
+}
+
+/// CRC-16/MCRF4XX as used by MAVLink
+pub(crate) fn crc16_x25(data: &[u8], mut crc: u16) -> u16 {
+    for byte in data {
+        let mut tmp = *byte ^ (crc & 0xFF) as u8;
+        tmp ^= tmp << 4;
+        crc = (crc >> 8) ^ ((tmp as u16) << 8) ^ ((tmp as u16) << 3) ^ ((tmp as u16) >> 4);
+    }
+    crc
+}
@@ -0,0 +1,46 @@
+//! BITCH - Basic Intercept & Transform Command Handler
+//!
+//! A MAVLink proxy that intercepts and transforms messages between Ground Control
+//! Stations and drones via mavlink-router. The `bitch` binary is a thin wrapper
+//! around this library; embedders can run the full [`ProxyServer`] or drive the
+//! [`RuleEngine`] directly on parsed messages.
+
+mod actions;
+mod admin;
+mod batch;
+mod capture;
+mod coalesce;
+mod command_compat;
+pub mod config;
+mod connections;
+mod drift;
+pub mod error;
+mod framing;
+pub mod messages;
+mod modifiers;
+mod pacing;
+mod plugins;
+mod proxy;
+mod quarantine;
+mod retry;
+mod rule_state;
+mod rules;
+mod signing;
+mod stats;
+mod subscriptions;
+mod tls;
+mod transport;
+
+pub use config::{CommandRule, Config, Direction};
+pub use drift::{ConfigDrift, DriftedFile};
+pub use error::{BitchError, Result};
+pub use framing::{CrcCheck, FrameFlags, FrameReader, RawHeader};
+pub use modifiers::ModifierManager;
+pub use plugins::{PluginContext, PluginManager, RouteContext, RouteVerdict};
+pub use proxy::ProxyServer;
+pub use rule_state::RuleStateManager;
+pub use rules::{
+    get_message_name, parse_mavlink_frame, parse_mavlink_message, AckInfo, Action, Ingress, ParsedFrame, ProcessResult,
+    RuleEngine,
+};
+pub use tokio_util::sync::CancellationToken;
@@ -1,41 +1,269 @@
-mod batch;
-mod config;
-mod modifiers;
-mod plugins;
-mod proxy;
-mod rule_state;
-mod rules;
-
-use anyhow::Result;
-use std::path::PathBuf;
+use anyhow::{Context, Result};
+use bitch::{
+    get_message_name, parse_mavlink_frame, parse_mavlink_message, Action, CancellationToken, Config, Direction,
+    ModifierManager, PluginManager, ProxyServer,
+};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tracing::{info, warn};
 use tracing_subscriber::filter::LevelFilter;
 
-use crate::config::Config;
-use crate::modifiers::ModifierManager;
-use crate::plugins::PluginManager;
-use crate::proxy::ProxyServer;
+/// Environment variable that may hold the full config TOML
+const CONFIG_ENV_VAR: &str = "BITCH_CONFIG";
+
+/// Default config file path
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Command-line usage
+const USAGE: &str = "Usage: bitch [--config <path>|-] [--overlay <path>] [--strict] [test --frame <hex> | replay-dir <path>] \
+                     [--direction gcs_to_router|router_to_gcs] | [--config <path>] --dump-config[=toml|json] | [--config <path>] init [--force] | list-messages";
+
+/// Where to read the configuration from
+enum ConfigSource {
+    File(String),
+    Stdin,
+    Env,
+    /// No config file: built-in defaults with `BITCH_*` settings from the environment
+    Defaults,
+}
+
+/// What to do once the config is loaded
+enum Command {
+    /// Run the proxy
+    Run,
+    /// Run one hex-encoded frame through the rules and print the outcome (no networking)
+    Test { frame: String, direction: Direction },
+    /// Run every frame file in a directory through the rules, in filename order
+    ReplayDir { dir: PathBuf, direction: Direction },
+    /// Write the starter config to the config path (no config is loaded)
+    Init { force: bool },
+    /// Print every message type in the bundled dialect (no config is loaded)
+    ListMessages,
+    /// Print the effective config after loading and validation, then exit
+    DumpConfig { json: bool },
+}
+
+/// Parsed command line
+struct Cli {
+    config: ConfigSource,
+    /// Overlay file deep-merged onto a config file
+    overlay: Option<String>,
+    /// Reject deprecated config settings instead of warning about them
+    strict: bool,
+    command: Command,
+}
+
+/// Parse the command line. The config source is `--config <path>` / `--config -` (stdin),
+/// then a non-empty $BITCH_CONFIG, then ./config.toml, then built-in defaults if it doesn't exist
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Cli> {
+    let mut config_arg = None;
+    let mut overlay = None;
+    let mut strict = false;
+    let mut test = false;
+    let mut replay_dir = false;
+    let mut init = false;
+    let mut force = false;
+    let mut list_messages = false;
+    let mut dump_config = None;
+    let mut dir = None;
+    let mut frame = None;
+    let mut direction = Direction::GcsToRouter;
+
+    while let Some(arg) = args.next() {
+        if let Some(value) = flag_value(&arg, &["--config", "-c"], &mut args)? {
+            config_arg = Some(value);
+        } else if let Some(value) = flag_value(&arg, &["--overlay"], &mut args)? {
+            overlay = Some(value);
+        } else if arg == "--strict" {
+            strict = true;
+        } else if arg == "--dump-config" {
+            dump_config = Some(false);
+        } else if let Some(format) = arg.strip_prefix("--dump-config=") {
+            dump_config = Some(match format {
+                "toml" => false,
+                "json" => true,
+                other => anyhow::bail!("--dump-config format must be toml or json, got '{}'", other),
+            });
+        } else if arg == "test" && !test && !replay_dir && !init && !list_messages {
+            test = true;
+        } else if arg == "init" && !test && !replay_dir && !init && !list_messages {
+            init = true;
+        } else if arg == "--force" && init {
+            force = true;
+        } else if arg == "list-messages" && !test && !replay_dir && !init && !list_messages {
+            list_messages = true;
+        } else if arg == "replay-dir" && !test && !replay_dir && !init && !list_messages {
+            replay_dir = true;
+            dir = Some(PathBuf::from(
+                args.next()
+                    .ok_or_else(|| anyhow::anyhow!("replay-dir requires a directory. {}", USAGE))?,
+            ));
+        } else if let Some(value) = flag_value(&arg, &["--frame"], &mut args)?.filter(|_| test) {
+            frame = Some(value);
+        } else if let Some(value) = flag_value(&arg, &["--direction"], &mut args)?.filter(|_| test || replay_dir) {
+            direction = value.parse()?;
+            if direction == Direction::Both {
+                anyhow::bail!("--direction must be gcs_to_router or router_to_gcs");
+            }
+        } else {
+            anyhow::bail!("Unknown argument '{}'. {}", arg, USAGE);
+        }
+    }
+
+    let config = match config_arg.as_deref() {
+        Some("-") => ConfigSource::Stdin,
+        Some(path) => ConfigSource::File(path.to_string()),
+        None if std::env::var(CONFIG_ENV_VAR).is_ok_and(|v| !v.trim().is_empty()) => ConfigSource::Env,
+        None if !Path::new(DEFAULT_CONFIG_PATH).exists() => ConfigSource::Defaults,
+        None => ConfigSource::File(DEFAULT_CONFIG_PATH.to_string()),
+    };
+    if overlay.is_some() && matches!(config, ConfigSource::Defaults) {
+        anyhow::bail!("--overlay requires a base config file (./{} not found)", DEFAULT_CONFIG_PATH);
+    }
+    if overlay.is_some() && !matches!(config, ConfigSource::File(_)) {
+        anyhow::bail!("--overlay requires the base config to be a file");
+    }
+
+    if dump_config.is_some() && (test || replay_dir || init || list_messages) {
+        anyhow::bail!("--dump-config can't be combined with a command. {}", USAGE);
+    }
+
+    let command = if let Some(json) = dump_config {
+        Command::DumpConfig { json }
+    } else if test {
+        let frame = frame.ok_or_else(|| anyhow::anyhow!("test requires --frame <hex>. {}", USAGE))?;
+        Command::Test { frame, direction }
+    } else if let Some(dir) = dir {
+        Command::ReplayDir { dir, direction }
+    } else if init {
+        if !matches!(config, ConfigSource::File(_) | ConfigSource::Defaults) {
+            anyhow::bail!("init writes to a file; use --config <path> or omit it for ./{}", DEFAULT_CONFIG_PATH);
+        }
+        Command::Init { force }
+    } else if list_messages {
+        Command::ListMessages
+    } else {
+        Command::Run
+    };
+
+    Ok(Cli { config, overlay, strict, command })
+}
+
+/// Value of `arg` if it is one of the given flags (`--flag <value>` or `--flag=<value>`)
+fn flag_value(arg: &str, names: &[&str], args: &mut impl Iterator<Item = String>) -> Result<Option<String>> {
+    if names.contains(&arg) {
+        return args
+            .next()
+            .map(Some)
+            .ok_or_else(|| anyhow::anyhow!("{} requires a value", arg));
+    }
+
+    Ok(names
+        .iter()
+        .find_map(|name| arg.strip_prefix(name)?.strip_prefix('='))
+        .map(str::to_string))
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = parse_args(std::env::args().skip(1))?;
+
+    // These don't need (or, for init, can't have) a loaded config
+    match (&cli.command, &cli.config) {
+        (Command::Init { force }, ConfigSource::File(path)) => return write_starter_config(Path::new(path), *force),
+        (Command::Init { force }, ConfigSource::Defaults) => {
+            return write_starter_config(Path::new(DEFAULT_CONFIG_PATH), *force)
+        }
+        (Command::ListMessages, _) => {
+            list_messages();
+            return Ok(());
+        }
+        _ => {}
+    }
+
     // Load and validate configuration
-    let config = Config::load("config.toml")?;
+    let using_defaults = matches!(cli.config, ConfigSource::Defaults);
+    let config = match cli.config {
+        ConfigSource::File(path) => match &cli.overlay {
+            Some(overlay) => Config::load_with_overlay(&path, overlay)?,
+            None => Config::load(&path)?,
+        },
+        ConfigSource::Stdin => Config::load_from_stdin()?,
+        ConfigSource::Env => Config::load_from_env(CONFIG_ENV_VAR)?,
+        ConfigSource::Defaults => Config::load_defaults(Config::env_overlay(|name| std::env::var(name).ok())?)?,
+    };
     config.validate()?;
+    if cli.strict && !config.deprecations.is_empty() {
+        anyhow::bail!("Deprecated config settings (--strict): {}", config.deprecations.join("; "));
+    }
+    if let Command::DumpConfig { json } = cli.command {
+        let dump = if json { config.to_json()? } else { config.to_toml()? };
+        println!("{}", dump.trim_end());
+        return Ok(());
+    }
 
     // Initialize logging
     init_logging(&config.logging.level);
+    for deprecation in &config.deprecations {
+        warn!("{}", deprecation);
+    }
+    if using_defaults {
+        info!(
+            "No ./{} found, running with built-in defaults: GCS {}:{} -> router {}, no rules",
+            DEFAULT_CONFIG_PATH,
+            config.network.gcs_listen_address,
+            config.network.gcs_listen_port,
+            config.network.router_endpoints().join(", ")
+        );
+    }
+
+    let (plugin_manager, modifier_manager) = load_scripts(&config)?;
 
+    // Create the proxy server (no sockets are opened until run)
+    let server = ProxyServer::new(config, plugin_manager, modifier_manager)?;
+
+    match cli.command {
+        Command::Test { frame, direction } => run_test(&server, &frame, direction),
+        Command::ReplayDir { dir, direction } => run_replay_dir(&server, &dir, direction),
+        Command::Init { .. } | Command::ListMessages => unreachable!("handled before loading the config"),
+        Command::DumpConfig { .. } => unreachable!("handled after loading the config"),
+        Command::Run => {
+            // Cancel the shutdown token on Ctrl+C / SIGTERM
+            let shutdown = CancellationToken::new();
+            tokio::spawn(wait_for_shutdown_signal(shutdown.clone()));
+
+            server.run(shutdown).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Create the plugin and modifier managers and load every configured script
+fn load_scripts(config: &Config) -> Result<(PluginManager, ModifierManager)> {
     // Initialize plugin manager
     let mut plugin_manager = PluginManager::new()?;
+    plugin_manager.set_warn_on_load_error(config.plugins.warn_on_load_error);
+    plugin_manager.set_timeout(Duration::from_millis(config.plugins.timeout_ms));
+    plugin_manager.set_module_dir(Path::new(&config.plugins.directory));
+    if let Some(dir) = &config.plugins.outbox_dir {
+        plugin_manager.enable_outbox(Path::new(dir))?;
+    }
 
     // Load plugins
     for (name, filename) in &config.plugins.load {
         let path = PathBuf::from(&config.plugins.directory).join(filename);
-        match plugin_manager.load_plugin(name, &path) {
+        match plugin_manager.load_plugin(name, &path, config.plugins.config.get(name)) {
             Ok(_) => info!("Loaded plugin: {}", name),
             Err(e) => warn!("Failed to load plugin '{}': {}", name, e),
         }
     }
+    for (name, process) in &config.plugins.process {
+        let dir = Path::new(&config.plugins.directory);
+        match plugin_manager.load_process(name, &process.command, &process.args, dir, config.plugins.config.get(name)) {
+            Ok(_) => info!("Started plugin process: {}", name),
+            Err(e) => warn!("Failed to start plugin '{}': {}", name, e),
+        }
+    }
 
     // Initialize modifier manager
     let mut modifier_manager = ModifierManager::new()?;
@@ -43,15 +271,224 @@ async fn main() -> Result<()> {
     // Load modifiers
     for (name, filename) in &config.modifiers.load {
         let path = PathBuf::from(&config.modifiers.directory).join(filename);
-        match modifier_manager.load_modifier(name, &path) {
+        let loaded = if config.modifiers.stateful.contains(name) {
+            modifier_manager.load_stateful_modifier(name, &path)
+        } else {
+            modifier_manager.load_modifier(name, &path)
+        };
+        match loaded {
             Ok(_) => info!("Loaded modifier: {}", name),
             Err(e) => warn!("Failed to load modifier '{}': {}", name, e),
         }
     }
 
-    // Create and run the proxy server
-    let server = ProxyServer::new(config, plugin_manager, modifier_manager)?;
-    server.run().await
+    // Inline scripts live in the config itself, so a broken one is a config error
+    for rule in &config.rules {
+        let name = rule.inline_script_name();
+        if let Some(code) = &rule.modifier_script {
+            modifier_manager.load_inline(&name, code)?;
+        }
+        if let Some(code) = &rule.plugin_script {
+            plugin_manager.load_inline(&name, code)?;
+        }
+    }
+
+    Ok((plugin_manager, modifier_manager))
+}
+
+/// Write the commented starter config, refusing to replace an existing file unless `force`
+fn write_starter_config(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        anyhow::bail!("{} already exists (use init --force to overwrite it)", path.display());
+    }
+    std::fs::write(path, bitch::config::STARTER_CONFIG)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Wrote starter config to {}", path.display());
+    println!("Edit the [network] section, then enable or replace the sample rules");
+    Ok(())
+}
+
+/// Print every message type name usable as `message_type`, with its ID
+fn list_messages() {
+    let names = bitch::messages::message_names();
+    for (id, name) in &names {
+        println!("{:>6}  {}", id, name);
+    }
+    println!("{} message types", names.len());
+}
+
+/// Run one frame through the rule engine and print what the proxy would do with it
+fn run_test(server: &ProxyServer, frame_hex: &str, direction: Direction) -> Result<()> {
+    let frame = decode_hex(frame_hex)?;
+    let result = print_outcome(server, &frame, direction);
+    server.rule_engine().shutdown_plugins();
+    result
+}
+
+/// Run every file in `dir` (one raw frame each) through the rule engine in filename order
+/// Rule state (triggers, events, first_seen) carries over from one frame to the next
+fn run_replay_dir(server: &ProxyServer, dir: &Path, direction: Direction) -> Result<()> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).context(format!("Failed to read directory {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    let mut failed = 0;
+    for path in &files {
+        println!("== {}", path.file_name().unwrap_or_default().to_string_lossy());
+        let outcome = std::fs::read(path)
+            .context("Failed to read frame file")
+            .and_then(|frame| print_outcome(server, &frame, direction));
+        if let Err(e) = outcome {
+            println!("Error:     {:#}", e);
+            failed += 1;
+        }
+        println!();
+    }
+
+    server.rule_engine().shutdown_plugins();
+    println!("Replayed {} frame file(s) from {}", files.len(), dir.display());
+    if failed > 0 {
+        anyhow::bail!("{} of {} frame file(s) could not be processed", failed, files.len());
+    }
+    Ok(())
+}
+
+/// Print the parsed frame, the matching rule and the resulting actions
+fn print_outcome(server: &ProxyServer, frame: &[u8], direction: Direction) -> Result<()> {
+    let parsed = parse_mavlink_frame(frame)?;
+    let (header, msg) = (&parsed.header, &parsed.message);
+
+    println!(
+        "Message:   {} (sysid={}, compid={}, seq={})",
+        get_message_name(msg),
+        header.system_id,
+        header.component_id,
+        header.sequence
+    );
+    println!("Fields:    {}", serde_json::to_string(msg)?);
+    println!("Flags:     incompat=0x{:02x} compat=0x{:02x}", parsed.flags.incompat, parsed.flags.compat);
+    println!("Direction: {}", direction);
+
+    let result = server.rule_engine().process_frame(&parsed, direction);
+
+    match &result.matched_rule {
+        Some(rule) => println!("Rule:      {}", rule),
+        None => println!("Rule:      (no rule matched, forwarded by default)"),
+    }
+
+    let actions: Vec<String> = result.actions.iter().map(describe_action).collect();
+    println!("Actions:   {}", actions.join(" -> "));
+
+    for action in &result.actions {
+        if let Action::Modify {
+            modified_message: Some(modified),
+            ..
+        } = action
+        {
+            println!("Modified:  {}", serde_json::to_string(modified)?);
+        }
+    }
+
+    match &result.ack_info {
+        Some(ack_info) => {
+            let ack = ProxyServer::build_ack(ack_info)?;
+            let (ack_header, ack_msg) = parse_mavlink_message(&ack)?;
+            println!(
+                "ACK:       {} (sysid={}, compid={}) {}",
+                get_message_name(&ack_msg),
+                ack_header.system_id,
+                ack_header.component_id,
+                serde_json::to_string(&ack_msg)?
+            );
+        }
+        None => println!("ACK:       none"),
+    }
+
+    Ok(())
+}
+
+/// One-line description of an action for `bitch test`
+fn describe_action(action: &Action) -> String {
+    match action {
+        Action::Forward => "forward".to_string(),
+        Action::Delay(duration) => format!("delay {}s", duration.as_secs()),
+        Action::Block { rule, reason } => match reason {
+            Some(reason) => format!("block by '{}' ({})", rule, reason),
+            None => format!("block by '{}'", rule),
+        },
+        Action::Batch {
+            count, timeout, key, ..
+        } => format!("batch '{}' ({} systems, {}s timeout)", key, count, timeout.as_secs()),
+        Action::Burst {
+            count, timeout, key, ..
+        } => format!("burst '{}' ({} packets, {}s timeout)", key, count, timeout.as_secs()),
+        Action::Modify {
+            modifier,
+            modified_message,
+        } => match modified_message {
+            Some(_) => format!("modify via '{}'", modifier),
+            None => format!("modify via '{}' (no result)", modifier),
+        },
+        Action::Coalesce { interval, .. } => format!("coalesce (latest every {}ms)", interval.as_millis()),
+        Action::RetryUntilAck {
+            message_type,
+            timeout,
+            attempts,
+        } => format!(
+            "retry until {} (every {}s, up to {} re-sends)",
+            message_type,
+            timeout.as_secs(),
+            attempts
+        ),
+    }
+}
+
+/// Decode a hex string, ignoring whitespace, ':' separators and a leading "0x"
+fn decode_hex(input: &str) -> Result<Vec<u8>> {
+    let digits: String = input
+        .trim()
+        .trim_start_matches("0x")
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .collect();
+
+    if digits.is_empty() || digits.len() % 2 == 1 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("--frame must be an even number of hex digits");
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).context("Invalid hex byte"))
+        .collect()
+}
+
+async fn wait_for_shutdown_signal(shutdown: CancellationToken) {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to install SIGTERM handler: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => info!("Received Ctrl+C"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+
+    shutdown.cancel();
 }
 
 fn init_logging(level: &str) {
@@ -0,0 +1,164 @@
+use anyhow::{Context, Result};
+use mavlink::ardupilotmega::{MavCmd, MavFrame, MavMessage, MavSeverity, COMMAND_INT_DATA, COMMAND_LONG_DATA};
+use mavlink::Message;
+use num_traits::FromPrimitive;
+use serde_json::Value as JsonValue;
+
+/// Build a complete message from a type name and a partial set of fields
+/// Fields not given keep the dialect's default values. Convenience forms are accepted
+/// on top of the internally-tagged format:
+/// - enum fields as a variant name string (`"MAV_CMD_COMPONENT_ARM_DISARM"`)
+/// - `command`/`severity` as their numeric value (`400`)
+/// - bitflag fields as a raw integer (`base_mode = 128`)
+/// - char array fields as a string (`text = "hello"`, truncated/zero-padded)
+pub fn build_message(message_type: &str, fields: &serde_json::Map<String, JsonValue>) -> Result<MavMessage> {
+    let id = MavMessage::message_id_from_name(message_type)
+        .map_err(|e| anyhow::anyhow!("Unknown message type '{}': {}", message_type, e))?;
+    let default_msg = MavMessage::default_message_from_id(id)
+        .map_err(|e| anyhow::anyhow!("No default for message type '{}': {}", message_type, e))?;
+
+    let mut message_json = serde_json::to_value(&default_msg)
+        .context("Failed to serialize default message")?;
+
+    if let Some(obj) = message_json.as_object_mut() {
+        for (name, value) in fields {
+            if name == "type" {
+                continue;
+            }
+            let Some(default) = obj.get(name) else {
+                anyhow::bail!("Message type '{}' has no field '{}'", message_type, name);
+            };
+            let coerced = coerce_field(name, default, value);
+            obj.insert(name.clone(), coerced);
+        }
+    }
+
+    serde_json::from_value(message_json)
+        .with_context(|| format!("Invalid field values for {}", message_type))
+}
+
+/// A message type's fields with their dialect default (zero/empty) values, without the `type` tag
+pub fn default_fields(message_type: &str) -> Result<serde_json::Map<String, JsonValue>> {
+    let id = MavMessage::message_id_from_name(message_type)
+        .map_err(|e| anyhow::anyhow!("Unknown message type '{}': {}", message_type, e))?;
+    default_fields_for_id(id)
+        .with_context(|| format!("No default for message type '{}'", message_type))
+}
+
+/// Default field values of a message ID (see `default_fields`)
+pub fn default_fields_for_id(id: u32) -> Option<serde_json::Map<String, JsonValue>> {
+    let default_msg = MavMessage::default_message_from_id(id).ok()?;
+    match serde_json::to_value(&default_msg).ok()? {
+        JsonValue::Object(mut fields) => {
+            fields.remove("type");
+            Some(fields)
+        }
+        _ => None,
+    }
+}
+
+/// Convert a convenience field value into the internally-tagged shape of the default value
+fn coerce_field(name: &str, default: &JsonValue, value: &JsonValue) -> JsonValue {
+    match (default, value) {
+        // Enum given by variant name
+        (JsonValue::Object(d), JsonValue::String(variant)) if d.contains_key("type") => {
+            serde_json::json!({ "type": variant })
+        }
+        // Enum given by numeric value
+        (JsonValue::Object(d), JsonValue::Number(n)) if d.contains_key("type") => {
+            numeric_enum(name, n).unwrap_or_else(|| value.clone())
+        }
+        // Bitflags given as a raw integer
+        (JsonValue::Object(d), JsonValue::Number(_)) if d.contains_key("bits") => {
+            serde_json::json!({ "bits": value })
+        }
+        // Fixed-size char array given as a string
+        (JsonValue::Array(d), JsonValue::String(text)) => {
+            let mut bytes: Vec<JsonValue> = text.bytes().take(d.len()).map(JsonValue::from).collect();
+            bytes.resize(d.len(), JsonValue::from(0u8));
+            JsonValue::Array(bytes)
+        }
+        _ => value.clone(),
+    }
+}
+
+/// Resolve a numeric enum value for the fields where that's unambiguous
+fn numeric_enum(field: &str, n: &serde_json::Number) -> Option<JsonValue> {
+    let n = u32::try_from(n.as_u64()?).ok()?;
+    match field {
+        "command" => serde_json::to_value(MavCmd::from_u32(n)?).ok(),
+        "severity" => serde_json::to_value(MavSeverity::from_u32(n)?).ok(),
+        _ => None,
+    }
+}
+
+/// Commands whose param5/6/7 are latitude/longitude/altitude and can be sent as COMMAND_INT
+pub const UPGRADABLE_COMMANDS: &[MavCmd] = &[
+    MavCmd::MAV_CMD_NAV_WAYPOINT,
+    MavCmd::MAV_CMD_NAV_LOITER_UNLIM,
+    MavCmd::MAV_CMD_NAV_LOITER_TURNS,
+    MavCmd::MAV_CMD_NAV_LOITER_TIME,
+    MavCmd::MAV_CMD_NAV_LAND,
+    MavCmd::MAV_CMD_NAV_TAKEOFF,
+    MavCmd::MAV_CMD_NAV_LOITER_TO_ALT,
+    MavCmd::MAV_CMD_NAV_VTOL_TAKEOFF,
+    MavCmd::MAV_CMD_NAV_VTOL_LAND,
+    MavCmd::MAV_CMD_DO_SET_HOME,
+    MavCmd::MAV_CMD_DO_LAND_START,
+    MavCmd::MAV_CMD_DO_REPOSITION,
+    MavCmd::MAV_CMD_DO_SET_ROI_LOCATION,
+];
+
+/// Every message in the bundled dialect as (message ID, name), ordered by ID
+pub fn message_names() -> Vec<(u32, &'static str)> {
+    (0..=crate::config::MAX_MESSAGE_ID)
+        .filter_map(|id| MavMessage::default_message_from_id(id).ok())
+        .map(|msg| (msg.message_id(), msg.message_name()))
+        .collect()
+}
+
+/// Name of a message ID in the bundled dialect
+pub fn message_name(id: u32) -> Option<&'static str> {
+    MavMessage::default_message_from_id(id).ok().map(|msg| msg.message_name())
+}
+
+/// Resolve a MAV_FRAME_* name to a frame
+pub fn parse_frame(name: &str) -> Result<MavFrame> {
+    serde_json::from_value(serde_json::json!({ "type": name }))
+        .with_context(|| format!("Unknown MAVLink frame '{}'", name))
+}
+
+/// Convert a COMMAND_LONG into the equivalent COMMAND_INT
+/// param1-4 are copied, param5/6 (degrees) become x/y (degE7), param7 becomes z.
+/// NaN lat/lon map to INT32_MAX ("unused"). Returns None for commands without
+/// positional parameters (see `UPGRADABLE_COMMANDS`).
+pub fn command_long_to_int(data: &COMMAND_LONG_DATA, frame: MavFrame) -> Option<MavMessage> {
+    if !UPGRADABLE_COMMANDS.contains(&data.command) {
+        return None;
+    }
+
+    Some(MavMessage::COMMAND_INT(COMMAND_INT_DATA {
+        param1: data.param1,
+        param2: data.param2,
+        param3: data.param3,
+        param4: data.param4,
+        x: degrees_to_e7(data.param5),
+        y: degrees_to_e7(data.param6),
+        z: data.param7,
+        command: data.command,
+        target_system: data.target_system,
+        target_component: data.target_component,
+        frame,
+        current: 0,
+        autocontinue: 0,
+    }))
+}
+
+/// Scale degrees to degE7, with NaN (unused) as INT32_MAX
+fn degrees_to_e7(degrees: f32) -> i32 {
+    if degrees.is_nan() {
+        i32::MAX
+    } else {
+        (degrees as f64 * 1e7).round() as i32
+    }
+}
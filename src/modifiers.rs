@@ -1,7 +1,7 @@
-use anyhow::{Context, Result};
+use crate::error::{BitchError, Result};
 use mavlink::ardupilotmega::MavMessage;
 use mavlink::MavHeader;
-use mlua::{Lua, LuaSerdeExt, Value};
+use mlua::{Lua, LuaSerdeExt, RegistryKey, Table, Value};
 use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
@@ -12,6 +12,8 @@ use serde_json::Value as JsonValue;
 pub struct ModifierManager {
     lua: Arc<Lua>,
     modifiers: HashMap<String, String>, // name -> lua code
+    /// Stateful modifiers: name -> environment the script was run in once at load
+    stateful: HashMap<String, RegistryKey>,
 }
 
 impl ModifierManager {
@@ -20,16 +22,18 @@ impl ModifierManager {
         let lua = Lua::new();
 
         // Initialize the Lua environment with logging API
-        Self::init_lua_api(&lua)?;
+        Self::init_lua_api(&lua)
+            .map_err(|e| BitchError::lua("Failed to initialize modifier APIs", e))?;
 
         Ok(Self {
             lua: Arc::new(lua),
             modifiers: HashMap::new(),
+            stateful: HashMap::new(),
         })
     }
 
     /// Initialize Lua APIs available to modifiers
-    fn init_lua_api(lua: &Lua) -> Result<()> {
+    fn init_lua_api(lua: &Lua) -> mlua::Result<()> {
         // Import log API for modifiers to use
         let log_table = lua.create_table()?;
 
@@ -67,14 +71,24 @@ impl ModifierManager {
         info!("Loading modifier '{}' from {:?}", name, path);
 
         let code = std::fs::read_to_string(path)
-            .with_context(|| format!("Failed to read modifier file: {:?}", path))?;
+            .map_err(|e| BitchError::Config(format!("Failed to read modifier file {:?}: {}", path, e)))?;
 
-        // Validate the modifier by compiling it
+        self.compile_modifier(name, code)
+    }
+
+    /// Load a modifier from source kept in the config (a rule's `modifier_script`)
+    pub fn load_inline(&mut self, name: &str, code: &str) -> Result<()> {
+        info!("Loading inline modifier '{}'", name);
+        self.compile_modifier(name, code.to_string())
+    }
+
+    /// Validate a modifier by compiling (and running) it, then keep its code
+    fn compile_modifier(&mut self, name: &str, code: String) -> Result<()> {
         self.lua
             .load(&code)
             .set_name(name)
             .exec()
-            .map_err(|e| anyhow::anyhow!("Failed to compile modifier '{}': {}", name, e))?;
+            .map_err(|e| BitchError::lua(format!("Failed to compile modifier '{}'", name), e))?;
 
         self.modifiers.insert(name.to_string(), code);
 
@@ -82,10 +96,52 @@ impl ModifierManager {
         Ok(())
     }
 
+    /// Load a stateful modifier from a file
+    /// The script runs once, in its own environment (globals fall through to the shared
+    /// ones), and only `modify(ctx)` is called per message, so top-level locals and
+    /// globals it sets persist between messages.
+    pub fn load_stateful_modifier(&mut self, name: &str, path: &Path) -> Result<()> {
+        info!("Loading stateful modifier '{}' from {:?}", name, path);
+
+        let code = std::fs::read_to_string(path)
+            .map_err(|e| BitchError::Config(format!("Failed to read modifier file {:?}: {}", path, e)))?;
+
+        let env = self.lua.create_table()
+            .map_err(|e| BitchError::lua("Failed to create modifier environment", e))?;
+        let env_meta = self.lua.create_table()
+            .map_err(|e| BitchError::lua("Failed to create modifier environment", e))?;
+        env_meta.set("__index", self.lua.globals())
+            .map_err(|e| BitchError::lua("Failed to create modifier environment", e))?;
+        env.set_metatable(Some(env_meta));
+
+        self.lua
+            .load(&code)
+            .set_name(name)
+            .set_environment(env.clone())
+            .exec()
+            .map_err(|e| BitchError::lua(format!("Failed to compile modifier '{}'", name), e))?;
+
+        if !matches!(env.raw_get::<Value>("modify"), Ok(Value::Function(_))) {
+            return Err(BitchError::Config(format!(
+                "Stateful modifier '{}' must define a modify() function",
+                name
+            )));
+        }
+
+        let env_key = self.lua.create_registry_value(env)
+            .map_err(|e| BitchError::lua("Failed to store modifier environment", e))?;
+        self.stateful.insert(name.to_string(), env_key);
+        self.modifiers.insert(name.to_string(), code);
+
+        debug!("Stateful modifier '{}' loaded successfully", name);
+        Ok(())
+    }
+
     /// Execute a modifier and return the modified message
     pub fn execute_modifier(
         &self,
         name: &str,
+        rule_name: &str,
         header: &MavHeader,
         msg: &MavMessage,
         trigger_context: &HashMap<String, JsonValue>,
@@ -93,83 +149,95 @@ impl ModifierManager {
         let code = self
             .modifiers
             .get(name)
-            .ok_or_else(|| anyhow::anyhow!("Modifier '{}' not found", name))?;
-
-        let globals = self.lua.globals();
+            .ok_or_else(|| BitchError::Config(format!("Modifier '{}' not found", name)))?;
 
         // Get message type name
-        let message_type = format!("{:?}", msg).split('(').next().unwrap_or("UNKNOWN").to_string();
+        let message_type = crate::rules::get_message_name(msg);
 
         // Create a context table with header and message data
         let context_table = self.lua.create_table()
-            .map_err(|e| anyhow::anyhow!("Failed to create context table: {}", e))?;
+            .map_err(|e| BitchError::lua("Failed to create context table", e))?;
 
         // Add header fields
         context_table.set("system_id", header.system_id)
-            .map_err(|e| anyhow::anyhow!("Failed to set system_id: {}", e))?;
+            .map_err(|e| BitchError::lua("Failed to set system_id", e))?;
         context_table.set("component_id", header.component_id)
-            .map_err(|e| anyhow::anyhow!("Failed to set component_id: {}", e))?;
+            .map_err(|e| BitchError::lua("Failed to set component_id", e))?;
         context_table.set("sequence", header.sequence)
-            .map_err(|e| anyhow::anyhow!("Failed to set sequence: {}", e))?;
+            .map_err(|e| BitchError::lua("Failed to set sequence", e))?;
         context_table.set("message_type", message_type.as_str())
-            .map_err(|e| anyhow::anyhow!("Failed to set message_type: {}", e))?;
+            .map_err(|e| BitchError::lua("Failed to set message_type", e))?;
+        context_table.set("rule_name", rule_name)
+            .map_err(|e| BitchError::lua("Failed to set rule_name", e))?;
 
         // Serialize message to JSON (mavlink internally-tagged format)
         let message_json = serde_json::to_value(msg)
-            .map_err(|e| anyhow::anyhow!("Failed to serialize message to JSON: {}", e))?;
+            .map_err(|e| BitchError::Mavlink(format!("Failed to serialize message to JSON: {}", e)))?;
 
         // Convert JSON value to Lua value
         let msg_value = self.lua.to_value(&message_json)
-            .map_err(|e| anyhow::anyhow!("Failed to serialize message to Lua: {}", e))?;
+            .map_err(|e| BitchError::lua("Failed to serialize message to Lua", e))?;
 
         context_table.set("message", msg_value)
-            .map_err(|e| anyhow::anyhow!("Failed to set message: {}", e))?;
+            .map_err(|e| BitchError::lua("Failed to set message", e))?;
 
         // Add trigger_context if present
         if !trigger_context.is_empty() {
             let trigger_ctx_value = self.lua.to_value(trigger_context)
-                .map_err(|e| anyhow::anyhow!("Failed to serialize trigger_context to Lua: {}", e))?;
+                .map_err(|e| BitchError::lua("Failed to serialize trigger_context to Lua", e))?;
             context_table.set("trigger_context", trigger_ctx_value)
-                .map_err(|e| anyhow::anyhow!("Failed to set trigger_context: {}", e))?;
+                .map_err(|e| BitchError::lua("Failed to set trigger_context", e))?;
         }
 
-        globals.set("context", context_table)
-            .map_err(|e| anyhow::anyhow!("Failed to set context global: {}", e))?;
+        // Stateful modifiers keep their environment; others re-run the whole script
+        let scope: Table = match self.stateful.get(name) {
+            Some(env_key) => self.lua.registry_value(env_key)
+                .map_err(|e| BitchError::lua("Failed to get modifier environment", e))?,
+            None => self.lua.globals(),
+        };
+
+        scope.set("context", context_table)
+            .map_err(|e| BitchError::lua("Failed to set context global", e))?;
 
         // Execute the modifier code
-        self.lua
-            .load(code)
-            .set_name(name)
-            .exec()
-            .map_err(|e| anyhow::anyhow!("Failed to execute modifier '{}': {}", name, e))?;
+        if !self.stateful.contains_key(name) {
+            self.lua
+                .load(code)
+                .set_name(name)
+                .exec()
+                .map_err(|e| BitchError::lua(format!("Failed to execute modifier '{}'", name), e))?;
+        }
 
         // Call modify function if it exists
-        let modify_fn: Option<mlua::Function> = globals.get("modify").ok();
+        let modify_fn: Option<mlua::Function> = scope.get("modify").ok();
         if let Some(modify_fn) = modify_fn {
-            let ctx_val: Value = globals
+            let ctx_val: Value = scope
                 .get("context")
-                .map_err(|e| anyhow::anyhow!("Failed to get context: {}", e))?;
+                .map_err(|e| BitchError::lua("Failed to get context", e))?;
 
             let result_ctx = modify_fn
                 .call::<Value>(ctx_val)
-                .map_err(|e| anyhow::anyhow!("Modifier '{}' modify() failed: {}", name, e))?;
+                .map_err(|e| BitchError::lua(format!("Modifier '{}' modify() failed", name), e))?;
 
             // Extract modified message from returned context
             if let Value::Table(result_table) = result_ctx {
                 let modified_msg_value: Value = result_table.get("message")
-                    .map_err(|e| anyhow::anyhow!("Failed to get modified message: {}", e))?;
+                    .map_err(|e| BitchError::lua("Failed to get modified message", e))?;
 
                 // Convert Lua value to JSON
                 let message_json: serde_json::Value = self.lua.from_value(modified_msg_value)
-                    .map_err(|e| anyhow::anyhow!("Failed to convert modified message to JSON: {}", e))?;
+                    .map_err(|e| BitchError::lua("Failed to convert modified message to JSON", e))?;
 
                 // Deserialize JSON to MavMessage (mavlink internally-tagged format)
                 let modified_msg: MavMessage = serde_json::from_value(message_json)
-                    .map_err(|e| anyhow::anyhow!("Failed to deserialize modified message: {}", e))?;
+                    .map_err(|e| BitchError::Mavlink(format!("Failed to deserialize modified message: {}", e)))?;
 
                 Ok(modified_msg)
             } else {
-                anyhow::bail!("Modifier '{}' modify() must return a table", name);
+                Err(BitchError::lua(
+                    format!("Modifier '{}' modify() failed", name),
+                    mlua::Error::RuntimeError("modify() must return a table".to_string()),
+                ))
             }
         } else {
             warn!("Modifier '{}' has no modify() function", name);
@@ -0,0 +1,75 @@
+use crate::config::RadioPacingConfig;
+use mavlink::ardupilotmega::MavMessage;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// GCS -> router pacing driven by RADIO_STATUS reports from the router side
+/// The latest report's `txbuf` (free transmit buffer, percent) sets the delay applied
+/// before each forwarded packet: zero at or above `congested_txbuf`, rising linearly
+/// to `max_delay_ms` as the buffer fills.
+pub struct RadioPacer {
+    config: RadioPacingConfig,
+    /// Current per-packet delay
+    delay_ms: AtomicU64,
+    /// When the last RADIO_STATUS arrived
+    last_report: Mutex<Option<Instant>>,
+}
+
+impl RadioPacer {
+    pub fn new(config: RadioPacingConfig) -> Self {
+        Self {
+            config,
+            delay_ms: AtomicU64::new(0),
+            last_report: Mutex::new(None),
+        }
+    }
+
+    /// Update the delay from a router -> GCS message (ignored unless it's RADIO_STATUS)
+    pub fn observe(&self, msg: &MavMessage) {
+        let MavMessage::RADIO_STATUS(status) = msg else {
+            return;
+        };
+
+        let delay_ms = self.delay_for(status.txbuf);
+        let previous = self.delay_ms.swap(delay_ms, Ordering::Relaxed);
+        *self.last_report.lock().unwrap() = Some(Instant::now());
+
+        if (previous == 0) != (delay_ms == 0) {
+            if delay_ms > 0 {
+                info!("Radio congested (txbuf={}%), pacing GCS -> router at {}ms/packet", status.txbuf, delay_ms);
+            } else {
+                info!("Radio buffer recovered (txbuf={}%), pacing off", status.txbuf);
+            }
+        } else if previous != delay_ms {
+            debug!("Radio txbuf={}%, pacing delay {}ms", status.txbuf, delay_ms);
+        }
+    }
+
+    /// Delay for a given free-buffer percentage
+    fn delay_for(&self, txbuf: u8) -> u64 {
+        let threshold = u64::from(self.config.congested_txbuf);
+        let free = u64::from(txbuf.min(100));
+        if free >= threshold {
+            return 0;
+        }
+        self.config.max_delay_ms * (threshold - free) / threshold
+    }
+
+    /// Current delay, or None if the link isn't congested or the last report is stale
+    pub fn current_delay(&self) -> Option<Duration> {
+        let delay_ms = self.delay_ms.load(Ordering::Relaxed);
+        if delay_ms == 0 {
+            return None;
+        }
+
+        let stale_after = Duration::from_secs(self.config.stale_after_seconds);
+        let fresh = self
+            .last_report
+            .lock()
+            .unwrap()
+            .is_some_and(|at| at.elapsed() < stale_after);
+        fresh.then(|| Duration::from_millis(delay_ms))
+    }
+}
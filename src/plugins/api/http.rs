@@ -1,9 +1,18 @@
 use anyhow::Result;
 use mlua::Lua;
+use std::sync::{Arc, OnceLock};
+
+use crate::plugins::outbox::Outbox;
 use tracing::{debug, warn};
 
+/// Shared HTTP client (pools connections across plugin calls and rule notifications)
+pub fn shared_client() -> reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(reqwest::Client::new).clone()
+}
+
 /// Initialize HTTP API for Lua
-pub fn init(lua: &Lua) -> Result<()> {
+pub fn init(lua: &Lua, outbox: Arc<Outbox>) -> Result<()> {
     let http_table = lua.create_table()
         .map_err(|e| anyhow::anyhow!("Failed to create http table: {}", e))?;
 
@@ -39,6 +48,14 @@ pub fn init(lua: &Lua) -> Result<()> {
         }).map_err(|e| anyhow::anyhow!("Failed to create http.post: {}", e))?,
     ).map_err(|e| anyhow::anyhow!("Failed to set http.post: {}", e))?;
 
+    // http.post_reliable(url, body) -> true if queued for (retried) delivery
+    http_table.set(
+        "post_reliable",
+        lua.create_function(move |_, (url, body): (String, String)| {
+            Ok(outbox.enqueue(&url, &body))
+        }).map_err(|e| anyhow::anyhow!("Failed to create http.post_reliable: {}", e))?,
+    ).map_err(|e| anyhow::anyhow!("Failed to set http.post_reliable: {}", e))?;
+
     lua.globals().set("http", http_table)
         .map_err(|e| anyhow::anyhow!("Failed to set http global: {}", e))?;
 
@@ -48,7 +65,7 @@ pub fn init(lua: &Lua) -> Result<()> {
 async fn http_get(url: &str) -> Result<String> {
     debug!("[Plugin] HTTP GET: {}", url);
 
-    let client = reqwest::Client::new();
+    let client = shared_client();
     let response = client.get(url).send().await?;
     let body = response.text().await?;
 
@@ -58,7 +75,7 @@ async fn http_get(url: &str) -> Result<String> {
 async fn http_post(url: &str, body: String) -> Result<String> {
     debug!("[Plugin] HTTP POST: {}", url);
 
-    let client = reqwest::Client::new();
+    let client = shared_client();
     let response = client.post(url).body(body).send().await?;
     let text = response.text().await?;
 
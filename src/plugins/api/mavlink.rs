@@ -0,0 +1,56 @@
+use anyhow::Result;
+use mlua::{Lua, LuaSerdeExt};
+use serde_json::Value as JsonValue;
+
+/// Convenience constructors: Lua name -> message type
+const BUILDERS: &[(&str, &str)] = &[
+    ("command_long", "COMMAND_LONG"),
+    ("heartbeat", "HEARTBEAT"),
+    ("statustext", "STATUSTEXT"),
+];
+
+/// Initialize MAVLink message builder API for Lua
+pub fn init(lua: &Lua) -> Result<()> {
+    let mavlink_table = lua.create_table()
+        .map_err(|e| anyhow::anyhow!("Failed to create mavlink table: {}", e))?;
+
+    // mavlink.message(message_type, [fields_table])
+    mavlink_table.set(
+        "message",
+        lua.create_function(|lua, (message_type, fields): (String, Option<mlua::Value>)| {
+            build_table(lua, &message_type, fields)
+        }).map_err(|e| anyhow::anyhow!("Failed to create mavlink.message: {}", e))?,
+    ).map_err(|e| anyhow::anyhow!("Failed to set mavlink.message: {}", e))?;
+
+    // mavlink.command_long{...}, mavlink.heartbeat{...}, mavlink.statustext{...}
+    for (name, message_type) in BUILDERS {
+        mavlink_table.set(
+            *name,
+            lua.create_function(move |lua, fields: Option<mlua::Value>| {
+                build_table(lua, message_type, fields)
+            }).map_err(|e| anyhow::anyhow!("Failed to create mavlink.{}: {}", name, e))?,
+        ).map_err(|e| anyhow::anyhow!("Failed to set mavlink.{}: {}", name, e))?;
+    }
+
+    lua.globals().set("mavlink", mavlink_table)
+        .map_err(|e| anyhow::anyhow!("Failed to set mavlink global: {}", e))?;
+
+    Ok(())
+}
+
+/// Build a message from Lua fields and return it as an internally-tagged Lua table
+fn build_table(lua: &Lua, message_type: &str, fields: Option<mlua::Value>) -> mlua::Result<mlua::Value> {
+    let fields: JsonValue = match fields {
+        Some(value) => lua.from_value(value)?,
+        None => JsonValue::Null,
+    };
+
+    // An empty Lua table may deserialize as an array; treat anything but an object as "no fields"
+    let fields = fields.as_object().cloned().unwrap_or_default();
+
+    let msg = crate::messages::build_message(message_type, &fields)
+        .map_err(|e| mlua::Error::RuntimeError(e.to_string()))?;
+    let message_json = serde_json::to_value(&msg).map_err(mlua::Error::external)?;
+
+    lua.to_value(&message_json)
+}
@@ -1,5 +1,8 @@
 mod http;
 mod log;
+mod mavlink;
+mod proxy;
+mod quarantine;
 mod serial;
 mod util;
 
@@ -8,6 +11,13 @@ use mlua::Lua;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::outbox::Outbox;
+use super::status::ProxyStatus;
+use crate::quarantine::Quarantine;
+
+pub use http::shared_client;
 
 /// Context passed to plugins when a rule matches
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,16 +30,51 @@ pub struct PluginContext {
     pub message_type: String,
     /// Full message data (works for ALL message types)
     pub message: JsonValue,
+    /// Name of the rule that matched and invoked the plugin
+    #[serde(default)]
+    pub rule_name: String,
     /// Trigger context data (if rule was activated by a trigger)
     #[serde(default)]
     pub trigger_context: HashMap<String, JsonValue>,
+    /// The matched rule's `plugin_config` table
+    #[serde(default)]
+    pub plugin_config: HashMap<String, JsonValue>,
+}
+
+/// Context passed to the `network.route_hook` function for every parsed frame
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteContext {
+    /// "gcs_to_router" or "router_to_gcs"
+    pub direction: String,
+    /// MAVLink header system ID
+    pub system_id: u8,
+    /// MAVLink header component ID
+    pub component_id: u8,
+    /// Message type name (e.g., "COMMAND_LONG", "HEARTBEAT")
+    pub message_type: String,
+    /// Full message data
+    pub message: JsonValue,
+    /// Sending GCS client (gcs_to_router only)
+    pub client_id: Option<u64>,
+    /// Client group of the sending GCS client, if it is in one
+    pub client_group: Option<String>,
+    /// Router the frame came from (router_to_gcs only)
+    pub router: Option<String>,
 }
 
 /// Initialize all Lua APIs
-pub fn init_lua_api(lua: &Lua) -> Result<()> {
+pub fn init_lua_api(
+    lua: &Lua,
+    outbox: Arc<Outbox>,
+    status: Arc<ProxyStatus>,
+    quarantine: Arc<Quarantine>,
+) -> Result<()> {
     log::init(lua)?;
     serial::init(lua)?;
-    http::init(lua)?;
+    http::init(lua, outbox)?;
+    mavlink::init(lua)?;
+    proxy::init(lua, status)?;
+    quarantine::init(lua, quarantine)?;
     util::init(lua)?;
 
     Ok(())
@@ -0,0 +1,39 @@
+use anyhow::Result;
+use mlua::Lua;
+use std::sync::Arc;
+
+use crate::plugins::status::ProxyStatus;
+
+/// Initialize the read-only proxy status API for Lua
+pub fn init(lua: &Lua, status: Arc<ProxyStatus>) -> Result<()> {
+    let proxy_table = lua.create_table()
+        .map_err(|e| anyhow::anyhow!("Failed to create proxy table: {}", e))?;
+
+    // proxy.client_count()
+    let client_status = status.clone();
+    proxy_table.set(
+        "client_count",
+        lua.create_function(move |_, ()| Ok(client_status.client_count()))
+            .map_err(|e| anyhow::anyhow!("Failed to create proxy.client_count: {}", e))?,
+    ).map_err(|e| anyhow::anyhow!("Failed to set proxy.client_count: {}", e))?;
+
+    // proxy.router_connected()
+    let router_status = status.clone();
+    proxy_table.set(
+        "router_connected",
+        lua.create_function(move |_, ()| Ok(router_status.router_connected()))
+            .map_err(|e| anyhow::anyhow!("Failed to create proxy.router_connected: {}", e))?,
+    ).map_err(|e| anyhow::anyhow!("Failed to set proxy.router_connected: {}", e))?;
+
+    // proxy.uptime_seconds()
+    proxy_table.set(
+        "uptime_seconds",
+        lua.create_function(move |_, ()| Ok(status.uptime_seconds()))
+            .map_err(|e| anyhow::anyhow!("Failed to create proxy.uptime_seconds: {}", e))?,
+    ).map_err(|e| anyhow::anyhow!("Failed to set proxy.uptime_seconds: {}", e))?;
+
+    lua.globals().set("proxy", proxy_table)
+        .map_err(|e| anyhow::anyhow!("Failed to set proxy global: {}", e))?;
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use mlua::Lua;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::quarantine::Quarantine;
+
+/// Initialize the quarantine API for Lua
+pub fn init(lua: &Lua, quarantine: Arc<Quarantine>) -> Result<()> {
+    let quarantine_table = lua.create_table()
+        .map_err(|e| anyhow::anyhow!("Failed to create quarantine table: {}", e))?;
+
+    // quarantine.add(system_id, seconds) - seconds omitted or 0 = until released
+    let add_quarantine = quarantine.clone();
+    quarantine_table.set(
+        "add",
+        lua.create_function(move |_, (system_id, seconds): (u8, Option<f64>)| {
+            let duration = seconds.filter(|s| *s > 0.0).map(Duration::from_secs_f64);
+            add_quarantine.add(system_id, duration);
+            Ok(())
+        })
+        .map_err(|e| anyhow::anyhow!("Failed to create quarantine.add: {}", e))?,
+    ).map_err(|e| anyhow::anyhow!("Failed to set quarantine.add: {}", e))?;
+
+    // quarantine.release(system_id) -> true if it was quarantined
+    let release_quarantine = quarantine.clone();
+    quarantine_table.set(
+        "release",
+        lua.create_function(move |_, system_id: u8| Ok(release_quarantine.release(system_id)))
+            .map_err(|e| anyhow::anyhow!("Failed to create quarantine.release: {}", e))?,
+    ).map_err(|e| anyhow::anyhow!("Failed to set quarantine.release: {}", e))?;
+
+    // quarantine.contains(system_id)
+    let contains_quarantine = quarantine.clone();
+    quarantine_table.set(
+        "contains",
+        lua.create_function(move |_, system_id: u8| Ok(contains_quarantine.contains(system_id)))
+            .map_err(|e| anyhow::anyhow!("Failed to create quarantine.contains: {}", e))?,
+    ).map_err(|e| anyhow::anyhow!("Failed to set quarantine.contains: {}", e))?;
+
+    // quarantine.list() -> array of quarantined system IDs
+    quarantine_table.set(
+        "list",
+        lua.create_function(move |_, ()| {
+            Ok(quarantine.snapshot().into_iter().map(|entry| entry.system_id).collect::<Vec<u8>>())
+        })
+        .map_err(|e| anyhow::anyhow!("Failed to create quarantine.list: {}", e))?,
+    ).map_err(|e| anyhow::anyhow!("Failed to set quarantine.list: {}", e))?;
+
+    lua.globals().set("quarantine", quarantine_table)
+        .map_err(|e| anyhow::anyhow!("Failed to set quarantine global: {}", e))?;
+
+    Ok(())
+}
@@ -1,49 +1,244 @@
 mod api;
+mod outbox;
+mod process;
+mod require;
+mod status;
 
-use anyhow::{Context, Result};
+use crate::error::{BitchError, Result};
+use crate::quarantine::Quarantine;
 use mlua::{Lua, LuaSerdeExt, Value};
+use serde_json::Value as JsonValue;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::{debug, info, warn};
 
-pub use api::PluginContext;
+use outbox::Outbox;
+use process::ProcessPlugin;
+pub use api::{shared_client, PluginContext, RouteContext};
+pub(crate) use status::ProxyStatus;
+
+/// A loaded plugin: its code and the Lua state it runs in
+/// Every plugin gets its own state with its own copies of the API tables, so one
+/// plugin redefining a hook or overwriting `http`/`log` can't affect another.
+struct Plugin {
+    lua: Lua,
+    code: String,
+}
+
+/// Where the route hook sends a frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteVerdict {
+    /// Deliver as the rules decided (`nil` or "forward")
+    Forward,
+    /// Drop the frame ("drop")
+    Drop,
+    /// Send only to these routers (`{ routers = { "host:port", ... } }`, GCS -> router)
+    Routers(Vec<String>),
+    /// Send only to this client group's GCS clients (`{ group = "name" }`, router -> GCS)
+    Group(String),
+}
+
+impl RouteVerdict {
+    /// Read the value a route hook returned
+    fn from_lua(value: Value) -> mlua::Result<Self> {
+        let invalid = |what: &str| mlua::Error::RuntimeError(format!("invalid route verdict: {}", what));
+        match value {
+            Value::Nil => Ok(RouteVerdict::Forward),
+            Value::String(verdict) => match &*verdict.to_str()? {
+                "forward" => Ok(RouteVerdict::Forward),
+                "drop" => Ok(RouteVerdict::Drop),
+                other => Err(invalid(&format!("'{}' (expected \"forward\" or \"drop\")", other))),
+            },
+            Value::Table(verdict) => {
+                if let Some(routers) = verdict.get::<Option<Vec<String>>>("routers")? {
+                    Ok(RouteVerdict::Routers(routers))
+                } else if let Some(group) = verdict.get::<Option<String>>("group")? {
+                    Ok(RouteVerdict::Group(group))
+                } else {
+                    Err(invalid("table needs `routers` or `group`"))
+                }
+            }
+            other => Err(invalid(other.type_name())),
+        }
+    }
+
+    /// Read the reply of a process plugin's route hook (same shapes as from Lua)
+    fn from_json(value: JsonValue) -> std::result::Result<Self, String> {
+        match value {
+            JsonValue::Null => Ok(RouteVerdict::Forward),
+            JsonValue::String(verdict) => match verdict.as_str() {
+                "forward" => Ok(RouteVerdict::Forward),
+                "drop" => Ok(RouteVerdict::Drop),
+                other => Err(format!("'{}' (expected \"forward\" or \"drop\")", other)),
+            },
+            JsonValue::Object(verdict) => {
+                if let Some(routers) = verdict.get("routers") {
+                    serde_json::from_value(routers.clone())
+                        .map(RouteVerdict::Routers)
+                        .map_err(|e| format!("routers: {}", e))
+                } else if let Some(group) = verdict.get("group").and_then(JsonValue::as_str) {
+                    Ok(RouteVerdict::Group(group.to_string()))
+                } else {
+                    Err("object needs `routers` or `group`".to_string())
+                }
+            }
+            other => Err(other.to_string()),
+        }
+    }
+}
 
 /// Plugin manager that handles loading and executing Lua scripts
 pub struct PluginManager {
-    lua: Arc<Lua>,
-    plugins: HashMap<String, String>, // name -> lua code
+    plugins: HashMap<String, Plugin>,
+    /// Plugins run as external processes (`[plugins.process]`)
+    processes: HashMap<String, ProcessPlugin>,
+    /// Log on_load() failures instead of rejecting the plugin
+    warn_on_load_error: bool,
+    /// Maximum run time of bounded hook calls
+    timeout: Duration,
+    /// Durable queue behind http.post_reliable
+    outbox: Arc<Outbox>,
+    /// Outbox delivery task (running once the outbox is enabled)
+    outbox_worker: Option<tokio::task::JoinHandle<()>>,
+    /// Proxy status behind the `proxy` API (updated by the proxy once it runs)
+    status: Arc<ProxyStatus>,
+    /// Quarantined systems behind the `quarantine` API (enforced by the rule engine)
+    quarantine: Arc<Quarantine>,
+    /// Directory `require` loads shared modules from (nothing can be required when unset)
+    module_dir: Option<PathBuf>,
 }
 
 impl PluginManager {
     /// Create a new plugin manager
     pub fn new() -> Result<Self> {
-        let lua = Lua::new();
-
-        // Initialize the Lua environment with our APIs
-        api::init_lua_api(&lua)?;
-
         Ok(Self {
-            lua: Arc::new(lua),
             plugins: HashMap::new(),
+            processes: HashMap::new(),
+            warn_on_load_error: false,
+            timeout: Duration::from_secs(2),
+            outbox: Arc::new(Outbox::new()),
+            outbox_worker: None,
+            status: Arc::new(ProxyStatus::new()),
+            quarantine: Arc::new(Quarantine::new()),
+            module_dir: None,
         })
     }
 
+    /// Create a Lua state with the plugin APIs installed
+    fn new_lua(&self) -> Result<Lua> {
+        let lua = Lua::new();
+        api::init_lua_api(&lua, self.outbox.clone(), self.status.clone(), self.quarantine.clone())
+            .map_err(|e| BitchError::lua("Failed to initialize plugin APIs", mlua::Error::external(e)))?;
+        require::install(&lua, self.module_dir.as_deref())
+            .map_err(|e| BitchError::lua("Failed to set up require", e))?;
+        Ok(lua)
+    }
+
+    /// Store http.post_reliable requests in `dir` and start delivering them in the background
+    /// Entries left over from a previous run are retried too.
+    pub fn enable_outbox(&mut self, dir: &Path) -> Result<()> {
+        let worker = self
+            .outbox
+            .enable(dir)
+            .map_err(|e| BitchError::Config(format!("{:#}", e)))?;
+        self.outbox_worker = Some(worker);
+        Ok(())
+    }
+
+    /// Handle the proxy keeps up to date for the plugins' `proxy` API
+    pub(crate) fn status(&self) -> Arc<ProxyStatus> {
+        self.status.clone()
+    }
+
+    /// Quarantine set the plugins' `quarantine` API changes
+    pub(crate) fn quarantine(&self) -> Arc<Quarantine> {
+        self.quarantine.clone()
+    }
+
+    /// Let plugins `require` modules from `dir` (set before loading plugins)
+    pub fn set_module_dir(&mut self, dir: &Path) {
+        self.module_dir = Some(dir.to_path_buf());
+    }
+
+    /// Set the maximum run time of bounded hook calls
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Keep plugins whose on_load() fails (logging the error) instead of rejecting them
+    pub fn set_warn_on_load_error(&mut self, warn_only: bool) {
+        self.warn_on_load_error = warn_only;
+    }
+
     /// Load a plugin from a file
-    pub fn load_plugin(&mut self, name: &str, path: &Path) -> Result<()> {
+    /// Calls the plugin's on_load() (if defined) once with its name and config
+    pub fn load_plugin(&mut self, name: &str, path: &Path, config: Option<&toml::Value>) -> Result<()> {
         info!("Loading plugin '{}' from {:?}", name, path);
 
         let code = std::fs::read_to_string(path)
-            .with_context(|| format!("Failed to read plugin file: {:?}", path))?;
+            .map_err(|e| BitchError::Config(format!("Failed to read plugin file {:?}: {}", path, e)))?;
+
+        self.compile_plugin(name, code, config)
+    }
+
+    /// Load a plugin from source kept in the config (a rule's `plugin_script`)
+    /// Inline plugins have no `[plugins.config]` entry, so `on_load` gets an empty config
+    pub fn load_inline(&mut self, name: &str, code: &str) -> Result<()> {
+        info!("Loading inline plugin '{}'", name);
+        self.compile_plugin(name, code.to_string(), None)
+    }
+
+    /// Start a plugin process (`[plugins.process.<name>]`) in `dir` and send it `on_load`
+    /// Calls to it are bounded by the plugin timeout, which must be set before.
+    pub fn load_process(
+        &mut self,
+        name: &str,
+        command: &str,
+        args: &[String],
+        dir: &Path,
+        config: Option<&toml::Value>,
+    ) -> Result<()> {
+        info!("Starting plugin process '{}': {} {}", name, command, args.join(" "));
+
+        let config = match config {
+            Some(config) => serde_json::to_value(config)
+                .map_err(|e| BitchError::Config(format!("Invalid config for plugin '{}': {}", name, e)))?,
+            None => JsonValue::Object(Default::default()),
+        };
+        let load_context = serde_json::json!({ "plugin": name, "config": config });
+        let process = ProcessPlugin::start(name, command, args, dir, load_context, self.timeout)?;
+
+        if let Err(e) = process.load() {
+            if !self.warn_on_load_error {
+                return Err(e);
+            }
+            warn!("{}", e);
+        }
+
+        self.processes.insert(name.to_string(), process);
+        Ok(())
+    }
+
+    /// Compile a plugin in its own Lua state, run `on_load`, then keep it
+    fn compile_plugin(&mut self, name: &str, code: String, config: Option<&toml::Value>) -> Result<()> {
+        let lua = self.new_lua()?;
 
         // Validate the plugin by compiling it
-        self.lua
-            .load(&code)
+        lua.load(&code)
             .set_name(name)
             .exec()
-            .map_err(|e| anyhow::anyhow!("Failed to compile plugin '{}': {}", name, e))?;
+            .map_err(|e| BitchError::lua(format!("Failed to compile plugin '{}'", name), e))?;
 
-        self.plugins.insert(name.to_string(), code);
+        if let Err(e) = Self::call_on_load(&lua, name, config) {
+            if !self.warn_on_load_error {
+                return Err(e);
+            }
+            warn!("{}", e);
+        }
+
+        self.plugins.insert(name.to_string(), Plugin { lua, code });
 
         debug!("Plugin '{}' loaded successfully", name);
         Ok(())
@@ -51,36 +246,38 @@ impl PluginManager {
 
     /// Execute a plugin's on_match function
     pub fn execute_plugin(&self, name: &str, context: &PluginContext) -> Result<()> {
-        let code = self
+        if let Some(process) = self.processes.get(name) {
+            return process.call("on_match", context).map(|_| ());
+        }
+
+        let Plugin { lua, code } = self
             .plugins
             .get(name)
-            .ok_or_else(|| anyhow::anyhow!("Plugin '{}' not found", name))?;
+            .ok_or_else(|| BitchError::Config(format!("Plugin '{}' not found", name)))?;
 
-        // Create a fresh environment for this execution
-        let globals = self.lua.globals();
+        let globals = lua.globals();
 
         // Serialize context to Lua table using serde (supports ALL message types automatically)
-        let context_value = self.lua.to_value(context)
-            .map_err(|e| anyhow::anyhow!("Failed to serialize context: {}", e))?;
+        let context_value = lua.to_value(context)
+            .map_err(|e| BitchError::lua("Failed to serialize context", e))?;
 
         globals.set("context", context_value)
-            .map_err(|e| anyhow::anyhow!("Failed to set context global: {}", e))?;
+            .map_err(|e| BitchError::lua("Failed to set context global", e))?;
 
         // Execute the plugin code
-        self.lua
-            .load(code)
+        lua.load(code)
             .set_name(name)
             .exec()
-            .map_err(|e| anyhow::anyhow!("Failed to execute plugin '{}': {}", name, e))?;
+            .map_err(|e| BitchError::lua(format!("Failed to execute plugin '{}'", name), e))?;
 
         // Call on_match if it exists
         let on_match: Option<mlua::Function> = globals.get("on_match").ok();
         if let Some(on_match) = on_match {
             let ctx_val: Value = globals.get("context")
-                .map_err(|e| anyhow::anyhow!("Failed to get context: {}", e))?;
+                .map_err(|e| BitchError::lua("Failed to get context", e))?;
             on_match
                 .call::<()>(ctx_val)
-                .map_err(|e| anyhow::anyhow!("Plugin '{}' on_match() failed: {}", name, e))?;
+                .map_err(|e| BitchError::lua(format!("Plugin '{}' on_match() failed", name), e))?;
         } else {
             warn!("Plugin '{}' has no on_match() function", name);
         }
@@ -88,9 +285,143 @@ impl PluginManager {
         Ok(())
     }
 
+    /// Call the on_load(context) hook of the plugin just compiled, if defined
+    fn call_on_load(lua: &Lua, name: &str, config: Option<&toml::Value>) -> Result<()> {
+        let on_load: Option<mlua::Function> = lua.globals().get("on_load").ok();
+        let Some(on_load) = on_load else {
+            return Ok(());
+        };
+
+        let context = lua.create_table()
+            .map_err(|e| BitchError::lua("Failed to create on_load context", e))?;
+        context.set("plugin", name)
+            .map_err(|e| BitchError::lua("Failed to set on_load context", e))?;
+        let config_value = match config {
+            Some(config) => lua.to_value(config)
+                .map_err(|e| BitchError::lua("Failed to serialize plugin config", e))?,
+            None => Value::Table(lua.create_table()
+                .map_err(|e| BitchError::lua("Failed to create plugin config", e))?),
+        };
+        context.set("config", config_value)
+            .map_err(|e| BitchError::lua("Failed to set on_load context", e))?;
+
+        on_load
+            .call::<()>(context)
+            .map_err(|e| BitchError::lua(format!("Plugin '{}' on_load() failed", name), e))?;
+
+        debug!("Plugin '{}' on_load() completed", name);
+        Ok(())
+    }
+
+    /// Call every loaded plugin's on_shutdown(context) hook, if defined
+    /// Each call is bounded by the plugin timeout; failures are logged and don't stop the others.
+    pub fn shutdown(&self) {
+        let mut names: Vec<&String> = self.plugins.keys().chain(self.processes.keys()).collect();
+        names.sort();
+
+        for name in names {
+            let result = match self.processes.get(name.as_str()) {
+                Some(process) => process.shutdown(),
+                None => self.call_on_shutdown(name),
+            };
+            if let Err(e) = result {
+                warn!("{}", e);
+            }
+        }
+
+        // Undelivered outbox entries stay on disk for the next run
+        if let Some(worker) = &self.outbox_worker {
+            worker.abort();
+        }
+    }
+
+    /// Re-run a plugin's code and call its on_shutdown() within the timeout
+    fn call_on_shutdown(&self, name: &str) -> Result<()> {
+        let Plugin { lua, code } = &self.plugins[name];
+        let globals = lua.globals();
+
+        lua.load(code)
+            .set_name(name)
+            .exec()
+            .map_err(|e| BitchError::lua(format!("Failed to execute plugin '{}'", name), e))?;
+
+        let on_shutdown: Option<mlua::Function> = globals.get("on_shutdown").ok();
+        let Some(on_shutdown) = on_shutdown else {
+            return Ok(());
+        };
+
+        let context = lua.create_table()
+            .map_err(|e| BitchError::lua("Failed to create on_shutdown context", e))?;
+        context.set("plugin", name)
+            .map_err(|e| BitchError::lua("Failed to set on_shutdown context", e))?;
+
+        self.call_with_timeout::<()>(lua, &on_shutdown, context)
+            .map_err(|e| BitchError::lua(format!("Plugin '{}' on_shutdown() failed", name), e))?;
+
+        info!("Plugin '{}' shut down", name);
+        Ok(())
+    }
+
+    /// Call a plugin's route hook function for one frame, within the timeout
+    /// The function runs in the plugin's loaded state; unlike on_match, the plugin's
+    /// code isn't re-run per call, so globals it sets persist between frames.
+    pub fn route(&self, plugin: &str, function: &str, context: &RouteContext) -> Result<RouteVerdict> {
+        if let Some(process) = self.processes.get(plugin) {
+            return process.call(function, context).and_then(|reply| {
+                RouteVerdict::from_json(reply).map_err(|e| {
+                    BitchError::Process(format!("Plugin '{}' {}() failed: invalid route verdict: {}", plugin, function, e))
+                })
+            });
+        }
+
+        let Plugin { lua, .. } = self
+            .plugins
+            .get(plugin)
+            .ok_or_else(|| BitchError::Config(format!("Plugin '{}' not found", plugin)))?;
+
+        let hook: mlua::Function = lua
+            .globals()
+            .get(function)
+            .map_err(|e| BitchError::lua(format!("Plugin '{}' has no {}() function", plugin, function), e))?;
+        let context_value = lua.to_value(context)
+            .map_err(|e| BitchError::lua("Failed to serialize route context", e))?;
+
+        self.call_with_timeout::<Value>(lua, &hook, context_value)
+            .and_then(RouteVerdict::from_lua)
+            .map_err(|e| BitchError::lua(format!("Plugin '{}' {}() failed", plugin, function), e))
+    }
+
+    /// Call a Lua function, aborting it if it runs longer than the plugin timeout
+    /// The check runs between Lua instructions, so time spent inside a single
+    /// blocking API call is only caught once that call returns.
+    fn call_with_timeout<R: mlua::FromLuaMulti>(
+        &self,
+        lua: &Lua,
+        func: &mlua::Function,
+        args: impl mlua::IntoLuaMulti,
+    ) -> mlua::Result<R> {
+        let timeout = self.timeout;
+        let started = Instant::now();
+
+        lua.set_hook(
+            mlua::HookTriggers::new().every_nth_instruction(1000),
+            move |_lua, _debug| {
+                if started.elapsed() > timeout {
+                    Err(mlua::Error::RuntimeError(format!("timed out after {:?}", timeout)))
+                } else {
+                    Ok(mlua::VmState::Continue)
+                }
+            },
+        );
+
+        let result = func.call::<R>(args);
+        lua.remove_hook();
+        result
+    }
+
     /// Get list of loaded plugins
     #[allow(dead_code)]
     pub fn loaded_plugins(&self) -> Vec<String> {
-        self.plugins.keys().cloned().collect()
+        self.plugins.keys().chain(self.processes.keys()).cloned().collect()
     }
 }
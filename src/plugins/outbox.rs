@@ -0,0 +1,193 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+
+/// First retry delay after a failed delivery round
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Upper bound for the retry delay
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Per-request timeout for outbox deliveries
+const POST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A queued HTTP POST, stored as one JSON file per entry
+#[derive(Debug, Serialize, Deserialize)]
+struct OutboxEntry {
+    url: String,
+    body: String,
+}
+
+/// Durable queue behind `http.post_reliable`
+/// Entries are files in the outbox directory, so they survive restarts and are
+/// delivered in enqueue order by a background worker.
+pub struct Outbox {
+    /// Outbox directory (unset until enabled)
+    dir: OnceLock<PathBuf>,
+    /// Tie-breaker for entries enqueued within the same millisecond
+    next_id: AtomicU64,
+    /// Wakes the worker when a new entry is queued
+    wake: Notify,
+}
+
+impl Outbox {
+    pub fn new() -> Self {
+        Self {
+            dir: OnceLock::new(),
+            next_id: AtomicU64::new(0),
+            wake: Notify::new(),
+        }
+    }
+
+    /// Use `dir` for the queue (created if missing) and start the delivery worker
+    pub fn enable(self: &Arc<Self>, dir: &Path) -> Result<tokio::task::JoinHandle<()>> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create outbox directory {:?}", dir))?;
+
+        if self.dir.set(dir.to_path_buf()).is_err() {
+            anyhow::bail!("Outbox is already enabled");
+        }
+
+        info!("Plugin HTTP outbox enabled at {:?}", dir);
+        Ok(tokio::spawn(self.clone().run()))
+    }
+
+    /// Persist a POST for delivery; returns false if the outbox is disabled or the write fails
+    pub fn enqueue(&self, url: &str, body: &str) -> bool {
+        let Some(dir) = self.dir.get() else {
+            warn!("[Plugin] http.post_reliable to {} dropped: [plugins] outbox_dir is not set", url);
+            return false;
+        };
+
+        match Self::write_entry(dir, &self.next_entry_name(), url, body) {
+            Ok(path) => {
+                debug!("[Plugin] Queued reliable POST to {} as {:?}", url, path);
+                self.wake.notify_one();
+                true
+            }
+            Err(e) => {
+                warn!("[Plugin] Failed to queue reliable POST to {}: {:#}", url, e);
+                false
+            }
+        }
+    }
+
+    /// File name that sorts in enqueue order (millisecond timestamp + counter)
+    fn next_entry_name(&self) -> String {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        format!("{:016}-{:08}", millis, id)
+    }
+
+    /// Write to a temp file and rename, so the worker never sees a partial entry
+    fn write_entry(dir: &Path, name: &str, url: &str, body: &str) -> Result<PathBuf> {
+        let entry = OutboxEntry {
+            url: url.to_string(),
+            body: body.to_string(),
+        };
+        let tmp_path = dir.join(format!("{}.tmp", name));
+        let path = dir.join(format!("{}.json", name));
+
+        std::fs::write(&tmp_path, serde_json::to_vec(&entry)?)
+            .with_context(|| format!("Failed to write {:?}", tmp_path))?;
+        std::fs::rename(&tmp_path, &path).with_context(|| format!("Failed to rename {:?}", tmp_path))?;
+
+        Ok(path)
+    }
+
+    /// Delivery loop: drain the queue, back off exponentially while anything fails
+    async fn run(self: Arc<Self>) {
+        let Some(dir) = self.dir.get() else {
+            return;
+        };
+        let mut backoff = MIN_BACKOFF;
+
+        loop {
+            let remaining = Self::drain(dir).await;
+            if remaining == 0 {
+                backoff = MIN_BACKOFF;
+                self.wake.notified().await;
+            } else {
+                warn!(
+                    "Plugin HTTP outbox: {} POST(s) undelivered, retrying in {}s",
+                    remaining,
+                    backoff.as_secs()
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+
+    /// Try every queued entry once, oldest first; returns how many are still queued
+    async fn drain(dir: &Path) -> usize {
+        let paths = match Self::pending(dir) {
+            Ok(paths) => paths,
+            Err(e) => {
+                warn!("Plugin HTTP outbox: {:#}", e);
+                return 1;
+            }
+        };
+
+        let mut remaining = 0;
+        for path in paths {
+            let entry = match std::fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| Ok(serde_json::from_slice::<OutboxEntry>(&bytes)?))
+            {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Plugin HTTP outbox: discarding unreadable entry {:?}: {}", path, e);
+                    let _ = std::fs::remove_file(&path);
+                    continue;
+                }
+            };
+
+            match Self::deliver(&entry).await {
+                Ok(()) => {
+                    debug!("Plugin HTTP outbox: delivered POST to {}", entry.url);
+                    if let Err(e) = std::fs::remove_file(&path) {
+                        warn!("Plugin HTTP outbox: failed to remove {:?}: {}", path, e);
+                    }
+                }
+                Err(e) => {
+                    debug!("Plugin HTTP outbox: POST to {} failed: {:#}", entry.url, e);
+                    remaining += 1;
+                }
+            }
+        }
+
+        remaining
+    }
+
+    /// Queued entry files, oldest first
+    fn pending(dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .with_context(|| format!("Failed to read outbox directory {:?}", dir))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// POST an entry; anything but a 2xx response counts as a failure
+    async fn deliver(entry: &OutboxEntry) -> Result<()> {
+        super::shared_client()
+            .post(&entry.url)
+            .timeout(POST_TIMEOUT)
+            .body(entry.body.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
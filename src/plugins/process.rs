@@ -0,0 +1,269 @@
+use crate::error::{BitchError, Result};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Minimum time between two starts of a plugin process, so one that exits right away
+/// isn't restarted for every message
+const RESTART_BACKOFF: Duration = Duration::from_secs(1);
+
+/// One line sent to the process
+#[derive(Serialize)]
+struct Request<'a, C: Serialize> {
+    hook: &'a str,
+    context: &'a C,
+}
+
+/// A started plugin process
+struct Running {
+    child: Child,
+    /// Closed (taken) to ask the process to exit
+    stdin: Option<ChildStdin>,
+    /// Lines the process wrote to stdout, read by a background thread
+    replies: Receiver<String>,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct ProcessState {
+    running: Option<Running>,
+    started: Instant,
+}
+
+/// Why a call failed
+enum CallError {
+    /// The process can't be talked to anymore (exited, pipe closed, no reply, garbled reply);
+    /// it is stopped and started again on the next call
+    Broken(String),
+    /// The process answered with `{"error": "..."}`
+    Failed(String),
+}
+
+/// A plugin run as an external process (`[plugins.process.<name>]`)
+/// Every hook call writes one JSON line `{"hook": "...", "context": {...}}` to the process's
+/// stdin and waits up to the plugin timeout for one JSON line back on its stdout. Lines it
+/// writes to stderr are logged. A process that exits or stops answering is started again
+/// (and sent `on_load` again) on the next call.
+pub struct ProcessPlugin {
+    name: String,
+    command: String,
+    args: Vec<String>,
+    /// Working directory of the process (`[plugins] directory`)
+    dir: PathBuf,
+    /// Context of the `on_load` call, repeated after every restart
+    load_context: JsonValue,
+    timeout: Duration,
+    state: Mutex<ProcessState>,
+}
+
+impl ProcessPlugin {
+    /// Start the process (`on_load` is sent by `load`)
+    pub fn start(
+        name: &str,
+        command: &str,
+        args: &[String],
+        dir: &Path,
+        load_context: JsonValue,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let plugin = Self {
+            name: name.to_string(),
+            command: command.to_string(),
+            args: args.to_vec(),
+            dir: dir.to_path_buf(),
+            load_context,
+            timeout,
+            state: Mutex::new(ProcessState {
+                running: None,
+                started: Instant::now(),
+            }),
+        };
+        let running = plugin.spawn()?;
+        plugin.state.lock().unwrap().running = Some(running);
+        Ok(plugin)
+    }
+
+    /// Send the `on_load` hook
+    pub fn load(&self) -> Result<()> {
+        self.call("on_load", &self.load_context).map(|_| ())
+    }
+
+    /// Run a hook in the process and return its reply
+    pub fn call(&self, hook: &str, context: &impl Serialize) -> Result<JsonValue> {
+        let line = serde_json::to_string(&Request { hook, context })
+            .map_err(|e| self.error(hook, format!("can't encode the context: {}", e)))?;
+
+        let mut state = self.state.lock().unwrap();
+        self.ensure_running(&mut state)?;
+        let running = state.running.as_mut().expect("started above");
+
+        match self.exchange(running, &line) {
+            Ok(reply) => Ok(reply),
+            Err(CallError::Failed(e)) => Err(self.error(hook, e)),
+            Err(CallError::Broken(e)) => {
+                state.running = None;
+                Err(self.error(hook, format!("{}; the process was stopped", e)))
+            }
+        }
+    }
+
+    /// Send `on_shutdown`, then close stdin and give the process the timeout to exit
+    pub fn shutdown(&self) -> Result<()> {
+        let context = serde_json::json!({ "plugin": self.name });
+        // A process that is down isn't restarted just to be shut down
+        let running = self.state.lock().unwrap().running.is_some();
+        let result = if running {
+            self.call("on_shutdown", &context).map(|_| ())
+        } else {
+            Ok(())
+        };
+
+        if let Some(mut running) = self.state.lock().unwrap().running.take() {
+            running.stdin.take();
+            let deadline = Instant::now() + self.timeout;
+            while Instant::now() < deadline {
+                if !matches!(running.child.try_wait(), Ok(None)) {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            // Dropping kills the process if it's still running
+        }
+
+        info!("Plugin process '{}' shut down", self.name);
+        result
+    }
+
+    /// Start the process again if it exited or was stopped
+    fn ensure_running(&self, state: &mut ProcessState) -> Result<()> {
+        if let Some(running) = &mut state.running {
+            match running.child.try_wait() {
+                Ok(None) => return Ok(()),
+                Ok(Some(status)) => warn!("Plugin process '{}' exited ({})", self.name, status),
+                Err(e) => warn!("Plugin process '{}' can't be checked: {}", self.name, e),
+            }
+            state.running = None;
+        }
+
+        if state.started.elapsed() < RESTART_BACKOFF {
+            return Err(BitchError::Process(format!(
+                "Plugin process '{}' is not running (restarting at most once per {:?})",
+                self.name, RESTART_BACKOFF
+            )));
+        }
+
+        let mut running = self.spawn()?;
+        state.started = Instant::now();
+        info!("Restarted plugin process '{}'", self.name);
+
+        let line = serde_json::to_string(&Request {
+            hook: "on_load",
+            context: &self.load_context,
+        })
+        .map_err(|e| self.error("on_load", format!("can't encode the context: {}", e)))?;
+        match self.exchange(&mut running, &line) {
+            Ok(_) => {}
+            Err(CallError::Failed(e)) => warn!("{}", self.error("on_load", e)),
+            Err(CallError::Broken(e)) => return Err(self.error("on_load", e)),
+        }
+
+        state.running = Some(running);
+        Ok(())
+    }
+
+    /// Write one request line and wait for the reply line
+    fn exchange(&self, running: &mut Running, line: &str) -> std::result::Result<JsonValue, CallError> {
+        // Nothing should be waiting, but a stale line would answer the wrong request
+        while running.replies.try_recv().is_ok() {}
+
+        let stdin = running
+            .stdin
+            .as_mut()
+            .ok_or_else(|| CallError::Broken("stdin is closed".to_string()))?;
+        writeln!(stdin, "{}", line)
+            .and_then(|_| stdin.flush())
+            .map_err(|e| CallError::Broken(format!("can't write to the process: {}", e)))?;
+
+        let reply = match running.replies.recv_timeout(self.timeout) {
+            Ok(reply) => reply,
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(CallError::Broken(format!("no reply within {:?}", self.timeout)))
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(CallError::Broken("the process closed its stdout".to_string()))
+            }
+        };
+
+        let reply: JsonValue = serde_json::from_str(&reply)
+            .map_err(|e| CallError::Broken(format!("reply is not JSON ({}): {}", e, reply)))?;
+        match reply.get("error") {
+            Some(JsonValue::String(error)) => Err(CallError::Failed(error.clone())),
+            Some(error) => Err(CallError::Failed(error.to_string())),
+            None => Ok(reply),
+        }
+    }
+
+    /// Start the process with piped stdio and the threads reading its output
+    fn spawn(&self) -> Result<Running> {
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .current_dir(&self.dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                BitchError::Process(format!(
+                    "Failed to start plugin process '{}' ({} in {:?}): {}",
+                    self.name, self.command, self.dir, e
+                ))
+            })?;
+
+        let stdin = child.stdin.take();
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+
+        let (sender, replies) = mpsc::channel();
+        let name = self.name.clone();
+        std::thread::Builder::new()
+            .name(format!("plugin-{}-stdout", self.name))
+            .spawn(move || {
+                for line in BufReader::new(stdout).lines() {
+                    let Ok(line) = line else { break };
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+                debug!("Plugin process '{}' closed its stdout", name);
+            })
+            .map_err(|e| BitchError::Process(format!("Failed to start plugin '{}' reader: {}", self.name, e)))?;
+
+        let name = self.name.clone();
+        std::thread::Builder::new()
+            .name(format!("plugin-{}-stderr", self.name))
+            .spawn(move || {
+                for line in BufReader::new(stderr).lines().map_while(std::result::Result::ok) {
+                    info!("[Plugin {}] {}", name, line);
+                }
+            })
+            .map_err(|e| BitchError::Process(format!("Failed to start plugin '{}' reader: {}", self.name, e)))?;
+
+        debug!("Started plugin process '{}' (pid {})", self.name, child.id());
+        Ok(Running { child, stdin, replies })
+    }
+
+    fn error(&self, hook: &str, message: impl std::fmt::Display) -> BitchError {
+        BitchError::Process(format!("Plugin '{}' {}() failed: {}", self.name, hook, message))
+    }
+}
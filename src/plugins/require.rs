@@ -0,0 +1,56 @@
+use mlua::{Lua, Table, Value};
+use std::path::{Path, PathBuf};
+
+/// Restrict `require` to modules in `dir` (`require("a.b")` loads `<dir>/a/b.lua`)
+/// Lua's own searchers are replaced, so the default package path, C modules and
+/// absolute or `..` paths are unreachable. With no `dir`, only `package.preload` resolves.
+pub fn install(lua: &Lua, dir: Option<&Path>) -> mlua::Result<()> {
+    let package: Table = lua.globals().get("package")?;
+    let default_searchers: Table = package.get("searchers")?;
+
+    let searchers = lua.create_table()?;
+    // Keep the package.preload searcher
+    searchers.push(default_searchers.get::<Value>(1)?)?;
+
+    if let Some(dir) = dir {
+        let dir = dir.to_path_buf();
+        let search = lua.create_function(move |lua, name: String| match module_path(&dir, &name) {
+            Ok(path) => {
+                let code = std::fs::read_to_string(&path).map_err(mlua::Error::external)?;
+                let loader = lua.load(code).set_name(format!("@{}", path.display())).into_function()?;
+                Ok((Value::Function(loader), Value::String(lua.create_string(path.to_string_lossy().as_bytes())?)))
+            }
+            // Searchers report a miss with a message that `require` appends to its error
+            Err(reason) => Ok((Value::String(lua.create_string(format!("\n\t{}", reason))?), Value::Nil)),
+        })?;
+        searchers.push(search)?;
+    }
+
+    package.set("searchers", searchers)?;
+    package.set("path", "")?;
+    package.set("cpath", "")?;
+    Ok(())
+}
+
+/// File a module name resolves to, as long as it stays inside `dir` (symlinks included)
+fn module_path(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    let plain = name
+        .split('.')
+        .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'));
+    if !plain {
+        return Err(format!("module '{}' is not a plain name like 'lib' or 'lib.util'", name));
+    }
+
+    let mut path = dir.to_path_buf();
+    path.extend(name.split('.'));
+    path.set_extension("lua");
+
+    let root = dir
+        .canonicalize()
+        .map_err(|e| format!("plugin directory {}: {}", dir.display(), e))?;
+    let resolved = path.canonicalize().map_err(|_| format!("no file '{}'", path.display()))?;
+    if !resolved.starts_with(&root) {
+        return Err(format!("'{}' is outside the plugin directory", path.display()));
+    }
+    Ok(resolved)
+}
@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+/// Live proxy status behind the plugins' `proxy` API
+/// Created with the plugin manager (before the proxy exists) and kept up to date by
+/// the proxy; plugins only ever read it.
+pub struct ProxyStatus {
+    /// Connected GCS clients
+    clients: AtomicUsize,
+    /// Router links currently up
+    routers: AtomicUsize,
+    /// When the proxy started running (unset until then)
+    started: OnceLock<Instant>,
+}
+
+impl ProxyStatus {
+    pub fn new() -> Self {
+        Self {
+            clients: AtomicUsize::new(0),
+            routers: AtomicUsize::new(0),
+            started: OnceLock::new(),
+        }
+    }
+
+    /// Record that the proxy is running (later calls are ignored)
+    pub fn mark_started(&self) {
+        let _ = self.started.set(Instant::now());
+    }
+
+    pub fn set_client_count(&self, count: usize) {
+        self.clients.store(count, Ordering::Relaxed);
+    }
+
+    pub fn router_up(&self) {
+        self.routers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn router_down(&self) {
+        self.routers.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.load(Ordering::Relaxed)
+    }
+
+    /// Whether at least one router link is up
+    pub fn router_connected(&self) -> bool {
+        self.routers.load(Ordering::Relaxed) > 0
+    }
+
+    /// Seconds since the proxy started running (0 before that)
+    pub fn uptime_seconds(&self) -> u64 {
+        self.started.get().map_or(0, |started| started.elapsed().as_secs())
+    }
+}
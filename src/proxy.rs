@@ -1,118 +1,671 @@
-use crate::batch::{BatchManager, BatchResult, Destination};
-use crate::config::Config;
+use crate::actions::{run_chain, ActionRegistry, ChainFuture};
+use crate::batch::{BatchManager, Destination};
+use crate::capture::Capture;
+use crate::coalesce::Coalescer;
+use crate::config::{
+    AnnounceConfig, ClientGroupConfig, Config, ConfigFile, Direction, ModifyErrorPolicy, NetworkConfig,
+};
+use crate::connections::ConnectionLog;
+use crate::drift::ConfigDrift;
+use crate::error::BitchError;
+use crate::framing::{CrcCheck, FrameFlags, FrameReader, RawHeader};
 use crate::modifiers::ModifierManager;
-use crate::plugins::PluginManager;
-use crate::rules::{parse_mavlink_message, Action, AckInfo, ProcessResult, RuleEngine};
+use crate::pacing::RadioPacer;
+use crate::plugins::{PluginManager, ProxyStatus, RouteContext, RouteVerdict};
+use crate::retry::RetryTracker;
+use crate::rules::{parse_mavlink_frame, Action, AckInfo, Ingress, ParsedFrame, ProcessResult, RuleEngine};
+use crate::signing::{Signer, Verification, Verifier};
+use crate::stats::{ClientInfo, ClientTraffic, ProxyStats};
+use crate::subscriptions::Subscriptions;
+use crate::tls::ClientIdentity;
+use crate::transport::{self, BoxedGcsStream, GcsReader, GcsWriter, RouterReader, RouterWriter, SocketOptions};
 use anyhow::{Context, Result};
 use mavlink::ardupilotmega::MavMessage;
 use mavlink::{MavHeader, MavlinkVersion};
 use std::collections::HashMap;
 use std::future::Future;
-use std::pin::Pin;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, Mutex, RwLock};
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
+/// First wait before retrying a failed router connect (doubles per attempt)
+const ROUTER_RETRY_INITIAL: Duration = Duration::from_secs(1);
+
+/// Longest wait between router connect attempts
+const ROUTER_RETRY_MAX: Duration = Duration::from_secs(30);
+
 /// Unique identifier for each GCS client
-type ClientId = u64;
+pub(crate) type ClientId = u64;
+
+/// A connected GCS client
+struct GcsClient {
+    writer: Arc<Mutex<GcsWriter>>,
+    /// Client group it was tagged with on connect
+    group: Option<String>,
+    address: SocketAddr,
+    connected_at: Instant,
+    /// Per-message-type frame counts in both directions
+    traffic: Arc<ClientTraffic>,
+    /// Cancelled to make the client's handler stop (admin disconnect)
+    disconnect: CancellationToken,
+}
 
 /// Shared state for the proxy
 pub struct ProxyState {
+    /// Handlers for each action type
+    actions: ActionRegistry,
     batch_manager: BatchManager,
-    /// Connected GCS clients (ClientId -> WriteHalf)
-    gcs_clients: RwLock<HashMap<ClientId, Arc<RwLock<tokio::net::tcp::OwnedWriteHalf>>>>,
+    burst_manager: BatchManager,
+    /// Latest-message holders for `coalesce` rules
+    coalescer: Coalescer,
+    /// Connected GCS clients (ClientId -> transport writer and client group)
+    gcs_clients: RwLock<HashMap<ClientId, GcsClient>>,
     /// Counter for generating unique client IDs
     next_client_id: AtomicU64,
+    /// Runtime counters
+    stats: ProxyStats,
+    /// Last time anything was broadcast to GCS clients (for keepalive)
+    last_gcs_broadcast: std::sync::Mutex<Instant>,
+    /// Fallback when a modified message can't be serialized
+    on_modify_error: ModifyErrorPolicy,
+    /// Messages waiting for an ACK (retry_until_ack)
+    retries: RetryTracker,
+    /// Dry-run engine for `[[shadow_rules]]` (decisions are logged, never executed)
+    shadow: Option<RuleEngine>,
+    /// RADIO_STATUS-driven GCS -> router pacing (if enabled)
+    pacer: Option<RadioPacer>,
+    /// Checks signatures of GCS frames (when a signing key is configured)
+    verifier: Option<Verifier>,
+    /// Drop GCS frames without a signature
+    reject_unsigned: bool,
+    /// Drop GCS frames whose signature is invalid or replayed
+    reject_bad_signature: bool,
+    /// Recent GCS connects/disconnects (for the admin API)
+    connections: ConnectionLog,
+    /// Raw per-direction frame capture (`logging.capture_dir`)
+    capture: Option<Capture>,
+    /// Client/router/uptime status shown to plugins (`proxy` API)
+    status: Arc<ProxyStatus>,
+    /// Plugin and function picking each frame's destination (`network.route_hook`)
+    route_hook: Option<(String, String)>,
+    /// Run self-built messages through the rules before sending (`network.process_injected`)
+    process_injected: bool,
+    /// Data streams each GCS client asked for (`network.stream_subscriptions`)
+    subscriptions: Option<Subscriptions>,
+    /// Loaded config files compared with what's on disk (`config_check_seconds`)
+    config_drift: ConfigDrift,
 }
 
 impl ProxyState {
-    pub fn new() -> Self {
+    pub fn new(
+        network: &NetworkConfig,
+        connection_log_size: usize,
+        shadow: Option<RuleEngine>,
+        capture: Option<Capture>,
+        status: Arc<ProxyStatus>,
+        config_files: Vec<ConfigFile>,
+    ) -> Self {
         Self {
+            actions: ActionRegistry::new(),
             batch_manager: BatchManager::new(),
+            burst_manager: BatchManager::bursts(),
+            coalescer: Coalescer::new(),
             gcs_clients: RwLock::new(HashMap::new()),
             next_client_id: AtomicU64::new(1),
+            stats: ProxyStats::new(),
+            last_gcs_broadcast: std::sync::Mutex::new(Instant::now()),
+            on_modify_error: network.on_modify_error,
+            retries: RetryTracker::new(),
+            shadow,
+            pacer: network.radio_pacing.clone().map(RadioPacer::new),
+            verifier: network.signing_key().map(Verifier::new),
+            reject_unsigned: network.reject_unsigned,
+            reject_bad_signature: network.reject_bad_signature,
+            connections: ConnectionLog::new(connection_log_size),
+            capture,
+            status,
+            route_hook: network
+                .route_hook()
+                .map(|(plugin, function)| (plugin.to_string(), function.to_string())),
+            process_injected: network.process_injected,
+            subscriptions: network.stream_subscriptions.then(Subscriptions::new),
+            config_drift: ConfigDrift::new(config_files),
+        }
+    }
+
+    /// Copy a frame as read from the wire to the capture file for its direction
+    fn capture(&self, direction: Direction, frame: &[u8]) {
+        if let Some(capture) = &self.capture {
+            capture.record(direction, frame);
+        }
+    }
+
+    /// Verify a GCS frame's signature; false if the frame must be dropped
+    fn admit_signed(&self, client_id: ClientId, packet: &[u8]) -> bool {
+        let Some(verifier) = &self.verifier else {
+            return true;
+        };
+
+        match verifier.verify(packet) {
+            Verification::Valid => {
+                self.stats.record_signature(true);
+                true
+            }
+            Verification::Unsigned if self.reject_unsigned => {
+                debug!("Dropping unsigned frame from GCS client {}", client_id);
+                self.stats.record_signature(false);
+                false
+            }
+            Verification::Invalid(reason) if self.reject_bad_signature => {
+                warn!("Dropping frame from GCS client {}: {}", client_id, reason);
+                self.stats.record_signature(false);
+                false
+            }
+            Verification::Invalid(reason) => {
+                debug!("Frame from GCS client {} has a bad signature ({}), forwarding", client_id, reason);
+                true
+            }
+            Verification::Unsigned => true,
+        }
+    }
+
+    /// Wait out the radio pacing delay before sending to the routers (no-op when not congested)
+    pub(crate) async fn pace(&self) {
+        if let Some(delay) = self.pacer.as_ref().and_then(RadioPacer::current_delay) {
+            sleep(delay).await;
+        }
+    }
+
+    /// Evaluate a message against the shadow ruleset and log where it disagrees with `live`
+    /// `ingress` is where the frame came in (the client group is set for GCS -> router frames only)
+    fn evaluate_shadow(&self, frame: &ParsedFrame, direction: Direction, ingress: Ingress, live: &ProcessResult) {
+        let Some(shadow) = &self.shadow else {
+            return;
+        };
+
+        let (header, msg) = (&frame.header, &frame.message);
+        let candidate = shadow.process_frame_from(frame, direction, ingress);
+        let live_actions: Vec<&str> = live.actions.iter().map(Action::name).collect();
+        let shadow_actions: Vec<&str> = candidate.actions.iter().map(Action::name).collect();
+        let mismatch = live_actions != shadow_actions;
+        self.stats.record_shadow(mismatch);
+
+        let describe = |rule: &Option<String>, actions: &[&str]| {
+            format!("{} [{}]", rule.as_deref().unwrap_or("<no rule>"), actions.join(" -> "))
+        };
+        if mismatch {
+            info!(
+                "Shadow mismatch for {} from sysid={} ({}): live {} vs shadow {}",
+                crate::rules::get_message_name(msg),
+                header.system_id,
+                direction,
+                describe(&live.matched_rule, &live_actions),
+                describe(&candidate.matched_rule, &shadow_actions)
+            );
+        } else if candidate.matched_rule.is_some() || live.matched_rule.is_some() {
+            debug!(
+                "Shadow agrees for {} from sysid={}: live {} / shadow {}",
+                crate::rules::get_message_name(msg),
+                header.system_id,
+                describe(&live.matched_rule, &live_actions),
+                describe(&candidate.matched_rule, &shadow_actions)
+            );
         }
     }
 
-    /// Add a new GCS client and return its ID
-    pub async fn add_gcs_client(&self, writer: tokio::net::tcp::OwnedWriteHalf) -> ClientId {
+    /// Ask the route hook where a parsed frame goes
+    /// Without a hook, or when the hook fails or times out, the rules' routing stands.
+    fn route(
+        &self,
+        rule_engine: &RuleEngine,
+        frame: &ParsedFrame,
+        direction: Direction,
+        client_id: Option<ClientId>,
+        client_group: Option<&str>,
+        router: Option<&str>,
+    ) -> RouteVerdict {
+        let Some((plugin, function)) = &self.route_hook else {
+            return RouteVerdict::Forward;
+        };
+
+        let message = match serde_json::to_value(&frame.message) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Failed to serialize message for route_hook, keeping default routing: {}", e);
+                return RouteVerdict::Forward;
+            }
+        };
+        let context = RouteContext {
+            direction: direction.to_string(),
+            system_id: frame.header.system_id,
+            component_id: frame.header.component_id,
+            message_type: crate::rules::get_message_name(&frame.message),
+            message,
+            client_id,
+            client_group: client_group.map(str::to_string),
+            router: router.map(str::to_string),
+        };
+
+        match rule_engine.route(plugin, function, &context) {
+            Ok(verdict) => verdict,
+            Err(e) => {
+                warn!("route_hook failed, keeping default routing: {}", e);
+                RouteVerdict::Forward
+            }
+        }
+    }
+
+    /// Handlers used to run action chains
+    pub(crate) fn actions(&self) -> &ActionRegistry {
+        &self.actions
+    }
+
+    /// Latest-message holders for `coalesce` rules
+    pub(crate) fn coalescer(&self) -> &Coalescer {
+        &self.coalescer
+    }
+
+    /// Messages waiting for an ACK (`retry_until_ack`)
+    pub(crate) fn retries(&self) -> &RetryTracker {
+        &self.retries
+    }
+
+    /// Fallback when a modified message can't be serialized (`on_modify_error`)
+    pub(crate) fn on_modify_error(&self) -> ModifyErrorPolicy {
+        self.on_modify_error
+    }
+
+    /// Pending batch groups
+    pub fn batch_manager(&self) -> &BatchManager {
+        &self.batch_manager
+    }
+
+    /// Pending burst groups
+    pub fn burst_manager(&self) -> &BatchManager {
+        &self.burst_manager
+    }
+
+    /// Runtime counters
+    pub fn stats(&self) -> &ProxyStats {
+        &self.stats
+    }
+
+    /// Loaded config files and which of them changed on disk
+    pub(crate) fn config_drift(&self) -> &ConfigDrift {
+        &self.config_drift
+    }
+
+    /// Client/router/uptime status
+    pub(crate) fn status(&self) -> &ProxyStatus {
+        &self.status
+    }
+
+    /// GCS connection event log
+    pub fn connections(&self) -> &ConnectionLog {
+        &self.connections
+    }
+
+    /// Add a new GCS client (tagged with its client group, if any) and return its ID
+    pub async fn add_gcs_client(&self, writer: GcsWriter, addr: SocketAddr, group: Option<String>) -> ClientId {
         let client_id = self.next_client_id.fetch_add(1, Ordering::SeqCst);
         let mut clients = self.gcs_clients.write().await;
-        clients.insert(client_id, Arc::new(RwLock::new(writer)));
-        info!("GCS client {} connected (total: {})", client_id, clients.len());
+        let client = GcsClient {
+            writer: Arc::new(Mutex::new(writer)),
+            group,
+            address: addr,
+            connected_at: Instant::now(),
+            traffic: Arc::new(ClientTraffic::default()),
+            disconnect: CancellationToken::new(),
+        };
+        clients.insert(client_id, client);
+        self.connections.connected(addr, client_id);
+        self.status.set_client_count(clients.len());
+        info!("GCS client {} ({}) connected (total: {})", client_id, addr, clients.len());
         client_id
     }
 
-    /// Remove a GCS client
-    pub async fn remove_gcs_client(&self, client_id: ClientId) {
+    /// Remove a GCS client, recording how long it was connected and why it left
+    pub async fn remove_gcs_client(&self, client_id: ClientId, addr: SocketAddr, connected_for: Duration, cause: &str) {
         let mut clients = self.gcs_clients.write().await;
         clients.remove(&client_id);
-        info!("GCS client {} disconnected (remaining: {})", client_id, clients.len());
+        if let Some(subscriptions) = &self.subscriptions {
+            subscriptions.remove(client_id);
+        }
+        self.connections.disconnected(addr, client_id, connected_for, cause);
+        self.status.set_client_count(clients.len());
+        info!(
+            "GCS client {} ({}) disconnected after {:.1}s: {} (remaining: {})",
+            client_id,
+            addr,
+            connected_for.as_secs_f64(),
+            cause,
+            clients.len()
+        );
+    }
+
+    /// Frame counters of a specific GCS client
+    pub(crate) async fn client_traffic(&self, client_id: ClientId) -> Option<Arc<ClientTraffic>> {
+        let clients = self.gcs_clients.read().await;
+        clients.get(&client_id).map(|client| client.traffic.clone())
     }
 
-    /// Get a clone of a specific GCS client writer
-    pub async fn get_gcs_client(&self, client_id: ClientId) -> Option<Arc<RwLock<tokio::net::tcp::OwnedWriteHalf>>> {
+    /// Token that is cancelled when a specific GCS client should be disconnected
+    pub(crate) async fn disconnect_token(&self, client_id: ClientId) -> Option<CancellationToken> {
         let clients = self.gcs_clients.read().await;
-        clients.get(&client_id).cloned()
+        clients.get(&client_id).map(|client| client.disconnect.clone())
+    }
+
+    /// Disconnect a GCS client: it gets no more frames and its handler stops reading
+    /// The handler logs the disconnect once it has stopped. False if no such client is connected.
+    pub async fn disconnect_gcs_client(&self, client_id: ClientId) -> bool {
+        let mut clients = self.gcs_clients.write().await;
+        let Some(client) = clients.remove(&client_id) else {
+            return false;
+        };
+        client.disconnect.cancel();
+        self.status.set_client_count(clients.len());
+        true
+    }
+
+    /// Connected GCS clients with their per-message-type traffic, by client ID
+    pub async fn clients(&self) -> Vec<ClientInfo> {
+        let clients = self.gcs_clients.read().await;
+        let mut info: Vec<ClientInfo> = clients
+            .iter()
+            .map(|(client_id, client)| ClientInfo {
+                client_id: *client_id,
+                address: client.address.to_string(),
+                group: client.group.clone(),
+                connected_seconds: client.connected_at.elapsed().as_secs_f64(),
+                sent: client.traffic.sent(),
+                received: client.traffic.received(),
+            })
+            .collect();
+        info.sort_by_key(|client| client.client_id);
+        info
+    }
+
+    /// Restart the GCS idle timer used by the keepalive
+    fn mark_gcs_broadcast(&self) {
+        *self.last_gcs_broadcast.lock().unwrap() = Instant::now();
+    }
+
+    /// Time since anything was last broadcast to GCS clients
+    pub fn gcs_idle_time(&self) -> Duration {
+        self.last_gcs_broadcast.lock().unwrap().elapsed()
     }
 
     /// Broadcast a packet to all connected GCS clients
     pub async fn broadcast_to_all_gcs(&self, packet: &[u8]) {
-        let clients = self.gcs_clients.read().await;
+        self.broadcast(packet, None).await;
+    }
+
+    /// Send a packet to the connected GCS clients of one client group
+    pub async fn broadcast_to_group(&self, group: &str, packet: &[u8]) {
+        self.broadcast(packet, Some(group)).await;
+    }
 
-        for (client_id, writer) in clients.iter() {
-            let mut stream = writer.write().await;
-            if let Err(e) = stream.write_all(packet).await {
+    /// Send a packet to one GCS client; false if it's gone or the write failed
+    pub async fn send_to_gcs_client(&self, client_id: ClientId, packet: &[u8]) -> bool {
+        let clients = self.gcs_clients.read().await;
+        let Some(client) = clients.get(&client_id) else {
+            return false;
+        };
+        let mut writer = client.writer.lock().await;
+        match writer.send_frame(packet).await {
+            Ok(()) => {
+                client.traffic.record_received(packet);
+                true
+            }
+            Err(e) => {
                 error!("Failed to send to GCS client {}: {}", client_id, e);
+                false
+            }
+        }
+    }
+
+    /// Send a message BITCH built itself (auto-ACK, keepalive, announcement or status)
+    /// With `network.process_injected` the rules see it first, as traffic in `direction`, and the
+    /// matched rule's actions decide what is sent. Auto-ACKs for injected messages are never
+    /// sent, so an injected message can't cause another injection.
+    /// Returns whether it was sent as is; false if the send failed or the matched rule's
+    /// actions took it over (blocked, delayed, modified...).
+    pub(crate) async fn inject(
+        self: &Arc<Self>,
+        rule_engine: &RuleEngine,
+        packet: Vec<u8>,
+        direction: Direction,
+        destination: Destination,
+    ) -> bool {
+        if !self.process_injected {
+            return self.send_injected(&destination, &packet).await;
+        }
+
+        let actions = match parse_mavlink_frame(&packet) {
+            Ok(frame) => {
+                let result = rule_engine.process_frame(&frame, direction);
+                if let Some(ack_info) = result.ack_info {
+                    debug!(
+                        "Not sending {} for an injected {} (injected messages are never acknowledged)",
+                        ack_info.message_type,
+                        crate::rules::get_message_name(&frame.message)
+                    );
+                }
+                result.actions
+            }
+            Err(e) => {
+                warn!("Failed to parse injected message for rule processing, sending it as is: {:#}", e);
+                Vec::new()
+            }
+        };
+
+        if actions.is_empty() || matches!(actions.as_slice(), [Action::Forward]) {
+            // Paced like every other frame the rules let through to a router
+            if matches!(destination, Destination::Router(_)) {
+                self.pace().await;
             }
+            return self.send_injected(&destination, &packet).await;
         }
+        execute_actions_impl(actions, vec![packet], destination, self.clone()).await;
+        false
     }
+
+    /// Send an injected packet as is, returning whether it went out
+    async fn send_injected(&self, destination: &Destination, packet: &[u8]) -> bool {
+        match destination {
+            Destination::Router(routers) => send_to_routers(routers, packet).await,
+            Destination::Gcs => {
+                self.broadcast_to_all_gcs(packet).await;
+                true
+            }
+            Destination::GcsGroup(group) => {
+                self.broadcast_to_group(group, packet).await;
+                true
+            }
+            Destination::GcsClient(client_id) => self.send_to_gcs_client(*client_id, packet).await,
+        }
+    }
+
+    /// Send a packet to every GCS client, or only those in `group`
+    async fn broadcast(&self, packet: &[u8], group: Option<&str>) {
+        self.mark_gcs_broadcast();
+
+        let clients = self.gcs_clients.read().await;
+
+        for (client_id, client) in clients.iter() {
+            if group.is_some() && client.group.as_deref() != group {
+                continue;
+            }
+            if let Some(subscriptions) = &self.subscriptions {
+                if !subscriptions.allows(*client_id, packet) {
+                    continue;
+                }
+            }
+            let mut writer = client.writer.lock().await;
+            match writer.send_frame(packet).await {
+                Ok(()) => client.traffic.record_received(packet),
+                Err(e) => error!("Failed to send to GCS client {}: {}", client_id, e),
+            }
+        }
+    }
+}
+
+/// Write side of one router connection (TCP or serial)
+/// Frames go through a bounded queue to a dedicated writer task, so senders never
+/// wait on the router socket itself. A router that stops draining the queue for
+/// `write_timeout` is treated as stalled and the link is closed.
+pub struct RouterLink {
+    /// Router address ("host:port") or serial device, for logging
+    pub addr: String,
+    /// Ingress identity for `source` conditions ("tcp:host:port" or "serial:<device>")
+    pub source: String,
+    queue: mpsc::Sender<Vec<u8>>,
+    /// How long a sender waits for queue space before giving up on the link
+    write_timeout: Duration,
+    /// Cancelled when the link is stalled or its writer failed
+    closed: CancellationToken,
 }
 
-/// Read a single MAVLink packet from an async reader
-async fn read_mavlink_packet<R: AsyncReadExt + Unpin>(reader: &mut R) -> Result<Vec<u8>> {
-    // MAVLink v2 magic byte
-    const MAVLINK_V2_MAGIC: u8 = 0xFD;
-
-    // Read until we find a magic byte
-    let magic = loop {
-        let mut byte = [0u8; 1];
-        reader.read_exact(&mut byte).await.context("Failed to read magic byte")?;
-        if byte[0] == MAVLINK_V2_MAGIC {
-            break byte[0];
-        }
-    };
-
-    // Read payload length and incompatibility flags
-    let mut header_buf = [0u8; 2];
-    reader.read_exact(&mut header_buf).await.context("Failed to read header")?;
-    let payload_len = header_buf[0] as usize;
-
-    // Read rest of header (7 more bytes after magic, len, incompat)
-    let mut rest_header = [0u8; 7];
-    reader.read_exact(&mut rest_header).await.context("Failed to read rest of header")?;
-
-    // Read payload
-    let mut payload = vec![0u8; payload_len];
-    reader.read_exact(&mut payload).await.context("Failed to read payload")?;
-
-    // Read checksum (2 bytes)
-    let mut checksum = [0u8; 2];
-    reader.read_exact(&mut checksum).await.context("Failed to read checksum")?;
-
-    // Reconstruct complete packet
-    let mut packet = Vec::with_capacity(10 + payload_len + 2);
-    packet.push(magic);
-    packet.extend_from_slice(&header_buf);
-    packet.extend_from_slice(&rest_header);
-    packet.extend_from_slice(&payload);
-    packet.extend_from_slice(&checksum);
-
-    Ok(packet)
+impl RouterLink {
+    /// Start the writer task for a router connection
+    /// Frames are signed in the writer task when `signer` is set (`sign_outbound`)
+    pub fn spawn(
+        addr: String,
+        source: String,
+        writer: RouterWriter,
+        signer: Option<Arc<Signer>>,
+        queue_size: usize,
+        write_timeout: Duration,
+    ) -> Self {
+        let (queue, frames) = mpsc::channel(queue_size);
+        let closed = CancellationToken::new();
+        tokio::spawn(Self::write_loop(addr.clone(), writer, signer, frames, closed.clone()));
+
+        Self {
+            addr,
+            source,
+            queue,
+            write_timeout,
+            closed,
+        }
+    }
+
+    /// Queue a packet for this router
+    /// Waits up to `write_timeout` when the queue is full, then closes the link
+    pub async fn send(&self, packet: &[u8]) -> std::io::Result<()> {
+        if self.closed.is_cancelled() {
+            return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "router link closed"));
+        }
+
+        let packet = match self.queue.try_send(packet.to_vec()) {
+            Ok(()) => return Ok(()),
+            Err(mpsc::error::TrySendError::Full(packet)) => packet,
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "router link closed"));
+            }
+        };
+
+        // Queue full: apply backpressure to the sender, but not forever
+        warn!("Write queue for router {} is full, waiting", self.addr);
+        match self.queue.send_timeout(packet, self.write_timeout).await {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::SendTimeoutError::Timeout(_)) => {
+                error!(
+                    "Router {} accepted no data for {:?}, closing the link",
+                    self.addr, self.write_timeout
+                );
+                self.close();
+                Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "router write queue stalled"))
+            }
+            Err(mpsc::error::SendTimeoutError::Closed(_)) => {
+                Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "router link closed"))
+            }
+        }
+    }
+
+    /// Close the link, stopping its writer task
+    pub fn close(&self) {
+        self.closed.cancel();
+    }
+
+    /// Resolves once the link has been closed (stalled, write error or reader gone)
+    pub async fn closed(&self) {
+        self.closed.cancelled().await
+    }
+
+    /// Drain the queue into the router connection until it fails or the link is closed
+    async fn write_loop(
+        addr: String,
+        mut writer: RouterWriter,
+        signer: Option<Arc<Signer>>,
+        mut frames: mpsc::Receiver<Vec<u8>>,
+        closed: CancellationToken,
+    ) {
+        loop {
+            let packet = tokio::select! {
+                _ = closed.cancelled() => break,
+                packet = frames.recv() => match packet {
+                    Some(packet) => packet,
+                    None => break,
+                },
+            };
+
+            // Signed here, in queue order, so timestamps reach the router in increasing order
+            let signed = signer.as_ref().and_then(|signer| {
+                let signed = signer.sign(&packet);
+                if signed.is_none() {
+                    debug!("Can't sign frame for router {}, sending unsigned", addr);
+                }
+                signed
+            });
+
+            let result = tokio::select! {
+                _ = closed.cancelled() => break,
+                result = writer.write_all(signed.as_deref().unwrap_or(&packet)) => result,
+            };
+            if let Err(e) = result {
+                error!("Failed to write to router {}: {}", addr, e);
+                closed.cancel();
+                break;
+            }
+        }
+
+        debug!("Router {} writer stopped", addr);
+    }
+}
+
+/// Every configured router; GCS -> router traffic is mirrored to all of them
+pub type RouterLinks = Arc<Vec<Arc<RouterLink>>>;
+
+/// Write a packet to every router, logging (and skipping) failed links
+pub(crate) async fn send_to_routers(routers: &RouterLinks, packet: &[u8]) -> bool {
+    let mut sent = false;
+    for router in routers.iter() {
+        match router.send(packet).await {
+            Ok(()) => sent = true,
+            Err(e) => error!("Failed to forward packet to router {}: {}", router.addr, e),
+        }
+    }
+    sent
+}
+
+/// Await a connection read, failing with `Elapsed` if no frame arrives within the idle timeout
+async fn read_with_idle_timeout<T>(
+    read: impl Future<Output = Result<T>>,
+    idle_timeout: Option<Duration>,
+) -> Result<T> {
+    match idle_timeout {
+        Some(limit) => tokio::time::timeout(limit, read).await?,
+        None => read.await,
+    }
 }
 
 /// Main proxy server that handles bidirectional TCP forwarding
@@ -127,7 +680,7 @@ impl ProxyServer {
         config: Config,
         plugin_manager: PluginManager,
         modifier_manager: ModifierManager,
-    ) -> Result<Self> {
+    ) -> Result<Self, BitchError> {
         // Initialize rule state manager with default states from config
         let initial_states: std::collections::HashMap<String, bool> = config
             .rules
@@ -137,283 +690,133 @@ impl ProxyServer {
 
         let state_manager = Arc::new(crate::rule_state::RuleStateManager::new(initial_states));
 
+        // Plugins read client/router status through this handle
+        let status = plugin_manager.status();
+
         // Spawn background task to clean up expired rule activations
         state_manager.clone().spawn_cleanup_task();
 
-        let rule_engine = RuleEngine::new(
+        let mut rule_engine = RuleEngine::new(
             config.rules.clone(),
             plugin_manager,
             modifier_manager,
             state_manager,
+            config.max_reprocess_depth,
         )?;
+        rule_engine.set_simplify_actions(config.simplify_actions);
+        rule_engine.set_on_modify_error(config.network.on_modify_error);
+        if let Some(seed) = config.modifiers.random_seed {
+            rule_engine.set_random_seed(seed);
+        }
+
+        // Shadow ruleset: its own rule states, so its triggers never touch the live rules
+        let shadow = if config.shadow_rules.is_empty() {
+            None
+        } else {
+            let shadow_states = config
+                .shadow_rules
+                .iter()
+                .map(|rule| (rule.name.clone(), rule.enabled_by_default))
+                .collect();
+            let shadow_state_manager = Arc::new(crate::rule_state::RuleStateManager::new(shadow_states));
+            shadow_state_manager.clone().spawn_cleanup_task();
+            info!("Shadow ruleset loaded: {} rule(s), evaluated in dry-run", config.shadow_rules.len());
+            let mut shadow = RuleEngine::new_shadow(
+                config.shadow_rules.clone(),
+                shadow_state_manager,
+                config.max_reprocess_depth,
+            )?;
+            shadow.set_simplify_actions(config.simplify_actions);
+            shadow.share_quarantine(rule_engine.quarantine().clone());
+            Some(shadow)
+        };
+
+        let capture = config
+            .logging
+            .capture_dir
+            .as_deref()
+            .map(|dir| Capture::start(Path::new(dir)))
+            .transpose()
+            .map_err(|e| BitchError::Config(format!("{:#}", e)))?;
+
+        let state = Arc::new(ProxyState::new(
+            &config.network,
+            config.logging.connection_log_size,
+            shadow,
+            capture,
+            status,
+            config.files.clone(),
+        ));
 
         Ok(Self {
             config: Arc::new(config),
             rule_engine: Arc::new(rule_engine),
-            state: Arc::new(ProxyState::new()),
+            state,
         })
     }
 }
 
-/// Execute actions and broadcast result to all GCS clients
-pub fn execute_actions_impl_broadcast(
-    mut actions: Vec<Action>,
-    packets: Vec<Vec<u8>>,
-    state: Arc<ProxyState>,
-) -> Pin<Box<dyn Future<Output = ()> + Send>> {
-    Box::pin(async move {
-        if actions.is_empty() {
-            // No actions, broadcast all packets
-            for packet in packets {
-                state.broadcast_to_all_gcs(&packet).await;
-            }
-            return;
-        }
-
-        // Take first action and process
-        let action = actions.remove(0);
-        let remaining_actions = actions;
-
-        match action {
-            Action::Forward => {
-                // Forward and continue with remaining actions
-                execute_actions_impl_broadcast(remaining_actions, packets, state).await;
-            }
-            Action::Block => {
-                warn!("Message(s) blocked by rule (broadcast direction)");
-            }
-            Action::Modify {
-                modifier,
-                modified_message,
-            } => {
-                if let Some(modified_msg) = modified_message {
-                    info!("Applying modification from '{}' (Router->GCS broadcast)", modifier);
-
-                    let mut modified_packets = Vec::new();
-                    for packet in packets {
-                        if let Ok((header, _original_msg)) = parse_mavlink_message(&packet) {
-                            let mut buf = Vec::new();
-                            if let Err(e) = mavlink::write_versioned_msg(
-                                &mut buf,
-                                MavlinkVersion::V2,
-                                header,
-                                &modified_msg,
-                            ) {
-                                error!("Failed to serialize modified message: {}", e);
-                                modified_packets.push(packet);
-                            } else {
-                                modified_packets.push(buf);
-                            }
-                        } else {
-                            warn!("Failed to parse packet for modification, using original");
-                            modified_packets.push(packet);
-                        }
-                    }
-
-                    execute_actions_impl_broadcast(remaining_actions, modified_packets, state).await;
-                } else {
-                    warn!("Modify action has no modified message, forwarding original");
-                    execute_actions_impl_broadcast(remaining_actions, packets, state).await;
-                }
-            }
-            Action::Delay(duration) => {
-                let delay_secs = duration.as_secs();
-                info!("Message(s) queued for {}s delay (broadcast)", delay_secs);
-
-                tokio::spawn(async move {
-                    sleep(duration).await;
-                    execute_actions_impl_broadcast(remaining_actions, packets, state).await;
-                    info!("Delayed broadcast message(s) forwarded after {}s", delay_secs);
-                });
-            }
-            Action::Batch { .. } => {
-                warn!("Batch action not supported in router->GCS direction, forwarding");
-                execute_actions_impl_broadcast(remaining_actions, packets, state).await;
-            }
-        }
-    })
-}
-
 /// Execute a sequence of actions on multiple packets
 /// Called from message handlers and batch timeout handlers
 pub fn execute_actions_impl(
-    mut actions: Vec<Action>,
+    actions: Vec<Action>,
     packets: Vec<Vec<u8>>,
     destination: Destination,
     state: Arc<ProxyState>,
-) -> Pin<Box<dyn Future<Output = ()> + Send>> {
-    Box::pin(async move {
-        if actions.is_empty() {
-            // No actions, forward all packets
-            for packet in packets {
-                let Destination::Router(writer) = &destination;
-                let mut stream = writer.write().await;
-                if let Err(e) = stream.write_all(&packet).await {
-                    error!("Failed to forward packet to router: {}", e);
-                }
-            }
-            return;
-        }
-
-        // Take first action and process
-        let action = actions.remove(0);
-        let remaining_actions = actions;
-
-        match action {
-            Action::Forward => {
-                // Forward and continue with remaining actions
-                execute_actions_impl(remaining_actions, packets, destination, state).await;
-            }
-            Action::Block => {
-                warn!("Message(s) blocked by rule");
-                // Don't process remaining actions
-            }
-            Action::Modify {
-                modifier,
-                modified_message,
-            } => {
-                // Modify action: replace message content with modified version
-                if let Some(modified_msg) = modified_message {
-                    info!("Applying modification from '{}' (GCS->Router)", modifier);
-
-                    // Reconstruct packet with modified message
-                    let mut modified_packets = Vec::new();
-
-                    for packet in packets {
-                        // Parse original packet to get header
-                        if let Ok((header, _original_msg)) = parse_mavlink_message(&packet) {
-                            // Serialize modified message
-                            let mut buf = Vec::new();
-                            if let Err(e) = mavlink::write_versioned_msg(
-                                &mut buf,
-                                MavlinkVersion::V2,
-                                header,
-                                &modified_msg,
-                            ) {
-                                error!("Failed to serialize modified message: {}", e);
-                                modified_packets.push(packet); // Use original on error
-                            } else {
-                                modified_packets.push(buf);
-                            }
-                        } else {
-                            warn!("Failed to parse packet for modification, using original");
-                            modified_packets.push(packet);
-                        }
-                    }
-
-                    // Continue with remaining actions using modified packets
-                    execute_actions_impl(
-                        remaining_actions,
-                        modified_packets,
-                        destination,
-                        state,
-                    )
-                    .await;
-                } else {
-                    warn!("Modify action has no modified message, forwarding original");
-                    execute_actions_impl(remaining_actions, packets, destination, state)
-                        .await;
-                }
-            }
-            Action::Delay(duration) => {
-                // Spawn task to delay then continue with remaining actions
-                let delay_secs = duration.as_secs();
-                info!(
-                    "Message(s) queued for {}s delay (other traffic continues)",
-                    delay_secs
-                );
-
-                tokio::spawn(async move {
-                    sleep(duration).await;
-                    execute_actions_impl(remaining_actions, packets, destination, state)
-                        .await;
-                    info!("Delayed message(s) forwarded after {}s", delay_secs);
-                });
-            }
-            Action::Batch {
-                count,
-                timeout,
-                key,
-                forward_on_timeout,
-                system_id_field,
-            } => {
-                // Batch action only makes sense for single packets
-                if packets.len() != 1 {
-                    warn!("Batch action applied to {} packets, only batching first", packets.len());
-                }
+) -> ChainFuture {
+    run_chain(actions, packets, destination, state)
+}
 
-                let packet = packets.into_iter().next().unwrap();
+impl ProxyServer {
+    /// Check a TLS client's certificate against the allowlist (always passes when no allowlist is set)
+    fn check_client_certificate(
+        tls_stream: &tokio_rustls::server::TlsStream<TcpStream>,
+        allowed_clients: &[String],
+        gcs_addr: std::net::SocketAddr,
+    ) -> bool {
+        if allowed_clients.is_empty() {
+            return true;
+        }
 
-                // Extract system ID (generic for all message types)
-                let system_id = if let Ok((header, msg)) = parse_mavlink_message(&packet) {
-                    if let Some(ref field_name) = system_id_field {
-                        // Extract from specified message field
-                        ProxyServer::extract_system_id_from_message(&msg, field_name).unwrap_or(header.system_id)
-                    } else {
-                        // Default: use header system_id
-                        header.system_id
-                    }
-                } else {
-                    0
-                };
+        let Some(cert) = tls_stream.get_ref().1.peer_certificates().and_then(|certs| certs.first()) else {
+            warn!("Rejected GCS connection from {}: no client certificate presented", gcs_addr);
+            return false;
+        };
 
-                let batch_result = state
-                    .batch_manager
-                    .queue_or_release(
-                        key.clone(),
-                        system_id,
-                        packet,
-                        count,
-                        timeout,
-                        forward_on_timeout,
-                        remaining_actions.clone(),
-                        destination.clone(),
-                        state.clone(),
-                    )
-                    .await;
-
-                match batch_result {
-                    BatchResult::Queued => {
-                        // Packet queued, nothing more to do
-                    }
-                    BatchResult::Release {
-                        packets,
-                        remaining_actions,
-                    } => {
-                        // Threshold met, apply remaining actions to all packets
-                        info!(
-                            "Batch '{}' threshold met, applying {} remaining action(s) to {} packets",
-                            key,
-                            remaining_actions.len(),
-                            packets.len()
-                        );
-                        execute_actions_impl(
-                            remaining_actions,
-                            packets,
-                            destination,
-                            state,
-                        )
-                        .await;
-                    }
-                }
-            }
+        let identity = crate::tls::client_identity(cert);
+        if crate::tls::is_client_allowed(&identity, allowed_clients) {
+            info!("GCS client certificate accepted from {}: {}", gcs_addr, identity);
+            true
+        } else {
+            warn!(
+                "Rejected GCS connection from {}: certificate not in gcs_allowed_clients ({})",
+                gcs_addr, identity
+            );
+            false
         }
-        })
-}
+    }
 
-impl ProxyServer {
-    /// Extract system_id from a message field generically
-    fn extract_system_id_from_message(msg: &MavMessage, field_name: &str) -> Option<u8> {
-        // Serialize message to JSON (mavlink internally-tagged format)
-        let message_json = serde_json::to_value(msg).ok()?;
+    /// Identity of the certificate a TLS client presented, if any
+    fn peer_identity(tls_stream: &tokio_rustls::server::TlsStream<TcpStream>) -> Option<ClientIdentity> {
+        tls_stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .map(crate::tls::client_identity)
+    }
 
-        // Extract field value directly
-        let field_value = message_json.get(field_name)?;
-        field_value.as_u64().map(|v| v as u8)
+    /// Rule engine used by this server
+    pub fn rule_engine(&self) -> &RuleEngine {
+        &self.rule_engine
     }
 
     /// Build a generic ACK message (works for ANY message type)
-    fn build_ack(ack_info: &AckInfo) -> Result<Vec<u8>> {
+    pub fn build_ack(ack_info: &AckInfo) -> Result<Vec<u8>, BitchError> {
+        Self::build_ack_frame(ack_info).map_err(|e| BitchError::Mavlink(format!("{:#}", e)))
+    }
+
+    fn build_ack_frame(ack_info: &AckInfo) -> Result<Vec<u8>> {
         // Start with fields from config
         let mut fields_json = serde_json::Map::new();
 
@@ -474,17 +877,216 @@ impl ProxyServer {
         Ok(buf)
     }
 
-    /// Start the proxy server
-    pub async fn run(&self) -> Result<()> {
-        info!("BITCH MAVLINK Interceptor starting...");
+    /// Run a frame the dialect can't decode through the rules by its raw header
+    /// Frames without a readable header are forwarded as they are.
+    fn process_unparsed(
+        state: &ProxyState,
+        rule_engine: &RuleEngine,
+        packet: &[u8],
+        direction: Direction,
+        ingress: Ingress,
+    ) -> ProcessResult {
+        let Some(raw) = RawHeader::of(packet) else {
+            debug!("Failed to parse {} message, forwarding anyway", direction);
+            return ProcessResult {
+                actions: vec![Action::Forward],
+                ack_info: None,
+                matched_rule: None,
+            };
+        };
+        debug!("Failed to decode msgid {} ({}), matching it as UNKNOWN", raw.message_id, direction);
+        state.stats().record_unparsed(raw.message_id);
+        rule_engine.process_unparsed(&raw, FrameFlags::of(packet), direction, ingress)
+    }
+
+    /// Build a keepalive HEARTBEAT packet (onboard controller, no autopilot)
+    fn build_keepalive(system_id: u8, component_id: u8, sequence: u8) -> Result<Vec<u8>> {
+        let fields = serde_json::json!({
+            "mavtype": "MAV_TYPE_ONBOARD_CONTROLLER",
+            "autopilot": "MAV_AUTOPILOT_INVALID",
+            "system_status": "MAV_STATE_ACTIVE",
+            "mavlink_version": 3,
+        });
+        let fields = fields.as_object().cloned().unwrap_or_default();
+        let msg = crate::messages::build_message("HEARTBEAT", &fields)
+            .context("Failed to build keepalive HEARTBEAT")?;
+
+        let header = MavHeader {
+            system_id,
+            component_id,
+            sequence,
+        };
+
+        let mut buf = Vec::new();
+        mavlink::write_versioned_msg(&mut buf, MavlinkVersion::V2, header, &msg)
+            .context("Failed to serialize keepalive HEARTBEAT")?;
+
+        Ok(buf)
+    }
+
+    /// Inject a HEARTBEAT to all GCS clients whenever router -> GCS traffic has been idle for `interval`
+    async fn keepalive_loop(
+        state: Arc<ProxyState>,
+        rule_engine: Arc<RuleEngine>,
+        interval: Duration,
+        system_id: u8,
+        component_id: u8,
+    ) {
+        info!("GCS keepalive enabled: HEARTBEAT after {}s of silence", interval.as_secs());
+        let mut sequence: u8 = 0;
+
+        loop {
+            let idle = state.gcs_idle_time();
+            if idle < interval {
+                sleep(interval - idle).await;
+                continue;
+            }
+
+            match Self::build_keepalive(system_id, component_id, sequence) {
+                Ok(packet) => {
+                    debug!("Injecting keepalive HEARTBEAT to GCS clients");
+                    state.inject(&rule_engine, packet, Direction::RouterToGcs, Destination::Gcs).await;
+                    // A keepalive a rule blocked or delayed still restarts the idle timer
+                    state.mark_gcs_broadcast();
+                    sequence = sequence.wrapping_add(1);
+                }
+                Err(e) => {
+                    error!("{}, disabling keepalive", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Send the `[network.announce]` message to the router(s) every interval
+    async fn announce_loop(
+        state: Arc<ProxyState>,
+        rule_engine: Arc<RuleEngine>,
+        routers: RouterLinks,
+        announce: AnnounceConfig,
+    ) {
+        let msg = match announce.message() {
+            Ok(msg) => msg,
+            Err(e) => {
+                error!("{:#}, disabling announcements", e);
+                return;
+            }
+        };
         info!(
-            "   GCS listening on {}:{}",
-            self.config.network.gcs_listen_address, self.config.network.gcs_listen_port
+            "Announcing {} to the router(s) every {}s as {}/{}",
+            announce.message_type, announce.interval_seconds, announce.system_id, announce.component_id
         );
+        let mut ticker = tokio::time::interval(announce.interval());
+        let mut sequence: u8 = 0;
+
+        loop {
+            ticker.tick().await;
+            let header = MavHeader {
+                system_id: announce.system_id,
+                component_id: announce.component_id,
+                sequence,
+            };
+            let mut buf = Vec::new();
+            if let Err(e) = mavlink::write_versioned_msg(&mut buf, MavlinkVersion::V2, header, &msg) {
+                error!("Failed to serialize announcement {}: {}, disabling announcements", announce.message_type, e);
+                return;
+            }
+            debug!("Announcing {} to the router(s)", announce.message_type);
+            state
+                .inject(&rule_engine, buf, Direction::GcsToRouter, Destination::Router(routers.clone()))
+                .await;
+            sequence = sequence.wrapping_add(1);
+        }
+    }
+
+    /// Build a STATUSTEXT summarizing the proxy's state (truncated to the 50-char text field)
+    fn build_status_text(
+        rule_engine: &RuleEngine,
+        state: &ProxyState,
+        system_id: u8,
+        component_id: u8,
+        sequence: u8,
+    ) -> Result<Vec<u8>> {
+        let text = format!(
+            "BITCH: {}/{} rules active, {} blocked",
+            rule_engine.active_rule_count(),
+            rule_engine.rule_count(),
+            state.stats().blocked_total()
+        );
+        let fields = serde_json::json!({
+            "severity": "MAV_SEVERITY_INFO",
+            "text": text,
+        });
+        let fields = fields.as_object().cloned().unwrap_or_default();
+        let msg = crate::messages::build_message("STATUSTEXT", &fields)
+            .context("Failed to build status STATUSTEXT")?;
+
+        let header = MavHeader {
+            system_id,
+            component_id,
+            sequence,
+        };
+
+        let mut buf = Vec::new();
+        mavlink::write_versioned_msg(&mut buf, MavlinkVersion::V2, header, &msg)
+            .context("Failed to serialize status STATUSTEXT")?;
+
+        Ok(buf)
+    }
+
+    /// Broadcast a status STATUSTEXT to all GCS clients every `interval`
+    async fn status_broadcast_loop(
+        state: Arc<ProxyState>,
+        rule_engine: Arc<RuleEngine>,
+        interval: Duration,
+        system_id: u8,
+        component_id: u8,
+    ) {
+        info!("Status STATUSTEXT broadcast every {}s", interval.as_secs());
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately; skip it so the first status follows a full interval
+        ticker.tick().await;
+        let mut sequence: u8 = 0;
+
+        loop {
+            ticker.tick().await;
+            match Self::build_status_text(&rule_engine, &state, system_id, component_id, sequence) {
+                Ok(packet) => {
+                    debug!("Broadcasting status STATUSTEXT to GCS clients");
+                    state.inject(&rule_engine, packet, Direction::RouterToGcs, Destination::Gcs).await;
+                    sequence = sequence.wrapping_add(1);
+                }
+                Err(e) => {
+                    error!("{}, disabling status broadcast", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Start the proxy server
+    pub async fn run(&self, shutdown: CancellationToken) -> Result<(), BitchError> {
+        info!("BITCH MAVLINK Interceptor starting...");
+        self.state.status.mark_started();
         info!(
-            "   Router at {}:{}",
-            self.config.network.router_address, self.config.network.router_port
+            "   GCS listening on {}:{} ({})",
+            self.config.network.gcs_listen_address,
+            self.config.network.gcs_listen_port,
+            self.config.network.gcs_transport
         );
+        let network = &self.config.network;
+        let router_endpoints = network.router_endpoints();
+        if network.router_transport == "serial" {
+            info!(
+                "   Router on serial {} ({} baud)",
+                network.router_serial_port.as_deref().unwrap_or_default(),
+                network.router_baud
+            );
+        } else {
+            for router_addr in &router_endpoints {
+                info!("   Router at {}", router_addr);
+            }
+        }
         info!("   Rules loaded: {}", self.config.rules.len());
 
         for rule in &self.config.rules {
@@ -494,84 +1096,286 @@ impl ProxyServer {
                 rule.message_type,
                 actions_str,
                 rule.delay_seconds
+                    .as_ref()
                     .map(|d| format!("({}s)", d))
                     .unwrap_or_default()
             );
         }
+        let rules = self.config.rules.iter().chain(&self.config.shadow_rules);
+        for warning in rules.flat_map(|rule| rule.action_warnings()) {
+            warn!("{}", warning);
+        }
 
-        // Connect to mavlink-router first (single persistent connection)
-        let router_addr = format!(
-            "{}:{}",
-            self.config.network.router_address, self.config.network.router_port
+        let crc = CrcCheck::new(
+            self.config.network.validate_crc,
+            self.config.network.crc_extra_overrides(),
         );
-        let router_stream = TcpStream::connect(&router_addr)
-            .await
-            .context("Failed to connect to mavlink-router")?;
-        info!("Connected to mavlink-router at {}", router_addr);
 
-        // Split router stream
-        let (router_read, router_write) = router_stream.into_split();
-        let router_write = Arc::new(RwLock::new(router_write));
+        let signer = network
+            .signing_key()
+            .filter(|_| network.sign_outbound)
+            .map(|key| Arc::new(Signer::new(key, network.link_id, crc.clone())));
+        if signer.is_some() {
+            info!("   Signing frames to the router (link_id={})", network.link_id);
+        }
+
+        let socket_options = SocketOptions {
+            nodelay: network.tcp_nodelay,
+            send_buffer: network.tcp_send_buffer,
+            recv_buffer: network.tcp_recv_buffer,
+        };
+
+        // Connect to every mavlink-router first (one persistent connection each)
+        let mut router_links = Vec::new();
+        let mut router_readers = Vec::new();
+        if network.router_transport == "serial" {
+            // A single serial link takes the place of the TCP endpoints
+            let port = network.router_serial_port.clone().unwrap_or_default();
+            let (router_read, router_write) = transport::open_router_serial(&port, network.router_baud)
+                .map_err(|e| BitchError::network(format!("Failed to open router serial port {}", port), e))?;
+            info!("Opened router serial port {} at {} baud", port, network.router_baud);
+
+            router_readers.push(FrameReader::new(router_read, crc.clone()));
+            let source = format!("serial:{}", port);
+            router_links.push(Arc::new(RouterLink::spawn(
+                port,
+                source,
+                router_write,
+                signer.clone(),
+                network.router_queue_size,
+                network.router_write_timeout(),
+            )));
+        } else {
+            for router_addr in router_endpoints {
+                let connected =
+                    connect_router_with_retry(&router_addr, socket_options, network.router_max_fatal_retries, &shutdown)
+                        .await?;
+                let Some((router_read, router_write)) = connected else {
+                    info!("Shutdown requested while connecting to mavlink-router at {}", router_addr);
+                    return Ok(());
+                };
+                info!("Connected to mavlink-router at {}", router_addr);
+
+                router_readers.push(FrameReader::new(router_read, crc.clone()));
+                let source = format!("tcp:{}", router_addr);
+                router_links.push(Arc::new(RouterLink::spawn(
+                    router_addr,
+                    source,
+                    router_write,
+                    signer.clone(),
+                    network.router_queue_size,
+                    network.router_write_timeout(),
+                )));
+            }
+        }
+        let routers: RouterLinks = Arc::new(router_links);
+        let idle_timeout = self.config.network.idle_timeout();
+
+        // Load TLS certificate up front so misconfiguration fails at startup
+        let tls_acceptor = match &self.config.network.gcs_tls {
+            Some(tls) => {
+                info!("   GCS TLS enabled (cert: {})", tls.cert_path);
+                Some(crate::tls::build_acceptor(tls).map_err(|e| BitchError::Config(format!("{:#}", e)))?)
+            }
+            None => None,
+        };
+
+        // Admin API (bound before the GCS listener, so it's up once GCS clients can connect)
+        if let Some(admin) = &self.config.admin {
+            let admin_addr = format!("{}:{}", admin.listen_address, admin.listen_port);
+            let admin_listener = TcpListener::bind(&admin_addr)
+                .await
+                .map_err(|e| BitchError::network(format!("Failed to bind admin API on {}", admin_addr), e))?;
+            info!("   Admin API listening on http://{}", admin_addr);
+            tokio::spawn(crate::admin::serve(
+                admin_listener,
+                self.state.clone(),
+                self.rule_engine.clone(),
+                self.config.clone(),
+            ));
+        }
 
         // Bind TCP listener for GCS connections
-        let gcs_listener = TcpListener::bind(format!(
+        let gcs_listen_addr = format!(
             "{}:{}",
             self.config.network.gcs_listen_address, self.config.network.gcs_listen_port
-        ))
-        .await
-        .context("Failed to bind GCS TCP listener")?;
+        );
+        let gcs_listener = TcpListener::bind(&gcs_listen_addr)
+            .await
+            .map_err(|e| BitchError::network(format!("Failed to bind GCS TCP listener on {}", gcs_listen_addr), e))?;
 
         info!("TCP listener initialized, accepting multiple GCS connections...");
 
-        // Spawn Router -> All GCS broadcast task
-        let router_to_all_gcs_task = {
+        // Spawn one Router -> All GCS broadcast task per router (fan-in)
+        let mut router_tasks = tokio::task::JoinSet::new();
+        for (router_read, router) in router_readers.into_iter().zip(routers.iter().cloned()) {
             let state = self.state.clone();
             let rule_engine = self.rule_engine.clone();
-            let router_write = router_write.clone();
 
-            tokio::spawn(async move {
-                Self::forward_router_to_all_gcs(router_read, router_write, state, rule_engine).await
-            })
+            router_tasks.spawn(async move {
+                let addr = router.addr.clone();
+                let result = Self::forward_router_to_all_gcs(router_read, router, state, rule_engine, idle_timeout).await;
+                (addr, result)
+            });
+        }
+
+        // Resolves once every router link has ended
+        let routers_ended = async move {
+            while let Some(result) = router_tasks.join_next().await {
+                match result {
+                    Ok((addr, result)) => error!("Router {} -> GCS broadcast task ended: {:?}", addr, result),
+                    Err(e) => error!("Router -> GCS broadcast task failed: {}", e),
+                }
+                if !router_tasks.is_empty() {
+                    warn!("{} router link(s) still up", router_tasks.len());
+                }
+            }
         };
 
+        // Announce BITCH itself to the router side
+        if let Some(announce) = &self.config.network.announce {
+            tokio::spawn(Self::announce_loop(
+                self.state.clone(),
+                self.rule_engine.clone(),
+                routers.clone(),
+                announce.clone(),
+            ));
+        }
+
+        // Keep idle GCS links alive with synthesized HEARTBEATs
+        if let Some(interval) = self.config.network.keepalive_interval() {
+            tokio::spawn(Self::keepalive_loop(
+                self.state.clone(),
+                self.rule_engine.clone(),
+                interval,
+                self.config.network.keepalive_system_id,
+                self.config.network.keepalive_component_id,
+            ));
+        }
+
+        // Report config files edited after they were loaded (they're never reloaded)
+        if self.config.config_check_seconds > 0 && !self.config.files.is_empty() {
+            let state = self.state.clone();
+            let interval = Duration::from_secs(self.config.config_check_seconds);
+            tokio::spawn(async move { state.config_drift().watch(interval).await });
+        }
+
+        // Periodic in-band status for operators
+        if let Some(interval) = self.config.logging.status_broadcast_interval() {
+            tokio::spawn(Self::status_broadcast_loop(
+                self.state.clone(),
+                self.rule_engine.clone(),
+                interval,
+                self.config.network.keepalive_system_id,
+                self.config.network.keepalive_component_id,
+            ));
+        }
+
         // Accept GCS connections in a loop
         let gcs_accept_task = {
             let state = self.state.clone();
             let rule_engine = self.rule_engine.clone();
-            let router_write = router_write.clone();
+            let routers = routers.clone();
+            let gcs_transport = self.config.network.gcs_transport.clone();
+            // Every GCS client comes in on the one listener
+            let gcs_source: Arc<str> =
+                format!("{}:{}", self.config.network.gcs_transport, self.config.network.gcs_listen_port).into();
+            let crc = crc.clone();
+            let tls_acceptor = tls_acceptor.clone();
+            let allowed_clients = Arc::new(self.config.network.gcs_allowed_clients.clone());
+            let client_groups = Arc::new(self.config.network.client_groups.clone());
+            let allow_cidrs = parse_cidrs(&self.config.network.gcs_allow_cidrs);
+            let deny_cidrs = parse_cidrs(&self.config.network.gcs_deny_cidrs);
 
             tokio::spawn(async move {
                 loop {
                     match gcs_listener.accept().await {
                         Ok((gcs_stream, gcs_addr)) => {
-                            info!("New GCS connection from: {}", gcs_addr);
-
-                            // Split the GCS stream
-                            let (gcs_read, gcs_write) = gcs_stream.into_split();
+                            // Drop connections from disallowed addresses before any handshake
+                            if !is_address_allowed(gcs_addr.ip(), &allow_cidrs, &deny_cidrs) {
+                                warn!("Rejected GCS connection from {}: address not allowed", gcs_addr);
+                                state.connections().rejected(gcs_addr, "address not allowed");
+                                continue;
+                            }
 
-                            // Register the client
-                            let client_id = state.add_gcs_client(gcs_write).await;
+                            info!("New GCS connection from: {}", gcs_addr);
+                            if let Err(e) = socket_options.apply(&gcs_stream) {
+                                warn!("Failed to set socket options for {}: {}", gcs_addr, e);
+                            }
 
                             // Spawn task to handle this GCS client (GCS -> Router)
                             let state_clone = state.clone();
                             let rule_engine_clone = rule_engine.clone();
-                            let router_write_clone = router_write.clone();
+                            let routers_clone = routers.clone();
+                            let gcs_transport = gcs_transport.clone();
+                            let gcs_source = gcs_source.clone();
+                            let tls_acceptor = tls_acceptor.clone();
+                            let allowed_clients = allowed_clients.clone();
+                            let client_groups = client_groups.clone();
+                            let crc = crc.clone();
 
                             tokio::spawn(async move {
-                                if let Err(e) = Self::forward_gcs_to_router(
+                                // Handshakes run here so a slow client can't stall the accept loop
+                                let mut identity = None;
+                                let stream: BoxedGcsStream = match tls_acceptor {
+                                    Some(acceptor) => match acceptor.accept(gcs_stream).await {
+                                        Ok(tls_stream) => {
+                                            if !Self::check_client_certificate(&tls_stream, &allowed_clients, gcs_addr) {
+                                                state_clone.connections().rejected(gcs_addr, "client certificate not allowed");
+                                                return;
+                                            }
+                                            identity = Self::peer_identity(&tls_stream);
+                                            Box::new(tls_stream)
+                                        }
+                                        Err(e) => {
+                                            warn!("TLS handshake with {} failed: {}", gcs_addr, e);
+                                            state_clone
+                                                .connections()
+                                                .rejected(gcs_addr, &format!("TLS handshake failed: {}", e));
+                                            return;
+                                        }
+                                    },
+                                    None => Box::new(gcs_stream),
+                                };
+
+                                // Wrap the connection for the configured transport
+                                let (gcs_read, gcs_write) =
+                                    match transport::accept_gcs(stream, &gcs_transport, crc).await {
+                                        Ok(halves) => halves,
+                                        Err(e) => {
+                                            warn!("GCS connection from {} failed: {}", gcs_addr, e);
+                                            state_clone
+                                                .connections()
+                                                .rejected(gcs_addr, &format!("{:#}", e));
+                                            return;
+                                        }
+                                    };
+
+                                // Register the client
+                                let group = find_client_group(&client_groups, gcs_addr.ip(), identity.as_ref());
+                                let group_name = group.as_ref().map(|group| group.name.clone());
+                                let client_id = state_clone.add_gcs_client(gcs_write, gcs_addr, group_name).await;
+                                let connected_at = Instant::now();
+                                if let Some(group) = &group {
+                                    info!("GCS client {} ({}) is in client group '{}'", client_id, gcs_addr, group.name);
+                                }
+
+                                let cause = Self::forward_gcs_to_router(
                                     client_id,
+                                    group,
+                                    gcs_source,
                                     gcs_read,
-                                    router_write_clone,
+                                    routers_clone,
                                     state_clone.clone(),
                                     rule_engine_clone,
+                                    idle_timeout,
                                 )
-                                .await
-                                {
-                                    error!("GCS client {} error: {}", client_id, e);
-                                }
+                                .await;
 
                                 // Remove client on disconnect
-                                state_clone.remove_gcs_client(client_id).await;
+                                state_clone
+                                    .remove_gcs_client(client_id, gcs_addr, connected_at.elapsed(), &cause)
+                                    .await;
                             });
                         }
                         Err(e) => {
@@ -582,67 +1386,160 @@ impl ProxyServer {
             })
         };
 
-        // Wait for tasks (router broadcast task should never end normally)
+        // Wait for tasks (router broadcast tasks should never end normally) or shutdown
         tokio::select! {
-            result = router_to_all_gcs_task => {
-                error!("Router->GCS broadcast task ended: {:?}", result);
+            _ = routers_ended => {
+                error!("All router links ended");
             }
             result = gcs_accept_task => {
                 error!("GCS accept task ended: {:?}", result);
             }
+            _ = shutdown.cancelled() => {
+                info!("Shutdown requested, stopping proxy");
+            }
         }
 
+        // Give plugins a chance to flush buffers / close connections
+        self.rule_engine.shutdown_plugins();
+
         Ok(())
     }
 
     /// Forward messages from a specific GCS client to Router with rule processing
+    /// Runs until the client goes away and returns why (for the connection log)
+    /// `source` is the listener's ingress identity for `source` conditions
+    #[allow(clippy::too_many_arguments)]
     async fn forward_gcs_to_router(
         client_id: ClientId,
-        mut gcs_read: tokio::net::tcp::OwnedReadHalf,
-        router_write: Arc<RwLock<tokio::net::tcp::OwnedWriteHalf>>,
+        client_group: Option<ClientGroupConfig>,
+        source: Arc<str>,
+        mut gcs_read: GcsReader,
+        routers: RouterLinks,
         state: Arc<ProxyState>,
         rule_engine: Arc<RuleEngine>,
-    ) -> Result<()> {
+        idle_timeout: Option<Duration>,
+    ) -> String {
         info!("GCS client {} -> Router forwarding started", client_id);
+        let traffic = state.client_traffic(client_id).await.unwrap_or_default();
+        let disconnect = state.disconnect_token(client_id).await.unwrap_or_default();
 
-        loop {
+        let cause = loop {
             // Read MAVLink packet from this GCS client
-            let packet = match read_mavlink_packet(&mut gcs_read).await {
+            let read = tokio::select! {
+                read = read_with_idle_timeout(gcs_read.read_frame(), idle_timeout) => read,
+                _ = disconnect.cancelled() => {
+                    info!("GCS client {} disconnect requested, stopping", client_id);
+                    break "disconnected by admin".to_string();
+                }
+            };
+            let packet = match read {
                 Ok(pkt) => pkt,
+                Err(e) if e.is::<tokio::time::error::Elapsed>() => {
+                    warn!("GCS client {} idle for too long, disconnecting", client_id);
+                    break "idle timeout".to_string();
+                }
                 Err(e) => {
                     debug!("GCS client {} read error: {}", client_id, e);
-                    break;
+                    break format!("read error: {:#}", e);
                 }
             };
 
             debug!("GCS client {} -> Router: {} bytes", client_id, packet.len());
+            state.capture(Direction::GcsToRouter, &packet);
+            traffic.record_sent(&packet);
+
+            // Signature checks run before any rule sees the frame
+            if !state.admit_signed(client_id, &packet) {
+                continue;
+            }
 
             // Try to parse and process the MAVLink message
-            let result = if let Ok((header, msg)) = parse_mavlink_message(&packet) {
-                rule_engine.process_message_with_direction(&header, &msg, "gcs_to_router")
+            let group_name = client_group.as_ref().map(|group| group.name.as_str());
+            let ingress = Ingress {
+                client_group: group_name,
+                client: Some(client_id),
+                source: Some(&*source),
+            };
+            let frame = parse_mavlink_frame(&packet).ok();
+            if let (Some(frame), Some(subscriptions)) = (&frame, &state.subscriptions) {
+                subscriptions.observe(client_id, &frame.message);
+            }
+            let mut result = if let Some(frame) = &frame {
+                let result = rule_engine.process_frame_from(frame, Direction::GcsToRouter, ingress);
+                state.evaluate_shadow(frame, Direction::GcsToRouter, ingress, &result);
+                result
             } else {
-                // If we can't parse it, forward it anyway
-                debug!("Failed to parse message, forwarding anyway");
-                ProcessResult {
-                    actions: vec![Action::Forward],
-                    ack_info: None,
+                Self::process_unparsed(&state, &rule_engine, &packet, Direction::GcsToRouter, ingress)
+            };
+
+            // Default-deny groups only get through what a rule matched
+            if let Some(group) = client_group.as_ref().filter(|group| group.default_deny) {
+                if result.matched_rule.is_none() && matches!(result.actions.as_slice(), [Action::Forward]) {
+                    debug!("GCS client {}: no rule matched, blocked by default-deny group '{}'", client_id, group.name);
+                    result.actions = vec![Action::Block {
+                        rule: format!("client_group:{}", group.name),
+                        reason: Some("no rule matched for a default-deny client group".to_string()),
+                    }];
+                }
+            }
+
+            // The route hook may drop the frame or narrow the routers it goes to
+            let verdict = match &frame {
+                Some(frame) => state.route(&rule_engine, frame, Direction::GcsToRouter, Some(client_id), group_name, None),
+                None => RouteVerdict::Forward,
+            };
+            let destination = match verdict {
+                RouteVerdict::Forward => routers.clone(),
+                RouteVerdict::Drop => {
+                    result.actions = vec![route_hook_block()];
+                    routers.clone()
+                }
+                RouteVerdict::Routers(addrs) => {
+                    let picked: Vec<_> = routers.iter().filter(|router| addrs.contains(&router.addr)).cloned().collect();
+                    if picked.is_empty() {
+                        debug!("route_hook picked no known router ({}), dropping", addrs.join(", "));
+                    }
+                    Arc::new(picked)
+                }
+                RouteVerdict::Group(group) => {
+                    warn!("route_hook returned client group '{}' for a GCS -> router frame, ignoring it", group);
+                    routers.clone()
                 }
             };
 
-            // Send ACK if auto_ack is enabled (to this specific GCS client)
+            // Send ACK if auto_ack is enabled (to this specific GCS client and/or the routers)
             if let Some(ref ack_info) = result.ack_info {
                 match Self::build_ack(ack_info) {
                     Ok(ack_packet) => {
-                        if let Some(gcs_writer) = state.get_gcs_client(client_id).await {
-                            let mut writer = gcs_writer.write().await;
-                            if let Err(e) = writer.write_all(&ack_packet).await {
-                                error!("Failed to send {} to GCS client {}: {}", ack_info.message_type, client_id, e);
-                            } else {
-                                info!(
-                                    "Sent {} to GCS client {} (sysid={})",
-                                    ack_info.message_type, client_id, ack_info.source_system
-                                );
-                            }
+                        if ack_info.direction.reaches_router()
+                            && state
+                                .inject(
+                                    &rule_engine,
+                                    ack_packet.clone(),
+                                    Direction::GcsToRouter,
+                                    Destination::Router(routers.clone()),
+                                )
+                                .await
+                        {
+                            info!(
+                                "Sent {} to the router(s) (sysid={})",
+                                ack_info.message_type, ack_info.source_system
+                            );
+                        }
+                        if ack_info.direction.reaches_source()
+                            && state
+                                .inject(
+                                    &rule_engine,
+                                    ack_packet,
+                                    Direction::RouterToGcs,
+                                    Destination::GcsClient(client_id),
+                                )
+                                .await
+                        {
+                            info!(
+                                "Sent {} to GCS client {} (sysid={})",
+                                ack_info.message_type, client_id, ack_info.source_system
+                            );
                         }
                     }
                     Err(e) => {
@@ -655,60 +1552,84 @@ impl ProxyServer {
             execute_actions_impl(
                 result.actions,
                 vec![packet],
-                Destination::Router(router_write.clone()),
+                Destination::Router(destination),
                 state.clone(),
             )
             .await;
-        }
+        };
 
         info!("GCS client {} -> Router forwarding ended", client_id);
-        Ok(())
+        cause
     }
 
     /// Forward messages from Router to all connected GCS clients (broadcast)
     async fn forward_router_to_all_gcs(
-        mut router_read: tokio::net::tcp::OwnedReadHalf,
-        router_write: Arc<RwLock<tokio::net::tcp::OwnedWriteHalf>>,
+        mut router_read: FrameReader<RouterReader>,
+        router: Arc<RouterLink>,
         state: Arc<ProxyState>,
         rule_engine: Arc<RuleEngine>,
+        idle_timeout: Option<Duration>,
     ) -> Result<()> {
-        info!("Router -> All GCS broadcast started");
+        info!("Router {} -> All GCS broadcast started", router.addr);
+        state.status.router_up();
 
         loop {
             // Read MAVLink packet from Router
-            let packet = match read_mavlink_packet(&mut router_read).await {
+            let read = tokio::select! {
+                read = read_with_idle_timeout(router_read.read_frame(), idle_timeout) => read,
+                _ = router.closed() => {
+                    error!("Router {} link closed by its writer, stopping", router.addr);
+                    break;
+                }
+            };
+            let packet = match read {
                 Ok(pkt) => pkt,
+                Err(e) if e.is::<tokio::time::error::Elapsed>() => {
+                    error!("No data from router {} within idle timeout, treating link as dead", router.addr);
+                    break;
+                }
                 Err(e) => {
-                    error!("Error reading from router: {}", e);
+                    error!("Error reading from router {}: {}", router.addr, e);
                     break;
                 }
             };
 
-            debug!("Router -> All GCS: {} bytes", packet.len());
+            debug!("Router {} -> All GCS: {} bytes", router.addr, packet.len());
+            state.capture(Direction::RouterToGcs, &packet);
 
             // Try to parse and process the MAVLink message
-            let result = if let Ok((header, msg)) = parse_mavlink_message(&packet) {
-                rule_engine.process_message_with_direction(&header, &msg, "router_to_gcs")
-            } else {
-                // If we can't parse it, forward it anyway
-                debug!("Failed to parse Router->GCS message, forwarding anyway");
-                ProcessResult {
-                    actions: vec![Action::Forward],
-                    ack_info: None,
+            let ingress = Ingress {
+                source: Some(router.source.as_str()),
+                ..Default::default()
+            };
+            let frame = parse_mavlink_frame(&packet).ok();
+            // ACKs for upgraded commands go only to the GCS client that sent the COMMAND_LONG
+            let requester = frame
+                .as_ref()
+                .and_then(|frame| rule_engine.response_requester(&frame.header, &frame.message));
+            let mut result = if let Some(frame) = &frame {
+                // ACKs cancel pending retry_until_ack re-sends
+                state.retries.observe(&frame.header, &frame.message);
+                if let Some(pacer) = &state.pacer {
+                    pacer.observe(&frame.message);
                 }
+                let result = rule_engine.process_frame_from(frame, Direction::RouterToGcs, ingress);
+                state.evaluate_shadow(frame, Direction::RouterToGcs, ingress, &result);
+                result
+            } else {
+                Self::process_unparsed(&state, &rule_engine, &packet, Direction::RouterToGcs, ingress)
             };
 
-            // Send ACK if auto_ack is enabled (back to router)
+            // Send ACK if auto_ack is enabled (back to the router it came from)
+            // Here the source is a router, so every `ack.direction` sends it there once
             if let Some(ref ack_info) = result.ack_info {
                 match Self::build_ack(ack_info) {
                     Ok(ack_packet) => {
-                        let mut writer = router_write.write().await;
-                        if let Err(e) = writer.write_all(&ack_packet).await {
-                            error!("Failed to send {} to router: {}", ack_info.message_type, e);
-                        } else {
+                        let source = Destination::Router(Arc::new(vec![router.clone()]));
+                        if state.inject(&rule_engine, ack_packet, Direction::GcsToRouter, source).await {
                             info!(
-                                "Sent {} to router (sysid={})",
-                                ack_info.message_type, ack_info.source_system
+                                "Sent {} to router {} (sysid={})",
+                                ack_info.message_type, router.addr, ack_info.source_system
                             );
                         }
                     }
@@ -718,27 +1639,133 @@ impl ProxyServer {
                 }
             }
 
-            // Process actions and broadcast to all GCS clients
-            // Note: For broadcast, we handle it specially since we need to send to multiple clients
-            if result.actions.is_empty() || matches!(result.actions.first(), Some(Action::Forward)) {
+            // The route hook may drop the frame or limit it to one client group
+            let verdict = match &frame {
+                Some(frame) => state.route(&rule_engine, frame, Direction::RouterToGcs, None, None, Some(router.addr.as_str())),
+                None => RouteVerdict::Forward,
+            };
+            let destination = match verdict {
+                RouteVerdict::Forward => requester.map_or(Destination::Gcs, Destination::GcsClient),
+                RouteVerdict::Drop => {
+                    result.actions = vec![route_hook_block()];
+                    Destination::Gcs
+                }
+                RouteVerdict::Group(group) => Destination::GcsGroup(group),
+                RouteVerdict::Routers(addrs) => {
+                    warn!("route_hook returned routers ({}) for a router -> GCS frame, ignoring them", addrs.join(", "));
+                    Destination::Gcs
+                }
+            };
+
+            // Process actions and broadcast to the GCS clients
+            if result.actions.is_empty() || matches!(result.actions.as_slice(), [Action::Forward]) {
                 // Simple forward - just broadcast the packet
-                state.broadcast_to_all_gcs(&packet).await;
+                match &destination {
+                    Destination::GcsGroup(group) => state.broadcast_to_group(group, &packet).await,
+                    Destination::GcsClient(client_id) => {
+                        state.send_to_gcs_client(*client_id, &packet).await;
+                    }
+                    _ => state.broadcast_to_all_gcs(&packet).await,
+                }
             } else {
                 // Complex actions (modify, delay, etc.) - process then broadcast
-                // We'll create a custom destination that broadcasts
-                execute_actions_impl_broadcast(
-                    result.actions,
-                    vec![packet],
-                    state.clone(),
-                )
-                .await;
+                execute_actions_impl(result.actions, vec![packet], destination, state.clone()).await;
             }
         }
 
+        // Stop the writer too, so GCS traffic for this router fails fast instead of queueing
+        state.status.router_down();
+        router.close();
         Ok(())
     }
 }
 
+/// Block action for frames the route hook drops (counted under "route_hook")
+fn route_hook_block() -> Action {
+    Action::Block {
+        rule: "route_hook".to_string(),
+        reason: Some("dropped by route_hook".to_string()),
+    }
+}
+
+/// Connect to a mavlink-router TCP endpoint, retrying with capped exponential backoff
+/// Transient errors (refused, reset, timed out) are retried until it comes up; any other
+/// error fails once it has recurred `max_fatal_retries` times. None if shutdown was requested.
+async fn connect_router_with_retry(
+    addr: &str,
+    options: SocketOptions,
+    max_fatal_retries: u32,
+    shutdown: &CancellationToken,
+) -> Result<Option<(RouterReader, RouterWriter)>, BitchError> {
+    let mut backoff = ROUTER_RETRY_INITIAL;
+    let mut fatal_failures = 0;
+    loop {
+        let error = match transport::connect_router_tcp(addr, options).await {
+            Ok(halves) => return Ok(Some(halves)),
+            Err(e) => e,
+        };
+
+        if transport::is_transient(&error) {
+            warn!("mavlink-router at {} not reachable ({}), retrying in {}s", addr, error, backoff.as_secs());
+        } else {
+            fatal_failures += 1;
+            if fatal_failures > max_fatal_retries {
+                return Err(BitchError::network(
+                    format!("Failed to connect to mavlink-router at {} ({} attempts)", addr, fatal_failures),
+                    error,
+                ));
+            }
+            warn!(
+                "Failed to connect to mavlink-router at {} ({}), retry {}/{} in {}s",
+                addr,
+                error,
+                fatal_failures,
+                max_fatal_retries,
+                backoff.as_secs()
+            );
+        }
+
+        tokio::select! {
+            _ = sleep(backoff) => {}
+            _ = shutdown.cancelled() => return Ok(None),
+        }
+        backoff = (backoff * 2).min(ROUTER_RETRY_MAX);
+    }
+}
+
+/// Parse CIDR strings (validated at startup, so invalid entries are skipped)
+fn parse_cidrs(cidrs: &[String]) -> Vec<ipnet::IpNet> {
+    cidrs.iter().filter_map(|c| c.parse().ok()).collect()
+}
+
+/// Check a source address against the deny and allow CIDR lists (deny wins)
+fn is_address_allowed(ip: std::net::IpAddr, allow: &[ipnet::IpNet], deny: &[ipnet::IpNet]) -> bool {
+    // Treat IPv4-mapped IPv6 addresses (dual-stack listeners) as IPv4
+    let ip = ip.to_canonical();
+
+    if deny.iter().any(|net| net.contains(&ip)) {
+        return false;
+    }
+
+    allow.is_empty() || allow.iter().any(|net| net.contains(&ip))
+}
+
+/// The first client group a GCS connection belongs to, by source address or certificate identity
+fn find_client_group(
+    groups: &[ClientGroupConfig],
+    ip: std::net::IpAddr,
+    identity: Option<&ClientIdentity>,
+) -> Option<ClientGroupConfig> {
+    let ip = ip.to_canonical();
+    groups
+        .iter()
+        .find(|group| {
+            parse_cidrs(&group.cidrs).iter().any(|net| net.contains(&ip))
+                || identity.is_some_and(|identity| crate::tls::is_client_allowed(identity, &group.clients))
+        })
+        .cloned()
+}
+
 /// Convert TOML value to JSON value, preserving structure
 fn toml_to_json(value: &toml::Value) -> serde_json::Value {
     match value {
@@ -0,0 +1,73 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// A quarantined system as reported by the admin API and the plugins' `quarantine.list()`
+#[derive(Debug, Clone, Serialize)]
+pub struct QuarantineEntry {
+    pub system_id: u8,
+    /// Seconds until the quarantine lifts (None = until released)
+    pub remaining_seconds: Option<f64>,
+}
+
+/// Systems whose traffic is blocked regardless of the rules
+/// Shared by the rule engine (which enforces it), triggers, plugins and the admin API.
+pub struct Quarantine {
+    /// system_id -> when the quarantine lifts (None = until released)
+    systems: RwLock<HashMap<u8, Option<Instant>>>,
+}
+
+impl Quarantine {
+    pub fn new() -> Self {
+        Self {
+            systems: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Quarantine a system for `duration` (None = until released); replaces any earlier expiry
+    pub fn add(&self, system_id: u8, duration: Option<Duration>) {
+        let until = duration.map(|duration| Instant::now() + duration);
+        self.systems.write().unwrap().insert(system_id, until);
+        match duration {
+            Some(duration) => warn!("System {} quarantined for {}s", system_id, duration.as_secs()),
+            None => warn!("System {} quarantined until released", system_id),
+        }
+    }
+
+    /// Lift a system's quarantine; false if it wasn't quarantined
+    pub fn release(&self, system_id: u8) -> bool {
+        let released = self.systems.write().unwrap().remove(&system_id).is_some();
+        if released {
+            warn!("System {} released from quarantine", system_id);
+        }
+        released
+    }
+
+    /// Whether a system is currently quarantined
+    pub fn contains(&self, system_id: u8) -> bool {
+        let systems = self.systems.read().unwrap();
+        match systems.get(&system_id) {
+            Some(until) => until.is_none_or(|until| Instant::now() < until),
+            None => false,
+        }
+    }
+
+    /// Quarantined systems (expired entries are dropped), ordered by system ID
+    pub fn snapshot(&self) -> Vec<QuarantineEntry> {
+        let now = Instant::now();
+        let mut systems = self.systems.write().unwrap();
+        systems.retain(|_, until| until.is_none_or(|until| now < until));
+
+        let mut entries: Vec<QuarantineEntry> = systems
+            .iter()
+            .map(|(system_id, until)| QuarantineEntry {
+                system_id: *system_id,
+                remaining_seconds: until.map(|until| (until - now).as_secs_f64()),
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.system_id);
+        entries
+    }
+}
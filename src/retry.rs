@@ -0,0 +1,110 @@
+use crate::rules::{get_message_name, parse_mavlink_message};
+use mavlink::ardupilotmega::MavMessage;
+use mavlink::MavHeader;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+/// A sent message waiting for its ACK
+struct PendingAck {
+    /// Message type that confirms delivery (e.g. "COMMAND_ACK")
+    ack_type: String,
+    /// Vehicle expected to answer (None = any)
+    target_system: Option<u8>,
+    /// Component expected to answer (None = any)
+    target_component: Option<u8>,
+    /// `command` field of the original message, matched against the ACK's
+    command: Option<JsonValue>,
+    /// Cancelled when the ACK is seen
+    acked: CancellationToken,
+}
+
+impl PendingAck {
+    fn is_acked_by(&self, header: &MavHeader, msg_name: &str, msg_json: &JsonValue) -> bool {
+        msg_name == self.ack_type
+            && self.target_system.is_none_or(|id| id == header.system_id)
+            && self.target_component.is_none_or(|id| id == header.component_id)
+            && match (&self.command, msg_json.get("command")) {
+                (Some(expected), Some(actual)) => expected == actual,
+                _ => true,
+            }
+    }
+}
+
+/// Messages sent with `retry_until_ack`, shared between the GCS -> router loops
+/// (which register them) and the router -> GCS loops (which observe ACKs)
+pub struct RetryTracker {
+    pending: Mutex<HashMap<u64, PendingAck>>,
+    next_id: AtomicU64,
+}
+
+impl RetryTracker {
+    pub fn new() -> Self {
+        Self {
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Start waiting for an ACK of `packet`
+    /// The ACK must come from the packet's `target_system`/`target_component` (when set and
+    /// non-zero) and carry the same `command` (when both messages have one).
+    pub fn register(&self, ack_type: &str, packet: &[u8]) -> (u64, CancellationToken) {
+        let msg_json = parse_mavlink_message(packet)
+            .ok()
+            .and_then(|(_, msg)| serde_json::to_value(&msg).ok())
+            .unwrap_or(JsonValue::Null);
+        let target = |field: &str| {
+            msg_json
+                .get(field)
+                .and_then(|v| v.as_u64())
+                .filter(|id| *id != 0)
+                .map(|id| id as u8)
+        };
+
+        let acked = CancellationToken::new();
+        let pending = PendingAck {
+            ack_type: ack_type.to_string(),
+            target_system: target("target_system"),
+            target_component: target("target_component"),
+            command: msg_json.get("command").cloned(),
+            acked: acked.clone(),
+        };
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.pending.lock().unwrap().insert(id, pending);
+        (id, acked)
+    }
+
+    /// Stop tracking a message (ACK seen or attempts exhausted)
+    pub fn finish(&self, id: u64) {
+        self.pending.lock().unwrap().remove(&id);
+    }
+
+    /// Check a message on the reverse path and cancel the retries it acknowledges
+    pub fn observe(&self, header: &MavHeader, msg: &MavMessage) {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.is_empty() {
+            return;
+        }
+
+        let msg_name = get_message_name(msg);
+        if !pending.values().any(|p| p.ack_type == msg_name) {
+            return;
+        }
+
+        let msg_json = serde_json::to_value(msg).unwrap_or(JsonValue::Null);
+        pending.retain(|id, p| {
+            if p.is_acked_by(header, &msg_name, &msg_json) {
+                debug!("{} from sysid={} acknowledges pending retry {}", msg_name, header.system_id, id);
+                p.acked.cancel();
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
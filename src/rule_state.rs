@@ -19,6 +19,8 @@ struct RuleActivation {
 pub struct RuleStateManager {
     /// Current activation state of each rule (by name)
     activations: Arc<RwLock<HashMap<String, RuleActivation>>>,
+    /// Last time each named event was recorded: (event name, source system_id) -> instant
+    events: Arc<RwLock<HashMap<(String, u8), Instant>>>,
 }
 
 impl RuleStateManager {
@@ -40,6 +42,7 @@ impl RuleStateManager {
 
         Self {
             activations: Arc::new(RwLock::new(activations)),
+            events: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
@@ -91,6 +94,25 @@ impl RuleStateManager {
         info!("Deactivated rule '{}'", rule_name);
     }
 
+    /// Record that a named event just happened on a system
+    pub fn record_event(&self, name: &str, system_id: u8) {
+        self.events
+            .write()
+            .unwrap()
+            .insert((name.to_string(), system_id), Instant::now());
+
+        debug!("Recorded event '{}' from system {}", name, system_id);
+    }
+
+    /// Check if a named event was recorded within `window`
+    /// With `system_id` only that system's events count, otherwise any system's
+    pub fn event_within(&self, name: &str, system_id: Option<u8>, window: Duration) -> bool {
+        let events = self.events.read().unwrap();
+        events.iter().any(|((event, sysid), at)| {
+            event == name && system_id.is_none_or(|id| id == *sysid) && at.elapsed() <= window
+        })
+    }
+
     /// Clean up expired rule activations
     pub fn cleanup_expired(&self) {
         let now = Instant::now();
@@ -1,14 +1,48 @@
-use crate::config::{CommandRule, RuleConditions};
+use crate::command_compat::CommandCompat;
+use crate::config::{
+    is_operator_table, AckDirection, ActionValue, CommandRule, DeltaCondition, Direction, ModifyErrorPolicy, RuleConditions,
+};
+use crate::error::{BitchError, Result};
+use crate::framing::{FrameFlags, RawHeader, KNOWN_COMPAT_FLAGS, KNOWN_INCOMPAT_FLAGS};
 use crate::modifiers::ModifierManager;
-use crate::plugins::{PluginContext, PluginManager};
-use anyhow::Result;
+use crate::plugins::{PluginContext, PluginManager, RouteContext, RouteVerdict};
+use crate::quarantine::Quarantine;
+use crate::stats::ScriptTimings;
 use mavlink::ardupilotmega::MavMessage;
-use mavlink::MavHeader;
+use mavlink::{MavHeader, Message};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use serde_json::Value as JsonValue;
 use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::Duration;
-use tracing::{debug, info, warn};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, debug_span, info, trace, warn};
+
+/// `message_type` matching frames the dialect can't decode
+pub const UNKNOWN_MESSAGE_TYPE: &str = "UNKNOWN";
+
+/// Where a frame came in, for `client_group` rules and `source` conditions
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ingress<'a> {
+    /// The sending GCS client's `[[network.client_groups]]` group
+    pub client_group: Option<&'a str>,
+    /// The sending GCS client's connection ID (for `upgrade_compat`)
+    pub client: Option<u64>,
+    /// Transport and listener or link, e.g. "tcp:14550", "ws:8888", "tcp:10.0.0.2:5760", "serial:/dev/ttyUSB0"
+    pub source: Option<&'a str>,
+}
+
+impl Ingress<'_> {
+    /// Whether a `source` condition names this ingress: the whole identity, or a
+    /// prefix of it ending at a `:` ("serial" matches any serial link)
+    pub fn source_matches(&self, wanted: &str) -> bool {
+        self.source.is_some_and(|source| {
+            source
+                .strip_prefix(wanted)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+        })
+    }
+}
 
 /// Information needed to send a generic ACK message
 #[derive(Debug, Clone)]
@@ -27,6 +61,17 @@ pub struct AckInfo {
     pub original_header: MavHeader,
     /// Original message data as JSON (for extracting fields)
     pub original_message: JsonValue,
+    /// Where the ACK is sent
+    pub direction: AckDirection,
+}
+
+/// A parsed frame with the header flag bytes the parsed message alone doesn't carry
+#[derive(Debug, Clone)]
+pub struct ParsedFrame {
+    pub header: MavHeader,
+    pub message: MavMessage,
+    /// Incompatibility/compatibility flags (zero for MAVLink 1 frames)
+    pub flags: FrameFlags,
 }
 
 /// Result of processing a message through the rule engine
@@ -34,6 +79,8 @@ pub struct AckInfo {
 pub struct ProcessResult {
     pub actions: Vec<Action>,
     pub ack_info: Option<AckInfo>,
+    /// Name of the rule that matched (None when no rule matched and the message is forwarded)
+    pub matched_rule: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -44,7 +91,12 @@ pub enum Action {
     /// Delay the message by the specified duration
     Delay(Duration),
     /// Block the message completely
-    Block,
+    Block {
+        /// Name of the rule that blocked the message
+        rule: String,
+        /// Optional operator-supplied reason (from `block_reason`)
+        reason: Option<String>,
+    },
     /// Batch messages until threshold is met or timeout occurs
     Batch {
         count: usize,
@@ -54,20 +106,92 @@ pub enum Action {
         /// Optional: Field name in message to extract system_id from (e.g., "target_system")
         /// If None, uses header.system_id
         system_id_field: Option<String>,
+        /// Optional: Actions run on the packets of a timed-out batch (replaces `forward_on_timeout`)
+        timeout_actions: Option<Vec<Action>>,
+    },
+    /// Hold `count` packets from any system, then release them together (or on timeout)
+    Burst {
+        count: usize,
+        timeout: Duration,
+        /// Rule name and direction
+        key: String,
+        forward_on_timeout: bool,
     },
     /// Modify the message using a Lua modifier script
     Modify {
         modifier: String,
         modified_message: Option<MavMessage>,
     },
+    /// Hold the latest message per `key` and forward only that one every `interval`
+    Coalesce {
+        /// Rule name, direction, system ID and message type
+        key: String,
+        interval: Duration,
+    },
+    /// Re-send the message every `timeout` (up to `attempts` times) until a matching ACK arrives
+    RetryUntilAck {
+        message_type: String,
+        timeout: Duration,
+        attempts: u32,
+    },
+}
+
+impl Action {
+    /// Action name as written in rule configs
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Forward => "forward",
+            Action::Delay(_) => "delay",
+            Action::Block { .. } => "block",
+            Action::Batch { .. } => "batch",
+            Action::Burst { .. } => "burst",
+            Action::Modify { .. } => "modify",
+            Action::Coalesce { .. } => "coalesce",
+            Action::RetryUntilAck { .. } => "retry_until_ack",
+        }
+    }
+}
+
+/// Header sequence state of one source (system_id, component_id)
+struct SequenceState {
+    /// Sequence number of the last frame
+    last: u8,
+    /// How far it moved from the frame before (None for the first frame seen)
+    jump: Option<u8>,
 }
 
 /// Rule engine for processing MAVLINK messages
 pub struct RuleEngine {
-    rules: Vec<CommandRule>,
+    /// Live rules, sorted by priority; swapped as a whole so a message in flight keeps
+    /// the snapshot it started with (see `replace_rule`)
+    rules: RwLock<Arc<Vec<CommandRule>>>,
     plugin_manager: Arc<PluginManager>,
     modifier_manager: Arc<ModifierManager>,
     state_manager: Arc<crate::rule_state::RuleStateManager>,
+    /// Last time each system was seen per first_seen rule: (rule name, system_id) -> instant
+    last_seen: Mutex<HashMap<(String, u8), Instant>>,
+    /// Previous field value per delta rule: (rule name, system_id) -> value
+    last_values: Mutex<HashMap<(String, u8), f64>>,
+    /// Last content passed per dedup_identical rule: (rule name, system_id, message type) -> message
+    last_content: Mutex<HashMap<(String, u8, String), JsonValue>>,
+    /// Per source (system_id, component_id): last header sequence and how far it jumped
+    sequences: Mutex<HashMap<(u8, u8), SequenceState>>,
+    /// Maximum nesting of modify `reprocess` re-evaluations
+    max_reprocess_depth: u32,
+    /// Decide actions without side effects (no plugins, notifications or modifier runs)
+    dry_run: bool,
+    /// Run rules' `simplified_actions` instead of their full chains
+    simplify_actions: bool,
+    /// What a modify action does when its modifier fails (`network.on_modify_error`)
+    on_modify_error: ModifyErrorPolicy,
+    /// Picks among a rule's weighted `modifiers`
+    rng: Mutex<StdRng>,
+    /// Upgraded commands awaiting their ACK (`upgrade_compat`)
+    command_compat: CommandCompat,
+    /// Execution time histograms of modifier and plugin runs
+    script_timings: ScriptTimings,
+    /// Systems whose traffic is blocked before any rule is checked
+    quarantine: Arc<Quarantine>,
 }
 
 impl RuleEngine {
@@ -76,20 +200,143 @@ impl RuleEngine {
         plugin_manager: PluginManager,
         modifier_manager: ModifierManager,
         state_manager: Arc<crate::rule_state::RuleStateManager>,
+        max_reprocess_depth: u32,
     ) -> Result<Self> {
+        let quarantine = plugin_manager.quarantine();
         Ok(Self {
-            rules,
+            rules: RwLock::new(Arc::new(rules)),
             plugin_manager: Arc::new(plugin_manager),
             modifier_manager: Arc::new(modifier_manager),
             state_manager,
+            last_seen: Mutex::new(HashMap::new()),
+            last_values: Mutex::new(HashMap::new()),
+            last_content: Mutex::new(HashMap::new()),
+            sequences: Mutex::new(HashMap::new()),
+            max_reprocess_depth,
+            dry_run: false,
+            simplify_actions: false,
+            on_modify_error: ModifyErrorPolicy::default(),
+            rng: Mutex::new(StdRng::from_entropy()),
+            command_compat: CommandCompat::new(),
+            script_timings: ScriptTimings::new(),
+            quarantine,
         })
     }
 
+    /// Make weighted modifier selection reproducible (`[modifiers] random_seed`)
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.rng = Mutex::new(StdRng::seed_from_u64(seed));
+    }
+
+    /// Drop redundant actions from rule chains before running them (top-level `simplify_actions`)
+    pub fn set_simplify_actions(&mut self, simplify: bool) {
+        self.simplify_actions = simplify;
+    }
+
+    /// Decide what a failed modifier (error, timeout, invalid result) sends (`network.on_modify_error`)
+    pub fn set_on_modify_error(&mut self, policy: ModifyErrorPolicy) {
+        self.on_modify_error = policy;
+    }
+
+    /// Create a dry-run engine for a shadow ruleset
+    /// Rules are matched (and triggers update `state_manager`) as usual, but plugins and
+    /// notify webhooks are skipped and modify actions are reported without running the modifier.
+    pub fn new_shadow(
+        rules: Vec<CommandRule>,
+        state_manager: Arc<crate::rule_state::RuleStateManager>,
+        max_reprocess_depth: u32,
+    ) -> Result<Self> {
+        let mut engine = Self::new(
+            rules,
+            PluginManager::new()?,
+            ModifierManager::new()?,
+            state_manager,
+            max_reprocess_depth,
+        )?;
+        engine.dry_run = true;
+        Ok(engine)
+    }
+
+    /// Number of rules currently enabled (by default or via triggers)
+    pub fn active_rule_count(&self) -> usize {
+        self.rules()
+            .iter()
+            .filter(|rule| self.state_manager.is_rule_enabled(&rule.name))
+            .count()
+    }
+
+    /// Total number of rules
+    pub fn rule_count(&self) -> usize {
+        self.rules().len()
+    }
+
+    /// Whether a live rule has this name
+    pub fn has_rule(&self, name: &str) -> bool {
+        self.rules().iter().any(|rule| rule.name == name)
+    }
+
+    /// Snapshot of the live rules
+    fn rules(&self) -> Arc<Vec<CommandRule>> {
+        self.rules.read().unwrap().clone()
+    }
+
+    /// Swap in a new version of an existing rule (matched by name), keeping the others
+    /// The rule list is re-sorted by priority and validated as a whole before the swap;
+    /// the rule's enabled/triggered state carries over.
+    pub fn replace_rule(&self, rule: CommandRule) -> Result<()> {
+        let mut live = self.rules.write().unwrap();
+        let Some(index) = live.iter().position(|r| r.name == rule.name) else {
+            return Err(BitchError::Config(format!("Rule '{}' not found", rule.name)));
+        };
+
+        let mut rules = live.as_ref().clone();
+        rules[index] = rule;
+        rules.sort_by_key(|rule| std::cmp::Reverse(rule.priority));
+        crate::config::validate_rules(&rules).map_err(|e| BitchError::Config(format!("{:#}", e)))?;
+
+        *live = Arc::new(rules);
+        Ok(())
+    }
+
+    /// Systems whose traffic is blocked regardless of the rules
+    pub(crate) fn quarantine(&self) -> &Arc<Quarantine> {
+        &self.quarantine
+    }
+
+    /// Enforce another engine's quarantine set (the shadow engine shares the live one)
+    pub(crate) fn share_quarantine(&mut self, quarantine: Arc<Quarantine>) {
+        self.quarantine = quarantine;
+    }
+
+    /// Execution times of modifier and plugin runs (for the admin API)
+    pub(crate) fn script_timings(&self) -> &ScriptTimings {
+        &self.script_timings
+    }
+
+    /// The GCS client a router -> GCS response to a command sent with `upgrade_compat` goes to
+    /// None if it isn't such a response; it is then broadcast as usual.
+    pub fn response_requester(&self, header: &MavHeader, msg: &MavMessage) -> Option<u64> {
+        self.command_compat.requester(header, msg)
+    }
+
+    /// Run every plugin's on_shutdown() hook (called once during graceful shutdown)
+    pub fn shutdown_plugins(&self) {
+        self.plugin_manager.shutdown();
+    }
+
+    /// Ask a plugin's route hook function where a frame goes (timed like any plugin call)
+    pub fn route(&self, plugin: &str, function: &str, context: &RouteContext) -> Result<RouteVerdict> {
+        let start = Instant::now();
+        let verdict = self.plugin_manager.route(plugin, function, context);
+        self.script_timings.record_plugin(plugin, start.elapsed());
+        verdict
+    }
+
     /// Process a MAVLINK message and return the appropriate action
-    /// Defaults to "gcs_to_router" direction for backward compatibility
+    /// Defaults to GCS -> router direction for backward compatibility
     #[allow(dead_code)]
     pub fn process_message(&self, header: &MavHeader, msg: &MavMessage) -> ProcessResult {
-        self.process_message_with_direction(header, msg, "gcs_to_router")
+        self.process_message_with_direction(header, msg, Direction::GcsToRouter)
     }
 
     /// Process a MAVLINK message with a specified direction filter
@@ -97,69 +344,342 @@ impl RuleEngine {
         &self,
         header: &MavHeader,
         msg: &MavMessage,
-        direction: &str,
+        direction: Direction,
     ) -> ProcessResult {
+        self.process_message_at_depth(header, msg, FrameFlags::default(), direction, Ingress::default(), &[])
+    }
+
+    /// Process a parsed frame with a specified direction filter
+    /// Unlike `process_message_with_direction`, `incompat_flags`/`compat_flags` conditions
+    /// see the frame's actual flags.
+    pub fn process_frame(&self, frame: &ParsedFrame, direction: Direction) -> ProcessResult {
+        self.process_frame_from(frame, direction, Ingress::default())
+    }
+
+    /// Process a frame a GCS client sent (GCS -> router)
+    /// `client_group` is the client's `[[network.client_groups]]` group; rules with a
+    /// `client_group` only match frames from that group.
+    pub fn process_client_frame(&self, frame: &ParsedFrame, client_group: Option<&str>) -> ProcessResult {
+        let ingress = Ingress {
+            client_group,
+            ..Default::default()
+        };
+        self.process_frame_from(frame, Direction::GcsToRouter, ingress)
+    }
+
+    /// Process a parsed frame together with where it came in
+    /// Rules with a `client_group` or a `source` condition only match frames from that ingress.
+    pub fn process_frame_from(&self, frame: &ParsedFrame, direction: Direction, ingress: Ingress) -> ProcessResult {
+        self.process_message_at_depth(&frame.header, &frame.message, frame.flags, direction, ingress, &[])
+    }
+
+    /// Process a frame the dialect can't decode, from the header fields in its bytes
+    /// Rules see it as message type `UNKNOWN` whose only field is `msgid`. Only `forward`,
+    /// `block` and `delay` apply (the others need a decoded message) and no auto-ACK is sent.
+    pub fn process_unparsed(
+        &self,
+        raw: &RawHeader,
+        flags: FrameFlags,
+        direction: Direction,
+        ingress: Ingress,
+    ) -> ProcessResult {
+        let header = raw.mav_header();
+        debug!(
+            "Processing undecodable frame: sysid={}, compid={}, msgid={}, direction={}",
+            header.system_id, header.component_id, raw.message_id, direction
+        );
+        self.record_sequence(&header);
+
+        let message_json = serde_json::json!({ "type": UNKNOWN_MESSAGE_TYPE, "msgid": raw.message_id });
+        if self.quarantine.contains(header.system_id) {
+            debug!("msgid {} from sysid={} blocked: system is quarantined", raw.message_id, header.system_id);
+            return ProcessResult {
+                actions: vec![Action::Block {
+                    rule: "quarantine".to_string(),
+                    reason: Some(format!("system {} is quarantined", header.system_id)),
+                }],
+                ack_info: None,
+                matched_rule: None,
+            };
+        }
+
+        let rules = self.rules();
+        let Some(rule) =
+            self.first_match(&rules, &header, flags, UNKNOWN_MESSAGE_TYPE, &message_json, direction, ingress, &[])
+        else {
+            return ProcessResult {
+                actions: vec![Action::Forward],
+                ack_info: None,
+                matched_rule: None,
+            };
+        };
+
+        let action_names = if self.simplify_actions {
+            rule.simplified_actions()
+        } else {
+            rule.get_actions()
+        };
+        let actions = action_names
+            .iter()
+            .filter_map(|name| match name.as_str() {
+                "forward" => Some(Action::Forward),
+                "delay" => Some(Action::Delay(Duration::from_secs(self.action_value(
+                    rule,
+                    "delay_seconds",
+                    rule.delay_seconds.as_ref(),
+                    0,
+                )))),
+                "block" => Some(Action::Block {
+                    rule: rule.name.clone(),
+                    reason: rule.block_reason.clone(),
+                }),
+                other => {
+                    debug!("Rule '{}': '{}' needs a decoded message, skipping it", rule.name, other);
+                    None
+                }
+            })
+            .collect();
+
+        ProcessResult {
+            actions,
+            ack_info: None,
+            matched_rule: Some(rule.name.clone()),
+        }
+    }
+
+    /// Process a message; `reprocessed_by` lists the rules whose `reprocess` led here, outermost
+    /// first (its length is the re-evaluation depth). Those rules are skipped when matching.
+    fn process_message_at_depth(
+        &self,
+        header: &MavHeader,
+        msg: &MavMessage,
+        flags: FrameFlags,
+        direction: Direction,
+        ingress: Ingress,
+        reprocessed_by: &[&str],
+    ) -> ProcessResult {
+        let depth = reprocessed_by.len();
         let msg_name = get_message_name(msg);
         debug!(
-            "Processing message: sysid={}, compid={}, msg={}, direction={}",
-            header.system_id, header.component_id, msg_name, direction
+            "Processing message: sysid={}, compid={}, msg={}, direction={}, depth={}",
+            header.system_id, header.component_id, msg_name, direction, depth
         );
 
-        // Find the first matching rule (rules are sorted by priority)
-        for rule in &self.rules {
+        // Track sequence jumps from every frame, not only those a rule ends up evaluating
+        // (re-evaluations of a modified message reuse the original frame's jump)
+        if depth == 0 {
+            self.record_sequence(header);
+        }
+
+        // Serialize once (mavlink internally-tagged format); shared by conditions, plugins, ACKs
+        let message_json = match serde_json::to_value(msg) {
+            Ok(val) => val,
+            Err(e) => {
+                warn!("Failed to serialize {} for rule processing, forwarding: {}", msg_name, e);
+                return ProcessResult {
+                    actions: vec![Action::Forward],
+                    ack_info: None,
+                    matched_rule: None,
+                };
+            }
+        };
+
+        // Quarantined systems are blocked before any rule sees the message
+        if let Some(system_id) = self.quarantined_party(header, &message_json) {
+            debug!("{} from sysid={} blocked: system {} is quarantined", msg_name, header.system_id, system_id);
+            return ProcessResult {
+                actions: vec![Action::Block {
+                    rule: "quarantine".to_string(),
+                    reason: Some(format!("system {} is quarantined", system_id)),
+                }],
+                ack_info: None,
+                matched_rule: None,
+            };
+        }
+
+        let rules = self.rules();
+        match self.first_match(&rules, header, flags, &msg_name, &message_json, direction, ingress, reprocessed_by) {
+            Some(rule) => self.execute_action(rule, msg, &message_json, header, direction, ingress, reprocessed_by),
+            // No rule matched, forward by default
+            None => ProcessResult {
+                actions: vec![Action::Forward],
+                ack_info: None,
+                matched_rule: None,
+            },
+        }
+    }
+
+    /// Find the first enabled rule matching a message (rules are sorted by priority)
+    /// and run its match side effects: event, triggers, plugins and notify webhook.
+    /// Rules in `skip` (those whose `reprocess` produced this message) are passed over.
+    #[allow(clippy::too_many_arguments)]
+    fn first_match<'a>(
+        &self,
+        rules: &'a [CommandRule],
+        header: &MavHeader,
+        flags: FrameFlags,
+        msg_name: &str,
+        message_json: &JsonValue,
+        direction: Direction,
+        ingress: Ingress,
+        skip: &[&str],
+    ) -> Option<&'a CommandRule> {
+        for rule in rules {
+            if skip.contains(&rule.name.as_str()) {
+                continue;
+            }
+
             // Check if rule is enabled
             if !self.state_manager.is_rule_enabled(&rule.name) {
                 debug!("Rule '{}' is disabled, skipping", rule.name);
                 continue;
             }
 
-            if self.matches_rule(header, msg, rule, direction) {
-                info!(
-                    "Rule matched: '{}' - {}",
-                    rule.name,
-                    rule.description.as_deref().unwrap_or("no description")
-                );
+            // Check if rule is inside its scheduled time window
+            if let Some(schedule) = &rule.schedule {
+                if !schedule.is_active_now() {
+                    debug!("Rule '{}' is outside its schedule, skipping", rule.name);
+                    continue;
+                }
+            }
+
+            // Group-scoped rules only see traffic from their group's GCS clients
+            if rule.client_group.is_some() && rule.client_group.as_deref() != ingress.client_group {
+                continue;
+            }
+
+            // Source-scoped rules only see frames from the transport/link they name
+            if let Some(source) = &rule.conditions.source {
+                if !ingress.source_matches(source) {
+                    debug!("Rule '{}' wants source '{}', frame came from {:?}", rule.name, source, ingress.source);
+                    continue;
+                }
+            }
+
+            if self.matches_rule(header, flags, msg_name, message_json, rule, direction) {
+                if self.dry_run {
+                    debug!("Shadow rule matched: '{}'", rule.name);
+                } else {
+                    info!(
+                        "Rule matched: '{}' - {}",
+                        rule.name,
+                        rule.description.as_deref().unwrap_or("no description")
+                    );
+                }
+
+                if let Some(event) = &rule.record_event {
+                    self.state_manager.record_event(event, header.system_id);
+                }
 
                 // Execute triggers on_match if configured
                 if let Some(triggers) = &rule.triggers {
                     if triggers.on_match {
-                        self.execute_triggers(triggers, &rule.name, header, msg);
+                        self.execute_triggers(triggers, &rule.name, header, message_json);
                     }
                 }
 
-                // Execute plugins for this rule
-                self.execute_plugins(rule, header, msg);
+                if !self.dry_run && self.sampled(rule) {
+                    // Execute plugins for this rule
+                    self.execute_plugins(rule, header, msg_name, message_json);
+
+                    // Fire notify webhook (non-blocking)
+                    self.send_notification(rule, header, message_json);
+                }
 
-                return self.execute_action(rule, msg, header);
+                return Some(rule);
             }
         }
 
-        // No rule matched, forward by default
-        ProcessResult {
-            actions: vec![Action::Forward],
-            ack_info: None,
+        None
+    }
+
+    /// The quarantined system a message comes from or is addressed to (`target_system`), if any
+    /// Broadcasts (target 0) are only blocked when their sender is quarantined.
+    fn quarantined_party(&self, header: &MavHeader, msg_json: &JsonValue) -> Option<u8> {
+        if self.quarantine.contains(header.system_id) {
+            return Some(header.system_id);
+        }
+        msg_json
+            .get("target_system")
+            .and_then(JsonValue::as_u64)
+            .and_then(|target| u8::try_from(target).ok())
+            .filter(|target| *target != 0 && self.quarantine.contains(*target))
+    }
+
+    /// Whether this match runs the rule's plugins and notify webhook (`sample_rate`)
+    fn sampled(&self, rule: &CommandRule) -> bool {
+        match rule.sample_rate {
+            Some(rate) if rate < 1.0 => {
+                let sampled = self.rng.lock().unwrap().gen_bool(rate.max(0.0));
+                if !sampled {
+                    trace!("Rule '{}' match not sampled, skipping plugins and notify", rule.name);
+                }
+                sampled
+            }
+            _ => true,
         }
     }
 
     /// Execute all plugins attached to a rule
-    fn execute_plugins(&self, rule: &CommandRule, header: &MavHeader, msg: &MavMessage) {
+    fn execute_plugins(&self, rule: &CommandRule, header: &MavHeader, msg_name: &str, msg_json: &JsonValue) {
         if rule.plugins.is_empty() {
             return;
         }
 
         // Build context for plugins (includes trigger_context if rule was activated by trigger)
-        let context = self.build_plugin_context(header, msg, &rule.name);
+        let context = self.build_plugin_context(header, msg_name, msg_json, rule);
 
         // Execute each plugin
         for plugin_name in &rule.plugins {
-            if let Err(e) = self.plugin_manager.execute_plugin(plugin_name, &context) {
+            let _span = debug_span!("plugin", name = %plugin_name, rule = %rule.name).entered();
+            let start = Instant::now();
+            let result = self.plugin_manager.execute_plugin(plugin_name, &context);
+            let elapsed = start.elapsed();
+            self.script_timings.record_plugin(plugin_name, elapsed);
+            trace!(elapsed_us = elapsed.as_micros() as u64, "Plugin '{}' finished", plugin_name);
+
+            if let Err(e) = result {
                 warn!("Plugin '{}' execution failed: {}", plugin_name, e);
             }
         }
     }
 
+    /// Send the rule's notify webhook in the background so forwarding is never blocked
+    fn send_notification(&self, rule: &CommandRule, header: &MavHeader, msg_json: &JsonValue) {
+        let Some(notify) = &rule.notify else {
+            return;
+        };
+
+        let body = render_template(&notify.template, &rule.name, header, msg_json);
+
+        let url = notify.url.clone();
+        let content_type = notify.content_type.clone();
+        let rule_name = rule.name.clone();
+
+        tokio::spawn(async move {
+            let result = crate::plugins::shared_client()
+                .post(&url)
+                .header(reqwest::header::CONTENT_TYPE, content_type)
+                .body(body)
+                .send()
+                .await;
+
+            match result {
+                Ok(response) if response.status().is_success() => {
+                    debug!("Notify webhook for rule '{}' delivered", rule_name);
+                }
+                Ok(response) => {
+                    warn!("Notify webhook for rule '{}' returned {}", rule_name, response.status());
+                }
+                Err(e) => {
+                    warn!("Notify webhook for rule '{}' failed: {}", rule_name, e);
+                }
+            }
+        });
+    }
+
     /// Execute triggers (activate/deactivate other rules)
-    fn execute_triggers(&self, triggers: &crate::config::TriggerConfig, source_rule: &str, header: &MavHeader, msg: &MavMessage) {
+    fn execute_triggers(&self, triggers: &crate::config::TriggerConfig, source_rule: &str, header: &MavHeader, msg_json: &JsonValue) {
         use std::time::Duration;
         use std::collections::HashMap;
         use serde_json::Value as JsonValue;
@@ -174,9 +694,7 @@ impl RuleEngine {
             ctx.insert("sequence".to_string(), JsonValue::from(header.sequence));
 
             // Add full message
-            if let Ok(message_json) = serde_json::to_value(msg) {
-                ctx.insert("message".to_string(), message_json);
-            }
+            ctx.insert("message".to_string(), msg_json.clone());
 
             ctx
         } else {
@@ -197,62 +715,164 @@ impl RuleEngine {
             self.state_manager.deactivate_rule(rule_name);
             info!("Rule '{}' deactivated rule '{}'", source_rule, rule_name);
         }
+
+        // The shadow engine shares the live quarantine set, so it must never change it
+        if triggers.quarantine_source && !self.dry_run {
+            let duration = triggers.quarantine_seconds.filter(|secs| *secs > 0).map(Duration::from_secs);
+            self.quarantine.add(header.system_id, duration);
+            info!("Rule '{}' quarantined system {}", source_rule, header.system_id);
+        }
     }
 
     /// Build plugin context from MAVLINK message (works for all message types)
-    fn build_plugin_context(&self, header: &MavHeader, msg: &MavMessage, rule_name: &str) -> PluginContext {
-        let message_type = get_message_name(msg);
-
-        // Serialize message to JSON (mavlink internally-tagged format)
-        let message_json = serde_json::to_value(msg)
-            .unwrap_or_else(|_| serde_json::json!({}));
-
+    fn build_plugin_context(&self, header: &MavHeader, msg_name: &str, msg_json: &JsonValue, rule: &CommandRule) -> PluginContext {
         // Get trigger context for this rule (if activated by trigger)
-        let trigger_context = self.state_manager.get_trigger_context(rule_name);
+        let trigger_context = self.state_manager.get_trigger_context(&rule.name);
+        let plugin_config = rule
+            .plugin_config
+            .iter()
+            .map(|(key, value)| (key.clone(), toml_to_json_value(value)))
+            .collect();
 
         PluginContext {
             system_id: header.system_id,
             component_id: header.component_id,
-            message_type,
-            message: message_json,
+            message_type: msg_name.to_string(),
+            message: msg_json.clone(),
+            rule_name: rule.name.clone(),
             trigger_context,
+            plugin_config,
         }
     }
 
     /// Check if a message matches a specific rule (works for all message types)
-    fn matches_rule(&self, header: &MavHeader, msg: &MavMessage, rule: &CommandRule, direction: &str) -> bool {
+    fn matches_rule(
+        &self,
+        header: &MavHeader,
+        flags: FrameFlags,
+        msg_name: &str,
+        msg_json: &JsonValue,
+        rule: &CommandRule,
+        direction: Direction,
+    ) -> bool {
         // Check direction filter first
-        if rule.direction != "both" && rule.direction != direction {
+        if !rule.direction.matches(direction) {
             return false;
         }
 
         // Check message type
-        let msg_name = get_message_name(msg);
         if rule.message_type != msg_name {
             return false;
         }
 
-        // Serialize message to JSON (mavlink internally-tagged format)
-        let message_json = match serde_json::to_value(msg) {
-            Ok(val) => val,
-            Err(e) => {
-                warn!("Failed to serialize message for condition checking: {}", e);
-                return false;
-            }
-        };
+        // Record the sighting before other conditions so the gap reflects all traffic of this type
+        let first_seen = rule
+            .conditions
+            .first_seen
+            .map(|gap| self.record_seen(&rule.name, header.system_id, Duration::from_secs(gap)));
+        let delta = rule
+            .conditions
+            .delta
+            .as_ref()
+            .map(|delta| self.record_delta(&rule.name, header.system_id, msg_json, delta));
+        if first_seen == Some(false) {
+            debug!("Rule '{}': system {} seen recently, skipping", rule.name, header.system_id);
+            return false;
+        }
+        if delta == Some(false) {
+            debug!("Rule '{}': no jump in delta field from system {}", rule.name, header.system_id);
+            return false;
+        }
 
         // Check conditions (fields accessed directly from internally-tagged format)
-        if !self.matches_conditions(header, &message_json, &rule.conditions) {
+        if !self.matches_conditions(header, flags, msg_json, &rule.conditions) {
             return false;
         }
 
         true
     }
 
+    /// Update the last-seen time of a system for a rule
+    /// Returns true if the system is new or hasn't been seen for longer than `gap`
+    fn record_seen(&self, rule_name: &str, system_id: u8, gap: Duration) -> bool {
+        let now = Instant::now();
+        let mut last_seen = self.last_seen.lock().unwrap();
+
+        match last_seen.insert((rule_name.to_string(), system_id), now) {
+            Some(previous) => now.duration_since(previous) > gap,
+            None => true,
+        }
+    }
+
+    /// Store a system's current value of the delta field for a rule
+    /// Returns true if it moved by more than the threshold since the previous message
+    /// (never for the first message, or when the field is missing or not numeric)
+    fn record_delta(&self, rule_name: &str, system_id: u8, msg_json: &JsonValue, delta: &DeltaCondition) -> bool {
+        let Some(value) = msg_json.get(&delta.field).and_then(JsonValue::as_f64) else {
+            debug!("Delta field '{}' missing or not numeric", delta.field);
+            return false;
+        };
+
+        let mut last_values = self.last_values.lock().unwrap();
+        match last_values.insert((rule_name.to_string(), system_id), value) {
+            Some(previous) => (value - previous).abs() > delta.threshold,
+            None => false,
+        }
+    }
+
+    /// Block a message whose content equals the last one passed for its system and type
+    /// Only differing messages are stored, so a repeat is always compared with what went out.
+    fn dedup_identical(&self, rule: &CommandRule, header: &MavHeader, msg: &MavMessage, msg_json: &JsonValue) -> Action {
+        let key = (rule.name.clone(), header.system_id, get_message_name(msg));
+        let mut last_content = self.last_content.lock().unwrap();
+        if last_content.get(&key) == Some(msg_json) {
+            debug!("Rule '{}': {} from sysid={} unchanged, dropping", rule.name, key.2, header.system_id);
+            return Action::Block {
+                rule: rule.name.clone(),
+                reason: Some(
+                    rule.block_reason
+                        .clone()
+                        .unwrap_or_else(|| "identical to the previous message".to_string()),
+                ),
+            };
+        }
+
+        last_content.insert(key, msg_json.clone());
+        Action::Forward
+    }
+
+    /// Store a frame's sequence number and how far it moved from the previous frame of its source
+    /// The sequence wraps at 256, so 255 -> 0 is a jump of 1
+    fn record_sequence(&self, header: &MavHeader) {
+        let mut sequences = self.sequences.lock().unwrap();
+        let source = (header.system_id, header.component_id);
+        let jump = sequences
+            .get(&source)
+            .map(|previous| header.sequence.wrapping_sub(previous.last));
+        sequences.insert(
+            source,
+            SequenceState {
+                last: header.sequence,
+                jump,
+            },
+        );
+    }
+
+    /// How far the current frame's sequence jumped from the previous frame of its source
+    /// (None for the first frame seen from it)
+    fn sequence_jump(&self, header: &MavHeader) -> Option<u8> {
+        self.sequences
+            .lock()
+            .unwrap()
+            .get(&(header.system_id, header.component_id))
+            .and_then(|state| state.jump)
+    }
+
     /// Check if conditions match for any message type
     fn matches_conditions(
         &self,
         header: &MavHeader,
+        flags: FrameFlags,
         msg_json: &JsonValue,
         conditions: &RuleConditions,
     ) -> bool {
@@ -271,10 +891,42 @@ impl RuleEngine {
             }
         }
 
+        if let Some(gap) = conditions.sequence_gap_above {
+            match self.sequence_jump(header) {
+                Some(jump) if jump > gap => {
+                    debug!("Sequence jumped by {} (> {}) from system {}", jump, gap, header.system_id);
+                }
+                _ => return false,
+            }
+        }
+
+        if let Some(condition) = &conditions.incompat_flags {
+            if !condition.matches(flags.incompat, KNOWN_INCOMPAT_FLAGS) {
+                debug!("Incompat flags 0x{:02x} don't match", flags.incompat);
+                return false;
+            }
+        }
+
+        if let Some(condition) = &conditions.compat_flags {
+            if !condition.matches(flags.compat, KNOWN_COMPAT_FLAGS) {
+                debug!("Compat flags 0x{:02x} don't match", flags.compat);
+                return false;
+            }
+        }
+
+        if let Some(since_event) = &conditions.since_event {
+            let system_id = since_event.per_system.then_some(header.system_id);
+            let window = Duration::from_secs(since_event.within_seconds);
+            if !self.state_manager.event_within(&since_event.name, system_id, window) {
+                debug!("No '{}' event within {}s", since_event.name, since_event.within_seconds);
+                return false;
+            }
+        }
+
         // Check all field conditions generically (works for ALL message types)
         // Fields accessed directly from internally-tagged format
         for (field_name, expected_value) in &conditions.custom {
-            if !self.check_field_condition(msg_json, field_name, expected_value) {
+            if !self.check_field_condition(header, msg_json, field_name, expected_value) {
                 return false;
             }
         }
@@ -283,7 +935,20 @@ impl RuleEngine {
     }
 
     /// Check a field condition (works for any field in any message type)
-    fn check_field_condition(&self, msg_data: &JsonValue, field_name: &str, expected_value: &toml::Value) -> bool {
+    fn check_field_condition(
+        &self,
+        header: &MavHeader,
+        msg_data: &JsonValue,
+        field_name: &str,
+        expected_value: &toml::Value,
+    ) -> bool {
+        // Operator conditions (e.g. { field_eq = "param2" }) instead of a literal value
+        if let toml::Value::Table(table) = expected_value {
+            if is_operator_table(table) {
+                return self.check_operator_conditions(header, msg_data, field_name, table);
+            }
+        }
+
         // Get the actual field value from the message
         let actual_value = match msg_data.get(field_name) {
             Some(val) => val,
@@ -329,77 +994,486 @@ impl RuleEngine {
         matches
     }
 
+    /// Check operator conditions on a field (all operators in the table must match)
+    fn check_operator_conditions(
+        &self,
+        header: &MavHeader,
+        msg_data: &JsonValue,
+        field_name: &str,
+        operators: &toml::map::Map<String, toml::Value>,
+    ) -> bool {
+        let actual_value = msg_data.get(field_name);
+        let ignore_case = operators.get("ignore_case").and_then(|v| v.as_bool()).unwrap_or(false);
+
+        for (operator, operand) in operators {
+            let matches = match operator.as_str() {
+                // Compare against another field of the same message
+                "field_eq" | "field_ne" => {
+                    let other = operand.as_str().and_then(|name| msg_data.get(name));
+                    match (actual_value, other) {
+                        (Some(actual), Some(other)) => {
+                            json_values_equal(actual, other) == (operator == "field_eq")
+                        }
+                        _ => false,
+                    }
+                }
+                // Compare against a header field (system_id, component_id, sequence)
+                "header_eq" | "header_ne" => {
+                    let other = operand.as_str().and_then(|name| header_field(header, name));
+                    match (actual_value, other) {
+                        (Some(actual), Some(other)) => {
+                            json_values_equal(actual, &other) == (operator == "header_eq")
+                        }
+                        _ => false,
+                    }
+                }
+                // Field present / absent in the decoded message
+                "exists" => operand.as_bool() == Some(actual_value.is_some()),
+                // All bits of the mask set
+                "bit_set" => match (actual_value.and_then(field_bits), operand.as_integer()) {
+                    (Some(bits), Some(mask)) => bits & mask as u64 == mask as u64,
+                    _ => false,
+                },
+                // Any / all of the listed masks set
+                "bits_any" | "bits_all" => {
+                    let masks: Vec<u64> = operand
+                        .as_array()
+                        .map(|masks| masks.iter().filter_map(|m| m.as_integer()).map(|m| m as u64).collect())
+                        .unwrap_or_default();
+                    match actual_value.and_then(field_bits) {
+                        Some(bits) if operator == "bits_any" => masks.iter().any(|&mask| bits & mask != 0),
+                        Some(bits) => !masks.is_empty() && masks.iter().all(|&mask| bits & mask == mask),
+                        None => false,
+                    }
+                }
+                // Substring checks on string and char array fields (e.g. STATUSTEXT text)
+                "starts_with" | "ends_with" | "contains" => {
+                    match (actual_value.and_then(field_text), operand.as_str()) {
+                        (Some(text), Some(pattern)) => text_matches(operator, &text, pattern, ignore_case),
+                        _ => false,
+                    }
+                }
+                // Modifier for the substring checks above
+                "ignore_case" => true,
+                _ => false,
+            };
+
+            if !matches {
+                debug!(
+                    "Operator condition mismatch for '{}': {} {:?}, got {:?}",
+                    field_name, operator, operand, actual_value
+                );
+                return false;
+            }
+        }
+
+        true
+    }
+
     /// Execute the action sequence specified by a rule
-    fn execute_action(&self, rule: &CommandRule, msg: &MavMessage, header: &MavHeader) -> ProcessResult {
+    #[allow(clippy::too_many_arguments)]
+    fn execute_action(
+        &self,
+        rule: &CommandRule,
+        msg: &MavMessage,
+        msg_json: &JsonValue,
+        header: &MavHeader,
+        direction: Direction,
+        ingress: Ingress,
+        reprocessed_by: &[&str],
+    ) -> ProcessResult {
         // Build ACK info if auto_ack is enabled (works for ANY message type)
-        let ack_info = if rule.auto_ack {
-            self.build_ack_info(rule, msg, header)
+        let mut ack_info = if rule.auto_ack {
+            self.build_ack_info(rule, msg_json, header)
         } else {
             None
         };
 
         // Build action sequence from rule
-        let action_names = rule.get_actions();
+        let action_names = if self.simplify_actions {
+            rule.simplified_actions()
+        } else {
+            rule.get_actions()
+        };
         let mut actions = Vec::new();
+        // Result of re-running rule matching on a modified message (reprocess = true)
+        let mut reprocessed = None;
 
         for action_name in action_names {
             let action = match action_name.as_str() {
                 "delay" => {
-                    let delay = Duration::from_secs(rule.delay_seconds.unwrap_or(0));
-                    Action::Delay(delay)
+                    let delay = self.action_value(rule, "delay_seconds", rule.delay_seconds.as_ref(), 0);
+                    Action::Delay(Duration::from_secs(delay))
                 }
                 "batch" => {
                     let count = rule.batch_count.unwrap_or(1);
-                    let timeout = Duration::from_secs(rule.batch_timeout_seconds.unwrap_or(30));
-                    let key = rule.batch_key.clone();
+                    let timeout = Duration::from_secs(self.action_value(
+                        rule,
+                        "batch_timeout_seconds",
+                        rule.batch_timeout_seconds.as_ref(),
+                        30,
+                    ));
+                    let key = self.resolve_batch_key(rule, msg_json, header);
                     let forward_on_timeout = rule.batch_timeout_forward;
                     let system_id_field = rule.batch_system_id_field.clone();
+                    let timeout_actions = rule
+                        .batch_timeout_actions
+                        .as_ref()
+                        .map(|names| names.iter().map(|name| self.timeout_action(rule, name)).collect());
                     Action::Batch {
                         count,
                         timeout,
                         key,
                         forward_on_timeout,
                         system_id_field,
+                        timeout_actions,
                     }
                 }
-                "block" => Action::Block,
+                "burst" => Action::Burst {
+                    count: rule.burst_count.unwrap_or(1),
+                    timeout: Duration::from_secs(self.action_value(
+                        rule,
+                        "burst_timeout_seconds",
+                        rule.burst_timeout_seconds.as_ref(),
+                        30,
+                    )),
+                    key: format!("{}/{}", rule.name, direction),
+                    forward_on_timeout: rule.burst_timeout_forward,
+                },
+                "block" => Action::Block {
+                    rule: rule.name.clone(),
+                    reason: rule.block_reason.clone(),
+                },
                 "forward" => Action::Forward,
+                "dedup_identical" => self.dedup_identical(rule, header, msg, msg_json),
+                "coalesce" => Action::Coalesce {
+                    key: format!("{}/{}/{}/{}", rule.name, direction, header.system_id, get_message_name(msg)),
+                    interval: Duration::from_millis(
+                        self.action_value(rule, "coalesce_interval_ms", rule.coalesce_interval_ms.as_ref(), 1000)
+                            .max(1),
+                    ),
+                },
                 "modify" => {
-                    if let Some(ref modifier_name) = rule.modifier {
+                    let modifier = self.select_modifier(rule);
+                    if let (true, Some(modifier_name)) = (self.dry_run, &modifier) {
+                        Action::Modify {
+                            modifier: modifier_name.clone(),
+                            modified_message: None,
+                        }
+                    } else if let Some(ref modifier_name) = modifier {
                         // Get trigger context for this rule (if activated by trigger)
                         let trigger_context = self.state_manager.get_trigger_context(&rule.name);
 
                         // Execute the modifier with the full message and trigger context
-                        match self.modifier_manager.execute_modifier(modifier_name, header, msg, &trigger_context) {
+                        let span = debug_span!("modifier", name = %modifier_name, rule = %rule.name).entered();
+                        let start = Instant::now();
+                        let result = self.modifier_manager.execute_modifier(modifier_name, &rule.name, header, msg, &trigger_context);
+                        let elapsed = start.elapsed();
+                        self.script_timings.record_modifier(modifier_name, elapsed);
+                        trace!(elapsed_us = elapsed.as_micros() as u64, "Modifier '{}' finished", modifier_name);
+                        drop(span);
+
+                        match result {
                             Ok(modified_msg) => {
+                                if rule.reprocess {
+                                    reprocessed = self.reprocess(rule, header, &modified_msg, direction, ingress, reprocessed_by);
+                                }
                                 Action::Modify {
                                     modifier: modifier_name.clone(),
                                     modified_message: Some(modified_msg),
                                 }
                             }
-                            Err(e) => {
-                                warn!("Modifier '{}' execution failed: {}", modifier_name, e);
-                                Action::Forward
-                            }
+                            Err(e) => self.modifier_failed(rule, modifier_name, e),
                         }
                     } else {
                         warn!("Modify action specified but no modifier configured");
                         Action::Forward
                     }
                 }
+                "upgrade_command" => self.upgrade_command(rule, msg, ingress),
+                "transform" => self.transform_fields(rule, msg_json),
+                "redact" => self.redact_fields(rule, msg, msg_json),
+                "readdress" => self.readdress(rule, msg_json),
+                "retry_until_ack" => Action::RetryUntilAck {
+                    message_type: rule.retry_ack_type.clone().unwrap_or_else(|| "COMMAND_ACK".to_string()),
+                    timeout: Duration::from_secs(
+                        self.action_value(rule, "retry_timeout_seconds", rule.retry_timeout_seconds.as_ref(), 3)
+                            .max(1),
+                    ),
+                    attempts: rule.retry_attempts.unwrap_or(3),
+                },
                 _ => {
                     info!("Unknown action '{}', using forward", action_name);
                     Action::Forward
                 }
             };
             actions.push(action);
+
+            // The rule matching the modified message takes over the rest of the chain;
+            // if none matched, this rule's remaining actions run as usual
+            if let Some(result) = reprocessed.take() {
+                let unmatched = result.matched_rule.is_none() && matches!(result.actions.as_slice(), [Action::Forward]);
+                if !unmatched {
+                    actions.extend(result.actions);
+                    ack_info = ack_info.or(result.ack_info);
+                    break;
+                }
+                debug!("Rule '{}': no rule matched the modified message, continuing its chain", rule.name);
+            }
+        }
+
+        ProcessResult {
+            actions,
+            ack_info,
+            matched_rule: Some(rule.name.clone()),
+        }
+    }
+
+    /// The action replacing a modify whose modifier failed, per `on_modify_error`
+    /// There is no modified message to re-encode, so best effort forwards the original.
+    fn modifier_failed(&self, rule: &CommandRule, modifier_name: &str, error: BitchError) -> Action {
+        if self.on_modify_error == ModifyErrorPolicy::Block {
+            warn!("Modifier '{}' failed, blocking (on_modify_error = block): {}", modifier_name, error);
+            Action::Block {
+                rule: rule.name.clone(),
+                reason: Some(format!("modifier '{}' failed", modifier_name)),
+            }
+        } else {
+            warn!("Modifier '{}' failed, forwarding original: {}", modifier_name, error);
+            Action::Forward
+        }
+    }
+
+    /// Build one of a rule's `batch_timeout_actions` (validated to delay, block or forward)
+    fn timeout_action(&self, rule: &CommandRule, name: &str) -> Action {
+        match name {
+            "delay" => Action::Delay(Duration::from_secs(self.action_value(
+                rule,
+                "delay_seconds",
+                rule.delay_seconds.as_ref(),
+                0,
+            ))),
+            "block" => Action::Block {
+                rule: rule.name.clone(),
+                reason: rule.block_reason.clone(),
+            },
+            _ => Action::Forward,
+        }
+    }
+
+    /// The modifier to run for a rule: its `modifier`, or one of its `modifiers` picked at random by weight
+    fn select_modifier(&self, rule: &CommandRule) -> Option<String> {
+        if rule.modifiers.is_empty() {
+            return rule.modifier.clone();
+        }
+
+        let total: u64 = rule.modifiers.iter().map(|m| m.weight as u64).sum();
+        let mut pick = self.rng.lock().unwrap().gen_range(0..total.max(1));
+        for entry in &rule.modifiers {
+            if pick < entry.weight as u64 {
+                debug!("Rule '{}' picked modifier '{}'", rule.name, entry.name);
+                return Some(entry.name.clone());
+            }
+            pick -= entry.weight as u64;
+        }
+        rule.modifiers.last().map(|entry| entry.name.clone())
+    }
+
+    /// Re-run rule matching on a modified message, unless the depth limit is reached
+    /// The rule (and any rule whose reprocess led to it) can't match the result again.
+    fn reprocess(
+        &self,
+        rule: &CommandRule,
+        header: &MavHeader,
+        modified_msg: &MavMessage,
+        direction: Direction,
+        ingress: Ingress,
+        reprocessed_by: &[&str],
+    ) -> Option<ProcessResult> {
+        if reprocessed_by.len() >= self.max_reprocess_depth as usize {
+            warn!(
+                "Rule '{}': reprocess depth limit ({}) reached, continuing without re-evaluation",
+                rule.name, self.max_reprocess_depth
+            );
+            return None;
+        }
+
+        debug!("Rule '{}': re-evaluating rules on modified message", rule.name);
+        let mut chain = reprocessed_by.to_vec();
+        chain.push(&rule.name);
+        // The modified message is re-serialized unsigned, so it carries no flags
+        Some(self.process_message_at_depth(
+            header,
+            modified_msg,
+            FrameFlags::default(),
+            direction,
+            ingress,
+            &chain,
+        ))
+    }
+
+    /// Numeric action setting, resolving `{ctx.path}` templates from the rule's trigger context
+    /// (`fallback` is used when the setting is unset, or the key is missing and has no default)
+    fn action_value(&self, rule: &CommandRule, field: &str, value: Option<&ActionValue>, fallback: u64) -> u64 {
+        match value {
+            None => fallback,
+            Some(ActionValue::Fixed(value)) => *value,
+            Some(template) => {
+                let trigger_context = self.state_manager.get_trigger_context(&rule.name);
+                template.resolve(&trigger_context).unwrap_or_else(|| {
+                    warn!(
+                        "Rule '{}': {} = \"{}\" has no numeric value in the trigger context, using {}",
+                        rule.name, field, template, fallback
+                    );
+                    fallback
+                })
+            }
+        }
+    }
+
+    /// Resolve `{field}` placeholders in a rule's batch_key from the matched message
+    fn resolve_batch_key(&self, rule: &CommandRule, msg_json: &JsonValue, header: &MavHeader) -> String {
+        if !rule.batch_key.contains('{') {
+            return rule.batch_key.clone();
+        }
+
+        let key = render_template(&rule.batch_key, &rule.name, header, msg_json);
+        debug!("Resolved batch key '{}' -> '{}'", rule.batch_key, key);
+        key
+    }
+
+    /// Apply the rule's numeric `transform_fields` (forwarded unchanged if any field can't be transformed)
+    fn transform_fields(&self, rule: &CommandRule, msg_json: &JsonValue) -> Action {
+        let mut patched = msg_json.clone();
+        for (field_name, transform) in &rule.transform_fields {
+            let Some(current) = patched.get(field_name) else {
+                warn!("Rule '{}': transform field '{}' not in message, forwarding unchanged", rule.name, field_name);
+                return Action::Forward;
+            };
+            let Some(input) = current.as_f64() else {
+                warn!("Rule '{}': transform field '{}' is not numeric, forwarding unchanged", rule.name, field_name);
+                return Action::Forward;
+            };
+
+            let output = transform.apply(input);
+            // Integer fields stay integers (rounded); the range is checked on deserialize
+            let value = if current.is_f64() {
+                JsonValue::from(output)
+            } else {
+                JsonValue::from(output.round() as i64)
+            };
+            debug!("Rule '{}': {} {} {} -> {}", rule.name, field_name, transform.op, input, value);
+            patched[field_name] = value;
+        }
+
+        match serde_json::from_value::<MavMessage>(patched) {
+            Ok(modified) => Action::Modify {
+                modifier: "transform_fields".to_string(),
+                modified_message: Some(modified),
+            },
+            Err(e) => {
+                warn!("Rule '{}': transformed message is invalid ({}), forwarding unchanged", rule.name, e);
+                Action::Forward
+            }
         }
+    }
+
+    /// Reset the rule's `redact_fields` to their zero/empty defaults
+    /// Fails closed: a message that can't be redacted is blocked rather than forwarded as-is.
+    fn redact_fields(&self, rule: &CommandRule, msg: &MavMessage, msg_json: &JsonValue) -> Action {
+        let blocked = |why: &str| {
+            warn!("Rule '{}': {}, blocking", rule.name, why);
+            Action::Block {
+                rule: rule.name.clone(),
+                reason: Some("redaction failed".to_string()),
+            }
+        };
 
-        ProcessResult { actions, ack_info }
+        let Some(defaults) = crate::messages::default_fields_for_id(msg.message_id()) else {
+            return blocked("no default values for the message type");
+        };
+        let mut patched = msg_json.clone();
+        for field_name in &rule.redact_fields {
+            let Some(blank) = defaults.get(field_name) else {
+                return blocked(&format!("redact field '{}' not in message", field_name));
+            };
+            patched[field_name] = blank.clone();
+        }
+        debug!("Rule '{}': redacted {}", rule.name, rule.redact_fields.join(", "));
+
+        match serde_json::from_value::<MavMessage>(patched) {
+            Ok(modified) => Action::Modify {
+                modifier: "redact_fields".to_string(),
+                modified_message: Some(modified),
+            },
+            Err(e) => blocked(&format!("redacted message is invalid ({})", e)),
+        }
+    }
+
+    /// Set the rule's `readdress` target fields (forwarded unchanged if the message has no such field)
+    fn readdress(&self, rule: &CommandRule, msg_json: &JsonValue) -> Action {
+        let fields = rule.readdress.as_ref().map(|readdress| readdress.fields()).unwrap_or_default();
+        let mut patched = msg_json.clone();
+        for (field_name, value) in &fields {
+            if patched.get(field_name).is_none() {
+                warn!("Rule '{}': readdress field '{}' not in message, forwarding unchanged", rule.name, field_name);
+                return Action::Forward;
+            }
+            patched[field_name] = JsonValue::from(*value);
+        }
+        debug!("Rule '{}': readdressed to {:?}", rule.name, fields);
+
+        match serde_json::from_value::<MavMessage>(patched) {
+            Ok(modified) => Action::Modify {
+                modifier: "readdress".to_string(),
+                modified_message: Some(modified),
+            },
+            Err(e) => {
+                warn!("Rule '{}': readdressed message is invalid ({}), forwarding unchanged", rule.name, e);
+                Action::Forward
+            }
+        }
+    }
+
+    /// Build the COMMAND_INT replacement for a COMMAND_LONG (forwarded unchanged if not convertible)
+    fn upgrade_command(&self, rule: &CommandRule, msg: &MavMessage, ingress: Ingress) -> Action {
+        let MavMessage::COMMAND_LONG(data) = msg else {
+            warn!("upgrade_command applied to {}, forwarding unchanged", get_message_name(msg));
+            return Action::Forward;
+        };
+
+        let frame_name = rule.upgrade_frame.as_deref().unwrap_or("MAV_FRAME_GLOBAL");
+        let frame = match crate::messages::parse_frame(frame_name) {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!("Rule '{}': {}, forwarding unchanged", rule.name, e);
+                return Action::Forward;
+            }
+        };
+
+        match crate::messages::command_long_to_int(data, frame) {
+            Some(upgraded) => {
+                if rule.upgrade_compat && !self.dry_run {
+                    // Only frames from a GCS client connection have someone to answer
+                    if let Some(client) = ingress.client {
+                        let timeout = Duration::from_secs(rule.upgrade_compat_timeout_seconds.unwrap_or(5));
+                        self.command_compat.record(client, data, timeout);
+                    }
+                }
+                Action::Modify {
+                    modifier: "upgrade_command".to_string(),
+                    modified_message: Some(upgraded),
+                }
+            }
+            None => {
+                debug!("{:?} has no COMMAND_INT form, forwarding unchanged", data.command);
+                Action::Forward
+            }
+        }
     }
 
     /// Build ACK info generically from any message type
-    fn build_ack_info(&self, rule: &CommandRule, msg: &MavMessage, header: &MavHeader) -> Option<AckInfo> {
+    fn build_ack_info(&self, rule: &CommandRule, message_json: &JsonValue, header: &MavHeader) -> Option<AckInfo> {
         // Get ACK config
         let ack_config = rule.ack.as_ref()?;
 
@@ -407,15 +1481,6 @@ impl RuleEngine {
         let source_system_field = &ack_config.source_system_field;
         let source_component_field = &ack_config.source_component_field;
 
-        // Serialize message to JSON (mavlink internally-tagged format)
-        let message_json = match serde_json::to_value(msg) {
-            Ok(val) => val,
-            Err(e) => {
-                warn!("Failed to serialize message for ACK building: {}", e);
-                return None;
-            }
-        };
-
         // Extract source system_id from specified field
         let source_system = match message_json.get(source_system_field) {
             Some(val) => match val.as_u64() {
@@ -453,7 +1518,8 @@ impl RuleEngine {
             fields: ack_config.fields.clone(),
             copy_fields: ack_config.copy_fields.clone(),
             original_header: *header,
-            original_message: message_json,
+            original_message: message_json.clone(),
+            direction: ack_config.direction,
         })
     }
 }
@@ -474,20 +1540,135 @@ pub fn parse_mavlink_message(packet: &[u8]) -> Result<(MavHeader, MavMessage)> {
             let cursor = Cursor::new(packet);
             let mut peek_reader = PeekReader::new(cursor);
             mavlink::read_v1_msg::<MavMessage, _>(&mut peek_reader)
-                .map_err(|e| anyhow::anyhow!("Failed to parse MAVLink: {:?}", e))
+                .map_err(|e| BitchError::Mavlink(format!("Failed to parse MAVLink: {:?}", e)))
         }
     }
 }
 
-/// Get the name of a MAVLINK message enum variant as a string
+/// Parse a MAVLINK frame, keeping its header flags
+pub fn parse_mavlink_frame(packet: &[u8]) -> Result<ParsedFrame> {
+    let (header, message) = parse_mavlink_message(packet)?;
+    Ok(ParsedFrame {
+        header,
+        message,
+        flags: FrameFlags::of(packet),
+    })
+}
+
+/// Get the name of a MAVLINK message enum variant as a string (e.g. "HEARTBEAT")
+/// Same name as the internally-tagged `type` field used in message JSON.
 pub fn get_message_name(msg: &MavMessage) -> String {
-    let debug_str = format!("{:?}", msg);
-    // Extract just the variant name (before the '(')
-    debug_str
-        .split('(')
-        .next()
-        .unwrap_or("UNKNOWN")
-        .to_string()
+    msg.message_name().to_string()
+}
+
+/// Get a header field by name as JSON
+fn header_field(header: &MavHeader, name: &str) -> Option<JsonValue> {
+    match name {
+        "system_id" => Some(JsonValue::from(header.system_id)),
+        "component_id" => Some(JsonValue::from(header.component_id)),
+        "sequence" => Some(JsonValue::from(header.sequence)),
+        _ => None,
+    }
+}
+
+/// Integer value of a bitmask field: plain integers or bitflags serialized as `{"bits": N}`
+fn field_bits(value: &JsonValue) -> Option<u64> {
+    let value = value.get("bits").unwrap_or(value);
+    value.as_u64().or_else(|| value.as_i64().map(|v| v as u64))
+}
+
+/// Text of a string field, or of a char array field (bytes up to the first NUL)
+fn field_text(value: &JsonValue) -> Option<String> {
+    if let Some(text) = value.as_str() {
+        return Some(text.to_string());
+    }
+
+    let bytes: Vec<u8> = value
+        .as_array()?
+        .iter()
+        .map(|b| b.as_u64().and_then(|b| u8::try_from(b).ok()))
+        .collect::<Option<_>>()?;
+    let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    Some(String::from_utf8_lossy(&bytes[..len]).into_owned())
+}
+
+/// Apply a `starts_with`/`ends_with`/`contains` condition
+fn text_matches(operator: &str, text: &str, pattern: &str, ignore_case: bool) -> bool {
+    let (text, pattern) = if ignore_case {
+        (text.to_lowercase(), pattern.to_lowercase())
+    } else {
+        (text.to_string(), pattern.to_string())
+    };
+    match operator {
+        "starts_with" => text.starts_with(&pattern),
+        "ends_with" => text.ends_with(&pattern),
+        _ => text.contains(&pattern),
+    }
+}
+
+/// Compare two JSON values, treating numbers as equal regardless of integer/float representation
+fn json_values_equal(a: &JsonValue, b: &JsonValue) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(x), Some(y)) => (x - y).abs() < f64::EPSILON,
+        _ => a == b,
+    }
+}
+
+/// Substitute `{placeholder}` tokens in a template with rule, header and message field values
+/// Only identifier-like placeholders are replaced, so literal JSON braces pass through untouched.
+/// Unknown placeholders are left as-is.
+pub fn render_template(template: &str, rule_name: &str, header: &MavHeader, msg_json: &JsonValue) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        output.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+
+        let name_len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+
+        if name_len > 0 && after[name_len..].starts_with('}') {
+            let name = &after[..name_len];
+            let value = match name {
+                "rule" => Some(rule_name.to_string()),
+                "message_type" => msg_json.get("type").and_then(|t| t.as_str()).map(str::to_string),
+                "system_id" => Some(header.system_id.to_string()),
+                "component_id" => Some(header.component_id.to_string()),
+                "sequence" => Some(header.sequence.to_string()),
+                field => msg_json.get(field).map(template_value),
+            };
+
+            match value {
+                Some(v) => output.push_str(&v),
+                None => {
+                    debug!("Template placeholder '{{{}}}' not found in message", name);
+                    output.push_str(&rest[open..open + name_len + 2]);
+                }
+            }
+            rest = &after[name_len + 1..];
+        } else {
+            output.push('{');
+            rest = after;
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Render a JSON field value for template substitution
+/// Strings are inserted unquoted and internally-tagged enums collapse to their variant name
+fn template_value(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(s) => s.clone(),
+        JsonValue::Object(obj) => match obj.get("type").and_then(|t| t.as_str()) {
+            Some(variant) => variant.to_string(),
+            None => value.to_string(),
+        },
+        other => other.to_string(),
+    }
 }
 
 /// Convert TOML value to JSON value, preserving structure
@@ -0,0 +1,157 @@
+use crate::framing::{
+    crc16_x25, CrcCheck, CHECKSUM_LEN, HEADER_LEN, MAVLINK_IFLAG_SIGNED, MAVLINK_V2_MAGIC, SIGNATURE_LEN,
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Unix time of the MAVLink signing epoch (2015-01-01 00:00:00 UTC)
+const SIGNING_EPOCH_UNIX_SECS: u64 = 1_420_070_400;
+
+/// Length of the truncated SHA-256 signature at the end of the trailer
+const SIGNATURE_HASH_LEN: usize = 6;
+
+/// How far behind local time the first timestamp of a new stream may be (60s in 10us units)
+const NEW_STREAM_MAX_AGE: u64 = 60 * 100_000;
+
+/// Signs frames sent toward the router (MAVLink 2 message signing)
+pub struct Signer {
+    key: [u8; 32],
+    link_id: u8,
+    crc: CrcCheck,
+    /// Last timestamp used; each signature gets a strictly larger one
+    last_timestamp: AtomicU64,
+}
+
+impl Signer {
+    pub fn new(key: [u8; 32], link_id: u8, crc: CrcCheck) -> Self {
+        Self {
+            key,
+            link_id,
+            crc,
+            last_timestamp: AtomicU64::new(0),
+        }
+    }
+
+    /// Sign a v2 frame, replacing any signature it already carries
+    /// Returns None for frames that can't be signed: MAVLink 1, truncated, or a message
+    /// ID whose CRC_EXTRA is unknown (the checksum must be recomputed for the signed flag)
+    pub fn sign(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        if frame.len() < HEADER_LEN + CHECKSUM_LEN || frame[0] != MAVLINK_V2_MAGIC {
+            return None;
+        }
+        let crc_end = HEADER_LEN + frame[1] as usize;
+        if frame.len() < crc_end + CHECKSUM_LEN {
+            return None;
+        }
+        let msg_id = u32::from_le_bytes([frame[7], frame[8], frame[9], 0]);
+        let crc_extra = self.crc.crc_extra(msg_id)?;
+
+        // The incompat flags are covered by the checksum, so it changes with the signed flag
+        let mut signed = frame[..crc_end].to_vec();
+        signed[2] |= MAVLINK_IFLAG_SIGNED;
+        let crc = crc16_x25(&[crc_extra], crc16_x25(&signed[1..], 0xFFFF));
+        signed.extend_from_slice(&crc.to_le_bytes());
+
+        // Trailer: link ID, 48-bit timestamp, then the signature over everything before it
+        signed.push(self.link_id);
+        signed.extend_from_slice(&self.next_timestamp().to_le_bytes()[..6]);
+        let signature = signature(&self.key, &signed);
+        signed.extend_from_slice(&signature);
+        Some(signed)
+    }
+
+    /// Current signing timestamp, bumped past the last one if the clock hasn't moved
+    fn next_timestamp(&self) -> u64 {
+        let now = signing_timestamp_now();
+        let last = self
+            .last_timestamp
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| Some(now.max(last + 1)))
+            .unwrap_or_else(|last| last);
+        now.max(last + 1)
+    }
+}
+
+/// Outcome of checking an incoming frame's signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    /// No signature (or not a MAVLink 2 frame)
+    Unsigned,
+    /// Signed with the configured key and not a replay
+    Valid,
+    /// Signed, but rejected for the given reason
+    Invalid(&'static str),
+}
+
+/// Verifies signatures of frames arriving from GCS clients
+pub struct Verifier {
+    key: [u8; 32],
+    /// Last accepted timestamp per (system_id, component_id, link_id) stream
+    last_timestamps: Mutex<HashMap<(u8, u8, u8), u64>>,
+}
+
+impl Verifier {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self {
+            key,
+            last_timestamps: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check a frame's signature and, if valid, remember its timestamp
+    /// A stream's timestamps must keep increasing; a new stream may start at most
+    /// a minute behind local time
+    pub fn verify(&self, frame: &[u8]) -> Verification {
+        if frame.len() < HEADER_LEN || frame[0] != MAVLINK_V2_MAGIC || frame[2] & MAVLINK_IFLAG_SIGNED == 0 {
+            return Verification::Unsigned;
+        }
+        if frame.len() != HEADER_LEN + frame[1] as usize + CHECKSUM_LEN + SIGNATURE_LEN {
+            return Verification::Invalid("truncated signature");
+        }
+
+        let hash_start = frame.len() - SIGNATURE_HASH_LEN;
+        if signature(&self.key, &frame[..hash_start]) != frame[hash_start..] {
+            return Verification::Invalid("signature mismatch");
+        }
+
+        let trailer = &frame[frame.len() - SIGNATURE_LEN..];
+        let mut timestamp = [0u8; 8];
+        timestamp[..6].copy_from_slice(&trailer[1..7]);
+        let timestamp = u64::from_le_bytes(timestamp);
+        let stream = (frame[5], frame[6], trailer[0]);
+
+        let mut last_timestamps = self.last_timestamps.lock().unwrap();
+        match last_timestamps.get(&stream) {
+            Some(last) if timestamp <= *last => return Verification::Invalid("replayed timestamp"),
+            None if timestamp + NEW_STREAM_MAX_AGE < signing_timestamp_now() => {
+                return Verification::Invalid("stale timestamp")
+            }
+            _ => {}
+        }
+        last_timestamps.insert(stream, timestamp);
+        Verification::Valid
+    }
+}
+
+/// Time since the signing epoch in 10 microsecond units
+fn signing_timestamp_now() -> u64 {
+    let unix_micros = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64;
+    (unix_micros / 10).saturating_sub(SIGNING_EPOCH_UNIX_SECS * 100_000)
+}
+
+/// First 6 bytes of SHA-256(secret key + frame up to and including the timestamp)
+fn signature(key: &[u8; 32], signed_data: &[u8]) -> [u8; SIGNATURE_HASH_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(key);
+    hasher.update(signed_data);
+    let digest = hasher.finalize();
+
+    let mut signature = [0u8; SIGNATURE_HASH_LEN];
+    signature.copy_from_slice(&digest[..SIGNATURE_HASH_LEN]);
+    signature
+}
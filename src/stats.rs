@@ -0,0 +1,278 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+/// Distinct message IDs counted separately in each per-ID count; frames with further IDs
+/// are counted under "other", since a peer sending arbitrary 24-bit IDs would otherwise
+/// grow the map without bound
+pub const MAX_COUNTED_MESSAGE_IDS: usize = 256;
+
+/// Key the frames beyond `MAX_COUNTED_MESSAGE_IDS` are counted under
+pub const OTHER_MESSAGE_IDS: &str = "other";
+
+/// Frame counts per message ID, with at most `MAX_COUNTED_MESSAGE_IDS` distinct IDs
+#[derive(Default)]
+struct MessageIdCounts {
+    counts: HashMap<u32, u64>,
+    /// Frames whose ID came after the map was full
+    other: u64,
+}
+
+impl MessageIdCounts {
+    fn record(&mut self, message_id: u32) {
+        let full = self.counts.len() >= MAX_COUNTED_MESSAGE_IDS;
+        match self.counts.get_mut(&message_id) {
+            Some(count) => *count += 1,
+            None if full => self.other += 1,
+            None => {
+                self.counts.insert(message_id, 1);
+            }
+        }
+    }
+
+    /// Counts keyed by `key(id)`, plus "other" once IDs were folded into it
+    fn snapshot(&self, key: impl Fn(u32) -> String) -> BTreeMap<String, u64> {
+        let mut snapshot: BTreeMap<String, u64> = self.counts.iter().map(|(id, count)| (key(*id), *count)).collect();
+        if self.other > 0 {
+            snapshot.insert(OTHER_MESSAGE_IDS.to_string(), self.other);
+        }
+        snapshot
+    }
+}
+
+/// Runtime counters for the proxy (shared across all forwarding tasks)
+pub struct ProxyStats {
+    /// Total packets blocked by rules
+    blocked_total: AtomicU64,
+    /// Packets blocked per rule name
+    blocked_by_rule: RwLock<HashMap<String, u64>>,
+    /// Messages evaluated by the shadow ruleset
+    shadow_evaluated: AtomicU64,
+    /// Messages where the shadow ruleset chose different actions than the live one
+    shadow_mismatches: AtomicU64,
+    /// GCS frames whose signature verified
+    signatures_accepted: AtomicU64,
+    /// GCS frames dropped for a missing or bad signature
+    signatures_rejected: AtomicU64,
+    /// Frames the dialect couldn't decode, per message ID from the frame header
+    unparsed_by_message_id: RwLock<MessageIdCounts>,
+}
+
+impl ProxyStats {
+    pub fn new() -> Self {
+        Self {
+            blocked_total: AtomicU64::new(0),
+            blocked_by_rule: RwLock::new(HashMap::new()),
+            shadow_evaluated: AtomicU64::new(0),
+            shadow_mismatches: AtomicU64::new(0),
+            signatures_accepted: AtomicU64::new(0),
+            signatures_rejected: AtomicU64::new(0),
+            unparsed_by_message_id: RwLock::new(MessageIdCounts::default()),
+        }
+    }
+
+    /// Record packets blocked by a rule
+    pub fn record_block(&self, rule_name: &str, count: u64) {
+        self.blocked_total.fetch_add(count, Ordering::Relaxed);
+        *self
+            .blocked_by_rule
+            .write()
+            .unwrap()
+            .entry(rule_name.to_string())
+            .or_insert(0) += count;
+    }
+
+    /// Total packets blocked since startup
+    pub fn blocked_total(&self) -> u64 {
+        self.blocked_total.load(Ordering::Relaxed)
+    }
+
+    /// Snapshot of blocked packet counts per rule
+    pub fn blocked_by_rule(&self) -> HashMap<String, u64> {
+        self.blocked_by_rule.read().unwrap().clone()
+    }
+
+    /// Record one shadow ruleset evaluation
+    pub fn record_shadow(&self, mismatch: bool) {
+        self.shadow_evaluated.fetch_add(1, Ordering::Relaxed);
+        if mismatch {
+            self.shadow_mismatches.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Messages evaluated by the shadow ruleset since startup
+    pub fn shadow_evaluated(&self) -> u64 {
+        self.shadow_evaluated.load(Ordering::Relaxed)
+    }
+
+    /// Shadow evaluations whose actions differed from the live ruleset
+    pub fn shadow_mismatches(&self) -> u64 {
+        self.shadow_mismatches.load(Ordering::Relaxed)
+    }
+
+    /// Record a verified (accepted) or dropped (rejected) GCS frame signature
+    pub fn record_signature(&self, accepted: bool) {
+        let counter = if accepted {
+            &self.signatures_accepted
+        } else {
+            &self.signatures_rejected
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// GCS frames whose signature verified since startup
+    pub fn signatures_accepted(&self) -> u64 {
+        self.signatures_accepted.load(Ordering::Relaxed)
+    }
+
+    /// GCS frames dropped for a missing or bad signature since startup
+    pub fn signatures_rejected(&self) -> u64 {
+        self.signatures_rejected.load(Ordering::Relaxed)
+    }
+
+    /// Record a frame the dialect couldn't decode
+    pub fn record_unparsed(&self, message_id: u32) {
+        self.unparsed_by_message_id.write().unwrap().record(message_id);
+    }
+
+    /// Snapshot of undecodable frame counts per message ID (and "other")
+    pub fn unparsed_by_message_id(&self) -> BTreeMap<String, u64> {
+        self.unparsed_by_message_id.read().unwrap().snapshot(|id| id.to_string())
+    }
+}
+
+/// Frames exchanged with one GCS client, counted per message ID
+#[derive(Default)]
+pub struct ClientTraffic {
+    /// Frames the client sent to the proxy
+    sent: Mutex<MessageIdCounts>,
+    /// Frames the proxy delivered to the client
+    received: Mutex<MessageIdCounts>,
+}
+
+impl ClientTraffic {
+    /// Count a frame read from the client
+    pub fn record_sent(&self, frame: &[u8]) {
+        Self::record(&self.sent, frame);
+    }
+
+    /// Count a frame written to the client
+    pub fn record_received(&self, frame: &[u8]) {
+        Self::record(&self.received, frame);
+    }
+
+    /// Frames the client sent, by message name
+    pub fn sent(&self) -> BTreeMap<String, u64> {
+        Self::by_name(&self.sent)
+    }
+
+    /// Frames delivered to the client, by message name
+    pub fn received(&self) -> BTreeMap<String, u64> {
+        Self::by_name(&self.received)
+    }
+
+    fn record(counts: &Mutex<MessageIdCounts>, frame: &[u8]) {
+        if let Some(id) = crate::framing::message_id(frame) {
+            counts.lock().unwrap().record(id);
+        }
+    }
+
+    /// IDs missing from the dialect are listed by number
+    fn by_name(counts: &Mutex<MessageIdCounts>) -> BTreeMap<String, u64> {
+        counts
+            .lock()
+            .unwrap()
+            .snapshot(|id| crate::messages::message_name(id).map_or_else(|| id.to_string(), str::to_string))
+    }
+}
+
+/// A connected GCS client and its traffic so far (admin API `GET /clients`)
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientInfo {
+    pub client_id: u64,
+    /// Remote address of the client
+    pub address: String,
+    /// Client group it was tagged with on connect
+    pub group: Option<String>,
+    pub connected_seconds: f64,
+    /// Frames the client sent, by message type
+    pub sent: BTreeMap<String, u64>,
+    /// Frames delivered to the client, by message type
+    pub received: BTreeMap<String, u64>,
+}
+
+/// Upper bounds (microseconds) of the Lua execution time histogram buckets; slower runs
+/// land in a final overflow bucket
+pub const SCRIPT_TIME_BUCKETS_US: [u64; 10] = [50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 50_000, 100_000];
+
+/// Execution time histogram for one Lua script
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScriptTiming {
+    pub calls: u64,
+    pub total_us: u64,
+    pub max_us: u64,
+    /// Calls per bucket of `SCRIPT_TIME_BUCKETS_US`, plus one overflow bucket
+    pub buckets: Vec<u64>,
+}
+
+impl ScriptTiming {
+    fn record(&mut self, elapsed: Duration) {
+        let us = elapsed.as_micros().min(u64::MAX as u128) as u64;
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; SCRIPT_TIME_BUCKETS_US.len() + 1];
+        }
+        let bucket = SCRIPT_TIME_BUCKETS_US
+            .iter()
+            .position(|bound| us <= *bound)
+            .unwrap_or(SCRIPT_TIME_BUCKETS_US.len());
+        self.buckets[bucket] += 1;
+        self.calls += 1;
+        self.total_us = self.total_us.saturating_add(us);
+        self.max_us = self.max_us.max(us);
+    }
+}
+
+/// Lua execution times per modifier and per plugin
+pub struct ScriptTimings {
+    modifiers: Mutex<HashMap<String, ScriptTiming>>,
+    plugins: Mutex<HashMap<String, ScriptTiming>>,
+}
+
+impl ScriptTimings {
+    pub fn new() -> Self {
+        Self {
+            modifiers: Mutex::new(HashMap::new()),
+            plugins: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record one modifier run
+    pub fn record_modifier(&self, name: &str, elapsed: Duration) {
+        Self::record(&self.modifiers, name, elapsed);
+    }
+
+    /// Record one plugin `on_match` run
+    pub fn record_plugin(&self, name: &str, elapsed: Duration) {
+        Self::record(&self.plugins, name, elapsed);
+    }
+
+    fn record(timings: &Mutex<HashMap<String, ScriptTiming>>, name: &str, elapsed: Duration) {
+        let mut timings = timings.lock().unwrap();
+        match timings.get_mut(name) {
+            Some(timing) => timing.record(elapsed),
+            None => timings.entry(name.to_string()).or_default().record(elapsed),
+        }
+    }
+
+    /// Snapshot of modifier execution times
+    pub fn modifiers(&self) -> HashMap<String, ScriptTiming> {
+        self.modifiers.lock().unwrap().clone()
+    }
+
+    /// Snapshot of plugin execution times
+    pub fn plugins(&self) -> HashMap<String, ScriptTiming> {
+        self.plugins.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,232 @@
+use crate::framing::RawHeader;
+use crate::proxy::ClientId;
+use mavlink::ardupilotmega::{MavCmd, MavMessage};
+use mavlink::Message;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Messages in each `MAV_DATA_STREAM` group (ArduPilot's grouping); 0 (`ALL`) is every group
+const STREAM_GROUPS: &[(u8, &[&str])] = &[
+    (
+        1,
+        &[
+            "RAW_IMU",
+            "SCALED_IMU2",
+            "SCALED_IMU3",
+            "SCALED_PRESSURE",
+            "SCALED_PRESSURE2",
+            "SENSOR_OFFSETS",
+        ],
+    ),
+    (
+        2,
+        &[
+            "SYS_STATUS",
+            "POWER_STATUS",
+            "MEMINFO",
+            "MISSION_CURRENT",
+            "GPS_RAW_INT",
+            "GPS_RTK",
+            "GPS2_RAW",
+            "NAV_CONTROLLER_OUTPUT",
+            "FENCE_STATUS",
+        ],
+    ),
+    (3, &["SERVO_OUTPUT_RAW", "RC_CHANNELS", "RC_CHANNELS_RAW"]),
+    (6, &["GLOBAL_POSITION_INT", "LOCAL_POSITION_NED"]),
+    (10, &["ATTITUDE", "SIMSTATE", "AHRS2", "PID_TUNING"]),
+    (11, &["VFR_HUD"]),
+    (
+        12,
+        &[
+            "AHRS",
+            "HWSTATUS",
+            "SYSTEM_TIME",
+            "RANGEFINDER",
+            "DISTANCE_SENSOR",
+            "TERRAIN_REQUEST",
+            "BATTERY_STATUS",
+            "MOUNT_STATUS",
+            "OPTICAL_FLOW",
+            "GIMBAL_REPORT",
+            "MAG_CAL_REPORT",
+            "MAG_CAL_PROGRESS",
+            "EKF_STATUS_REPORT",
+            "VIBRATION",
+            "RPM",
+            "ESC_TELEMETRY_1_TO_4",
+        ],
+    ),
+];
+
+/// A frame is sent once this share of the requested interval passed, so jitter in the
+/// vehicle's own rate doesn't halve the rate a client gets
+const INTERVAL_TOLERANCE: f64 = 0.9;
+
+/// What a GCS asked for one message ID
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Request {
+    /// Send it, at most once per interval (None: at whatever rate it arrives)
+    Send(Option<Duration>),
+    /// Don't send it
+    Stop,
+}
+
+/// Message types a client asked for and when each was last sent to it
+#[derive(Default)]
+struct ClientStreams {
+    /// Keyed by (target system, message ID); system 0 is a request to every system.
+    /// A request to one system overrides one to every system, so `Stop` entries are kept.
+    wanted: HashMap<(u8, u32), Request>,
+    /// Keyed by (source system, source component, message ID)
+    last_sent: HashMap<(u8, u8, u32), Instant>,
+}
+
+impl ClientStreams {
+    fn record(&mut self, target_system: u8, message_id: u32, request: Request) {
+        if target_system == 0 {
+            // A request to every system replaces the ones to single systems
+            self.wanted.retain(|(_, id), _| *id != message_id);
+            if request != Request::Stop {
+                self.wanted.insert((0, message_id), request);
+            }
+        } else {
+            self.wanted.insert((target_system, message_id), request);
+        }
+    }
+
+    /// The rate asked for a message from `system_id` (None: not asked for)
+    fn interval(&self, system_id: u8, message_id: u32) -> Option<Option<Duration>> {
+        let request = self
+            .wanted
+            .get(&(system_id, message_id))
+            .or_else(|| self.wanted.get(&(0, message_id)))?;
+        match request {
+            Request::Send(interval) => Some(*interval),
+            Request::Stop => None,
+        }
+    }
+
+    fn subscribed(&self) -> usize {
+        self.wanted.values().filter(|request| **request != Request::Stop).count()
+    }
+}
+
+/// Per-client stream subscriptions (`network.stream_subscriptions`)
+/// GCS clients ask for telemetry with REQUEST_DATA_STREAM and MAV_CMD_SET_MESSAGE_INTERVAL.
+/// Once a client sent one, broadcast stream messages only reach it if it asked for them,
+/// at most at the rate it asked for. Clients that never asked get everything, and messages
+/// outside the data streams (HEARTBEAT, acknowledgements, parameters, missions...) always go out.
+pub struct Subscriptions {
+    /// Message IDs of each data stream group
+    groups: HashMap<u8, Vec<u32>>,
+    /// Every message ID that belongs to a data stream
+    streamed: HashSet<u32>,
+    clients: Mutex<HashMap<ClientId, ClientStreams>>,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        let groups: HashMap<u8, Vec<u32>> = STREAM_GROUPS
+            .iter()
+            .map(|(stream, names)| {
+                let ids = names
+                    .iter()
+                    .filter_map(|name| MavMessage::message_id_from_name(name).ok())
+                    .collect();
+                (*stream, ids)
+            })
+            .collect();
+        let streamed = groups.values().flatten().copied().collect();
+
+        Self {
+            groups,
+            streamed,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a subscription change if `msg` (sent by `client_id`) is a stream request
+    pub fn observe(&self, client_id: ClientId, msg: &MavMessage) {
+        let Some((target_system, requests)) = self.requests(msg) else {
+            return;
+        };
+
+        let mut clients = self.clients.lock().unwrap();
+        let streams = clients.entry(client_id).or_default();
+        for (message_id, request) in requests {
+            streams.record(target_system, message_id, request);
+        }
+        debug!("GCS client {} now subscribes to {} stream(s)", client_id, streams.subscribed());
+    }
+
+    /// Whether a broadcast `packet` goes to `client_id`; counts it as sent if so
+    pub fn allows(&self, client_id: ClientId, packet: &[u8]) -> bool {
+        let Some(header) = RawHeader::of(packet) else {
+            return true;
+        };
+        let message_id = header.message_id;
+        if !self.streamed.contains(&message_id) {
+            return true;
+        }
+
+        let mut clients = self.clients.lock().unwrap();
+        let Some(streams) = clients.get_mut(&client_id) else {
+            return true;
+        };
+        let Some(interval) = streams.interval(header.system_id, message_id) else {
+            return false;
+        };
+
+        let key = (header.system_id, header.component_id, message_id);
+        let now = Instant::now();
+        if let (Some(interval), Some(last)) = (interval, streams.last_sent.get(&key)) {
+            if now.duration_since(*last) < interval.mul_f64(INTERVAL_TOLERANCE) {
+                return false;
+            }
+        }
+        streams.last_sent.insert(key, now);
+        true
+    }
+
+    /// Forget a disconnected client
+    pub fn remove(&self, client_id: ClientId) {
+        self.clients.lock().unwrap().remove(&client_id);
+    }
+
+    /// The system a stream request is for (0: every system) and the message IDs it covers,
+    /// with what it asks for each; None if `msg` isn't a stream request
+    fn requests(&self, msg: &MavMessage) -> Option<(u8, Vec<(u32, Request)>)> {
+        match msg {
+            MavMessage::REQUEST_DATA_STREAM(data) => {
+                // Rate 0 stops the stream, as on the vehicle
+                let request = if data.start_stop == 0 || data.req_message_rate == 0 {
+                    Request::Stop
+                } else {
+                    Request::Send(Some(Duration::from_secs(1) / u32::from(data.req_message_rate)))
+                };
+                let ids: Vec<u32> = if data.req_stream_id == 0 {
+                    self.streamed.iter().copied().collect()
+                } else {
+                    self.groups.get(&data.req_stream_id).cloned().unwrap_or_default()
+                };
+                Some((data.target_system, ids.into_iter().map(|id| (id, request)).collect()))
+            }
+            MavMessage::COMMAND_LONG(data) if data.command == MavCmd::MAV_CMD_SET_MESSAGE_INTERVAL => {
+                // param1: message ID, param2: interval in microseconds (-1 stops it, 0 is the default rate)
+                if data.param1 < 0.0 {
+                    return None;
+                }
+                let request = match data.param2 {
+                    us if us < 0.0 => Request::Stop,
+                    0.0 => Request::Send(None),
+                    us => Request::Send(Some(Duration::from_micros(us as u64))),
+                };
+                Some((data.target_system, vec![(data.param1 as u32, request)]))
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::config::GcsTlsConfig;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{crypto, RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+/// Identity presented by a GCS client certificate
+#[derive(Debug, Clone)]
+pub struct ClientIdentity {
+    /// Subject Common Name (if present)
+    pub common_name: Option<String>,
+    /// SHA-256 fingerprint of the DER certificate (lowercase hex, no separators)
+    pub fingerprint: String,
+}
+
+impl fmt::Display for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CN={} sha256={}",
+            self.common_name.as_deref().unwrap_or("<none>"),
+            self.fingerprint
+        )
+    }
+}
+
+/// Build a TLS acceptor for the GCS listener from PEM certificate and key files
+pub fn build_acceptor(tls: &GcsTlsConfig) -> Result<TlsAcceptor> {
+    let certs = load_certs(&tls.cert_path)?;
+    let key = load_private_key(&tls.key_path)?;
+
+    let provider = Arc::new(crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .context("Failed to configure TLS protocol versions")?;
+
+    // Require client certificates signed by the configured CA (mutual TLS)
+    let builder = match &tls.client_ca_path {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_path)? {
+                roots
+                    .add(cert)
+                    .with_context(|| format!("Invalid client CA certificate in {}", ca_path))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .context("Failed to build client certificate verifier")?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let config = builder
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate/key pair")?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Load a PEM certificate chain
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open TLS certificate: {}", path))?;
+
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse TLS certificate: {}", path))?;
+
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in {}", path);
+    }
+
+    Ok(certs)
+}
+
+/// Load the first PEM private key (PKCS#1, PKCS#8 or SEC1)
+fn load_private_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("Failed to open TLS key: {}", path))?;
+
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to parse TLS key: {}", path))?
+        .ok_or_else(|| anyhow::anyhow!("No private key found in {}", path))
+}
+
+/// Extract the identity (CN and fingerprint) from a client certificate
+pub fn client_identity(cert: &CertificateDer<'_>) -> ClientIdentity {
+    let fingerprint = Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    let common_name = x509_parser::parse_x509_certificate(cert.as_ref())
+        .ok()
+        .and_then(|(_, parsed)| {
+            parsed
+                .subject()
+                .iter_common_name()
+                .next()
+                .and_then(|cn| cn.as_str().ok())
+                .map(str::to_string)
+        });
+
+    ClientIdentity {
+        common_name,
+        fingerprint,
+    }
+}
+
+/// Check a client identity against the allowlist
+/// Entries prefixed with "cn:" match the Common Name; all others are SHA-256
+/// fingerprints (hex, case-insensitive, ':' separators optional)
+pub fn is_client_allowed(identity: &ClientIdentity, allowlist: &[String]) -> bool {
+    allowlist.iter().any(|entry| match entry.strip_prefix("cn:") {
+        Some(cn) => identity.common_name.as_deref() == Some(cn),
+        None => normalize_fingerprint(entry) == identity.fingerprint,
+    })
+}
+
+/// Normalize a fingerprint to lowercase hex without separators
+fn normalize_fingerprint(value: &str) -> String {
+    value
+        .trim_start_matches("sha256:")
+        .chars()
+        .filter(|c| *c != ':')
+        .collect::<String>()
+        .to_lowercase()
+}
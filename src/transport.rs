@@ -0,0 +1,157 @@
+use crate::framing::{CrcCheck, FrameReader};
+use anyhow::{Context, Result};
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+use tokio_serial::SerialPortBuilderExt;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+/// Byte-stream connection usable as a GCS transport (plain TCP, TLS, ...)
+pub trait GcsStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> GcsStream for T {}
+
+/// Type-erased GCS byte stream
+pub type BoxedGcsStream = Box<dyn GcsStream>;
+
+/// Read side of a router link (TCP connection or serial port)
+pub type RouterReader = Box<dyn AsyncRead + Unpin + Send>;
+
+/// Write side of a router link
+pub type RouterWriter = Box<dyn AsyncWrite + Unpin + Send + Sync>;
+
+/// TCP socket options for the router connection and accepted GCS sockets
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SocketOptions {
+    /// Disable Nagle's algorithm so small frames are sent immediately
+    pub nodelay: bool,
+    /// Kernel send buffer size in bytes (None = OS default)
+    pub send_buffer: Option<usize>,
+    /// Kernel receive buffer size in bytes (None = OS default)
+    pub recv_buffer: Option<usize>,
+}
+
+impl SocketOptions {
+    /// Apply the options to a connected socket
+    pub fn apply(&self, stream: &TcpStream) -> std::io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+        let socket = socket2::SockRef::from(stream);
+        if let Some(size) = self.send_buffer {
+            socket.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer {
+            socket.set_recv_buffer_size(size)?;
+        }
+        Ok(())
+    }
+}
+
+/// Connect to a mavlink-router TCP endpoint ("host:port")
+pub async fn connect_router_tcp(addr: &str, options: SocketOptions) -> std::io::Result<(RouterReader, RouterWriter)> {
+    let stream = TcpStream::connect(addr).await?;
+    options.apply(&stream)?;
+    let (read, write) = stream.into_split();
+    Ok((Box::new(read), Box::new(write)))
+}
+
+/// Whether a failed router connect is worth retrying indefinitely: the router isn't up yet
+/// or the network dropped the attempt, as opposed to a misconfiguration (unresolvable host,
+/// permission denied, invalid address, ...)
+pub fn is_transient(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    matches!(
+        error.kind(),
+        ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+    )
+}
+
+/// Open a serial device (e.g. a telemetry radio) as the router link
+pub fn open_router_serial(path: &str, baud: u32) -> std::io::Result<(RouterReader, RouterWriter)> {
+    let port = tokio_serial::new(path, baud)
+        .open_native_async()
+        .map_err(std::io::Error::from)?;
+    let (read, write) = tokio::io::split(port);
+    Ok((Box::new(read), Box::new(write)))
+}
+
+/// Read side of a GCS client connection
+pub enum GcsReader {
+    /// Raw MAVLink byte stream (frames delimited by the frame reader)
+    Stream(FrameReader<ReadHalf<BoxedGcsStream>>),
+    /// WebSocket connection (one binary message per MAVLink frame)
+    WebSocket(SplitStream<WebSocketStream<BoxedGcsStream>>),
+}
+
+/// Write side of a GCS client connection
+pub enum GcsWriter {
+    /// Raw MAVLink byte stream
+    Stream(WriteHalf<BoxedGcsStream>),
+    /// WebSocket connection (each frame sent as one binary message)
+    WebSocket(SplitSink<WebSocketStream<BoxedGcsStream>, Message>),
+}
+
+/// Wrap an accepted GCS connection for the configured transport
+/// For "ws" this performs the WebSocket upgrade handshake
+pub async fn accept_gcs(
+    stream: BoxedGcsStream,
+    transport: &str,
+    crc: CrcCheck,
+) -> Result<(GcsReader, GcsWriter)> {
+    match transport {
+        "ws" => {
+            let ws = tokio_tungstenite::accept_async(stream)
+                .await
+                .context("WebSocket handshake failed")?;
+            let (sink, stream) = ws.split();
+            Ok((GcsReader::WebSocket(stream), GcsWriter::WebSocket(sink)))
+        }
+        _ => {
+            let (reader, writer) = tokio::io::split(stream);
+            Ok((
+                GcsReader::Stream(FrameReader::new(reader, crc)),
+                GcsWriter::Stream(writer),
+            ))
+        }
+    }
+}
+
+impl GcsReader {
+    /// Read the next complete MAVLink frame from the client
+    pub async fn read_frame(&mut self) -> Result<Vec<u8>> {
+        match self {
+            GcsReader::Stream(reader) => reader.read_frame().await,
+            GcsReader::WebSocket(stream) => loop {
+                match stream.next().await {
+                    Some(Ok(Message::Binary(data))) => return Ok(data),
+                    Some(Ok(Message::Close(_))) | None => anyhow::bail!("WebSocket closed"),
+                    // Text, ping and pong messages carry no MAVLink data
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e).context("WebSocket read error"),
+                }
+            },
+        }
+    }
+}
+
+impl GcsWriter {
+    /// Send one complete MAVLink frame to the client
+    pub async fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+        match self {
+            GcsWriter::Stream(writer) => {
+                writer.write_all(frame).await.context("Stream write failed")?;
+            }
+            GcsWriter::WebSocket(sink) => {
+                sink.send(Message::Binary(frame.to_vec()))
+                    .await
+                    .context("WebSocket send failed")?;
+            }
+        }
+        Ok(())
+    }
+}
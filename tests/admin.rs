@@ -0,0 +1,318 @@
+//! Admin API: pending batch groups, the GCS connection log, per-client traffic and disconnects, live rule
+//! replacement and quarantine
+
+mod common;
+
+use common::{request, request_with_body};
+use mavlink::ardupilotmega::{MavMessage, HEARTBEAT_DATA};
+use serde_json::Value as JsonValue;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+use tokio::time::sleep;
+
+#[tokio::test]
+async fn lists_pending_batches() {
+    let admin_port = common::free_port().await;
+    let rules = format!(
+        r#"
+[admin]
+listen_port = {admin_port}
+
+[[rules]]
+name = "batch_heartbeats"
+message_type = "HEARTBEAT"
+action = "batch"
+batch_count = 3
+batch_timeout_seconds = 60
+batch_key = "heartbeats"
+"#
+    );
+    let mut proxy = common::start(&rules, &[]).await;
+
+    let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+    proxy.send(1, 0, &heartbeat).await;
+    proxy.send(2, 0, &heartbeat).await;
+    proxy.send(2, 1, &heartbeat).await;
+
+    // Frames are processed asynchronously; poll until all three are queued
+    let mut batches = JsonValue::Null;
+    for _ in 0..100 {
+        let (status, body) = request(admin_port, "GET", "/batches").await;
+        assert_eq!(status, 200);
+        batches = body["batches"].clone();
+        if batches[0]["packets"] == 3 {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+
+    assert_eq!(batches.as_array().unwrap().len(), 1);
+    let batch = &batches[0];
+    assert_eq!(batch["key"], "heartbeats");
+    assert_eq!(batch["systems"], serde_json::json!([1, 2]));
+    assert_eq!(batch["unique_systems"], 2);
+    assert_eq!(batch["packets"], 3);
+    assert_eq!(batch["threshold"], 3);
+    assert_eq!(batch["timeout_seconds"], 60.0);
+
+    // Inspecting doesn't release anything
+    assert!(proxy.try_recv(Duration::from_millis(200)).await.is_none());
+
+    assert_eq!(request(admin_port, "POST", "/batches").await.0, 405);
+    assert_eq!(request(admin_port, "GET", "/nope").await.0, 404);
+}
+
+#[tokio::test]
+async fn logs_gcs_connects_and_disconnects() {
+    let admin_port = common::free_port().await;
+    let rules = format!("[admin]\nlisten_port = {admin_port}\n");
+    let proxy = common::start(&rules, &[]).await;
+
+    // A second client that connects and leaves again
+    let gcs_addr = proxy.gcs.peer_addr().unwrap();
+    let second = TcpStream::connect(gcs_addr).await.unwrap();
+    let second_addr = second.local_addr().unwrap().to_string();
+    sleep(Duration::from_millis(100)).await;
+    drop(second);
+
+    let mut connections = Vec::new();
+    for _ in 0..100 {
+        let (status, body) = request(admin_port, "GET", "/connections").await;
+        assert_eq!(status, 200);
+        connections = body["connections"].as_array().unwrap().clone();
+        if connections.iter().any(|e| e["event"] == "disconnect") {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+
+    let events: Vec<&JsonValue> = connections.iter().filter(|e| e["address"] == second_addr.as_str()).collect();
+    assert_eq!(events.len(), 2, "{:?}", connections);
+    assert_eq!(events[0]["event"], "connect");
+    assert_eq!(events[1]["event"], "disconnect");
+    assert_eq!(events[0]["client_id"], events[1]["client_id"]);
+    assert!(events[1]["cause"].as_str().unwrap().starts_with("read error"));
+    assert!(events[1]["duration_seconds"].as_f64().unwrap() > 0.0);
+}
+
+#[tokio::test]
+async fn replaces_a_single_rule() {
+    let admin_port = common::free_port().await;
+    let rules = format!(
+        r#"
+[admin]
+listen_port = {admin_port}
+
+[[rules]]
+name = "gate"
+message_type = "HEARTBEAT"
+action = "block"
+"#
+    );
+    let mut proxy = common::start(&rules, &[]).await;
+    let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+
+    proxy.send(1, 0, &heartbeat).await;
+    assert!(proxy.try_recv(Duration::from_millis(200)).await.is_none());
+
+    let (status, body) = request_with_body(
+        admin_port,
+        "PUT",
+        "/rules/gate",
+        "name = \"gate\"\nmessage_type = \"HEARTBEAT\"\naction = \"forward\"\n",
+    )
+    .await;
+    assert_eq!(status, 200, "{}", body);
+
+    proxy.send(1, 1, &heartbeat).await;
+    assert!(matches!(proxy.recv().await.1, MavMessage::HEARTBEAT(_)));
+
+    let invalid = r#"{"name": "gate", "message_type": "HEARTBEAT", "action": "delay"}"#;
+    assert_eq!(request_with_body(admin_port, "PUT", "/rules/gate", invalid).await.0, 400);
+    let unknown = r#"{"name": "other", "message_type": "HEARTBEAT", "action": "block"}"#;
+    assert_eq!(request_with_body(admin_port, "PUT", "/rules/other", unknown).await.0, 404);
+    assert_eq!(request(admin_port, "GET", "/rules/gate").await.0, 405);
+}
+
+#[tokio::test]
+async fn stats_report_lua_execution_times() {
+    let admin_port = common::free_port().await;
+    let rules = format!(
+        r#"
+[admin]
+listen_port = {admin_port}
+
+[modifiers.load]
+stamp = "stamp.lua"
+
+[[rules]]
+name = "stamp_heartbeats"
+message_type = "HEARTBEAT"
+actions = ["modify", "forward"]
+modifier = "stamp"
+"#
+    );
+    let stamp = "function modify(ctx)\n    ctx.message.custom_mode = 7\n    return ctx\nend\n";
+    let mut proxy = common::start(&rules, &[("stamp", stamp)]).await;
+
+    let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+    proxy.send(1, 0, &heartbeat).await;
+    proxy.send(1, 1, &heartbeat).await;
+    proxy.recv().await;
+    proxy.recv().await;
+
+    let (status, body) = request(admin_port, "GET", "/stats").await;
+    assert_eq!(status, 200);
+    let lua = &body["lua"];
+    let bounds = lua["bucket_bounds_us"].as_array().unwrap();
+    let timing = &lua["modifiers"]["stamp"];
+    assert_eq!(timing["calls"], 2);
+    let buckets: Vec<u64> = timing["buckets"].as_array().unwrap().iter().map(|b| b.as_u64().unwrap()).collect();
+    assert_eq!(buckets.len(), bounds.len() + 1, "one overflow bucket");
+    assert_eq!(buckets.iter().sum::<u64>(), 2);
+    assert!(timing["max_us"].as_u64().unwrap() <= timing["total_us"].as_u64().unwrap());
+    assert_eq!(lua["plugins"], serde_json::json!({}));
+}
+
+#[tokio::test]
+async fn quarantine_blocks_a_system_until_released() {
+    let admin_port = common::free_port().await;
+    let rules = format!(
+        r#"
+[admin]
+listen_port = {admin_port}
+"#
+    );
+    let mut proxy = common::start(&rules, &[]).await;
+
+    let (status, body) = request_with_body(admin_port, "PUT", "/quarantine/5", r#"{"seconds": 0}"#).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["quarantined"], 5);
+    let (_, body) = request(admin_port, "GET", "/quarantine").await;
+    assert_eq!(body["quarantine"], serde_json::json!([{ "system_id": 5, "remaining_seconds": null }]));
+
+    // Quarantine applies regardless of rules; other systems are unaffected
+    let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+    proxy.send(5, 0, &heartbeat).await;
+    proxy.send(1, 0, &heartbeat).await;
+    assert_eq!(proxy.recv().await.0.system_id, 1);
+    assert!(proxy.try_recv(Duration::from_millis(200)).await.is_none());
+
+    assert_eq!(request(admin_port, "DELETE", "/quarantine/5").await.0, 200);
+    assert_eq!(request(admin_port, "DELETE", "/quarantine/5").await.0, 404);
+    proxy.send(5, 1, &heartbeat).await;
+    assert_eq!(proxy.recv().await.0.system_id, 5);
+
+    assert_eq!(request(admin_port, "PUT", "/quarantine/300").await.0, 400);
+}
+
+#[tokio::test]
+async fn lists_clients_with_per_message_counts() {
+    let admin_port = common::free_port().await;
+    let rules = format!("[admin]\nlisten_port = {admin_port}\n");
+    let mut proxy = common::start(&rules, &[]).await;
+
+    let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+    proxy.send(1, 0, &heartbeat).await;
+    proxy.send(1, 1, &heartbeat).await;
+    proxy.recv().await;
+    proxy.recv().await;
+
+    // Counters are bumped right after each write, so poll until the second echo is counted
+    let mut clients = Vec::new();
+    for _ in 0..100 {
+        let (status, body) = request(admin_port, "GET", "/clients").await;
+        assert_eq!(status, 200);
+        clients = body["clients"].as_array().unwrap().clone();
+        if clients[0]["received"]["HEARTBEAT"] == 2 {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(clients.len(), 1, "{:?}", clients);
+    assert_eq!(clients[0]["address"], proxy.gcs.local_addr().unwrap().to_string());
+    assert_eq!(clients[0]["group"], JsonValue::Null);
+    assert_eq!(clients[0]["sent"]["HEARTBEAT"], 2);
+    assert_eq!(clients[0]["received"]["HEARTBEAT"], 2);
+
+    assert_eq!(request(admin_port, "DELETE", "/clients").await.0, 405);
+}
+
+#[tokio::test]
+async fn per_message_counts_fold_extra_ids_into_other() {
+    let admin_port = common::free_port().await;
+    let rules = format!("[admin]\nlisten_port = {admin_port}\n");
+    let mut proxy = common::start(&rules, &[]).await;
+
+    // 300 distinct IDs outside the dialect, each passed through and echoed back
+    for (sequence, message_id) in (100_000..100_300u32).enumerate() {
+        proxy.send_raw(&common::raw_frame(sequence as u8, message_id, 0, &[1], false)).await;
+    }
+    for _ in 0..300 {
+        proxy.recv_raw().await;
+    }
+
+    let mut unparsed = serde_json::Map::new();
+    for _ in 0..100 {
+        let (status, body) = request(admin_port, "GET", "/stats").await;
+        assert_eq!(status, 200);
+        unparsed = body["unparsed_by_message_id"].as_object().unwrap().clone();
+        if unparsed.values().map(|count| count.as_u64().unwrap()).sum::<u64>() == 600 {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(unparsed.len(), 257, "256 IDs and \"other\"");
+    assert_eq!(unparsed["other"], 2 * 44, "both directions of the last 44 IDs");
+    assert_eq!(unparsed["100000"], 2);
+
+    let (_, body) = request(admin_port, "GET", "/clients").await;
+    let sent = body["clients"][0]["sent"].as_object().unwrap();
+    assert_eq!(sent.len(), 257);
+    assert_eq!(sent["other"], 44);
+}
+
+#[tokio::test]
+async fn disconnects_a_single_client() {
+    let admin_port = common::free_port().await;
+    let rules = format!("[admin]\nlisten_port = {admin_port}\n");
+    let mut proxy = common::start(&rules, &[]).await;
+
+    // The harness client registers asynchronously after connecting
+    let mut client_id = None;
+    for _ in 0..100 {
+        let (_, body) = request(admin_port, "GET", "/clients").await;
+        client_id = body["clients"][0]["client_id"].as_u64();
+        if client_id.is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    let client_id = client_id.expect("harness client should be listed");
+
+    let path = format!("/clients/{client_id}/disconnect");
+    let (status, body) = request(admin_port, "POST", &path).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["disconnected"], client_id);
+
+    // The proxy closes the connection
+    let mut buf = [0u8; 16];
+    let read = tokio::time::timeout(Duration::from_secs(5), proxy.gcs.read(&mut buf)).await.unwrap();
+    assert!(matches!(read, Ok(0) | Err(_)), "{:?}", read);
+
+    let mut cause = JsonValue::Null;
+    for _ in 0..100 {
+        let (_, body) = request(admin_port, "GET", "/connections").await;
+        if let Some(event) = body["connections"].as_array().unwrap().iter().find(|e| e["event"] == "disconnect") {
+            cause = event["cause"].clone();
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(cause, "disconnected by admin");
+
+    assert_eq!(request(admin_port, "POST", &path).await.0, 404);
+    assert_eq!(request(admin_port, "GET", &path).await.0, 405);
+    assert_eq!(request(admin_port, "POST", "/clients/abc/disconnect").await.0, 400);
+}
@@ -0,0 +1,96 @@
+//! auto_ack: where synthesized ACKs are sent (`ack.direction`)
+
+mod common;
+
+use mavlink::ardupilotmega::{MavCmd, MavMessage, MavResult, COMMAND_LONG_DATA};
+use std::time::Duration;
+
+/// Blocks ARM commands but answers them; the ACK's route depends on `direction` (unset: the default)
+fn rules(direction: Option<&str>) -> String {
+    let direction = direction.map(|d| format!("direction = \"{d}\"\n")).unwrap_or_default();
+    format!(
+        r#"
+[[rules]]
+name = "ack_arm"
+message_type = "COMMAND_LONG"
+actions = ["block"]
+auto_ack = true
+
+[rules.conditions]
+command = {{ type = "MAV_CMD_COMPONENT_ARM_DISARM" }}
+
+[rules.ack]
+message_type = "COMMAND_ACK"
+source_system_field = "target_system"
+source_component_field = "target_component"
+fields = {{ result = {{ type = "MAV_RESULT_ACCEPTED" }} }}
+copy_fields = {{ command = "command" }}
+{direction}"#
+    )
+}
+
+fn arm() -> MavMessage {
+    MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_COMPONENT_ARM_DISARM,
+        target_system: 1,
+        target_component: 1,
+        param1: 1.0,
+        ..Default::default()
+    })
+}
+
+fn assert_accepted(msg: MavMessage) {
+    match msg {
+        MavMessage::COMMAND_ACK(ack) => {
+            assert_eq!(ack.command, MavCmd::MAV_CMD_COMPONENT_ARM_DISARM);
+            assert_eq!(ack.result, MavResult::MAV_RESULT_ACCEPTED);
+        }
+        other => panic!("expected COMMAND_ACK, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn ack_goes_back_to_the_requesting_gcs_by_default() {
+    let mut proxy = common::start(&rules(None), &[]).await;
+
+    proxy.send(255, 0, &arm()).await;
+    let (header, ack) = proxy.recv().await;
+    assert_eq!(header.system_id, 1, "the ACK appears to come from the target");
+    assert_accepted(ack);
+
+    assert!(proxy.try_recv(Duration::from_millis(200)).await.is_none(), "nothing reached the router");
+}
+
+#[tokio::test]
+async fn ack_can_be_injected_toward_the_router_as_well() {
+    // The echoing router returns whatever it receives, so an ACK sent to it reaches the GCS too
+    let mut proxy = common::start(&rules(Some("both")), &[]).await;
+
+    proxy.send(255, 0, &arm()).await;
+    assert_accepted(proxy.recv().await.1);
+    assert_accepted(proxy.recv().await.1);
+    assert!(proxy.try_recv(Duration::from_millis(200)).await.is_none());
+}
+
+#[tokio::test]
+async fn ack_can_be_sent_only_toward_the_router() {
+    // The echoing router returns the ACK, so the GCS sees it exactly once
+    let mut proxy = common::start(&rules(Some("to_router")), &[]).await;
+
+    proxy.send(255, 0, &arm()).await;
+    assert_accepted(proxy.recv().await.1);
+    assert!(proxy.try_recv(Duration::from_millis(200)).await.is_none());
+
+    // ...and only by way of the router: dropping ACKs coming back from it leaves the GCS with none
+    let drop_echoed = r#"
+[[rules]]
+name = "drop_echoed_acks"
+message_type = "COMMAND_ACK"
+direction = "router_to_gcs"
+actions = ["block"]
+"#;
+    let mut proxy = common::start(&format!("{}{}", rules(Some("to_router")), drop_echoed), &[]).await;
+
+    proxy.send(255, 0, &arm()).await;
+    assert!(proxy.try_recv(Duration::from_millis(300)).await.is_none());
+}
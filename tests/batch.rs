@@ -0,0 +1,126 @@
+//! Batches: release at the threshold or on timeout, and the actions that follow
+
+mod common;
+
+use mavlink::ardupilotmega::{MavMessage, HEARTBEAT_DATA};
+use std::time::{Duration, Instant};
+
+const RULES: &str = r#"
+[[rules]]
+name = "batch_heartbeats"
+message_type = "HEARTBEAT"
+actions = ["batch", "block"]
+batch_count = 2
+batch_timeout_seconds = 1
+batch_timeout_actions = ["forward"]
+"#;
+
+#[tokio::test]
+async fn timeout_actions_replace_the_remaining_chain() {
+    let mut proxy = common::start(RULES, &[]).await;
+
+    proxy.send(1, 0, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+
+    // Only one of two systems showed up: the timeout forwards instead of reaching `block`
+    let (header, msg) = proxy
+        .try_recv(Duration::from_secs(3))
+        .await
+        .expect("timed-out batch should be forwarded");
+    assert_eq!(header.system_id, 1);
+    assert!(matches!(msg, MavMessage::HEARTBEAT(_)));
+}
+
+const BATCH_THEN_DELAY: &str = r#"
+[[rules]]
+name = "batch_then_delay"
+message_type = "HEARTBEAT"
+actions = ["batch", "delay", "forward"]
+batch_count = 2
+batch_timeout_seconds = 1
+delay_seconds = 1
+"#;
+
+#[tokio::test]
+async fn threshold_release_delays_the_whole_batch_in_order() {
+    let mut proxy = common::start(BATCH_THEN_DELAY, &[]).await;
+
+    proxy.send(1, 0, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+    proxy.send(2, 1, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+    let released = Instant::now();
+
+    // The delay starts when the batch is released and applies to both packets at once
+    let (first, _) = proxy.try_recv(Duration::from_secs(3)).await.expect("batch should be forwarded");
+    let (second, _) = proxy.try_recv(Duration::from_secs(1)).await.expect("whole batch should be forwarded");
+    assert!(released.elapsed() >= Duration::from_millis(900), "released after {:?}", released.elapsed());
+    assert_eq!((first.system_id, second.system_id), (1, 2), "packets keep their arrival order");
+}
+
+#[tokio::test]
+async fn timeout_release_runs_the_remaining_delay() {
+    let mut proxy = common::start(BATCH_THEN_DELAY, &[]).await;
+
+    let sent = Instant::now();
+    proxy.send(1, 0, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+
+    // 1s batch timeout, then the 1s delay that follows `batch` in the chain
+    let (header, _) = proxy.try_recv(Duration::from_secs(4)).await.expect("timed-out batch should be forwarded");
+    assert_eq!(header.system_id, 1);
+    assert!(sent.elapsed() >= Duration::from_millis(1900), "forwarded after {:?}", sent.elapsed());
+}
+
+const SHORT_LIVED_BATCHES: &str = r#"
+[[rules]]
+name = "short_lived_batches"
+message_type = "HEARTBEAT"
+actions = ["batch", "forward"]
+batch_count = 2
+batch_timeout_seconds = 2
+batch_timeout_forward = false
+"#;
+
+#[tokio::test]
+async fn a_released_batch_timer_does_not_expire_the_next_batch() {
+    let mut proxy = common::start(SHORT_LIVED_BATCHES, &[]).await;
+
+    proxy.send(1, 0, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+    proxy.send(2, 1, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+    proxy.recv().await;
+    proxy.recv().await;
+
+    // A new batch under the same key opens shortly before the first batch's timer fires
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    proxy.send(1, 2, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+    proxy.send(2, 3, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+
+    let (first, _) = proxy.try_recv(Duration::from_secs(1)).await.expect("second batch should reach its threshold");
+    let (second, _) = proxy.try_recv(Duration::from_secs(1)).await.expect("second batch should be complete");
+    assert_eq!((first.sequence, second.sequence), (2, 3));
+}
+
+const BURST: &str = r#"
+[[rules]]
+name = "burst_heartbeats"
+message_type = "HEARTBEAT"
+actions = ["burst", "forward"]
+burst_count = 3
+burst_timeout_seconds = 5
+"#;
+
+#[tokio::test]
+async fn burst_counts_packets_from_any_system() {
+    let mut proxy = common::start(BURST, &[]).await;
+
+    // One system alone fills a burst: held until the third packet, then released in order
+    proxy.send(1, 0, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+    proxy.send(1, 1, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+    assert!(proxy.try_recv(Duration::from_millis(300)).await.is_none(), "burst should hold its packets");
+
+    proxy.send(1, 2, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+    let mut sequences = Vec::new();
+    for _ in 0..3 {
+        let (header, _) = proxy.try_recv(Duration::from_secs(2)).await.expect("burst should be released");
+        sequences.push(header.sequence);
+    }
+    assert_eq!(sequences, [0, 1, 2]);
+}
@@ -0,0 +1,50 @@
+//! Client groups: rules scoped to GCS connections by source address
+
+mod common;
+
+use mavlink::ardupilotmega::{MavCmd, MavMessage, COMMAND_LONG_DATA, HEARTBEAT_DATA};
+use std::time::Duration;
+
+/// Test clients connect from loopback, so they land in "observers"
+const RULES: &str = r#"
+[[network.client_groups]]
+name = "observers"
+cidrs = ["127.0.0.0/8"]
+default_deny = true
+
+[[network.client_groups]]
+name = "remote"
+cidrs = ["10.0.0.0/8"]
+
+[[rules]]
+name = "observer_heartbeats"
+message_type = "HEARTBEAT"
+client_group = "observers"
+actions = ["forward"]
+
+[[rules]]
+name = "remote_no_heartbeats"
+message_type = "HEARTBEAT"
+client_group = "remote"
+actions = ["block"]
+priority = 10
+"#;
+
+#[tokio::test]
+async fn default_deny_group_only_passes_matched_messages() {
+    let mut proxy = common::start(RULES, &[]).await;
+
+    // No rule matches a command from an observer, so default_deny blocks it
+    let command = MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_COMPONENT_ARM_DISARM,
+        param1: 1.0,
+        ..Default::default()
+    });
+    proxy.send(1, 0, &command).await;
+    assert!(proxy.try_recv(Duration::from_millis(200)).await.is_none());
+
+    // The other group's higher-priority block rule doesn't apply to this client
+    let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+    proxy.send(1, 1, &heartbeat).await;
+    assert!(matches!(proxy.recv().await.1, MavMessage::HEARTBEAT(_)));
+}
@@ -0,0 +1,31 @@
+//! coalesce: only the latest message per system is forwarded each interval
+
+mod common;
+
+use mavlink::ardupilotmega::{MavMessage, HEARTBEAT_DATA};
+use std::time::Duration;
+
+const RULES: &str = r#"
+[[rules]]
+name = "coalesce_heartbeats"
+message_type = "HEARTBEAT"
+actions = ["coalesce", "forward"]
+coalesce_interval_ms = 300
+"#;
+
+#[tokio::test]
+async fn forwards_only_the_freshest_message() {
+    let mut proxy = common::start(RULES, &[]).await;
+
+    let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+    for sequence in 0..5 {
+        proxy.send(1, sequence, &heartbeat).await;
+    }
+
+    let (header, _) = proxy.recv().await;
+    assert_eq!(header.sequence, 4, "the latest message wins");
+    assert!(
+        proxy.try_recv(Duration::from_millis(600)).await.is_none(),
+        "older messages are dropped"
+    );
+}
@@ -0,0 +1,356 @@
+//! Shared harness for integration tests: a proxy wired to an echoing fake router
+//! and a fake GCS client, all on loopback; and a bare rule engine for tests without networking.
+
+#![allow(dead_code)]
+
+use bitch::{
+    CancellationToken, CommandRule, Config, ModifierManager, PluginManager, ProxyServer, RuleEngine, RuleStateManager,
+};
+use mavlink::ardupilotmega::MavMessage;
+use mavlink::{MavHeader, MavlinkVersion, Message};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, timeout};
+
+/// How long to wait for an expected frame before failing the test
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A running proxy with a connected fake GCS
+/// The fake router echoes every byte it receives, so whatever the proxy forwards
+/// to the router comes straight back to the GCS (through router -> GCS processing).
+pub struct Harness {
+    pub gcs: TcpStream,
+    shutdown: CancellationToken,
+    script_dir: PathBuf,
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+        let _ = std::fs::remove_dir_all(&self.script_dir);
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct Rules {
+    rules: Vec<CommandRule>,
+}
+
+/// The `[[rules]]` of a TOML snippet
+pub fn rules(rules_toml: &str) -> Vec<CommandRule> {
+    toml::from_str::<Rules>(rules_toml).unwrap().rules
+}
+
+/// A rule engine over `[[rules]]` TOML, each rule in its `enabled_by_default` state
+pub fn engine(rules_toml: &str) -> RuleEngine {
+    engine_with_modifiers(rules_toml, &[])
+}
+
+/// Like `engine`, with Lua modifiers (name, code) loaded inline
+pub fn engine_with_modifiers(rules_toml: &str, modifiers: &[(&str, &str)]) -> RuleEngine {
+    let rules = rules(rules_toml);
+    let mut modifier_manager = ModifierManager::new().unwrap();
+    for (name, code) in modifiers {
+        modifier_manager.load_inline(name, code).unwrap();
+    }
+    let states: HashMap<String, bool> = rules.iter().map(|r| (r.name.clone(), r.enabled_by_default)).collect();
+    RuleEngine::new(
+        rules,
+        PluginManager::new().unwrap(),
+        modifier_manager,
+        Arc::new(RuleStateManager::new(states)),
+        4,
+    )
+    .unwrap()
+}
+
+/// Start a proxy with the given `[[rules]]` TOML and Lua modifiers (name, code)
+/// Modifiers listed in a `[modifiers] stateful` table in `rules` are loaded as stateful
+pub async fn start(rules: &str, modifiers: &[(&str, &str)]) -> Harness {
+    start_with_routers(rules, modifiers, 1).await
+}
+
+/// Like `start`, but with `router_count` echoing fake routers listed in `[[network.routers]]`
+/// (a single router uses the plain `router_address`/`router_port` form)
+pub async fn start_with_routers(rules: &str, modifiers: &[(&str, &str)], router_count: usize) -> Harness {
+    launch(rules, modifiers, router_count, "", "").await
+}
+
+/// Like `start`, with extra `[network]` settings (e.g. `"process_injected = true"`)
+pub async fn start_with_network(rules: &str, modifiers: &[(&str, &str)], network: &str) -> Harness {
+    launch(rules, modifiers, 1, network, "").await
+}
+
+/// Like `start`, but with the router link on the serial device at `serial_port` instead of TCP
+/// (no fake router is started: the caller drives the other end of the device)
+pub async fn start_with_serial_router(rules: &str, modifiers: &[(&str, &str)], serial_port: &str) -> Harness {
+    let network = format!("router_transport = \"serial\"\nrouter_serial_port = \"{serial_port}\"\n");
+    launch(rules, modifiers, 0, &network, "").await
+}
+
+/// Like `start`, with extra `[logging]` settings (e.g. `"status_broadcast_seconds = 1"`)
+pub async fn start_with_logging(rules: &str, modifiers: &[(&str, &str)], logging: &str) -> Harness {
+    launch(rules, modifiers, 1, "", logging).await
+}
+
+async fn launch(
+    rules: &str,
+    modifiers: &[(&str, &str)],
+    router_count: usize,
+    network: &str,
+    logging: &str,
+) -> Harness {
+    let mut router_listeners = Vec::new();
+    for _ in 0..router_count {
+        router_listeners.push(TcpListener::bind("127.0.0.1:0").await.unwrap());
+    }
+    let router_ports: Vec<u16> = router_listeners
+        .iter()
+        .map(|l| l.local_addr().unwrap().port())
+        .collect();
+    let gcs_port = free_port().await;
+
+    let routers_toml = match router_ports.as_slice() {
+        [port] => format!("router_address = \"127.0.0.1\"\nrouter_port = {port}\n"),
+        ports => ports
+            .iter()
+            .map(|port| format!("\n[[network.routers]]\naddress = \"127.0.0.1\"\nport = {port}\n"))
+            .collect(),
+    };
+
+    let config_toml = format!(
+        r#"
+[network]
+gcs_listen_port = {gcs_port}
+gcs_listen_address = "127.0.0.1"
+{network}
+{routers_toml}
+[logging]
+level = "warn"
+{logging}
+
+{rules}
+"#
+    );
+    let config: Config = toml::from_str(&config_toml).expect("test config should parse");
+    config.validate().expect("test config should validate");
+
+    // Modifiers are loaded from files, so write them to a per-harness temp dir
+    let script_dir = std::env::temp_dir().join(format!("bitch-test-{}-{}", std::process::id(), gcs_port));
+    std::fs::create_dir_all(&script_dir).unwrap();
+    let mut modifier_manager = ModifierManager::new().unwrap();
+    for (name, code) in modifiers {
+        let path = script_dir.join(format!("{}.lua", name));
+        std::fs::write(&path, code).unwrap();
+        if config.modifiers.stateful.iter().any(|s| s == name) {
+            modifier_manager.load_stateful_modifier(name, &path).unwrap();
+        } else {
+            modifier_manager.load_modifier(name, &path).unwrap();
+        }
+    }
+
+    let server = ProxyServer::new(config, PluginManager::new().unwrap(), modifier_manager).unwrap();
+    let shutdown = CancellationToken::new();
+    let run_token = shutdown.clone();
+    tokio::spawn(async move {
+        let _ = server.run(run_token).await;
+    });
+
+    // Fake routers: echo everything back
+    for router_listener in router_listeners {
+        let (router, _) = timeout(READ_TIMEOUT, router_listener.accept())
+            .await
+            .expect("proxy should connect to the router")
+            .unwrap();
+        tokio::spawn(echo(router));
+    }
+
+    // The GCS listener is bound after the router connection, so retry briefly
+    let mut attempts = 0;
+    let gcs = loop {
+        match TcpStream::connect(("127.0.0.1", gcs_port)).await {
+            Ok(stream) => break stream,
+            Err(_) if attempts < 100 => {
+                attempts += 1;
+                sleep(Duration::from_millis(20)).await;
+            }
+            Err(e) => panic!("GCS could not connect to proxy: {}", e),
+        }
+    };
+
+    Harness {
+        gcs,
+        shutdown,
+        script_dir,
+    }
+}
+
+impl Harness {
+    /// Send raw bytes from the GCS
+    pub async fn send_raw(&mut self, bytes: &[u8]) {
+        self.gcs.write_all(bytes).await.unwrap();
+    }
+
+    /// Send a message from the GCS
+    pub async fn send(&mut self, system_id: u8, sequence: u8, msg: &MavMessage) {
+        let bytes = frame(system_id, sequence, msg);
+        self.send_raw(&bytes).await;
+    }
+
+    /// Receive the next frame at the GCS (as echoed by the router)
+    pub async fn recv(&mut self) -> (MavHeader, MavMessage) {
+        let frame = self.recv_raw().await;
+        bitch::parse_mavlink_message(&frame).expect("received frame should parse")
+    }
+
+    /// Receive the next frame at the GCS, or None if nothing arrives within `wait`
+    pub async fn try_recv(&mut self, wait: Duration) -> Option<(MavHeader, MavMessage)> {
+        try_recv_from(&mut self.gcs, wait).await
+    }
+
+    /// Connect another GCS client, registered with the proxy by the time this returns
+    pub async fn connect_gcs(&self) -> TcpStream {
+        let gcs = TcpStream::connect(self.gcs.peer_addr().unwrap()).await.unwrap();
+        sleep(Duration::from_millis(100)).await;
+        gcs
+    }
+
+    /// Receive the next frame at the GCS as raw bytes
+    pub async fn recv_raw(&mut self) -> Vec<u8> {
+        timeout(READ_TIMEOUT, read_frame(&mut self.gcs))
+            .await
+            .expect("timed out waiting for a frame")
+    }
+}
+
+/// Receive the next frame on a GCS connection, or None if nothing arrives within `wait`
+pub async fn try_recv_from(gcs: &mut TcpStream, wait: Duration) -> Option<(MavHeader, MavMessage)> {
+    let frame = timeout(wait, read_frame(gcs)).await.ok()?;
+    Some(bitch::parse_mavlink_message(&frame).expect("received frame should parse"))
+}
+
+/// Echo every byte back until the connection closes
+async fn echo(mut router: TcpStream) {
+    let mut buf = [0u8; 1024];
+    loop {
+        match router.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                if router.write_all(&buf[..n]).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Serialize a message as a MAVLink v2 frame
+pub fn frame(system_id: u8, sequence: u8, msg: &MavMessage) -> Vec<u8> {
+    let header = MavHeader {
+        system_id,
+        component_id: 1,
+        sequence,
+    };
+    let mut buf = Vec::new();
+    mavlink::write_versioned_msg(&mut buf, MavlinkVersion::V2, header, msg).unwrap();
+    buf
+}
+
+/// Message ID not defined in the ardupilotmega dialect (passed through unparsed)
+pub const CUSTOM_MSG_ID: u32 = 60000;
+
+/// CRC-16/MCRF4XX as used by MAVLink
+pub fn crc16(data: &[u8], mut crc: u16) -> u16 {
+    for byte in data {
+        let mut tmp = *byte ^ (crc & 0xFF) as u8;
+        tmp ^= tmp << 4;
+        crc = (crc >> 8) ^ ((tmp as u16) << 8) ^ ((tmp as u16) << 3) ^ ((tmp as u16) >> 4);
+    }
+    crc
+}
+
+/// Sign an unsigned v2 frame with `key` (link ID 0) at `timestamp` (10us units since 2015)
+pub fn sign(unsigned: &[u8], key: &[u8], timestamp: u64) -> Vec<u8> {
+    let mut frame = unsigned[..unsigned.len() - 2].to_vec();
+    frame[2] |= 0x01;
+    let msg_id = u32::from_le_bytes([frame[7], frame[8], frame[9], 0]);
+    let crc = crc16(&[MavMessage::extra_crc(msg_id)], crc16(&frame[1..], 0xFFFF));
+    frame.extend_from_slice(&crc.to_le_bytes());
+    frame.push(0);
+    frame.extend_from_slice(&timestamp.to_le_bytes()[..6]);
+    let digest = Sha256::new().chain_update(key).chain_update(&frame).finalize();
+    frame.extend_from_slice(&digest[..6]);
+    frame
+}
+
+/// Current signing timestamp (10us units since 2015)
+pub fn now() -> u64 {
+    let micros = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as u64;
+    micros / 10 - 1_420_070_400 * 100_000
+}
+
+/// v2 frame built byte by byte from system 1, component 1, checksummed with `crc_extra`
+/// `signed` appends a (never verified) signature trailer
+pub fn raw_frame(sequence: u8, msg_id: u32, crc_extra: u8, payload: &[u8], signed: bool) -> Vec<u8> {
+    let incompat = if signed { 0x01 } else { 0 };
+    let mut frame = vec![0xFD, payload.len() as u8, incompat, 0, sequence, 1, 1];
+    frame.extend_from_slice(&msg_id.to_le_bytes()[..3]);
+    frame.extend_from_slice(payload);
+    let crc = crc16(&[crc_extra], crc16(&frame[1..], 0xFFFF));
+    frame.extend_from_slice(&crc.to_le_bytes());
+    if signed {
+        frame.extend_from_slice(&[7u8; 13]);
+    }
+    frame
+}
+
+/// Read one MAVLink v2 frame (including the signature trailer of signed frames)
+async fn read_frame(stream: &mut TcpStream) -> Vec<u8> {
+    let mut frame = vec![0u8; 10];
+    stream.read_exact(&mut frame).await.unwrap();
+    assert_eq!(frame[0], 0xFD, "proxy should emit frames starting at the magic byte");
+
+    let payload_len = frame[1] as usize;
+    let signature_len = if frame[2] & 0x01 != 0 { 13 } else { 0 };
+    frame.resize(10 + payload_len + 2 + signature_len, 0);
+    stream.read_exact(&mut frame[10..]).await.unwrap();
+    frame
+}
+
+/// Send a bodiless request to the admin API and return (status, JSON body)
+pub async fn request(port: u16, method: &str, path: &str) -> (u16, JsonValue) {
+    request_with_body(port, method, path, "").await
+}
+
+/// Send a request with a body and return (status, JSON body)
+pub async fn request_with_body(port: u16, method: &str, path: &str, body: &str) -> (u16, JsonValue) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream
+        .write_all(
+            format!(
+                "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").expect("response should have a head");
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+/// Find a free loopback port
+pub async fn free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap().port()
+}
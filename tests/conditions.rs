@@ -0,0 +1,528 @@
+//! Rule engine condition tests (no networking)
+
+mod common;
+
+use bitch::{Action, Direction, FrameFlags, Ingress, ParsedFrame, RawHeader, RuleEngine};
+use common::engine;
+use mavlink::ardupilotmega::{
+    MavCmd, MavMessage, MavModeFlag, PositionTargetTypemask, COMMAND_LONG_DATA, GLOBAL_POSITION_INT_DATA,
+    HEARTBEAT_DATA, SET_POSITION_TARGET_LOCAL_NED_DATA, STATUSTEXT_DATA,
+};
+use mavlink::MavHeader;
+use std::time::Duration;
+
+fn blocked(engine: &RuleEngine, msg: &MavMessage) -> bool {
+    engine
+        .process_message(&MavHeader::default(), msg)
+        .actions
+        .iter()
+        .any(|a| matches!(a, Action::Block { .. }))
+}
+
+fn heartbeat(base_mode: MavModeFlag) -> MavMessage {
+    MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+        base_mode,
+        ..Default::default()
+    })
+}
+
+fn position_target(type_mask: u16) -> MavMessage {
+    MavMessage::SET_POSITION_TARGET_LOCAL_NED(SET_POSITION_TARGET_LOCAL_NED_DATA {
+        type_mask: PositionTargetTypemask::from_bits_truncate(type_mask),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn bit_set_matches_flag_fields() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "armed"
+message_type = "HEARTBEAT"
+action = "block"
+
+[rules.conditions]
+base_mode = { bit_set = 128 }
+"#,
+    );
+
+    assert!(blocked(
+        &engine,
+        &heartbeat(MavModeFlag::MAV_MODE_FLAG_SAFETY_ARMED | MavModeFlag::MAV_MODE_FLAG_CUSTOM_MODE_ENABLED)
+    ));
+    assert!(!blocked(&engine, &heartbeat(MavModeFlag::MAV_MODE_FLAG_CUSTOM_MODE_ENABLED)));
+}
+
+#[test]
+fn bits_any_and_bits_all() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "any"
+message_type = "SET_POSITION_TARGET_LOCAL_NED"
+action = "block"
+
+[rules.conditions]
+type_mask = { bits_any = [1, 2] }
+
+[[rules]]
+name = "all"
+message_type = "SET_POSITION_TARGET_LOCAL_NED"
+action = "block"
+
+[rules.conditions]
+type_mask = { bits_all = [8, 16] }
+"#,
+    );
+
+    assert!(blocked(&engine, &position_target(2)));
+    assert!(blocked(&engine, &position_target(8 | 16)));
+    assert!(!blocked(&engine, &position_target(8)));
+    assert!(!blocked(&engine, &position_target(0)));
+}
+
+#[test]
+fn exists_checks_field_presence() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "present"
+message_type = "HEARTBEAT"
+action = "block"
+
+[rules.conditions]
+custom_mode = { exists = true, bit_set = 4 }
+altitude = { exists = false }
+
+[[rules]]
+name = "absent"
+message_type = "SET_POSITION_TARGET_LOCAL_NED"
+action = "block"
+
+[rules.conditions]
+type_mask = { exists = false }
+"#,
+    );
+
+    let custom_mode = |custom_mode| {
+        MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+            custom_mode,
+            ..Default::default()
+        })
+    };
+    assert!(blocked(&engine, &custom_mode(4)));
+    assert!(!blocked(&engine, &custom_mode(0)));
+    assert!(!blocked(&engine, &position_target(0)));
+}
+
+#[test]
+fn transform_patches_numeric_fields() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "scale"
+message_type = "SET_POSITION_TARGET_LOCAL_NED"
+actions = ["transform", "forward"]
+transform_fields = { x = { op = "mul", value = 0.5 }, target_system = { op = "clamp", max = 10 } }
+"#,
+    );
+
+    let msg = MavMessage::SET_POSITION_TARGET_LOCAL_NED(SET_POSITION_TARGET_LOCAL_NED_DATA {
+        x: 3.0,
+        target_system: 200,
+        ..Default::default()
+    });
+    let result = engine.process_message(&MavHeader::default(), &msg);
+    let Some(Action::Modify { modified_message: Some(MavMessage::SET_POSITION_TARGET_LOCAL_NED(data)), .. }) =
+        result.actions.first()
+    else {
+        panic!("expected a transformed message, got {:?}", result.actions);
+    };
+    assert_eq!(data.x, 1.5);
+    assert_eq!(data.target_system, 10);
+}
+
+#[test]
+fn redact_zeroes_listed_fields() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "hide_position"
+message_type = "GLOBAL_POSITION_INT"
+actions = ["redact", "forward"]
+redact_fields = ["lat", "lon"]
+"#,
+    );
+
+    let msg = MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA {
+        lat: 473_977_420,
+        lon: 85_455_940,
+        alt: 488_000,
+        ..Default::default()
+    });
+    let result = engine.process_message(&MavHeader::default(), &msg);
+    let Some(Action::Modify { modified_message: Some(MavMessage::GLOBAL_POSITION_INT(data)), .. }) =
+        result.actions.first()
+    else {
+        panic!("expected a redacted message, got {:?}", result.actions);
+    };
+    assert_eq!((data.lat, data.lon), (0, 0));
+    assert_eq!(data.alt, 488_000, "unlisted fields are kept");
+}
+
+#[test]
+fn dedup_identical_blocks_repeats_per_system_and_type() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "changes_only"
+message_type = "HEARTBEAT"
+actions = ["dedup_identical", "forward"]
+"#,
+    );
+    let disarmed = heartbeat(MavModeFlag::empty());
+    let armed = heartbeat(MavModeFlag::MAV_MODE_FLAG_SAFETY_ARMED);
+
+    assert!(!blocked(&engine, &disarmed), "first message always passes");
+    assert!(blocked(&engine, &disarmed));
+    assert!(!blocked(&engine, &armed), "changed content passes");
+    assert!(!blocked(&engine, &disarmed), "compared with the last one passed, not the first");
+    assert!(blocked(&engine, &disarmed));
+
+    let other_system = MavHeader {
+        system_id: 2,
+        ..Default::default()
+    };
+    let result = engine.process_message(&other_system, &disarmed);
+    assert!(result.actions.iter().all(|a| !matches!(a, Action::Block { .. })), "tracked per system");
+}
+
+#[test]
+fn since_event_matches_within_window_of_recorded_event() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "disarm_seen"
+message_type = "COMMAND_LONG"
+action = "forward"
+record_event = "disarm"
+
+[rules.conditions]
+param1 = 0.0
+
+[[rules]]
+name = "no_quick_rearm"
+message_type = "COMMAND_LONG"
+action = "block"
+
+[rules.conditions]
+param1 = 1.0
+since_event = { name = "disarm", within_seconds = 5, per_system = true }
+"#,
+    );
+
+    let arm_disarm = |param1| {
+        MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+            command: MavCmd::MAV_CMD_COMPONENT_ARM_DISARM,
+            param1,
+            ..Default::default()
+        })
+    };
+    let from = |system_id| MavHeader {
+        system_id,
+        ..Default::default()
+    };
+    let arm_blocked = |system_id| {
+        engine
+            .process_message(&from(system_id), &arm_disarm(1.0))
+            .actions
+            .iter()
+            .any(|a| matches!(a, Action::Block { .. }))
+    };
+
+    assert!(!arm_blocked(255), "no disarm recorded yet");
+    engine.process_message(&from(255), &arm_disarm(0.0));
+    assert!(arm_blocked(255));
+    assert!(!arm_blocked(254), "per_system only counts the same sender");
+}
+
+#[test]
+fn delta_matches_jumps_between_consecutive_messages() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "altitude_jump"
+message_type = "GLOBAL_POSITION_INT"
+action = "block"
+
+[rules.conditions]
+delta = { field = "alt", threshold = 100000 }
+"#,
+    );
+
+    let position = |alt| MavMessage::GLOBAL_POSITION_INT(GLOBAL_POSITION_INT_DATA { alt, ..Default::default() });
+
+    assert!(!blocked(&engine, &position(50_000)), "first message has nothing to compare to");
+    assert!(!blocked(&engine, &position(120_000)));
+    assert!(blocked(&engine, &position(250_000)), "jumped 130m");
+    assert!(blocked(&engine, &position(100_000)), "drops count too");
+    assert!(!blocked(&engine, &position(100_000)));
+}
+
+#[test]
+fn string_operators_match_char_array_text() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "prearm"
+message_type = "STATUSTEXT"
+action = "block"
+
+[rules.conditions]
+text = { starts_with = "PreArm", contains = "gps", ignore_case = true }
+"#,
+    );
+
+    let status_text = |text: &str| {
+        let mut data = STATUSTEXT_DATA::default();
+        data.text[..text.len()].copy_from_slice(text.as_bytes());
+        MavMessage::STATUSTEXT(data)
+    };
+    assert!(blocked(&engine, &status_text("PreArm: GPS not healthy")));
+    assert!(blocked(&engine, &status_text("prearm: need gps lock")));
+    assert!(!blocked(&engine, &status_text("PreArm: Compass not calibrated")));
+    assert!(!blocked(&engine, &status_text("GPS glitch")));
+}
+
+#[test]
+fn sequence_gap_above_detects_lost_frames_across_wraparound() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "dropped_frames"
+message_type = "HEARTBEAT"
+action = "block"
+
+[rules.conditions]
+sequence_gap_above = 3
+"#,
+    );
+
+    let heartbeat = heartbeat(MavModeFlag::empty());
+    let gap_detected = |system_id, sequence| {
+        let header = MavHeader {
+            system_id,
+            component_id: 1,
+            sequence,
+        };
+        engine
+            .process_message(&header, &heartbeat)
+            .actions
+            .iter()
+            .any(|a| matches!(a, Action::Block { .. }))
+    };
+
+    assert!(!gap_detected(1, 250), "first frame from a source");
+    assert!(!gap_detected(1, 253), "jump of 3 is not above 3");
+    assert!(gap_detected(1, 1), "253 -> 1 wraps around: a jump of 4");
+    assert!(!gap_detected(1, 2));
+    assert!(!gap_detected(2, 100), "sources are tracked separately");
+}
+
+#[test]
+fn incompat_flags_unknown_matches_only_undefined_bits() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "unknown_incompat"
+message_type = "HEARTBEAT"
+actions = ["block"]
+
+[rules.conditions]
+incompat_flags = { unknown = true }
+"#,
+    );
+
+    let blocked_with = |incompat| {
+        let frame = ParsedFrame {
+            header: MavHeader::default(),
+            message: heartbeat(MavModeFlag::empty()),
+            flags: FrameFlags { incompat, compat: 0 },
+        };
+        engine
+            .process_frame(&frame, Direction::GcsToRouter)
+            .actions
+            .iter()
+            .any(|a| matches!(a, Action::Block { .. }))
+    };
+
+    assert!(blocked_with(0x02));
+    assert!(blocked_with(0x03), "unknown bit next to the signed flag");
+    assert!(!blocked_with(0x01), "signed is a known flag");
+    assert!(!blocked_with(0x00));
+    assert!(!blocked(&engine, &heartbeat(MavModeFlag::empty())), "messages without a frame have no flags");
+}
+
+#[test]
+fn simplify_actions_drops_redundant_actions() {
+    let rules = r#"
+[[rules]]
+name = "noisy"
+message_type = "HEARTBEAT"
+actions = ["forward", "forward", "block", "delay"]
+"#;
+    let rule = &common::rules(rules)[0];
+    assert_eq!(rule.simplified_actions(), ["block"]);
+    let warnings = rule.action_warnings();
+    assert_eq!(warnings.len(), 3, "{:?}", warnings);
+    assert!(warnings.iter().any(|w| w.contains("after 'block' never run: delay")), "{:?}", warnings);
+
+    let names = |engine: &RuleEngine| -> Vec<&'static str> {
+        let result = engine.process_message(&MavHeader::default(), &heartbeat(MavModeFlag::empty()));
+        result.actions.iter().map(|a| a.name()).collect()
+    };
+    let mut simplified = engine(rules);
+    assert_eq!(names(&simplified), ["forward", "forward", "block", "delay"], "chains run in full by default");
+    simplified.set_simplify_actions(true);
+    assert_eq!(names(&simplified), ["block"]);
+}
+
+#[test]
+fn undecodable_frames_match_unknown_rules_by_msgid() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "drop_custom"
+message_type = "UNKNOWN"
+actions = ["forward", "block"]
+
+[rules.conditions]
+msgid = 60000
+system_id = 3
+"#,
+    );
+
+    // v2 header: magic, len, incompat, compat, seq, sysid, compid, msgid (24-bit little endian)
+    let frame = [0xFD, 0, 0, 0, 7, 3, 1, 0x60, 0xEA, 0x00, 0, 0];
+    let raw = RawHeader::of(&frame).expect("a full v2 header");
+    assert_eq!((raw.system_id, raw.component_id, raw.sequence, raw.message_id), (3, 1, 7, 60000));
+
+    let result = engine.process_unparsed(&raw, FrameFlags::of(&frame), Direction::GcsToRouter, Ingress::default());
+    assert_eq!(result.matched_rule.as_deref(), Some("drop_custom"));
+    assert!(matches!(result.actions.as_slice(), [Action::Forward, Action::Block { .. }]));
+
+    let other = RawHeader { message_id: 60001, ..raw };
+    let result = engine.process_unparsed(&other, FrameFlags::default(), Direction::GcsToRouter, Ingress::default());
+    assert!(result.matched_rule.is_none());
+    assert!(RawHeader::of(&frame[..6]).is_none());
+}
+
+#[test]
+fn source_condition_matches_the_ingress_transport() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "serial_only"
+message_type = "HEARTBEAT"
+actions = ["block"]
+
+[rules.conditions]
+source = "serial"
+
+[[rules]]
+name = "gcs_listener"
+message_type = "HEARTBEAT"
+actions = ["delay"]
+
+[rules.conditions]
+source = "tcp:14550"
+"#,
+    );
+
+    let frame = ParsedFrame {
+        header: MavHeader::default(),
+        message: heartbeat(MavModeFlag::empty()),
+        flags: FrameFlags::default(),
+    };
+    let matched = |source| {
+        let ingress = Ingress {
+            source,
+            ..Default::default()
+        };
+        engine.process_frame_from(&frame, Direction::GcsToRouter, ingress).matched_rule
+    };
+
+    assert_eq!(matched(Some("serial:/dev/ttyUSB0")).as_deref(), Some("serial_only"));
+    assert_eq!(matched(Some("tcp:14550")).as_deref(), Some("gcs_listener"));
+    assert_eq!(matched(Some("tcp:145500")), None, "a prefix only matches up to a ':'");
+    assert_eq!(matched(Some("serialx:/dev/ttyS0")), None);
+    assert_eq!(matched(None), None, "frames without an ingress never match a source condition");
+}
+
+#[test]
+fn readdress_sets_the_target() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "vehicle_1_to_2"
+message_type = "COMMAND_LONG"
+actions = ["readdress", "forward"]
+readdress = { target_system = 2 }
+"#,
+    );
+
+    let msg = MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        target_system: 1,
+        target_component: 1,
+        param1: 1.0,
+        ..Default::default()
+    });
+    let result = engine.process_message(&MavHeader::default(), &msg);
+    let Some(Action::Modify { modified_message: Some(MavMessage::COMMAND_LONG(data)), .. }) = result.actions.first()
+    else {
+        panic!("expected a readdressed message, got {:?}", result.actions);
+    };
+    assert_eq!((data.target_system, data.target_component), (2, 1), "target_component is kept");
+    assert_eq!(data.param1, 1.0);
+}
+
+#[test]
+fn triggered_delay_reads_the_trigger_context() {
+    let engine = engine(
+        r#"
+[[rules]]
+name = "arm_command"
+message_type = "COMMAND_LONG"
+actions = ["forward"]
+
+[rules.triggers]
+activate_rules = ["hold"]
+duration_seconds = 60
+context = true
+
+[[rules]]
+name = "hold"
+message_type = "HEARTBEAT"
+actions = ["delay"]
+delay_seconds = "{ctx.message.param1|5}"
+enabled_by_default = false
+"#,
+    );
+    let status = heartbeat(MavModeFlag::empty());
+    let actions = engine.process_message(&MavHeader::default(), &status).actions;
+    assert!(matches!(actions.as_slice(), [Action::Forward]), "hold isn't active yet: {:?}", actions);
+
+    let command = MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_COMPONENT_ARM_DISARM,
+        param1: 7.0,
+        ..Default::default()
+    });
+    engine.process_message(&MavHeader::default(), &command);
+    let actions = engine.process_message(&MavHeader::default(), &status).actions;
+    assert!(
+        matches!(actions.as_slice(), [Action::Delay(delay)] if *delay == Duration::from_secs(7)),
+        "param1 of the triggering command is the delay: {:?}",
+        actions
+    );
+}
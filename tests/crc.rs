@@ -0,0 +1,22 @@
+//! crc_extra_overrides: frames of messages outside the bundled dialect are checked
+//! against the configured CRC_EXTRA
+
+mod common;
+
+/// Unsigned v2 frame of the custom message, checksummed with `crc_extra`
+fn custom_frame(sequence: u8, crc_extra: u8) -> Vec<u8> {
+    common::raw_frame(sequence, common::CUSTOM_MSG_ID, crc_extra, &[1, 2, 3, 4], false)
+}
+
+#[tokio::test]
+async fn custom_messages_are_checked_against_override() {
+    let mut proxy = common::start_with_network("", &[], "crc_extra_overrides = { 60000 = 12 }").await;
+
+    let bad = custom_frame(0, 99);
+    let good = custom_frame(1, 12);
+    proxy.send_raw(&bad).await;
+    proxy.send_raw(&good).await;
+
+    // The mis-checksummed frame is dropped; the valid one passes through untouched
+    assert_eq!(proxy.recv_raw().await, good);
+}
@@ -0,0 +1,58 @@
+//! Payload length edge cases: empty and maximum-length payloads, signed and unsigned
+
+mod common;
+
+use mavlink::ardupilotmega::MavMessage;
+
+/// HEARTBEAT message ID and CRC_EXTRA
+const HEARTBEAT_ID: u32 = 0;
+const HEARTBEAT_CRC_EXTRA: u8 = 50;
+
+#[tokio::test]
+async fn empty_payload_is_forwarded_and_parsed() {
+    let mut proxy = common::start("", &[]).await;
+
+    // MAVLink 2 truncates trailing zeros, so an all-zero HEARTBEAT may arrive with no payload
+    let empty = common::raw_frame(0, HEARTBEAT_ID, HEARTBEAT_CRC_EXTRA, &[], false);
+    proxy.send_raw(&empty).await;
+
+    let received = proxy.recv_raw().await;
+    assert_eq!(received, empty);
+    let (_, msg) = bitch::parse_mavlink_message(&received).unwrap();
+    match msg {
+        MavMessage::HEARTBEAT(heartbeat) => assert_eq!(heartbeat.custom_mode, 0, "missing bytes read as zero"),
+        other => panic!("expected HEARTBEAT, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn max_payload_frames_are_forwarded_intact() {
+    let mut proxy = common::start("", &[]).await;
+
+    // 0xFD bytes inside the payload must not be taken for the start of another frame
+    let payload: Vec<u8> = (0..255u16).map(|i| if i % 16 == 0 { 0xFD } else { i as u8 }).collect();
+    let unsigned = common::raw_frame(0, common::CUSTOM_MSG_ID, 0, &payload, false);
+    let signed = common::raw_frame(0, common::CUSTOM_MSG_ID, 0, &payload, true);
+    assert_eq!(unsigned.len(), 10 + 255 + 2);
+    assert_eq!(signed.len(), 10 + 255 + 2 + 13);
+
+    proxy.send_raw(&unsigned).await;
+    proxy.send_raw(&signed).await;
+
+    assert_eq!(proxy.recv_raw().await, unsigned);
+    assert_eq!(proxy.recv_raw().await, signed);
+}
+
+#[tokio::test]
+async fn signed_empty_payload_keeps_its_trailer() {
+    let mut proxy = common::start("", &[]).await;
+
+    let signed = common::raw_frame(0, HEARTBEAT_ID, HEARTBEAT_CRC_EXTRA, &[], true);
+    let next = common::raw_frame(0, HEARTBEAT_ID, HEARTBEAT_CRC_EXTRA, &[3], false);
+    proxy.send_raw(&signed).await;
+    proxy.send_raw(&next).await;
+
+    // The trailer length comes from the incompat flag, so the following frame stays aligned
+    assert_eq!(proxy.recv_raw().await, signed);
+    assert_eq!(proxy.recv_raw().await, next);
+}
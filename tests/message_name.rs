@@ -0,0 +1,78 @@
+//! `get_message_name` must agree with the internally-tagged `type` used for rule matching
+
+use bitch::get_message_name;
+use mavlink::ardupilotmega::{
+    MavCmd, MavMessage, MavModeFlag, MavState, MavType, COMMAND_ACK_DATA, COMMAND_LONG_DATA, HEARTBEAT_DATA,
+    PARAM_REQUEST_LIST_DATA, STATUSTEXT_DATA, SYS_STATUS_DATA,
+};
+
+fn json_type(msg: &MavMessage) -> String {
+    let json = serde_json::to_value(msg).unwrap();
+    json["type"].as_str().unwrap().to_string()
+}
+
+fn assert_name(msg: MavMessage, expected: &str) {
+    assert_eq!(get_message_name(&msg), expected);
+    assert_eq!(json_type(&msg), expected, "name must match the JSON type tag");
+}
+
+#[test]
+fn heartbeat() {
+    assert_name(
+        MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+            mavtype: MavType::MAV_TYPE_QUADROTOR,
+            base_mode: MavModeFlag::MAV_MODE_FLAG_SAFETY_ARMED,
+            system_status: MavState::MAV_STATE_ACTIVE,
+            ..Default::default()
+        }),
+        "HEARTBEAT",
+    );
+}
+
+#[test]
+fn messages_with_enum_fields() {
+    assert_name(
+        MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+            command: MavCmd::MAV_CMD_COMPONENT_ARM_DISARM,
+            ..Default::default()
+        }),
+        "COMMAND_LONG",
+    );
+    assert_name(
+        MavMessage::COMMAND_ACK(COMMAND_ACK_DATA {
+            command: MavCmd::MAV_CMD_DO_SET_MODE,
+            ..Default::default()
+        }),
+        "COMMAND_ACK",
+    );
+}
+
+#[test]
+fn messages_with_bitflags_and_arrays() {
+    assert_name(MavMessage::SYS_STATUS(SYS_STATUS_DATA::default()), "SYS_STATUS");
+    assert_name(MavMessage::STATUSTEXT(STATUSTEXT_DATA::default()), "STATUSTEXT");
+}
+
+#[test]
+fn all_default_payload() {
+    assert_name(
+        MavMessage::PARAM_REQUEST_LIST(PARAM_REQUEST_LIST_DATA::default()),
+        "PARAM_REQUEST_LIST",
+    );
+}
+
+#[test]
+fn parsed_frames() {
+    let msg = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+    let mut frame = Vec::new();
+    mavlink::write_versioned_msg(
+        &mut frame,
+        mavlink::MavlinkVersion::V2,
+        mavlink::MavHeader::default(),
+        &msg,
+    )
+    .unwrap();
+
+    let (_, parsed) = bitch::parse_mavlink_message(&frame).unwrap();
+    assert_eq!(get_message_name(&parsed), "HEARTBEAT");
+}
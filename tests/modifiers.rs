@@ -0,0 +1,182 @@
+//! Stateful modifiers keep top-level Lua state between messages; weighted modifier selection; on_modify_error; reprocess re-matching
+
+mod common;
+
+use bitch::Action;
+use mavlink::ardupilotmega::{MavMessage, HEARTBEAT_DATA};
+use mavlink::MavHeader;
+use std::time::Duration;
+
+const RULES: &str = r#"
+[modifiers]
+stateful = ["counter"]
+
+[modifiers.load]
+counter = "counter.lua"
+
+[[rules]]
+name = "count_heartbeats"
+message_type = "HEARTBEAT"
+actions = ["modify", "forward"]
+modifier = "counter"
+"#;
+
+/// Stamps each HEARTBEAT with how many it has seen so far
+const COUNTER: &str = r#"
+local seen = 0
+
+function modify(ctx)
+    seen = seen + 1
+    ctx.message.custom_mode = seen
+    return ctx
+end
+"#;
+
+#[tokio::test]
+async fn stateful_modifier_keeps_locals_between_messages() {
+    let mut proxy = common::start(RULES, &[("counter", COUNTER)]).await;
+    let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+
+    for sequence in 0..3u8 {
+        proxy.send(1, sequence, &heartbeat).await;
+    }
+
+    for expected in 1..=3 {
+        match proxy.recv().await.1 {
+            MavMessage::HEARTBEAT(data) => assert_eq!(data.custom_mode, expected),
+            other => panic!("expected HEARTBEAT, got {:?}", other),
+        }
+    }
+}
+
+/// Run HEARTBEATs through a rule picking "one" (weight 3) or "two" (weight 1) and count each
+fn weighted_picks(seed: u64) -> Vec<u32> {
+    let mut engine = common::engine_with_modifiers(
+        r#"
+[[rules]]
+name = "chaos"
+message_type = "HEARTBEAT"
+actions = ["modify", "forward"]
+modifiers = [{ name = "one", weight = 3 }, { name = "two" }]
+"#,
+        &[
+            ("one", "function modify(ctx) ctx.message.custom_mode = 1 return ctx end"),
+            ("two", "function modify(ctx) ctx.message.custom_mode = 2 return ctx end"),
+        ],
+    );
+    engine.set_random_seed(seed);
+
+    let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+    (0..400)
+        .map(|_| match &engine.process_message(&MavHeader::default(), &heartbeat).actions[0] {
+            Action::Modify {
+                modified_message: Some(MavMessage::HEARTBEAT(data)),
+                ..
+            } => data.custom_mode,
+            other => panic!("expected a modified HEARTBEAT, got {:?}", other),
+        })
+        .collect()
+}
+
+#[test]
+fn weighted_modifiers_follow_weights_and_seed() {
+    let picks = weighted_picks(7);
+    let ones = picks.iter().filter(|mode| **mode == 1).count();
+    assert!((250..350).contains(&ones), "expected ~300 of 400 picks for weight 3, got {}", ones);
+    assert_eq!(picks, weighted_picks(7), "same seed, same picks");
+}
+
+/// A modifier that always fails
+const FAILING: &str = r#"
+function modify(ctx)
+    error("boom")
+end
+"#;
+
+const FAILING_RULE: &str = r#"
+[[rules]]
+name = "fragile"
+message_type = "HEARTBEAT"
+actions = ["modify", "forward"]
+modifier = "failing"
+"#;
+
+#[tokio::test]
+async fn failing_modifier_follows_on_modify_error() {
+    let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+        custom_mode: 7,
+        ..Default::default()
+    });
+
+    for policy in ["forward_original", "forward_modified_best_effort"] {
+        let network = format!("on_modify_error = \"{}\"", policy);
+        let mut proxy = common::start_with_network(FAILING_RULE, &[("failing", FAILING)], &network).await;
+        proxy.send(1, 0, &heartbeat).await;
+        match proxy.recv().await.1 {
+            MavMessage::HEARTBEAT(data) => assert_eq!(data.custom_mode, 7, "{} sends the original", policy),
+            other => panic!("expected HEARTBEAT, got {:?}", other),
+        }
+    }
+
+    let mut proxy =
+        common::start_with_network(FAILING_RULE, &[("failing", FAILING)], "on_modify_error = \"block\"").await;
+    proxy.send(1, 0, &heartbeat).await;
+    assert!(
+        proxy.try_recv(Duration::from_millis(300)).await.is_none(),
+        "block drops the message when the modifier fails"
+    );
+}
+
+const STAMP: &str = "function modify(ctx) ctx.message.custom_mode = 5 return ctx end";
+
+#[test]
+fn reprocess_without_a_match_keeps_the_rest_of_the_chain() {
+    let engine = common::engine_with_modifiers(
+        r#"
+[[rules]]
+name = "stamp"
+message_type = "HEARTBEAT"
+actions = ["modify", "block"]
+modifier = "stamp"
+reprocess = true
+
+[[rules]]
+name = "never"
+message_type = "HEARTBEAT"
+actions = ["forward"]
+
+[rules.conditions]
+custom_mode = 99
+"#,
+        &[("stamp", STAMP)],
+    );
+
+    let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+    let actions = engine.process_message(&MavHeader::default(), &heartbeat).actions;
+    assert!(
+        matches!(actions.as_slice(), [Action::Modify { .. }, Action::Block { .. }]),
+        "expected the rule's own block after the modify, got {:?}",
+        actions
+    );
+}
+
+#[test]
+fn reprocess_skips_the_rule_that_modified_the_message() {
+    let engine = common::engine_with_modifiers(
+        r#"
+[[rules]]
+name = "stamp"
+message_type = "HEARTBEAT"
+actions = ["modify", "forward"]
+modifier = "stamp"
+reprocess = true
+"#,
+        &[("stamp", STAMP)],
+    );
+
+    let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+    let actions = engine.process_message(&MavHeader::default(), &heartbeat).actions;
+    let modifies = actions.iter().filter(|a| matches!(a, Action::Modify { .. })).count();
+    assert_eq!(modifies, 1, "the modifier runs once, got {:?}", actions);
+    assert!(matches!(actions.last(), Some(Action::Forward)));
+}
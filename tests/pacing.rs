@@ -0,0 +1,43 @@
+//! radio_pacing: a congested RADIO_STATUS from the router side slows GCS -> router forwarding
+
+mod common;
+
+use mavlink::ardupilotmega::{MavMessage, HEARTBEAT_DATA, RADIO_STATUS_DATA};
+use std::time::{Duration, Instant};
+
+const PACING: &str = r#"
+[network.radio_pacing]
+congested_txbuf = 50
+max_delay_ms = 300
+"#;
+
+fn radio_status(txbuf: u8) -> MavMessage {
+    MavMessage::RADIO_STATUS(RADIO_STATUS_DATA {
+        txbuf,
+        ..Default::default()
+    })
+}
+
+#[tokio::test]
+async fn congested_radio_delays_forwarding() {
+    let mut proxy = common::start(PACING, &[]).await;
+    let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+
+    // The echoing router reflects RADIO_STATUS back, as a radio would report it
+    proxy.send(51, 0, &radio_status(0)).await;
+    proxy.recv().await;
+
+    let started = Instant::now();
+    proxy.send(1, 0, &heartbeat).await;
+    proxy.recv().await;
+    assert!(started.elapsed() >= Duration::from_millis(300), "full buffer should pace by max_delay_ms");
+
+    // A healthy report turns pacing off again
+    proxy.send(51, 1, &radio_status(100)).await;
+    proxy.recv().await;
+
+    let started = Instant::now();
+    proxy.send(1, 1, &heartbeat).await;
+    proxy.recv().await;
+    assert!(started.elapsed() < Duration::from_millis(300));
+}
@@ -0,0 +1,242 @@
+//! Plugin isolation: each plugin runs in its own Lua state (or process) and can only require its own directory
+
+use bitch::{PluginContext, PluginManager, RouteContext, RouteVerdict};
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Sets a global in on_load and checks it's still its own on every match
+const OWNER_A: &str = r#"
+function on_load(ctx)
+    owner = "a"
+end
+
+function on_match(ctx)
+    if owner ~= "a" then error("owner clobbered: " .. tostring(owner)) end
+    log.debug("plugin a matched " .. ctx.message_type)
+end
+"#;
+
+/// Same global names, and it removes the shared API tables
+const OWNER_B: &str = r#"
+function on_load(ctx)
+    owner = "b"
+end
+
+function on_match(ctx)
+    if owner ~= "b" then error("owner clobbered: " .. tostring(owner)) end
+    log = nil
+    http = nil
+end
+"#;
+
+fn write_plugin(dir: &Path, name: &str, code: &str) -> PathBuf {
+    let path = dir.join(format!("{}.lua", name));
+    std::fs::write(&path, code).unwrap();
+    path
+}
+
+fn context() -> PluginContext {
+    PluginContext {
+        system_id: 1,
+        component_id: 1,
+        message_type: "HEARTBEAT".to_string(),
+        message: json!({ "type": "HEARTBEAT" }),
+        rule_name: "test".to_string(),
+        trigger_context: HashMap::new(),
+        plugin_config: HashMap::new(),
+    }
+}
+
+#[test]
+fn plugins_cannot_clobber_each_other() {
+    let dir = std::env::temp_dir().join(format!("bitch-plugins-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut plugins = PluginManager::new().unwrap();
+    plugins.load_plugin("a", &write_plugin(&dir, "a", OWNER_A), None).unwrap();
+    plugins.load_plugin("b", &write_plugin(&dir, "b", OWNER_B), None).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let ctx = context();
+    for _ in 0..2 {
+        plugins.execute_plugin("b", &ctx).unwrap();
+        plugins.execute_plugin("a", &ctx).unwrap();
+    }
+}
+
+#[test]
+fn proxy_status_is_idle_before_the_proxy_runs() {
+    let status = r#"
+function on_match(ctx)
+    if proxy.client_count() ~= 0 then error("clients: " .. proxy.client_count()) end
+    if proxy.router_connected() then error("router reported connected") end
+    if proxy.uptime_seconds() ~= 0 then error("uptime: " .. proxy.uptime_seconds()) end
+end
+"#;
+
+    let mut plugins = PluginManager::new().unwrap();
+    plugins.load_inline("status", status).unwrap();
+    plugins.execute_plugin("status", &context()).unwrap();
+}
+
+#[test]
+fn require_is_confined_to_the_plugin_directory() {
+    let dir = std::env::temp_dir().join(format!("bitch-plugins-require-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    write_plugin(&dir.join("lib"), "shared", "return { answer = function() return 42 end }\n");
+
+    let uses_shared = r#"
+local shared = require("lib.shared")
+
+function on_match(ctx)
+    if shared.answer() ~= 42 then error("unexpected answer") end
+    for _, name in ipairs({ "../outside", "/etc/passwd", "missing" }) do
+        if pcall(require, name) then error("required " .. name) end
+    end
+end
+"#;
+
+    let mut plugins = PluginManager::new().unwrap();
+    plugins.set_module_dir(&dir);
+    plugins.load_plugin("uses_shared", &write_plugin(&dir, "uses_shared", uses_shared), None).unwrap();
+    plugins.execute_plugin("uses_shared", &context()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn route_hook_returns_verdicts_and_is_bounded_by_the_timeout() {
+    let routing = r#"
+function route(ctx)
+    if ctx.system_id == 1 then return "drop" end
+    if ctx.system_id == 2 then return { routers = { "10.0.0.2:5760" } } end
+    if ctx.system_id == 3 then return { group = ctx.client_group } end
+    if ctx.system_id == 4 then return "sideways" end
+    if ctx.system_id == 5 then while true do end end
+end
+"#;
+
+    let mut plugins = PluginManager::new().unwrap();
+    plugins.set_timeout(Duration::from_millis(50));
+    plugins.load_inline("routing", routing).unwrap();
+
+    let route = |system_id| {
+        let ctx = RouteContext {
+            direction: "gcs_to_router".to_string(),
+            system_id,
+            component_id: 1,
+            message_type: "HEARTBEAT".to_string(),
+            message: json!({ "type": "HEARTBEAT" }),
+            client_id: Some(1),
+            client_group: Some("pilots".to_string()),
+            router: None,
+        };
+        plugins.route("routing", "route", &ctx)
+    };
+
+    assert_eq!(route(1).unwrap(), RouteVerdict::Drop);
+    assert_eq!(route(2).unwrap(), RouteVerdict::Routers(vec!["10.0.0.2:5760".to_string()]));
+    assert_eq!(route(3).unwrap(), RouteVerdict::Group("pilots".to_string()));
+    assert!(route(4).is_err());
+    assert!(route(5).is_err());
+    assert_eq!(route(6).unwrap(), RouteVerdict::Forward);
+}
+
+#[test]
+fn on_match_sees_the_rule_plugin_config() {
+    const CONFIGURED: &str = r#"
+function on_match(ctx)
+    local config = ctx.plugin_config
+    if config.url ~= "http://example.com/hook" or config.retries ~= 3 or config.tags[2] ~= "b" then
+        error("unexpected plugin_config")
+    end
+end
+"#;
+    let dir = std::env::temp_dir().join(format!("bitch-plugin-config-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut plugins = PluginManager::new().unwrap();
+    plugins.load_plugin("configured", &write_plugin(&dir, "configured", CONFIGURED), None).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut ctx = context();
+    ctx.plugin_config = HashMap::from([
+        ("url".to_string(), json!("http://example.com/hook")),
+        ("retries".to_string(), json!(3)),
+        ("tags".to_string(), json!(["a", "b"])),
+    ]);
+    plugins.execute_plugin("configured", &ctx).unwrap();
+
+    ctx.plugin_config.clear();
+    assert!(plugins.execute_plugin("configured", &ctx).is_err(), "config is per call, not remembered");
+}
+
+#[test]
+fn on_match_sees_which_rule_called_it() {
+    // One plugin shared by two rules, strict for one of them only
+    let shared = r#"
+function on_match(ctx)
+    if ctx.rule_name == "strict_heartbeats" then
+        error("rejected by " .. ctx.rule_name)
+    end
+end
+"#;
+
+    let mut plugins = PluginManager::new().unwrap();
+    plugins.load_inline("shared", shared).unwrap();
+
+    let mut ctx = context();
+    ctx.rule_name = "lenient_heartbeats".to_string();
+    plugins.execute_plugin("shared", &ctx).unwrap();
+
+    ctx.rule_name = "strict_heartbeats".to_string();
+    let error = plugins.execute_plugin("shared", &ctx).unwrap_err();
+    assert!(format!("{:#}", error).contains("rejected by strict_heartbeats"), "{:#}", error);
+}
+
+#[cfg(unix)]
+#[test]
+fn process_plugin_answers_route_hooks_and_restarts_after_a_crash() {
+    let script = r#"
+while read -r line; do
+    case "$line" in
+        *'"hook":"on_load"'*) echo '{}' ;;
+        *'"message_type":"CRASH"'*) exit 1 ;;
+        *'"system_id":1,'*) echo '"drop"' ;;
+        *'"system_id":2,'*) echo '{"routers":["10.0.0.2:5760"]}' ;;
+        *'"system_id":3,'*) echo '{"error":"no route"}' ;;
+        *) echo 'null' ;;
+    esac
+done
+"#;
+    let mut plugins = PluginManager::new().unwrap();
+    plugins.set_timeout(Duration::from_millis(500));
+    plugins
+        .load_process("external", "sh", &["-c".to_string(), script.to_string()], &std::env::temp_dir(), None)
+        .unwrap();
+
+    let route = |system_id, message_type: &str| {
+        let ctx = RouteContext {
+            direction: "gcs_to_router".to_string(),
+            system_id,
+            component_id: 1,
+            message_type: message_type.to_string(),
+            message: json!({ "type": message_type }),
+            client_id: Some(1),
+            client_group: None,
+            router: None,
+        };
+        plugins.route("external", "route", &ctx)
+    };
+
+    assert_eq!(route(1, "HEARTBEAT").unwrap(), RouteVerdict::Drop);
+    assert_eq!(route(2, "HEARTBEAT").unwrap(), RouteVerdict::Routers(vec!["10.0.0.2:5760".to_string()]));
+    assert!(route(3, "HEARTBEAT").is_err(), "an error reply fails the call");
+    assert_eq!(route(4, "HEARTBEAT").unwrap(), RouteVerdict::Forward);
+
+    assert!(route(4, "CRASH").is_err());
+    std::thread::sleep(Duration::from_millis(1100));
+    assert_eq!(route(1, "HEARTBEAT").unwrap(), RouteVerdict::Drop, "restarted on the next call");
+
+    plugins.shutdown();
+}
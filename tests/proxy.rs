@@ -0,0 +1,411 @@
+//! End-to-end tests of the GCS -> router -> GCS forward path
+
+mod common;
+
+use bitch::{CancellationToken, Config, ModifierManager, PluginManager, ProxyServer};
+use mavlink::ardupilotmega::{
+    MavCmd, MavMessage, MavSeverity, MavState, MavType, ATTITUDE_DATA, COMMAND_LONG_DATA, HEARTBEAT_DATA, REQUEST_DATA_STREAM_DATA,
+    VFR_HUD_DATA,
+};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use tokio::time::{sleep, timeout};
+
+const RULES: &str = r#"
+[[rules]]
+name = "block_disarm"
+message_type = "COMMAND_LONG"
+action = "block"
+
+[rules.conditions]
+command = { type = "MAV_CMD_COMPONENT_ARM_DISARM" }
+param1 = 0.0
+
+[[rules]]
+name = "force_mode"
+message_type = "COMMAND_LONG"
+actions = ["modify", "forward"]
+modifier = "set_param2"
+
+[rules.conditions]
+command = { type = "MAV_CMD_DO_SET_MODE" }
+"#;
+
+/// Sets param2 = 4 only when invoked by the "force_mode" rule
+const SET_PARAM2: &str = r#"
+function modify(ctx)
+    if ctx.rule_name == "force_mode" then
+        ctx.message.param2 = 4.0
+    end
+    return ctx
+end
+"#;
+
+fn heartbeat(custom_mode: u32) -> MavMessage {
+    MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+        custom_mode,
+        ..Default::default()
+    })
+}
+
+fn command(command: MavCmd, param1: f32) -> MavMessage {
+    MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command,
+        param1,
+        target_system: 1,
+        target_component: 1,
+        ..Default::default()
+    })
+}
+
+async fn start() -> common::Harness {
+    common::start(RULES, &[("set_param2", SET_PARAM2)]).await
+}
+
+#[tokio::test]
+async fn forwards_unmatched_messages() {
+    let mut proxy = start().await;
+
+    proxy.send(7, 0, &heartbeat(42)).await;
+
+    let (header, msg) = proxy.recv().await;
+    assert_eq!(header.system_id, 7);
+    match msg {
+        MavMessage::HEARTBEAT(data) => assert_eq!(data.custom_mode, 42),
+        other => panic!("expected HEARTBEAT, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn router_to_gcs_runs_the_whole_chain_after_forward() {
+    let rules = r#"
+[[rules]]
+name = "hold_telemetry"
+message_type = "HEARTBEAT"
+direction = "router_to_gcs"
+actions = ["forward", "delay"]
+delay_seconds = 1
+"#;
+    let mut proxy = common::start(rules, &[]).await;
+
+    proxy.send(1, 0, &heartbeat(42)).await;
+    assert!(
+        proxy.try_recv(Duration::from_millis(600)).await.is_none(),
+        "a leading forward doesn't skip the delay"
+    );
+    let (header, _) = proxy.recv().await;
+    assert_eq!(header.sequence, 0);
+}
+
+#[tokio::test]
+async fn blocks_matching_messages() {
+    let mut proxy = start().await;
+
+    // Disarm is blocked, arm is not
+    proxy.send(255, 0, &command(MavCmd::MAV_CMD_COMPONENT_ARM_DISARM, 0.0)).await;
+    proxy.send(255, 1, &command(MavCmd::MAV_CMD_COMPONENT_ARM_DISARM, 1.0)).await;
+
+    let (header, msg) = proxy.recv().await;
+    assert_eq!(header.sequence, 1, "blocked frame must not reach the router");
+    match msg {
+        MavMessage::COMMAND_LONG(data) => assert_eq!(data.param1, 1.0),
+        other => panic!("expected COMMAND_LONG, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn modifies_matching_messages_with_rule_name_in_context() {
+    let mut proxy = start().await;
+
+    proxy.send(255, 3, &command(MavCmd::MAV_CMD_DO_SET_MODE, 1.0)).await;
+
+    let (header, msg) = proxy.recv().await;
+    assert_eq!(header.sequence, 3, "modified frame keeps the original header");
+    match msg {
+        MavMessage::COMMAND_LONG(data) => {
+            assert_eq!(data.command, MavCmd::MAV_CMD_DO_SET_MODE);
+            assert_eq!(data.param1, 1.0);
+            assert_eq!(data.param2, 4.0, "modifier should see ctx.rule_name");
+        }
+        other => panic!("expected COMMAND_LONG, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn forwards_payload_containing_magic_bytes_intact() {
+    let mut proxy = start().await;
+
+    proxy.send(1, 0, &heartbeat(0xFDFD_FDFD)).await;
+
+    match proxy.recv().await.1 {
+        MavMessage::HEARTBEAT(data) => assert_eq!(data.custom_mode, 0xFDFD_FDFD),
+        other => panic!("expected HEARTBEAT, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn resyncs_after_false_magic_byte() {
+    let mut proxy = start().await;
+
+    // Noise with a 0xFD claiming a 255-byte payload, directly followed by real frames.
+    // Without checksum-based resync the bogus length would swallow the first frames.
+    let mut bytes = vec![0x00, 0x42, 0xFD, 0xFF, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00];
+    for sequence in 0..40 {
+        bytes.extend(common::frame(1, sequence, &heartbeat(sequence as u32 + 1)));
+    }
+    proxy.send_raw(&bytes).await;
+
+    let (header, _) = proxy.recv().await;
+    assert_eq!(header.sequence, 0, "first real frame after the noise must survive");
+    let (header, _) = proxy.recv().await;
+    assert_eq!(header.sequence, 1);
+}
+
+#[tokio::test]
+async fn mirrors_to_every_router_and_merges_replies() {
+    let mut proxy = common::start_with_routers(RULES, &[("set_param2", SET_PARAM2)], 2).await;
+
+    proxy.send(9, 5, &heartbeat(7)).await;
+
+    // Each router echoes its copy, and both copies reach the GCS
+    for _ in 0..2 {
+        let (header, msg) = proxy.recv().await;
+        assert_eq!((header.system_id, header.sequence), (9, 5));
+        assert!(matches!(msg, MavMessage::HEARTBEAT(data) if data.custom_mode == 7));
+    }
+}
+
+#[tokio::test]
+async fn waits_for_a_router_that_starts_late() {
+    // Nothing listens on the router port yet, so connects are refused (transient)
+    let router_port = common::free_port().await;
+    let gcs_port = common::free_port().await;
+    let config: Config = toml::from_str(&format!(
+        r#"
+[network]
+gcs_listen_port = {gcs_port}
+gcs_listen_address = "127.0.0.1"
+router_address = "127.0.0.1"
+router_port = {router_port}
+router_max_fatal_retries = 0
+
+[logging]
+level = "warn"
+"#
+    ))
+    .unwrap();
+    let server = ProxyServer::new(config, PluginManager::new().unwrap(), ModifierManager::new().unwrap()).unwrap();
+    let shutdown = CancellationToken::new();
+    let run_token = shutdown.clone();
+    let run = tokio::spawn(async move { server.run(run_token).await });
+
+    sleep(Duration::from_millis(300)).await;
+    assert!(!run.is_finished(), "refused connects are retried, not fatal");
+
+    let router = TcpListener::bind(("127.0.0.1", router_port)).await.unwrap();
+    timeout(Duration::from_secs(5), router.accept())
+        .await
+        .expect("proxy should connect once the router is up")
+        .unwrap();
+
+    shutdown.cancel();
+    assert!(run.await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn closes_the_link_to_a_router_that_stops_reading() {
+    // A small receive buffer (inherited by the accepted socket), so the kernel can't absorb the flood
+    let socket = TcpSocket::new_v4().unwrap();
+    socket.set_recv_buffer_size(4096).unwrap();
+    socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let router = socket.listen(1).unwrap();
+    let router_port = router.local_addr().unwrap().port();
+    let gcs_port = common::free_port().await;
+    let config: Config = toml::from_str(&format!(
+        r#"
+[network]
+gcs_listen_port = {gcs_port}
+gcs_listen_address = "127.0.0.1"
+router_address = "127.0.0.1"
+router_port = {router_port}
+router_queue_size = 1
+router_write_timeout_ms = 200
+tcp_send_buffer = 4096
+
+[logging]
+level = "error"
+"#
+    ))
+    .unwrap();
+    let server = ProxyServer::new(config, PluginManager::new().unwrap(), ModifierManager::new().unwrap()).unwrap();
+    let shutdown = CancellationToken::new();
+    let run_token = shutdown.clone();
+    let run = tokio::spawn(async move { server.run(run_token).await });
+
+    // The router accepts the connection but never reads from it
+    let (_stalled, _) = timeout(Duration::from_secs(5), router.accept()).await.unwrap().unwrap();
+    // The GCS listener is bound after the router connection, so retry briefly
+    let connect = async {
+        loop {
+            match TcpStream::connect(("127.0.0.1", gcs_port)).await {
+                Ok(stream) => break stream,
+                Err(_) => sleep(Duration::from_millis(20)).await,
+            }
+        }
+    };
+    let mut gcs = timeout(Duration::from_secs(5), connect).await.expect("GCS should connect");
+    let flood = tokio::spawn(async move {
+        let heartbeats: Vec<u8> = (0..1000u32)
+            .flat_map(|n| common::frame(255, n as u8, &heartbeat(n)))
+            .collect();
+        while gcs.write_all(&heartbeats).await.is_ok() {}
+    });
+
+    // Once the queue stays full for router_write_timeout_ms the link is closed, and with its
+    // only router gone the proxy stops - well before the 5 s default timeout could have fired
+    let stopped = timeout(Duration::from_secs(3), run).await;
+    flood.abort();
+    shutdown.cancel();
+    assert!(stopped.is_ok(), "the stalled router link should be closed");
+}
+
+#[tokio::test]
+async fn announces_itself_to_the_router() {
+    let announce = r#"
+[network.announce]
+interval_seconds = 1
+
+[network.announce.fields]
+system_status = "MAV_STATE_STANDBY"
+"#;
+    let mut proxy = common::start(announce, &[]).await;
+
+    // The echoing router sends the announcement straight back
+    let (header, msg) = proxy.recv().await;
+    assert_eq!((header.system_id, header.component_id), (255, 190));
+    let MavMessage::HEARTBEAT(data) = msg else {
+        panic!("expected a HEARTBEAT, got {:?}", msg);
+    };
+    assert_eq!(data.mavtype, MavType::MAV_TYPE_GCS);
+    assert_eq!(data.system_status, MavState::MAV_STATE_STANDBY);
+}
+
+#[tokio::test]
+async fn broadcasts_status_text_to_gcs_clients() {
+    let rules = r#"
+[[rules]]
+name = "block_takeoff"
+message_type = "COMMAND_LONG"
+action = "block"
+"#;
+    let mut proxy = common::start_with_logging(rules, &[], "status_broadcast_seconds = 1").await;
+
+    // The blocked command never comes back, so the next frame is the status broadcast
+    proxy
+        .send(
+            255,
+            0,
+            &MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+                command: MavCmd::MAV_CMD_NAV_TAKEOFF,
+                ..Default::default()
+            }),
+        )
+        .await;
+
+    let (header, msg) = proxy.recv().await;
+    assert_eq!((header.system_id, header.component_id), (1, 191));
+    let MavMessage::STATUSTEXT(data) = msg else {
+        panic!("expected a STATUSTEXT, got {:?}", msg);
+    };
+    assert_eq!(data.severity, MavSeverity::MAV_SEVERITY_INFO);
+    let text = String::from_utf8_lossy(&data.text);
+    assert_eq!(text.trim_end_matches('\0'), "BITCH: 1/1 rules active, 1 blocked");
+}
+
+#[tokio::test]
+async fn process_injected_runs_announcements_through_the_rules() {
+    let rules = r#"
+[network.announce]
+interval_seconds = 1
+
+[[rules]]
+name = "drop_own_announcements"
+message_type = "HEARTBEAT"
+action = "block"
+
+[rules.conditions]
+system_id = 255
+"#;
+    let mut proxy = common::start_with_network(rules, &[], "process_injected = true").await;
+
+    proxy.send(1, 0, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+    let (header, _) = proxy.recv().await;
+    assert_eq!(header.system_id, 1);
+
+    // Without process_injected the announcement would come back within a second
+    assert!(proxy.try_recv(Duration::from_millis(2500)).await.is_none());
+}
+
+#[tokio::test]
+async fn stream_subscriptions_filter_broadcasts_per_client() {
+    let mut proxy = common::start_with_network("", &[], "stream_subscriptions = true").await;
+
+    // Ask for EXTRA1 (ATTITUDE among others) at 1 Hz; the request itself is echoed back
+    let request = MavMessage::REQUEST_DATA_STREAM(REQUEST_DATA_STREAM_DATA {
+        req_message_rate: 1,
+        target_system: 1,
+        target_component: 1,
+        req_stream_id: 10,
+        start_stop: 1,
+    });
+    proxy.send(255, 0, &request).await;
+    assert!(matches!(proxy.recv().await.1, MavMessage::REQUEST_DATA_STREAM(_)));
+
+    // VFR_HUD (EXTRA2) wasn't asked for, the second ATTITUDE is over the rate and HEARTBEAT isn't a stream
+    proxy.send(1, 1, &MavMessage::VFR_HUD(VFR_HUD_DATA::default())).await;
+    proxy.send(1, 2, &MavMessage::ATTITUDE(ATTITUDE_DATA::default())).await;
+    proxy.send(1, 3, &MavMessage::ATTITUDE(ATTITUDE_DATA::default())).await;
+    proxy.send(1, 4, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+
+    let (header, msg) = proxy.recv().await;
+    assert!(matches!(msg, MavMessage::ATTITUDE(_)), "got {:?}", msg);
+    assert_eq!(header.sequence, 2);
+    let (_, msg) = proxy.recv().await;
+    assert!(matches!(msg, MavMessage::HEARTBEAT(_)), "got {:?}", msg);
+}
+
+#[tokio::test]
+async fn stream_subscriptions_follow_the_target_system() {
+    let mut proxy = common::start_with_network("", &[], "stream_subscriptions = true").await;
+    let extra1 = |target_system, start_stop| {
+        MavMessage::REQUEST_DATA_STREAM(REQUEST_DATA_STREAM_DATA {
+            req_message_rate: 1,
+            target_system,
+            target_component: 1,
+            req_stream_id: 10,
+            start_stop,
+        })
+    };
+
+    // EXTRA1 from every system: each vehicle's ATTITUDE has its own rate limit
+    proxy.send(255, 0, &extra1(0, 1)).await;
+    assert!(matches!(proxy.recv().await.1, MavMessage::REQUEST_DATA_STREAM(_)));
+    proxy.send(1, 1, &MavMessage::ATTITUDE(ATTITUDE_DATA::default())).await;
+    proxy.send(2, 2, &MavMessage::ATTITUDE(ATTITUDE_DATA::default())).await;
+    for system_id in [1, 2] {
+        let (header, msg) = proxy.recv().await;
+        assert!(matches!(msg, MavMessage::ATTITUDE(_)), "got {:?}", msg);
+        assert_eq!(header.system_id, system_id);
+    }
+
+    // Stopping it on system 2 leaves system 1 subscribed
+    proxy.send(255, 3, &extra1(2, 0)).await;
+    assert!(matches!(proxy.recv().await.1, MavMessage::REQUEST_DATA_STREAM(_)));
+    sleep(Duration::from_millis(1000)).await;
+    proxy.send(2, 4, &MavMessage::ATTITUDE(ATTITUDE_DATA::default())).await;
+    proxy.send(1, 5, &MavMessage::ATTITUDE(ATTITUDE_DATA::default())).await;
+    let (header, msg) = proxy.recv().await;
+    assert!(matches!(msg, MavMessage::ATTITUDE(_)), "got {:?}", msg);
+    assert_eq!((header.system_id, header.sequence), (1, 5));
+}
@@ -0,0 +1,82 @@
+//! `bitch replay-dir`: a directory of raw frame files run through the rules by the CLI
+
+mod common;
+
+use mavlink::ardupilotmega::{MavCmd, MavMessage, COMMAND_LONG_DATA, HEARTBEAT_DATA};
+use std::process::Command;
+
+const CONFIG: &str = r#"
+[network]
+gcs_listen_port = 5760
+gcs_listen_address = "127.0.0.1"
+router_address = "127.0.0.1"
+router_port = 5761
+
+[logging]
+level = "warn"
+
+[[rules]]
+name = "block_disarm"
+message_type = "COMMAND_LONG"
+action = "block"
+
+[rules.conditions]
+command = { type = "MAV_CMD_COMPONENT_ARM_DISARM" }
+param1 = 0.0
+"#;
+
+#[test]
+fn replays_every_file_in_filename_order() {
+    let dir = std::env::temp_dir().join(format!("bitch-replay-dir-{}", std::process::id()));
+    let frames = dir.join("frames");
+    std::fs::create_dir_all(&frames).unwrap();
+    let config = dir.join("config.toml");
+    std::fs::write(&config, CONFIG).unwrap();
+
+    let disarm = MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_COMPONENT_ARM_DISARM,
+        param1: 0.0,
+        ..Default::default()
+    });
+    // Written out of order: the replay sorts by filename
+    let heartbeat = MavMessage::HEARTBEAT(HEARTBEAT_DATA::default());
+    std::fs::write(frames.join("02_heartbeat.bin"), common::frame(255, 1, &heartbeat)).unwrap();
+    std::fs::write(frames.join("01_disarm.bin"), common::frame(255, 0, &disarm)).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_bitch"))
+        .arg("--config")
+        .arg(&config)
+        .arg("replay-dir")
+        .arg(&frames)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "replay-dir failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let disarm_at = stdout.find("== 01_disarm.bin").expect("disarm frame replayed");
+    let heartbeat_at = stdout.find("== 02_heartbeat.bin").expect("heartbeat frame replayed");
+    assert!(disarm_at < heartbeat_at);
+    assert!(stdout[disarm_at..heartbeat_at].contains("Actions:   block by 'block_disarm'"));
+    assert!(stdout[heartbeat_at..].contains("Actions:   forward"));
+    assert!(stdout.contains("Replayed 2 frame file(s)"));
+
+    // A file that isn't a frame is reported and fails the run, after the others are replayed
+    std::fs::write(frames.join("03_garbage.bin"), b"not a frame").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_bitch"))
+        .arg("--config")
+        .arg(&config)
+        .arg("replay-dir")
+        .arg(&frames)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success());
+    assert!(stdout.contains("== 03_garbage.bin\nError:"), "{}", stdout);
+    assert!(stdout.contains("Replayed 3 frame file(s)"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,40 @@
+//! Resync on false magic bytes: a 0xFD that fails the checksum is skipped one byte at a time
+
+mod common;
+
+use mavlink::ardupilotmega::{MavMessage, HEARTBEAT_DATA};
+use std::time::Duration;
+
+#[tokio::test]
+async fn false_magic_does_not_swallow_the_next_frame() {
+    let mut proxy = common::start("", &[]).await;
+
+    // 0xFD bytes inside the real frames' payloads
+    let first = common::frame(
+        1,
+        0,
+        &MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+            custom_mode: 0xFDFD_FDFD,
+            ..Default::default()
+        }),
+    );
+    let second = common::frame(
+        1,
+        1,
+        &MavMessage::HEARTBEAT(HEARTBEAT_DATA {
+            custom_mode: 0x00FD_00FD,
+            ..Default::default()
+        }),
+    );
+    assert!(first[10..first.len() - 2].contains(&0xFD));
+
+    // Noise with a false HEARTBEAT header whose claimed length runs into the real frames
+    let mut stream = vec![0x55, 0xFD, 20, 0, 0, 9, 1, 1, 0, 0, 0, 0xAA];
+    stream.extend_from_slice(&first);
+    stream.extend_from_slice(&second);
+    proxy.send_raw(&stream).await;
+
+    assert_eq!(proxy.recv_raw().await, first);
+    assert_eq!(proxy.recv_raw().await, second);
+    assert!(proxy.try_recv(Duration::from_millis(200)).await.is_none(), "no frame assembled from payload bytes");
+}
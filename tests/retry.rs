@@ -0,0 +1,66 @@
+//! retry_until_ack: re-sends until a matching ACK comes back from the router side
+
+mod common;
+
+use mavlink::ardupilotmega::{MavCmd, MavMessage, MavResult, COMMAND_ACK_DATA, COMMAND_LONG_DATA};
+use std::time::Duration;
+
+const RULES: &str = r#"
+[[rules]]
+name = "retry_takeoff"
+message_type = "COMMAND_LONG"
+actions = ["retry_until_ack", "forward"]
+retry_timeout_seconds = 1
+retry_attempts = 1
+
+[rules.conditions]
+command = { type = "MAV_CMD_NAV_TAKEOFF" }
+"#;
+
+fn takeoff() -> MavMessage {
+    MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_NAV_TAKEOFF,
+        target_system: 1,
+        target_component: 1,
+        ..Default::default()
+    })
+}
+
+fn takeoff_ack() -> MavMessage {
+    MavMessage::COMMAND_ACK(COMMAND_ACK_DATA {
+        command: MavCmd::MAV_CMD_NAV_TAKEOFF,
+        result: MavResult::MAV_RESULT_ACCEPTED,
+    })
+}
+
+#[tokio::test]
+async fn resends_until_attempts_run_out() {
+    let mut proxy = common::start(RULES, &[]).await;
+
+    proxy.send(255, 9, &takeoff()).await;
+
+    // Original, then exactly one re-send of the same frame
+    for _ in 0..2 {
+        let (header, msg) = proxy.recv().await;
+        assert_eq!(header.sequence, 9);
+        assert!(matches!(msg, MavMessage::COMMAND_LONG(_)));
+    }
+    assert!(proxy.try_recv(Duration::from_millis(1500)).await.is_none());
+}
+
+#[tokio::test]
+async fn ack_from_target_cancels_resend() {
+    let mut proxy = common::start(RULES, &[]).await;
+
+    proxy.send(255, 9, &takeoff()).await;
+    assert!(matches!(proxy.recv().await.1, MavMessage::COMMAND_LONG(_)));
+
+    // The echoing router returns this ACK on the router -> GCS path, as if from sysid 1 / compid 1
+    proxy.send(1, 0, &takeoff_ack()).await;
+    assert!(matches!(proxy.recv().await.1, MavMessage::COMMAND_ACK(_)));
+
+    assert!(
+        proxy.try_recv(Duration::from_millis(1500)).await.is_none(),
+        "no re-send after the ACK"
+    );
+}
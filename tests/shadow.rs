@@ -0,0 +1,46 @@
+//! Shadow rules are evaluated but never change forwarding, and their disagreements are counted
+
+mod common;
+
+use mavlink::ardupilotmega::{MavMessage, HEARTBEAT_DATA, SYS_STATUS_DATA};
+
+const RULES: &str = r#"
+[[shadow_rules]]
+name = "shadow_block_heartbeats"
+message_type = "HEARTBEAT"
+action = "block"
+"#;
+
+#[tokio::test]
+async fn shadow_block_does_not_block() {
+    let mut proxy = common::start(RULES, &[]).await;
+
+    proxy.send(3, 0, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+
+    let (header, msg) = proxy.recv().await;
+    assert_eq!(header.system_id, 3);
+    assert!(matches!(msg, MavMessage::HEARTBEAT(_)));
+}
+
+#[tokio::test]
+async fn stats_count_shadow_mismatches() {
+    let admin_port = common::free_port().await;
+    let rules = format!("[admin]\nlisten_port = {admin_port}\n{RULES}");
+    let mut proxy = common::start(&rules, &[]).await;
+
+    // Evaluated on the way to the router and again on the echo back; rules default to
+    // gcs_to_router, so the shadow only blocks (and disagrees) the first time
+    proxy.send(3, 0, &MavMessage::HEARTBEAT(HEARTBEAT_DATA::default())).await;
+    proxy.recv().await;
+    let (status, body) = common::request(admin_port, "GET", "/stats").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["shadow"]["evaluated"], 2);
+    assert_eq!(body["shadow"]["mismatches"], 1);
+
+    // The shadow ruleset agrees on everything it doesn't match
+    proxy.send(3, 1, &MavMessage::SYS_STATUS(SYS_STATUS_DATA::default())).await;
+    proxy.recv().await;
+    let (_, body) = common::request(admin_port, "GET", "/stats").await;
+    assert_eq!(body["shadow"]["evaluated"], 4);
+    assert_eq!(body["shadow"]["mismatches"], 1);
+}
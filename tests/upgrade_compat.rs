@@ -0,0 +1,67 @@
+//! upgrade_compat: ACKs for upgraded commands go only to the GCS that sent the COMMAND_LONG
+
+mod common;
+
+use mavlink::ardupilotmega::{MavCmd, MavMessage, MavResult, COMMAND_ACK_DATA, COMMAND_LONG_DATA};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+const RULES: &str = r#"
+[[rules]]
+name = "reposition_as_int"
+message_type = "COMMAND_LONG"
+actions = ["upgrade_command", "forward"]
+upgrade_compat = true
+
+[rules.conditions]
+command = { type = "MAV_CMD_DO_REPOSITION" }
+"#;
+
+fn reposition_ack(result: MavResult) -> MavMessage {
+    MavMessage::COMMAND_ACK(COMMAND_ACK_DATA {
+        command: MavCmd::MAV_CMD_DO_REPOSITION,
+        result,
+    })
+}
+
+fn ack_result(msg: Option<(mavlink::MavHeader, MavMessage)>) -> MavResult {
+    match msg {
+        Some((_, MavMessage::COMMAND_ACK(ack))) => ack.result,
+        other => panic!("expected COMMAND_ACK, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn ack_goes_only_to_the_requesting_gcs() {
+    let mut proxy = common::start(RULES, &[]).await;
+    let mut other = proxy.connect_gcs().await;
+    let wait = Duration::from_millis(300);
+
+    let reposition = MavMessage::COMMAND_LONG(COMMAND_LONG_DATA {
+        command: MavCmd::MAV_CMD_DO_REPOSITION,
+        target_system: 1,
+        target_component: 1,
+        ..Default::default()
+    });
+    proxy.send(255, 0, &reposition).await;
+    assert!(matches!(proxy.recv().await.1, MavMessage::COMMAND_INT(_)));
+    assert!(matches!(common::try_recv_from(&mut other, wait).await, Some((_, MavMessage::COMMAND_INT(_)))));
+
+    // The other client plays the vehicle: the echoing router returns its ACKs on the
+    // router -> GCS path, as if from sysid 1 / compid 1
+    let in_progress = common::frame(1, 1, &reposition_ack(MavResult::MAV_RESULT_IN_PROGRESS));
+    other.write_all(&in_progress).await.unwrap();
+    assert_eq!(ack_result(proxy.try_recv(wait).await), MavResult::MAV_RESULT_IN_PROGRESS, "in-progress ACKs are routed too");
+    assert!(common::try_recv_from(&mut other, wait).await.is_none());
+
+    let accepted = common::frame(1, 2, &reposition_ack(MavResult::MAV_RESULT_ACCEPTED));
+    other.write_all(&accepted).await.unwrap();
+    assert_eq!(ack_result(proxy.try_recv(wait).await), MavResult::MAV_RESULT_ACCEPTED);
+    assert!(common::try_recv_from(&mut other, wait).await.is_none());
+
+    // The final ACK completed the request, so a stray one is broadcast as usual
+    let stray = common::frame(1, 3, &reposition_ack(MavResult::MAV_RESULT_ACCEPTED));
+    other.write_all(&stray).await.unwrap();
+    assert_eq!(ack_result(proxy.try_recv(wait).await), MavResult::MAV_RESULT_ACCEPTED);
+    assert_eq!(ack_result(common::try_recv_from(&mut other, wait).await), MavResult::MAV_RESULT_ACCEPTED);
+}